@@ -0,0 +1,78 @@
+//! Interpreter loop throughput. Each program's throughput is its number of
+//! executed opcodes, so criterion reports opcodes/second.
+
+use abby_evm::evm::{ConsoleSink, EvmExecutor, ExecutionContext};
+use abby_evm::opcodes::{opcode_info, OpCode};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const GAS_LIMIT: u64 = 100_000_000;
+
+/// Count down from 1000: PUSH1 1, SWAP1, SUB, DUP1, JUMPI back while non-zero
+const ARITHMETIC_LOOP: &str = "6103e85b600190038060035700";
+
+/// For i from 256 down to 1: SSTORE(i, i), then SLOAD(i) and discard it
+const STORAGE_LOOP: &str = "6101005b808055805450600190038060035700";
+
+/// CODECOPY the code to memory, then copy 8 KiB of memory one word at a time
+/// with MLOAD/MSTORE into the region right after it
+const MEMORY_COPY: &str = "3860006000396120005b6020900380518161200001528060095700";
+
+fn opcode_count(bytecode: &[u8]) -> u64 {
+    let executor = EvmExecutor::new(GAS_LIMIT);
+    let mut debugger = executor.debugger(bytecode, &ExecutionContext::default());
+    debugger.run_to_end().len() as u64
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    // Console output would time stdout rather than the interpreter
+    let quiet = ConsoleSink::callback(|_| {});
+
+    for (name, program) in [
+        ("arithmetic_loop", ARITHMETIC_LOOP),
+        ("storage_loop", STORAGE_LOOP),
+        ("memory_copy", MEMORY_COPY),
+    ] {
+        let bytecode = hex::decode(program).unwrap();
+        group.throughput(Throughput::Elements(opcode_count(&bytecode)));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                // A fresh executor per run so storage never starts warm
+                let mut executor = EvmExecutor::new(GAS_LIMIT).with_console_sink(quiet.clone());
+                executor.execute(black_box(&bytecode), 0, false).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Per-instruction decoding before and after the opcode table: three matches
+/// (`from_byte`, `gas_cost`, `push_size`) against one table lookup
+fn dispatch(c: &mut Criterion) {
+    let bytes: Vec<u8> = (0..=255).collect();
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(bytes.len() as u64));
+
+    group.bench_function("match", |b| {
+        b.iter(|| {
+            for &byte in black_box(&bytes) {
+                let opcode = OpCode::from_byte(byte);
+                black_box((opcode.gas_cost(), opcode.push_size(), opcode));
+            }
+        })
+    });
+    group.bench_function("table", |b| {
+        b.iter(|| {
+            for &byte in black_box(&bytes) {
+                let info = opcode_info(byte);
+                black_box((info.gas, info.push_size, &info.opcode));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, interpreter, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Runs arbitrary bytes as bytecode. Bad code must end in an error status,
+//! never a panic.
+
+#![no_main]
+
+use abby_evm::evm::EvmExecutor;
+use libfuzzer_sys::fuzz_target;
+
+/// Every instruction that does not halt costs gas, so this also bounds the
+/// number of steps a single input can run
+const GAS_LIMIT: u64 = 100_000;
+
+fuzz_target!(|bytecode: &[u8]| {
+    let mut executor = EvmExecutor::new(GAS_LIMIT);
+    let result = executor.execute(bytecode, 0, false);
+    assert!(result.is_ok(), "execute failed outside the VM: {:?}", result.err());
+    assert!(executor.executed_steps() as u64 <= GAS_LIMIT + 1);
+});
@@ -0,0 +1,17 @@
+//! Lexes and parses arbitrary text. Malformed source must be reported as a
+//! lex or parse error, never a panic.
+
+#![no_main]
+
+use abby_evm::compiler::lexer::Lexer;
+use abby_evm::compiler::parser::Parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(tokens) = Lexer::new(source).tokenize() {
+        let _ = Parser::new(tokens).parse();
+    }
+});
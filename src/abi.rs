@@ -0,0 +1,640 @@
+use ethereum_types::{Address, U256};
+use sha3::{Digest, Keccak256};
+
+/// ABI parameter types supported by the encoder
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    Address,
+    Uint256,
+    Bool,
+    Bytes32,
+    String,
+    Bytes,
+    Array(Box<ParamType>),
+}
+
+impl ParamType {
+    /// Parse a Solidity type name such as `uint256` or `address`
+    pub fn parse(name: &str) -> Result<Self, AbiError> {
+        let name = name.trim();
+        if let Some(inner) = name.strip_suffix("[]") {
+            // Nested arrays can't be expressed with the comma-separated argument syntax
+            return match ParamType::parse(inner)? {
+                ParamType::Array(_) => Err(AbiError::UnsupportedType(name.to_string())),
+                element => Ok(ParamType::Array(Box::new(element))),
+            };
+        }
+        match name {
+            "address" => Ok(ParamType::Address),
+            "uint256" | "uint" => Ok(ParamType::Uint256),
+            "bool" => Ok(ParamType::Bool),
+            "bytes32" => Ok(ParamType::Bytes32),
+            "string" => Ok(ParamType::String),
+            "bytes" => Ok(ParamType::Bytes),
+            other => Err(AbiError::UnsupportedType(other.to_string())),
+        }
+    }
+
+    /// Parse a comma-separated type list such as `uint256,address`
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, AbiError> {
+        if list.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        list.split(',').map(ParamType::parse).collect()
+    }
+}
+
+/// Solidity type name, as accepted by `ParamType::parse`
+impl std::fmt::Display for ParamType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamType::Address => write!(f, "address"),
+            ParamType::Uint256 => write!(f, "uint256"),
+            ParamType::Bool => write!(f, "bool"),
+            ParamType::Bytes32 => write!(f, "bytes32"),
+            ParamType::String => write!(f, "string"),
+            ParamType::Bytes => write!(f, "bytes"),
+            ParamType::Array(element) => write!(f, "{}[]", element),
+        }
+    }
+}
+
+/// A typed ABI value ready to be encoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Address(Address),
+    Uint(U256),
+    Bool(bool),
+    FixedBytes([u8; 32]),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Token>),
+}
+
+impl Token {
+    /// Dynamic tokens are stored in the tail and referenced by an offset in the head
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self, Token::String(_) | Token::Bytes(_) | Token::Array(_))
+    }
+}
+
+/// Human-readable form: integers in decimal, byte strings in 0x-prefixed hex
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Address(address) => write!(f, "{:?}", address),
+            Token::Uint(value) => write!(f, "{}", value),
+            Token::Bool(value) => write!(f, "{}", value),
+            Token::FixedBytes(bytes) => write!(f, "0x{}", hex::encode(bytes)),
+            Token::String(text) => write!(f, "{:?}", text),
+            Token::Bytes(bytes) => write!(f, "0x{}", hex::encode(bytes)),
+            Token::Array(items) => {
+                let items: Vec<String> = items.iter().map(Token::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiError {
+    UnsupportedType(String),
+    InvalidHex(String),
+    InvalidAddressLength(usize),
+    UintOverflow(String),
+    InvalidUint(String),
+    InvalidBool(String),
+    InvalidBytes32Length(usize),
+    InvalidArray(String),
+    /// Encoded data ends before the word or bytes needed at this offset
+    Truncated(usize),
+    /// A word that is not a valid encoding of its type, e.g. a bool of 2
+    InvalidEncoding(String),
+    /// Number of arguments given does not match the number of types
+    ArgumentCount {
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl std::fmt::Display for AbiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AbiError::UnsupportedType(t) => write!(f, "Unsupported ABI type '{}'", t),
+            AbiError::InvalidHex(s) => write!(f, "Invalid hex string '{}'", s),
+            AbiError::InvalidAddressLength(len) => {
+                write!(f, "Invalid address length: expected 20 bytes, got {}", len)
+            }
+            AbiError::UintOverflow(s) => write!(f, "Value '{}' exceeds 256 bits", s),
+            AbiError::InvalidUint(s) => write!(f, "Invalid unsigned integer '{}'", s),
+            AbiError::InvalidBool(s) => {
+                write!(f, "Invalid bool '{}': expected true, false, 1 or 0", s)
+            }
+            AbiError::InvalidBytes32Length(len) => {
+                write!(f, "Invalid bytes32 length: expected 32 bytes, got {}", len)
+            }
+            AbiError::InvalidArray(s) => {
+                write!(f, "Invalid array '{}': expected [item, item, ...]", s)
+            }
+            AbiError::Truncated(offset) => write!(f, "ABI data truncated at offset {}", offset),
+            AbiError::InvalidEncoding(s) => write!(f, "Invalid ABI encoding: {}", s),
+            AbiError::ArgumentCount { expected, got } => {
+                write!(f, "Expected {} arguments, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AbiError {}
+
+/// Decode a hex string (with or without 0x prefix) into bytes
+fn decode_hex(value: &str) -> Result<Vec<u8>, AbiError> {
+    let trimmed = value.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    hex::decode(digits).map_err(|_| AbiError::InvalidHex(value.to_string()))
+}
+
+/// Parse a textual argument into a token, rejecting malformed input
+/// instead of silently truncating or padding it
+pub fn parse_token(param: &ParamType, value: &str) -> Result<Token, AbiError> {
+    match param {
+        ParamType::Address => {
+            let bytes = decode_hex(value)?;
+            if bytes.len() != 20 {
+                return Err(AbiError::InvalidAddressLength(bytes.len()));
+            }
+            Ok(Token::Address(Address::from_slice(&bytes)))
+        }
+        ParamType::Uint256 => {
+            let trimmed = value.trim();
+            if let Some(digits) = trimmed
+                .strip_prefix("0x")
+                .or_else(|| trimmed.strip_prefix("0X"))
+            {
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(AbiError::InvalidHex(value.to_string()));
+                }
+                let significant = digits.trim_start_matches('0');
+                if significant.len() > 64 {
+                    return Err(AbiError::UintOverflow(value.to_string()));
+                }
+                U256::from_str_radix(digits, 16)
+                    .map(Token::Uint)
+                    .map_err(|_| AbiError::UintOverflow(value.to_string()))
+            } else {
+                if trimmed.is_empty() || !trimmed.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(AbiError::InvalidUint(value.to_string()));
+                }
+                U256::from_dec_str(trimmed)
+                    .map(Token::Uint)
+                    .map_err(|_| AbiError::UintOverflow(value.to_string()))
+            }
+        }
+        ParamType::Bool => match value.trim() {
+            "true" | "1" => Ok(Token::Bool(true)),
+            "false" | "0" => Ok(Token::Bool(false)),
+            _ => Err(AbiError::InvalidBool(value.to_string())),
+        },
+        ParamType::Bytes32 => {
+            let bytes = decode_hex(value)?;
+            if bytes.len() != 32 {
+                return Err(AbiError::InvalidBytes32Length(bytes.len()));
+            }
+            let mut word = [0u8; 32];
+            word.copy_from_slice(&bytes);
+            Ok(Token::FixedBytes(word))
+        }
+        ParamType::String => Ok(Token::String(value.to_string())),
+        ParamType::Bytes => Ok(Token::Bytes(decode_hex(value)?)),
+        ParamType::Array(element) => {
+            let trimmed = value.trim();
+            let items = trimmed
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .ok_or_else(|| AbiError::InvalidArray(value.to_string()))?;
+            if items.trim().is_empty() {
+                return Ok(Token::Array(Vec::new()));
+            }
+            items
+                .split(',')
+                .map(|item| parse_token(element, item.trim()))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Token::Array)
+        }
+    }
+}
+
+/// Encode a static token as a 32-byte ABI word
+fn encode_word(token: &Token) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    match token {
+        Token::Address(address) => word[12..].copy_from_slice(address.as_bytes()),
+        Token::Uint(value) => value.to_big_endian(&mut word),
+        Token::Bool(b) => word[31] = *b as u8,
+        Token::FixedBytes(bytes) => word.copy_from_slice(bytes),
+        Token::String(_) | Token::Bytes(_) | Token::Array(_) => {
+            unreachable!("dynamic tokens are encoded in the tail")
+        }
+    }
+    word
+}
+
+/// Encode a length-prefixed byte string, right-padded to a word boundary
+fn encode_packed_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut out = encode_word(&Token::Uint(U256::from(bytes.len()))).to_vec();
+    out.extend_from_slice(bytes);
+    out.resize(32 + bytes.len().div_ceil(32) * 32, 0);
+    out
+}
+
+/// Encode the tail section of a dynamic token
+fn encode_tail(token: &Token) -> Vec<u8> {
+    match token {
+        Token::String(s) => encode_packed_bytes(s.as_bytes()),
+        Token::Bytes(bytes) => encode_packed_bytes(bytes),
+        Token::Array(items) => {
+            let mut out = encode_word(&Token::Uint(U256::from(items.len()))).to_vec();
+            out.extend(encode_tokens(items));
+            out
+        }
+        _ => encode_word(token).to_vec(),
+    }
+}
+
+/// Encode a list of tokens into ABI-encoded bytes.
+///
+/// Static tokens are written in place; dynamic tokens write an offset
+/// (relative to the start of this encoding) in the head and their
+/// contents in the tail.
+pub fn encode_tokens(tokens: &[Token]) -> Vec<u8> {
+    let mut head = Vec::with_capacity(tokens.len() * 32);
+    let mut tail = Vec::new();
+    for token in tokens {
+        if token.is_dynamic() {
+            let offset = tokens.len() * 32 + tail.len();
+            head.extend_from_slice(&encode_word(&Token::Uint(U256::from(offset))));
+            tail.extend(encode_tail(token));
+        } else {
+            head.extend_from_slice(&encode_word(token));
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+/// Call data for a function: its 4-byte selector followed by the encoded arguments
+pub fn encode(selector: [u8; 4], tokens: &[Token]) -> Vec<u8> {
+    let mut out = selector.to_vec();
+    out.extend(encode_tokens(tokens));
+    out
+}
+
+/// Decode ABI-encoded `data` (without a selector), such as return data, into
+/// one token per entry of `types`
+pub fn decode(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>, AbiError> {
+    types
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let head = i * 32;
+            if is_dynamic_type(param) {
+                let offset = read_offset(data, head)?;
+                decode_tail(param, data, offset)
+            } else {
+                decode_word(param, read_word(data, head)?)
+            }
+        })
+        .collect()
+}
+
+fn is_dynamic_type(param: &ParamType) -> bool {
+    matches!(
+        param,
+        ParamType::String | ParamType::Bytes | ParamType::Array(_)
+    )
+}
+
+fn read_word(data: &[u8], at: usize) -> Result<&[u8], AbiError> {
+    data.get(at..at + 32).ok_or(AbiError::Truncated(at))
+}
+
+/// Read a word holding an offset or length that must fit inside `data`
+fn read_offset(data: &[u8], at: usize) -> Result<usize, AbiError> {
+    let value = U256::from_big_endian(read_word(data, at)?);
+    if value > U256::from(data.len()) {
+        return Err(AbiError::InvalidEncoding(format!(
+            "offset or length {} at {} exceeds {} bytes of data",
+            value,
+            at,
+            data.len()
+        )));
+    }
+    Ok(value.as_usize())
+}
+
+fn decode_word(param: &ParamType, word: &[u8]) -> Result<Token, AbiError> {
+    match param {
+        ParamType::Address => {
+            if word[..12].iter().any(|&b| b != 0) {
+                return Err(AbiError::InvalidEncoding(
+                    "address with non-zero upper bytes".to_string(),
+                ));
+            }
+            Ok(Token::Address(Address::from_slice(&word[12..])))
+        }
+        ParamType::Uint256 => Ok(Token::Uint(U256::from_big_endian(word))),
+        ParamType::Bool => match U256::from_big_endian(word) {
+            value if value.is_zero() => Ok(Token::Bool(false)),
+            value if value == U256::one() => Ok(Token::Bool(true)),
+            value => Err(AbiError::InvalidEncoding(format!("bool of {}", value))),
+        },
+        ParamType::Bytes32 => {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(word);
+            Ok(Token::FixedBytes(bytes))
+        }
+        ParamType::String | ParamType::Bytes | ParamType::Array(_) => {
+            unreachable!("dynamic types are decoded from the tail")
+        }
+    }
+}
+
+fn decode_tail(param: &ParamType, data: &[u8], offset: usize) -> Result<Token, AbiError> {
+    let len = read_offset(data, offset)?;
+    let body = offset + 32;
+    match param {
+        ParamType::String | ParamType::Bytes => {
+            let bytes = data
+                .get(body..body + len)
+                .ok_or(AbiError::Truncated(body))?
+                .to_vec();
+            if *param == ParamType::Bytes {
+                return Ok(Token::Bytes(bytes));
+            }
+            String::from_utf8(bytes)
+                .map(Token::String)
+                .map_err(|_| AbiError::InvalidEncoding("string is not UTF-8".to_string()))
+        }
+        ParamType::Array(element) => {
+            let items = decode(&vec![(**element).clone(); len], &data[body..])?;
+            Ok(Token::Array(items))
+        }
+        _ => decode_word(param, read_word(data, offset)?),
+    }
+}
+
+/// Compute the 4-byte function selector for a canonical signature such as
+/// `transfer(address,uint256)`
+pub fn selector(signature: &str) -> [u8; 4] {
+    let hash = Keccak256::digest(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Widely used signatures (mostly ERC-20) for naming selectors found in bytecode
+pub const KNOWN_SIGNATURES: &[&str] = &[
+    "totalSupply()",
+    "balanceOf(address)",
+    "transfer(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "approve(address,uint256)",
+    "allowance(address,address)",
+    "name()",
+    "symbol()",
+    "decimals()",
+    "owner()",
+    "transferOwnership(address)",
+    "mint(address,uint256)",
+    "burn(uint256)",
+];
+
+/// Look up the signature for `selector` in `KNOWN_SIGNATURES`
+pub fn known_signature(selector: [u8; 4]) -> Option<&'static str> {
+    KNOWN_SIGNATURES
+        .iter()
+        .copied()
+        .find(|signature| self::selector(signature) == selector)
+}
+
+/// Parse and encode textual arguments against their declared types
+pub fn encode_args(types: &[ParamType], values: &[&str]) -> Result<Vec<u8>, AbiError> {
+    if types.len() != values.len() {
+        return Err(AbiError::ArgumentCount {
+            expected: types.len(),
+            got: values.len(),
+        });
+    }
+    let tokens = types
+        .iter()
+        .zip(values)
+        .map(|(param, value)| parse_token(param, value))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(encode_tokens(&tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_valid_arguments() {
+        let encoded = encode_args(
+            &[ParamType::Address, ParamType::Uint256, ParamType::Bool],
+            &["0x00000000000000000000000000000000000000ff", "42", "true"],
+        )
+        .unwrap();
+
+        assert_eq!(encoded.len(), 96);
+        assert_eq!(encoded[31], 0xff);
+        assert_eq!(encoded[63], 42);
+        assert_eq!(encoded[95], 1);
+    }
+
+    #[test]
+    fn test_reject_wrong_argument_count() {
+        let types = [ParamType::Uint256, ParamType::Bool];
+
+        assert_eq!(
+            encode_args(&types, &["1"]).unwrap_err(),
+            AbiError::ArgumentCount {
+                expected: 2,
+                got: 1
+            }
+        );
+        assert_eq!(
+            encode_args(&types, &["1", "true", "2"]).unwrap_err(),
+            AbiError::ArgumentCount {
+                expected: 2,
+                got: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_reject_21_byte_address() {
+        let result = parse_token(
+            &ParamType::Address,
+            "0x0000000000000000000000000000000000000000ff",
+        );
+        assert_eq!(result, Err(AbiError::InvalidAddressLength(21)));
+    }
+
+    #[test]
+    fn test_reject_uint_over_256_bits() {
+        // 2^256 in decimal
+        let too_big =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(matches!(
+            parse_token(&ParamType::Uint256, too_big),
+            Err(AbiError::UintOverflow(_))
+        ));
+
+        let too_big_hex = format!("0x1{}", "0".repeat(64));
+        assert!(matches!(
+            parse_token(&ParamType::Uint256, &too_big_hex),
+            Err(AbiError::UintOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_non_hex_bytes32() {
+        let value = format!("0x{}", "zz".repeat(32));
+        assert!(matches!(
+            parse_token(&ParamType::Bytes32, &value),
+            Err(AbiError::InvalidHex(_))
+        ));
+
+        let short = format!("0x{}", "ab".repeat(31));
+        assert_eq!(
+            parse_token(&ParamType::Bytes32, &short),
+            Err(AbiError::InvalidBytes32Length(31))
+        );
+    }
+
+    fn words(hex_words: &[&str]) -> Vec<u8> {
+        hex_words
+            .iter()
+            .flat_map(|w| hex::decode(format!("{:0>64}", w)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_string_argument() {
+        let encoded = encode_args(&[ParamType::String], &["hello"]).unwrap();
+        let expected = words(&[
+            "20",
+            "05",
+            "68656c6c6f000000000000000000000000000000000000000000000000000000",
+        ]);
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_uint_array_argument() {
+        let param = ParamType::parse("uint256[]").unwrap();
+        let encoded = encode_args(&[param], &["[1, 2, 3]"]).unwrap();
+        let expected = words(&["20", "03", "01", "02", "03"]);
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_mixed_static_and_dynamic() {
+        // f(uint256, string, bytes, uint256[]) with (7, "hello", 0xbeef, [])
+        let types = ["uint256", "string", "bytes", "uint256[]"]
+            .iter()
+            .map(|t| ParamType::parse(t).unwrap())
+            .collect::<Vec<_>>();
+        let encoded = encode_args(&types, &["7", "hello", "0xbeef", "[]"]).unwrap();
+        let expected = words(&[
+            "07",
+            "80",
+            "c0",
+            "0100",
+            "05",
+            "68656c6c6f000000000000000000000000000000000000000000000000000000",
+            "02",
+            "beef000000000000000000000000000000000000000000000000000000000000",
+            "00",
+        ]);
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_reject_malformed_array() {
+        let param = ParamType::parse("uint256[]").unwrap();
+        assert!(matches!(
+            parse_token(&param, "1, 2"),
+            Err(AbiError::InvalidArray(_))
+        ));
+        assert!(matches!(
+            ParamType::parse("uint256[][]"),
+            Err(AbiError::UnsupportedType(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_transfer_call() {
+        let to: Address = "0x00000000000000000000000000000000000000ff"
+            .parse()
+            .unwrap();
+        let call = encode(
+            selector("transfer(address,uint256)"),
+            &[Token::Address(to), Token::Uint(U256::from(1000))],
+        );
+
+        assert_eq!(call.len(), 4 + 64);
+        assert_eq!(hex::encode(&call[..4]), "a9059cbb");
+        assert_eq!(call[4 + 31], 0xff);
+        assert_eq!(U256::from_big_endian(&call[36..68]), U256::from(1000));
+
+        assert_eq!(
+            decode(&[ParamType::Address, ParamType::Uint256], &call[4..]).unwrap(),
+            vec![Token::Address(to), Token::Uint(U256::from(1000))]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_values_round_trip() {
+        let tokens = vec![
+            Token::Uint(U256::from(7)),
+            Token::String("hello, abby".to_string()),
+            Token::Bool(true),
+            Token::Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+            Token::Array(vec![Token::Uint(U256::one()), Token::Uint(U256::from(2))]),
+        ];
+        let types = [
+            ParamType::Uint256,
+            ParamType::String,
+            ParamType::Bool,
+            ParamType::Bytes,
+            ParamType::Array(Box::new(ParamType::Uint256)),
+        ];
+
+        let encoded = encode_tokens(&tokens);
+        // The string's offset points just past the five head words
+        assert_eq!(U256::from_big_endian(&encoded[32..64]), U256::from(160));
+        assert_eq!(decode(&types, &encoded).unwrap(), tokens);
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_data() {
+        assert_eq!(
+            decode(&[ParamType::Uint256], &[0u8; 16]),
+            Err(AbiError::Truncated(0))
+        );
+
+        let mut bool_word = [0u8; 32];
+        bool_word[31] = 2;
+        assert!(matches!(
+            decode(&[ParamType::Bool], &bool_word),
+            Err(AbiError::InvalidEncoding(_))
+        ));
+
+        // A string whose offset points past the end of the data
+        let mut bad_offset = [0u8; 32];
+        bad_offset[31] = 0xff;
+        assert!(decode(&[ParamType::String], &bad_offset).is_err());
+    }
+}
@@ -1,9 +1,72 @@
-use crate::types::ExecutionResult;
+use crate::types::{ExecutionResult, Log};
 use chrono::{DateTime, Utc};
 use ethereum_types::{Address, H256, U256};
+use rlp::RlpStream;
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
 
+/// Base fee of the genesis block, in wei (1 Gwei)
+pub const INITIAL_BASE_FEE: u64 = 1_000_000_000;
+/// The base fee moves by at most 1/8 from one block to the next (EIP-1559)
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+/// Blocks aim to use 1/ELASTICITY_MULTIPLIER of their gas limit
+const ELASTICITY_MULTIPLIER: u64 = 2;
+
+/// Bounds on what a single block may carry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BlockLimits {
+    pub max_transactions: usize,
+    /// Most bytes of RLP-encoded transactions, see `Block::size`
+    pub max_size: usize,
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        Self {
+            max_transactions: 1000,
+            max_size: 1024 * 1024, // 1MB
+        }
+    }
+}
+
+/// Block reward schedule: the proposer reward halves every `halving_interval`
+/// blocks until it reaches `minimum_reward`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RewardSchedule {
+    pub initial_reward: U256,
+    pub halving_interval: u64,
+    /// Reward paid forever once halving would take it below this
+    #[serde(default)]
+    pub minimum_reward: U256,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        Self {
+            initial_reward: U256::from(1_000_000_000_000_000_000u64), // 1 Abby token
+            halving_interval: 2_100_000, // ~290 days at 12 second blocks
+            minimum_reward: U256::from(10_000_000_000_000_000u64), // 0.01 Abby tokens
+        }
+    }
+}
+
+impl RewardSchedule {
+    /// Reward paid to the proposer of block `number`
+    pub fn reward_at(&self, number: u64) -> U256 {
+        if self.halving_interval == 0 {
+            return self.initial_reward;
+        }
+        let halvings = number / self.halving_interval;
+        let halved = if halvings >= 256 {
+            U256::zero()
+        } else {
+            self.initial_reward >> halvings as usize
+        };
+        halved.max(self.minimum_reward)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockHeader {
     pub number: u64,
@@ -29,6 +92,42 @@ impl BlockHeader {
     }
 
     pub fn new(number: u64, parent_hash: H256, proposer: Address, gas_limit: U256) -> Self {
+        Self::with_reward_schedule(
+            number,
+            parent_hash,
+            proposer,
+            gas_limit,
+            &RewardSchedule::default(),
+        )
+    }
+
+    /// Base fee a child of this block must carry (EIP-1559): higher than
+    /// ours when we used more than half our gas limit, lower when we used
+    /// less, by at most 1/8
+    pub fn next_base_fee(&self) -> U256 {
+        let target = self.gas_limit / ELASTICITY_MULTIPLIER;
+        if target.is_zero() || self.gas_used == target {
+            return self.base_fee;
+        }
+
+        if self.gas_used > target {
+            let delta =
+                self.base_fee * (self.gas_used - target) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+            self.base_fee + delta.max(U256::one())
+        } else {
+            let delta =
+                self.base_fee * (target - self.gas_used) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+            self.base_fee - delta
+        }
+    }
+
+    pub fn with_reward_schedule(
+        number: u64,
+        parent_hash: H256,
+        proposer: Address,
+        gas_limit: U256,
+        schedule: &RewardSchedule,
+    ) -> Self {
         Self {
             number,
             parent_hash,
@@ -41,8 +140,8 @@ impl BlockHeader {
             proposer,
             difficulty: U256::zero(),
             extra_data: Vec::new(),
-            base_fee: U256::from(1_000_000_000u64), // 1 Gwei
-            abby_reward: U256::from(1_000_000_000_000_000_000u64), // 1 Abby token
+            base_fee: U256::from(INITIAL_BASE_FEE),
+            abby_reward: schedule.reward_at(number),
         }
     }
 }
@@ -54,8 +153,37 @@ pub struct Block {
     pub validators: Vec<ValidatorInfo>, // Validators that participated in consensus
 }
 
+/// Binary Merkle root over `hashes`, duplicating the last node of odd levels.
+/// An empty list has a zero root.
+pub fn merkle_root(mut hashes: Vec<H256>) -> H256 {
+    if hashes.is_empty() {
+        return H256::zero();
+    }
+
+    while hashes.len() > 1 {
+        let mut next_level = Vec::new();
+
+        for chunk in hashes.chunks(2) {
+            let combined = if chunk.len() == 2 {
+                [chunk[0].as_bytes(), chunk[1].as_bytes()].concat()
+            } else {
+                [chunk[0].as_bytes(), chunk[0].as_bytes()].concat()
+            };
+
+            let hash = Keccak256::digest(&combined);
+            next_level.push(H256::from_slice(&hash));
+        }
+
+        hashes = next_level;
+    }
+
+    hashes[0]
+}
+
 impl Block {
-    pub fn new(header: BlockHeader, transactions: Vec<crate::blockchain::Transaction>) -> Self {
+    /// Create a block, committing to `transactions` in the header's transactions root
+    pub fn new(mut header: BlockHeader, transactions: Vec<crate::blockchain::Transaction>) -> Self {
+        header.transactions_root = Self::calculate_merkle_root(&transactions);
         Self {
             header,
             transactions,
@@ -68,30 +196,16 @@ impl Block {
     }
 
     pub fn calculate_merkle_root(transactions: &[crate::blockchain::Transaction]) -> H256 {
-        if transactions.is_empty() {
-            return H256::zero();
-        }
-
-        let mut hashes: Vec<H256> = transactions.iter().map(|tx| tx.hash()).collect();
-
-        while hashes.len() > 1 {
-            let mut next_level = Vec::new();
-
-            for chunk in hashes.chunks(2) {
-                let combined = if chunk.len() == 2 {
-                    [chunk[0].as_bytes(), chunk[1].as_bytes()].concat()
-                } else {
-                    [chunk[0].as_bytes(), chunk[0].as_bytes()].concat()
-                };
-
-                let hash = Keccak256::digest(&combined);
-                next_level.push(H256::from_slice(&hash));
-            }
-
-            hashes = next_level;
-        }
+        merkle_root(transactions.iter().map(|tx| tx.hash()).collect())
+    }
 
-        hashes[0]
+    pub fn calculate_receipts_root(receipts: &[TransactionReceipt]) -> H256 {
+        merkle_root(
+            receipts
+                .iter()
+                .map(|receipt| receipt.consensus_hash())
+                .collect(),
+        )
     }
 
     pub fn genesis() -> Self {
@@ -103,15 +217,34 @@ impl Block {
         Self::new(header, Vec::new())
     }
 
+    /// Bytes taken by the block's transactions in their RLP encoding
+    pub fn size(&self) -> usize {
+        self.transactions
+            .iter()
+            .map(crate::blockchain::Transaction::size)
+            .sum()
+    }
+
     pub fn validate(&self) -> Result<(), String> {
+        self.validate_with_limits(&BlockLimits::default())
+    }
+
+    pub fn validate_with_limits(&self, limits: &BlockLimits) -> Result<(), String> {
         // Basic validation
-        if self.transactions.len() as u64 > 1000 {
+        if self.transactions.len() > limits.max_transactions {
             return Err("Too many transactions in block".to_string());
         }
+        let size = self.size();
+        if size > limits.max_size {
+            return Err(format!(
+                "Block size {} bytes exceeds the limit of {}",
+                size, limits.max_size
+            ));
+        }
 
         // Validate transaction root
         let calculated_root = Self::calculate_merkle_root(&self.transactions);
-        if self.header.transactions_root != calculated_root && !self.transactions.is_empty() {
+        if self.header.transactions_root != calculated_root {
             return Err("Invalid transaction root".to_string());
         }
 
@@ -148,12 +281,28 @@ pub struct TransactionReceipt {
     pub cumulative_gas_used: U256,
     pub gas_used: U256,
     pub contract_address: Option<Address>,
-    pub logs: Vec<crate::types::Log>,
+    /// Logs emitted by the transaction; empty if it failed
+    pub logs: Vec<ReceiptLog>,
     pub status: bool,       // true for success, false for failure
     pub abby_rewards: U256, // Abby tokens earned from this transaction
 }
 
+/// Log emitted by a transaction, along with where in the chain it was emitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptLog {
+    #[serde(flatten)]
+    pub log: Log,
+    pub block_hash: H256,
+    pub block_number: u64,
+    pub transaction_hash: H256,
+    pub transaction_index: u64,
+    /// Position of the log among all logs of its block
+    pub log_index: u64,
+}
+
 impl TransactionReceipt {
+    /// Receipt for the transaction at `tx_index` of a block, whose logs are
+    /// numbered from `first_log_index`
     pub fn new(
         tx: &crate::blockchain::Transaction,
         result: &ExecutionResult,
@@ -161,8 +310,23 @@ impl TransactionReceipt {
         block_number: u64,
         tx_index: u64,
         cumulative_gas: U256,
+        first_log_index: u64,
     ) -> Self {
         let abby_rewards = Self::calculate_abby_rewards(result.gas_used);
+        let status = matches!(result.status, crate::types::ExecutionStatus::Success);
+        let logs = if status { result.logs.as_slice() } else { &[] };
+        let logs = logs
+            .iter()
+            .zip(first_log_index..)
+            .map(|(log, log_index)| ReceiptLog {
+                log: log.clone(),
+                block_hash,
+                block_number,
+                transaction_hash: tx.hash(),
+                transaction_index: tx_index,
+                log_index,
+            })
+            .collect();
 
         Self {
             transaction_hash: tx.hash(),
@@ -174,14 +338,136 @@ impl TransactionReceipt {
             cumulative_gas_used: cumulative_gas,
             gas_used: result.gas_used,
             contract_address: None, // TODO: Calculate for contract deployments
-            logs: result.logs.clone(),
-            status: matches!(result.status, crate::types::ExecutionStatus::Success),
+            logs,
+            status,
             abby_rewards,
         }
     }
 
+    /// Hash of the fields every node derives identically when executing the
+    /// block. The block hash and index are left out since the receipts root is
+    /// itself part of the block header.
+    pub fn consensus_hash(&self) -> H256 {
+        let mut stream = RlpStream::new_list(5);
+        stream.append(&self.transaction_hash);
+        stream.append(&self.status);
+        stream.append(&self.cumulative_gas_used);
+        stream.append(&self.gas_used);
+        stream.begin_list(self.logs.len());
+        for ReceiptLog { log, .. } in &self.logs {
+            stream.begin_list(3);
+            stream.append(&log.address);
+            stream.append_list(&log.topics);
+            stream.append(&log.data);
+        }
+        H256::from_slice(&Keccak256::digest(stream.out()))
+    }
+
     fn calculate_abby_rewards(gas_used: U256) -> U256 {
         // 1 Abby token for every 1000 gas used
         gas_used / U256::from(1000)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Transaction;
+
+    fn transfer(nonce: u64) -> Transaction {
+        Transaction::new(
+            Address::from_low_u64_be(1),
+            Some(Address::from_low_u64_be(2)),
+            U256::from(nonce + 1),
+            U256::from(21000),
+            U256::from(1),
+            Vec::new(),
+            U256::from(nonce),
+        )
+    }
+
+    #[test]
+    fn test_reward_halves_after_interval() {
+        let schedule = RewardSchedule {
+            initial_reward: U256::from(1000),
+            halving_interval: 100,
+            minimum_reward: U256::zero(),
+        };
+
+        assert_eq!(schedule.reward_at(1), U256::from(1000));
+        assert_eq!(schedule.reward_at(99), U256::from(1000));
+        assert_eq!(schedule.reward_at(100), U256::from(500));
+        assert_eq!(schedule.reward_at(250), U256::from(250));
+        assert_eq!(schedule.reward_at(100 * 300), U256::zero());
+
+        let header = BlockHeader::with_reward_schedule(
+            100,
+            H256::zero(),
+            Address::zero(),
+            U256::from(10_000_000u64),
+            &schedule,
+        );
+        assert_eq!(header.abby_reward, U256::from(500));
+    }
+
+    #[test]
+    fn test_default_reward_halves_down_to_its_floor() {
+        let schedule = RewardSchedule::default();
+        let interval = schedule.halving_interval;
+        let initial = schedule.initial_reward;
+
+        assert_eq!(schedule.reward_at(0), initial);
+        assert_eq!(schedule.reward_at(interval - 1), initial);
+        assert_eq!(schedule.reward_at(interval), initial / 2);
+        assert_eq!(schedule.reward_at(2 * interval - 1), initial / 2);
+        assert_eq!(schedule.reward_at(2 * interval), initial / 4);
+
+        // 1 token halved seven times is below 0.01 tokens
+        assert_eq!(schedule.reward_at(7 * interval), schedule.minimum_reward);
+        assert_eq!(schedule.reward_at(u64::MAX), schedule.minimum_reward);
+    }
+
+    #[test]
+    fn test_base_fee_follows_parent_gas_usage() {
+        let mut parent =
+            BlockHeader::new(1, H256::zero(), Address::zero(), U256::from(10_000_000u64));
+        let base_fee = U256::from(INITIAL_BASE_FEE);
+
+        parent.gas_used = U256::from(5_000_000u64);
+        assert_eq!(parent.next_base_fee(), base_fee);
+
+        parent.gas_used = parent.gas_limit;
+        assert_eq!(parent.next_base_fee(), base_fee + base_fee / 8);
+
+        parent.gas_used = U256::zero();
+        assert_eq!(parent.next_base_fee(), base_fee - base_fee / 8);
+
+        // A block just over its target still raises the fee
+        parent.base_fee = U256::from(7);
+        parent.gas_used = U256::from(5_000_001u64);
+        assert_eq!(parent.next_base_fee(), U256::from(8));
+    }
+
+    #[test]
+    fn test_tampered_transaction_list_invalidates_block() {
+        let header = BlockHeader::new(1, H256::zero(), Address::zero(), U256::from(10_000_000u64));
+        let block = Block::new(header, vec![transfer(0), transfer(1), transfer(2)]);
+        assert_ne!(block.header.transactions_root, H256::zero());
+        assert!(block.validate().is_ok());
+
+        let mut dropped = block.clone();
+        dropped.transactions.pop();
+        assert_eq!(dropped.validate().unwrap_err(), "Invalid transaction root");
+
+        let mut reordered = block.clone();
+        reordered.transactions.swap(0, 1);
+        assert_eq!(
+            reordered.validate().unwrap_err(),
+            "Invalid transaction root"
+        );
+
+        let mut emptied = block;
+        emptied.transactions.clear();
+        assert_eq!(emptied.validate().unwrap_err(), "Invalid transaction root");
+    }
+}
@@ -1,89 +1,224 @@
-use crate::blockchain::{Block, TransactionReceipt};
-use crate::types::{Account, ExecutionResult};
+use crate::blockchain::{
+    merkle_root, Block, BlockHeader, GenesisConfig, ReceiptLog, RewardSchedule, StakingOperation,
+    Transaction, TransactionReceipt, STAKING_ADDRESS,
+};
+use crate::evm::BlockContext;
+use crate::types::{Account, ExecutionResult, ExecutionStatus};
 use ethereum_types::{Address, H256, U256};
-use std::collections::HashMap;
+use rlp::RlpStream;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+const GENESIS_HASH_KEY: &str = "genesis_hash";
+const ACCOUNT_PREFIX: &str = "account_";
+const HEAD_HASH_KEY: &str = "head_hash";
+const STAKE_LEDGER_KEY: &str = "stake_ledger";
+/// Abby balances were kept apart from accounts under this prefix before
+/// account balances became the Abby ledger
+const LEGACY_ABBY_BALANCE_PREFIX: &str = "abby_balance_";
+/// How far ahead of local time, in seconds, a block timestamp may be
+pub const DEFAULT_MAX_TIMESTAMP_DRIFT: u64 = 15;
+
+/// Portable snapshot of a whole chain, as written by `Blockchain::export_to_file`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainExport {
+    pub genesis: GenesisConfig,
+    pub head_hash: H256,
+    /// Every stored block, side chains included, in height order
+    pub blocks: Vec<Block>,
+    pub receipts: Vec<TransactionReceipt>,
+    pub accounts: BTreeMap<Address, Account>,
+}
+
+/// Abby tokens that staking transactions took out of balances. The chain
+/// keeps them so that paying them back happens in block processing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StakeLedger {
+    /// Stake each staker has bonded and not unstaked
+    pub bonded: BTreeMap<Address, U256>,
+    /// Unstaked amounts waiting out the withdrawal delay
+    pub unbonding: Vec<PendingWithdrawal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingWithdrawal {
+    pub staker: Address,
+    pub amount: U256,
+    /// Unix time from which the next epoch-boundary block pays it out
+    pub release_at: u64,
+}
 
 #[derive(Debug, Clone)]
 pub struct Blockchain {
     pub blocks: HashMap<H256, Block>,
     pub block_by_number: HashMap<u64, H256>,
     pub receipts: HashMap<H256, TransactionReceipt>,
+    /// Block hash and position of every canonical transaction, by hash
+    pub transaction_index: HashMap<H256, (H256, usize)>,
+    /// The single ledger of the chain. Abby tokens are the native token, so
+    /// an account's EVM `balance` is its Abby balance: transaction values,
+    /// fees, block rewards and staking payouts all move the same number.
     pub accounts: HashMap<Address, Account>,
+    pub stake_ledger: StakeLedger,
     pub head_hash: H256,
     pub head_number: u64,
+    /// Cached copy of the block at `head_hash`, kept in sync by `set_head`
+    head_block: Block,
     pub total_difficulty: U256,
-    pub abby_balances: HashMap<Address, U256>, // Abby token balances
+    pub reward_schedule: RewardSchedule,
+    /// Allocation and parameters the chain was started from
+    pub genesis: GenesisConfig,
+    /// Seconds a block timestamp may run ahead of local time
+    pub max_timestamp_drift: u64,
     pub db: Option<sled::Db>,
 }
 
 impl Blockchain {
     pub fn new() -> Result<Self, String> {
-        let genesis = Block::genesis();
-        let genesis_hash = genesis.hash();
+        Self::from_genesis(GenesisConfig::default())
+    }
 
+    /// Start a chain from a genesis config. The genesis block commits to the
+    /// allocation through its state root, so different configs yield
+    /// different genesis hashes.
+    pub fn from_genesis(genesis_config: GenesisConfig) -> Result<Self, String> {
         let mut blockchain = Self {
             blocks: HashMap::new(),
             block_by_number: HashMap::new(),
             receipts: HashMap::new(),
+            transaction_index: HashMap::new(),
             accounts: HashMap::new(),
-            head_hash: genesis_hash,
+            stake_ledger: StakeLedger::default(),
+            head_hash: H256::zero(),
             head_number: 0,
+            head_block: Block::genesis(),
             total_difficulty: U256::zero(),
-            abby_balances: HashMap::new(),
+            reward_schedule: RewardSchedule::default(),
+            genesis: genesis_config,
+            max_timestamp_drift: DEFAULT_MAX_TIMESTAMP_DRIFT,
             db: None,
         };
 
-        blockchain.blocks.insert(genesis_hash, genesis);
-        blockchain.block_by_number.insert(0, genesis_hash);
-
         // Initialize genesis Abby token distribution
         blockchain.initialize_abby_genesis();
 
+        let mut genesis = Block::genesis();
+        genesis.header.state_root = blockchain.state_root();
+        let genesis_hash = genesis.hash();
+        blockchain.blocks.insert(genesis_hash, genesis.clone());
+        blockchain.block_by_number.insert(0, genesis_hash);
+        blockchain.set_head(genesis, genesis_hash);
+
         Ok(blockchain)
     }
 
-    pub fn new_with_persistence(db_path: &str) -> Result<Self, String> {
+    pub fn new_with_persistence(db_path: &str, genesis: GenesisConfig) -> Result<Self, String> {
         let db = sled::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+        Self::with_db(db, genesis)
+    }
 
-        let mut blockchain = Self::new()?;
+    /// Build a chain backed by an already opened database, restoring any
+    /// blocks and state it holds
+    fn with_db(db: sled::Db, genesis: GenesisConfig) -> Result<Self, String> {
+        let mut blockchain = Self::from_genesis(genesis)?;
         blockchain.db = Some(db);
+        blockchain.verify_stored_genesis()?;
         blockchain.load_from_disk()?;
 
         Ok(blockchain)
     }
 
+    pub fn genesis_hash(&self) -> H256 {
+        self.block_by_number[&0]
+    }
+
+    /// Make sure the database was created with the same genesis block,
+    /// recording it if the database is new
+    fn verify_stored_genesis(&self) -> Result<(), String> {
+        let Some(ref db) = self.db else {
+            return Ok(());
+        };
+
+        let expected = self.genesis_hash();
+        match db
+            .get(GENESIS_HASH_KEY)
+            .map_err(|e| format!("Failed to read genesis hash: {}", e))?
+        {
+            Some(stored) if stored.as_ref() != expected.as_bytes() => Err(format!(
+                "Genesis mismatch: database has {}, expected {}",
+                H256::from_slice(&stored),
+                expected
+            )),
+            Some(_) => Ok(()),
+            None => {
+                db.insert(GENESIS_HASH_KEY, expected.as_bytes())
+                    .map_err(|e| format!("Failed to persist genesis hash: {}", e))?;
+                db.flush()
+                    .map_err(|e| format!("Failed to flush database: {}", e))?;
+                Ok(())
+            }
+        }
+    }
+
     fn initialize_abby_genesis(&mut self) {
-        // Distribute initial Abby tokens to genesis addresses
-        let genesis_distribution = vec![
-            // Example addresses with initial Abby token allocations
-            (
-                Address::from_low_u64_be(1),
-                U256::from_dec_str("100000000000000000000000").unwrap(),
-            ), // 100k tokens
-            (
-                Address::from_low_u64_be(2),
-                U256::from_dec_str("50000000000000000000000").unwrap(),
-            ), // 50k tokens
-            (
-                Address::from_low_u64_be(3),
-                U256::from_dec_str("25000000000000000000000").unwrap(),
-            ), // 25k tokens
-        ];
-
-        for (address, balance) in genesis_distribution {
-            self.abby_balances.insert(address, balance);
-        }
-
-        log::info!("Initialized Abby token genesis distribution");
+        for (address, account) in &self.genesis.alloc {
+            self.accounts.entry(*address).or_default().balance = account.balance;
+        }
+
+        log::info!(
+            "Initialized Abby token genesis distribution for chain {}",
+            self.genesis.chain_id
+        );
     }
 
     pub fn add_block(&mut self, block: Block) -> Result<(), String> {
+        // Genesis is fixed at construction and can never be replaced
+        if block.header.number == 0 {
+            return Err("Cannot add a block at height 0: genesis is immutable".to_string());
+        }
+
         // Validate block
-        block.validate()?;
+        block.validate_with_limits(&self.genesis.block_limits)?;
 
         // Check if parent exists
-        if block.header.number > 0 && !self.blocks.contains_key(&block.header.parent_hash) {
-            return Err("Parent block not found".to_string());
+        let parent = self
+            .blocks
+            .get(&block.header.parent_hash)
+            .ok_or("Parent block not found")?;
+
+        let expected_reward = self.reward_schedule.reward_at(block.header.number);
+        if block.header.abby_reward != expected_reward {
+            return Err(format!(
+                "Invalid block reward {}, expected {}",
+                block.header.abby_reward, expected_reward
+            ));
+        }
+
+        let expected_base_fee = parent.header.next_base_fee();
+        if block.header.base_fee != expected_base_fee {
+            return Err(format!(
+                "Invalid base fee {}, expected {}",
+                block.header.base_fee, expected_base_fee
+            ));
+        }
+
+        // Timestamps must move forward and not run too far ahead of us
+        if block.header.timestamp <= parent.header.timestamp {
+            return Err(format!(
+                "Block timestamp {} is not after its parent's {}",
+                block.header.timestamp, parent.header.timestamp
+            ));
+        }
+        let latest_allowed =
+            chrono::Utc::now() + chrono::Duration::seconds(self.max_timestamp_drift as i64);
+        if block.header.timestamp > latest_allowed {
+            return Err(format!(
+                "Block timestamp {} is more than {}s in the future",
+                block.header.timestamp, self.max_timestamp_drift
+            ));
         }
 
         // Check if block already exists
@@ -92,23 +227,61 @@ impl Blockchain {
             return Err("Block already exists".to_string());
         }
 
-        // Process block transactions and update state
-        self.process_block(&block)?;
+        // Every transaction must be signed by its sender for this chain
+        for tx in &block.transactions {
+            tx.verify_for_chain(self.genesis.chain_id)
+                .map_err(|e| format!("Invalid transaction {:?}: {}", tx.hash(), e))?;
+        }
+
+        // Execute the block on a copy of its parent's state and check the
+        // header's commitments before touching our own state
+        let parent_hash = block.header.parent_hash;
+        let extends_head = parent_hash == self.head_hash;
+        let (post_state, receipts) = if extends_head {
+            self.execute_block(&block)?
+        } else {
+            self.state_after(parent_hash)?.execute_block(&block)?
+        };
+        if block.header.state_root != post_state.state_root() {
+            return Err("Invalid state root".to_string());
+        }
+        if block.header.receipts_root != Block::calculate_receipts_root(&receipts) {
+            return Err("Invalid receipts root".to_string());
+        }
+        if block.header.gas_used != total_gas_used(&receipts) {
+            return Err("Invalid gas used".to_string());
+        }
 
-        // Add block to chain
         self.blocks.insert(block_hash, block.clone());
-        self.block_by_number.insert(block.header.number, block_hash);
+        self.persist_block(&block)?;
 
-        // Update head if this block extends the chain
-        if block.header.number > self.head_number {
-            self.head_hash = block_hash;
-            self.head_number = block.header.number;
+        if extends_head {
+            self.accounts = post_state.accounts;
+            self.stake_ledger = post_state.stake_ledger;
+            for receipt in receipts {
+                self.receipts.insert(receipt.transaction_hash, receipt);
+            }
+            self.block_by_number.insert(block.header.number, block_hash);
+            self.index_transactions(&block, block_hash);
             self.total_difficulty += block.header.difficulty;
+            self.set_head(block.clone(), block_hash);
+            self.persist_state()?;
+        } else {
+            // Fork choice: the heavier chain wins, then the longer one; on a
+            // tie the current head stays
+            let total_difficulty = self.total_difficulty_of(parent_hash) + block.header.difficulty;
+            if (total_difficulty, block.header.number) > (self.total_difficulty, self.head_number) {
+                self.reorganize(block_hash)?;
+            } else {
+                log::info!(
+                    "Stored side-chain block #{} with hash {}",
+                    block.header.number,
+                    block_hash
+                );
+                return Ok(());
+            }
         }
 
-        // Persist to disk if enabled
-        self.persist_block(&block)?;
-
         log::info!(
             "Added block #{} with hash {}",
             block.header.number,
@@ -118,42 +291,311 @@ impl Blockchain {
         Ok(())
     }
 
-    fn process_block(&mut self, block: &Block) -> Result<(), String> {
+    /// Switch the canonical chain to end at `new_head`: rewind state to the
+    /// common ancestor with the current head and re-apply the new branch
+    fn reorganize(&mut self, new_head: H256) -> Result<(), String> {
+        // Walk the new branch back until it meets the canonical chain
+        let mut new_branch = Vec::new();
+        let mut ancestor = new_head;
+        while self
+            .block_by_number
+            .get(&self.blocks[&ancestor].header.number)
+            != Some(&ancestor)
+        {
+            new_branch.push(ancestor);
+            ancestor = self.blocks[&ancestor].header.parent_hash;
+        }
+        new_branch.reverse();
+        let ancestor_number = self.blocks[&ancestor].header.number;
+
+        let mut state = self.state_after(ancestor)?;
+        let mut new_receipts = Vec::new();
+        for hash in &new_branch {
+            new_receipts.extend(state.process_block(&self.blocks[hash])?);
+        }
+
+        // Drop the abandoned branch's receipts and number and transaction indexes
+        let abandoned: Vec<u64> = ((ancestor_number + 1)..=self.head_number).collect();
+        for number in &abandoned {
+            if let Some(hash) = self.block_by_number.remove(number) {
+                for tx in &self.blocks[&hash].transactions {
+                    self.receipts.remove(&tx.hash());
+                    self.transaction_index.remove(&tx.hash());
+                }
+            }
+        }
+
+        for hash in &new_branch {
+            let block = self.blocks[hash].clone();
+            self.block_by_number.insert(block.header.number, *hash);
+            self.index_transactions(&block, *hash);
+        }
+        for receipt in new_receipts {
+            self.receipts.insert(receipt.transaction_hash, receipt);
+        }
+        self.accounts = state.accounts;
+        self.stake_ledger = state.stake_ledger;
+        self.total_difficulty = self.total_difficulty_of(new_head);
+        let head = self.blocks[&new_head].clone();
+        self.set_head(head, new_head);
+        self.persist_state()?;
+
+        log::info!(
+            "Chain reorganization: rewound {} blocks to #{}, applied {} blocks",
+            abandoned.len(),
+            ancestor_number,
+            new_branch.len()
+        );
+
+        Ok(())
+    }
+
+    /// Account state after `hash` was applied. The head's state is at hand;
+    /// any other block is replayed from genesis along its ancestry.
+    fn state_after(&self, hash: H256) -> Result<Self, String> {
+        if hash == self.head_hash {
+            return Ok(self.state_snapshot());
+        }
+
+        let path = self
+            .ancestry_to_genesis(hash, self.genesis_hash())
+            .ok_or_else(|| format!("Block {} is not connected to genesis", hash))?;
+        let mut state = Self::from_genesis(self.genesis.clone())?;
+        state.reward_schedule = self.reward_schedule.clone();
+        for hash in &path[1..] {
+            state.process_block(&self.blocks[hash])?;
+        }
+        Ok(state)
+    }
+
+    /// Sum of block difficulties from genesis up to `hash`
+    fn total_difficulty_of(&self, hash: H256) -> U256 {
+        if hash == self.head_hash {
+            return self.total_difficulty;
+        }
+        self.ancestry_to_genesis(hash, self.genesis_hash())
+            .unwrap_or_default()
+            .iter()
+            .skip(1)
+            .fold(U256::zero(), |acc, hash| {
+                acc + self.blocks[hash].header.difficulty
+            })
+    }
+
+    /// Fill in the base fee, gas used and the state and receipts roots of a
+    /// freshly built block by executing it on top of the current state
+    pub fn seal_block(&self, block: &mut Block) -> Result<(), String> {
+        let parent = self
+            .blocks
+            .get(&block.header.parent_hash)
+            .ok_or("Parent block not found")?;
+        block.header.base_fee = parent.header.next_base_fee();
+
+        let (post_state, receipts) = self.execute_block(block)?;
+        block.header.gas_used = total_gas_used(&receipts);
+        block.header.state_root = post_state.state_root();
+        block.header.receipts_root = Block::calculate_receipts_root(&receipts);
+        Ok(())
+    }
+
+    /// Run `block` against a scratch copy of the account state, returning the
+    /// post-state and the receipts. `self` is left untouched.
+    fn execute_block(&self, block: &Block) -> Result<(Self, Vec<TransactionReceipt>), String> {
+        let mut post_state = self.state_snapshot();
+        let receipts = post_state.process_block(block)?;
+        Ok((post_state, receipts))
+    }
+
+    /// A copy of the account state without the block store or database
+    fn state_snapshot(&self) -> Self {
+        Self {
+            blocks: HashMap::new(),
+            block_by_number: HashMap::new(),
+            receipts: HashMap::new(),
+            transaction_index: HashMap::new(),
+            accounts: self.accounts.clone(),
+            stake_ledger: self.stake_ledger.clone(),
+            head_hash: self.head_hash,
+            head_number: self.head_number,
+            head_block: self.head_block.clone(),
+            total_difficulty: self.total_difficulty,
+            reward_schedule: self.reward_schedule.clone(),
+            genesis: self.genesis.clone(),
+            max_timestamp_drift: self.max_timestamp_drift,
+            db: None,
+        }
+    }
+
+    fn process_block(&mut self, block: &Block) -> Result<Vec<TransactionReceipt>, String> {
+        let reaches_unknown = block
+            .transactions
+            .iter()
+            .any(|tx| self.reaches_unknown_accounts(tx));
+        let groups = (!reaches_unknown)
+            .then(|| crate::blockchain::parallel::independent_groups(&block.transactions))
+            .flatten()
+            .unwrap_or_else(|| vec![(0..block.transactions.len()).collect()]);
+        self.process_block_in_groups(block, &groups)
+    }
+
+    /// Whether `tx` runs code that may touch accounts besides its sender and
+    /// recipient: contract creation, or a call into a contract whose
+    /// sub-calls can move value anywhere
+    fn reaches_unknown_accounts(&self, tx: &crate::blockchain::Transaction) -> bool {
+        tx.to.is_none_or(|to| {
+            self.accounts
+                .get(&to)
+                .is_some_and(|account| !account.code.is_empty())
+        })
+    }
+
+    /// Execute each group of transactions against its own slice of the
+    /// account state, in parallel, then merge the accounts and build receipts
+    /// in original transaction order. Groups must not share accounts.
+    fn process_block_in_groups(
+        &mut self,
+        block: &Block,
+        groups: &[Vec<usize>],
+    ) -> Result<Vec<TransactionReceipt>, String> {
+        use rayon::prelude::*;
+
+        let transactions = &block.transactions;
+        let block_context = self.block_context(&block.header);
+        let executed: Vec<_> = groups
+            .par_iter()
+            .map(|group| {
+                let mut accounts: HashMap<Address, Account> = group
+                    .iter()
+                    .flat_map(|&index| [Some(transactions[index].from), transactions[index].to])
+                    .flatten()
+                    .filter_map(|address| Some((address, self.accounts.get(&address)?.clone())))
+                    .collect();
+                if group
+                    .iter()
+                    .any(|&index| self.reaches_unknown_accounts(&transactions[index]))
+                {
+                    // Contract code touches addresses only known once it runs
+                    accounts = self.accounts.clone();
+                }
+                let loaded: Vec<Address> = accounts.keys().copied().collect();
+
+                let mut results = Vec::with_capacity(group.len());
+                for &index in group {
+                    let result = Self::execute_transaction(
+                        &transactions[index],
+                        &block_context,
+                        &mut accounts,
+                    );
+                    let failed = result.is_err();
+                    results.push((index, result));
+                    if failed {
+                        break;
+                    }
+                }
+                // Accounts left empty were pruned by the EVM (EIP-161)
+                let pruned: Vec<Address> = loaded
+                    .into_iter()
+                    .filter(|address| !accounts.contains_key(address))
+                    .collect();
+                (accounts, pruned, results)
+            })
+            .collect();
+
+        let mut results: Vec<Option<Result<ExecutionResult, String>>> =
+            (0..transactions.len()).map(|_| None).collect();
+        for (accounts, pruned, group_results) in executed {
+            for address in pruned {
+                self.accounts.remove(&address);
+            }
+            self.accounts.extend(accounts);
+            for (index, result) in group_results {
+                results[index] = Some(result);
+            }
+        }
+
+        let block_hash = block.hash();
         let mut cumulative_gas = U256::zero();
+        let mut log_count = 0;
+        let mut receipts: Vec<TransactionReceipt> = Vec::with_capacity(transactions.len());
 
-        // Process each transaction in the block
-        for (tx_index, tx) in block.transactions.iter().enumerate() {
-            // Execute transaction on EVM
-            let result = self.execute_transaction(tx)?;
+        // Receipts and gas rewards follow the original transaction order
+        for (tx_index, (tx, result)) in transactions.iter().zip(results).enumerate() {
+            let result = result.expect("only transactions after a failure are skipped")?;
             cumulative_gas += result.gas_used;
 
             // Create receipt
             let receipt = TransactionReceipt::new(
                 tx,
                 &result,
-                block.hash(),
+                block_hash,
                 block.header.number,
                 tx_index as u64,
                 cumulative_gas,
+                log_count,
             );
+            log_count += receipt.logs.len() as u64;
 
-            // Store receipt
-            self.receipts.insert(tx.hash(), receipt.clone());
-
-            // Update Abby token balances based on transaction fees and rewards
-            self.update_abby_balances(tx, &receipt);
+            if receipt.status && tx.to == Some(STAKING_ADDRESS) {
+                self.apply_staking_operation(tx, &block.header);
+            }
+            self.pay_gas_reward(tx, &receipt);
+            receipts.push(receipt);
         }
 
         // Distribute block rewards to proposer
-        self.distribute_block_reward(&block.header.proposer, block.header.abby_reward);
+        let reward = self.reward_schedule.reward_at(block.header.number);
+        self.distribute_block_reward(&block.header.proposer, reward);
+
+        // Matured withdrawals are paid at epoch boundaries, as of the
+        // block's own timestamp so every node pays the same ones
+        if block
+            .header
+            .number
+            .is_multiple_of(self.genesis.slots_per_epoch)
+        {
+            self.release_withdrawals(block.header.timestamp.timestamp() as u64);
+        }
 
-        Ok(())
+        Ok(receipts)
+    }
+
+    /// Merkle root over every account, in address order
+    pub fn state_root(&self) -> H256 {
+        accounts_root(self.accounts.iter().collect())
+    }
+
+    /// What NUMBER, TIMESTAMP, COINBASE and the other block opcodes read
+    /// while `header`'s transactions run
+    fn block_context(&self, header: &BlockHeader) -> BlockContext {
+        BlockContext {
+            number: U256::from(header.number),
+            timestamp: U256::from(header.timestamp.timestamp().max(0)),
+            coinbase: header.proposer,
+            gas_limit: header.gas_limit,
+            difficulty: header.difficulty,
+            base_fee: header.base_fee,
+            chain_id: U256::from(self.genesis.chain_id),
+        }
     }
 
     fn execute_transaction(
-        &mut self,
         tx: &crate::blockchain::Transaction,
+        block: &BlockContext,
+        accounts: &mut HashMap<Address, Account>,
     ) -> Result<ExecutionResult, String> {
+        // The fee is charged up front, whatever the transaction then does,
+        // and the sender must be able to cover it together with the value
+        let fee = tx.fee();
+        let sender = accounts.entry(tx.from).or_default();
+        if sender.balance < tx.value.saturating_add(fee) {
+            return Err("Insufficient balance".to_string());
+        }
+        sender.balance -= fee;
+
+        if tx.to == Some(STAKING_ADDRESS) {
+            return Ok(Self::execute_staking_transaction(tx, accounts));
+        }
+
         // Convert blockchain transaction to EVM transaction
         let evm_tx = crate::types::Transaction {
             from: tx.from,
@@ -164,34 +606,81 @@ impl Blockchain {
             data: tx.data.clone(),
         };
 
-        // Create EVM executor
-        let mut executor = crate::evm::EvmExecutor::new(1_000_000); // 1M gas limit
+        // Create EVM executor with a 1M gas limit, in the block being executed
+        let mut executor = crate::evm::EvmExecutor::new(1_000_000).with_block(block.clone());
 
         // Execute transaction
-        executor.execute_transaction(&evm_tx, &mut self.accounts)
+        executor.execute_transaction(&evm_tx, accounts)
+    }
+
+    /// Run a transaction to `STAKING_ADDRESS`, whose fee is already paid.
+    /// A bond takes its value out of the sender's balance here, in order with
+    /// the rest of its group; the stake ledger is updated from the receipt.
+    fn execute_staking_transaction(
+        tx: &crate::blockchain::Transaction,
+        accounts: &mut HashMap<Address, Account>,
+    ) -> ExecutionResult {
+        let sender = accounts.entry(tx.from).or_default();
+        sender.nonce += U256::one();
+
+        let status = match StakingOperation::decode(&tx.data) {
+            Ok(StakingOperation::Bond { .. }) => {
+                sender.balance -= tx.value;
+                ExecutionStatus::Success
+            }
+            Ok(StakingOperation::Unbond { .. }) if tx.value.is_zero() => ExecutionStatus::Success,
+            Ok(StakingOperation::Unbond { .. }) => {
+                ExecutionStatus::Revert("Unbonding carries no value".to_string())
+            }
+            Err(e) => ExecutionStatus::Revert(e),
+        };
+        let gas_used = U256::from(21000);
+        ExecutionResult {
+            status,
+            gas_used,
+            gas_remaining: tx.gas_limit.saturating_sub(gas_used),
+            ..Default::default()
+        }
     }
 
-    fn update_abby_balances(
+    /// Record a successful staking transaction in the stake ledger. Unbonded
+    /// stake is released `withdrawal_delay` seconds after `header`'s
+    /// timestamp, so every node replaying the block schedules the same payout.
+    fn apply_staking_operation(
         &mut self,
         tx: &crate::blockchain::Transaction,
-        receipt: &TransactionReceipt,
+        header: &BlockHeader,
     ) {
-        // Deduct Abby fee from sender
-        let sender_balance = self.abby_balances.entry(tx.from).or_insert(U256::zero());
-        *sender_balance = sender_balance.saturating_sub(tx.abby_fee);
+        match StakingOperation::decode(&tx.data) {
+            Ok(StakingOperation::Bond { .. }) => {
+                *self.stake_ledger.bonded.entry(tx.from).or_default() += tx.value;
+            }
+            Ok(StakingOperation::Unbond { amount, .. }) => {
+                let release_at =
+                    header.timestamp.timestamp() as u64 + self.genesis.withdrawal_delay;
+                self.unbond_stake(&tx.from, amount, release_at);
+            }
+            // Malformed operations fail, so their receipts never get here
+            Err(_) => {}
+        }
+    }
 
-        // Add Abby rewards to recipient (if transaction was successful)
+    /// Pay the recipient of a successful transaction its gas reward. The
+    /// sender's fee was already charged before the transaction ran.
+    fn pay_gas_reward(
+        &mut self,
+        tx: &crate::blockchain::Transaction,
+        receipt: &TransactionReceipt,
+    ) {
         if receipt.status && receipt.abby_rewards > U256::zero() {
-            if let Some(to) = tx.to {
-                let recipient_balance = self.abby_balances.entry(to).or_insert(U256::zero());
-                *recipient_balance += receipt.abby_rewards;
+            if let Some(to) = tx.to.filter(|to| *to != STAKING_ADDRESS) {
+                self.accounts.entry(to).or_default().balance += receipt.abby_rewards;
             }
         }
     }
 
     fn distribute_block_reward(&mut self, proposer: &Address, reward: U256) {
-        let proposer_balance = self.abby_balances.entry(*proposer).or_insert(U256::zero());
-        *proposer_balance += reward;
+        self.accounts.entry(*proposer).or_default().balance += reward;
 
         log::info!(
             "Distributed {} Abby tokens block reward to {}",
@@ -214,43 +703,150 @@ impl Blockchain {
         self.receipts.get(tx_hash)
     }
 
+    /// A transaction included in the canonical chain
+    pub fn get_transaction(&self, tx_hash: &H256) -> Option<&Transaction> {
+        let (block_hash, index) = self.transaction_index.get(tx_hash)?;
+        self.get_transaction_by_block_index(block_hash, *index)
+    }
+
+    /// The `index`-th transaction of the block with hash `block_hash`
+    pub fn get_transaction_by_block_index(
+        &self,
+        block_hash: &H256,
+        index: usize,
+    ) -> Option<&Transaction> {
+        self.blocks.get(block_hash)?.transactions.get(index)
+    }
+
+    /// Record where each of a canonical block's transactions lives
+    fn index_transactions(&mut self, block: &Block, block_hash: H256) {
+        for (index, tx) in block.transactions.iter().enumerate() {
+            self.transaction_index
+                .insert(tx.hash(), (block_hash, index));
+        }
+    }
+
+    /// Logs emitted by canonical blocks `from_block..=to_block`, in block,
+    /// transaction and log order. `topics[i]` constrains the i-th topic of a
+    /// log; `None` matches anything at that position.
+    pub fn get_logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        address: Option<Address>,
+        topics: &[Option<H256>],
+    ) -> Vec<ReceiptLog> {
+        let matches = |ReceiptLog { log, .. }: &ReceiptLog| {
+            address.is_none_or(|address| log.address == address)
+                && topics.iter().enumerate().all(|(i, topic)| match topic {
+                    Some(topic) => log.topics.get(i) == Some(topic),
+                    None => true,
+                })
+        };
+
+        (from_block..=to_block.min(self.head_number))
+            .filter_map(|number| self.get_block_by_number(number))
+            .flat_map(|block| &block.transactions)
+            .filter_map(|tx| self.receipts.get(&tx.hash()))
+            .flat_map(|receipt| &receipt.logs)
+            .filter(|log| matches(log))
+            .cloned()
+            .collect()
+    }
+
     pub fn get_account(&self, address: &Address) -> Option<&Account> {
         self.accounts.get(address)
     }
 
+    /// Value of a storage slot as of canonical block `block_number`. Only the
+    /// head's state is kept, so older blocks are replayed from genesis.
+    pub fn get_storage_at(
+        &self,
+        address: &Address,
+        slot: U256,
+        block_number: u64,
+    ) -> Result<U256, String> {
+        let hash = self
+            .block_by_number
+            .get(&block_number)
+            .copied()
+            .ok_or_else(|| format!("Block {} not found", block_number))?;
+        let state = self.state_after(hash)?;
+        Ok(state
+            .accounts
+            .get(address)
+            .and_then(|account| account.storage.get(&slot).copied())
+            .unwrap_or_default())
+    }
+
+    /// Number of transactions `address` has had executed, which is the
+    /// nonce its next transaction must use
+    pub fn get_nonce(&self, address: &Address) -> U256 {
+        self.accounts
+            .get(address)
+            .map_or(U256::zero(), |account| account.nonce)
+    }
+
     pub fn get_abby_balance(&self, address: &Address) -> U256 {
-        self.abby_balances
+        self.accounts
             .get(address)
-            .copied()
-            .unwrap_or(U256::zero())
+            .map_or(U256::zero(), |account| account.balance)
     }
 
-    pub fn transfer_abby(
-        &mut self,
-        from: &Address,
-        to: &Address,
-        amount: U256,
-    ) -> Result<(), String> {
-        let from_balance = self
-            .abby_balances
-            .get(from)
-            .copied()
-            .unwrap_or(U256::zero());
-        if from_balance < amount {
-            return Err("Insufficient Abby token balance".to_string());
+    /// Unbond up to `amount` of `staker`'s bonded stake, to be paid back by
+    /// the first epoch-boundary block with a timestamp at or after
+    /// `release_at`. Stake that never left a balance through a bond, such as
+    /// genesis stake, is not paid back.
+    fn unbond_stake(&mut self, staker: &Address, amount: U256, release_at: u64) {
+        let bonded = self.stake_ledger.bonded.entry(*staker).or_default();
+        let amount = amount.min(*bonded);
+        *bonded -= amount;
+        if bonded.is_zero() {
+            self.stake_ledger.bonded.remove(staker);
         }
 
-        let from_balance_mut = self.abby_balances.entry(*from).or_insert(U256::zero());
-        *from_balance_mut -= amount;
-
-        let to_balance_mut = self.abby_balances.entry(*to).or_insert(U256::zero());
-        *to_balance_mut += amount;
+        if !amount.is_zero() {
+            self.stake_ledger.unbonding.push(PendingWithdrawal {
+                staker: *staker,
+                amount,
+                release_at,
+            });
+        }
+    }
 
-        Ok(())
+    /// Pay back every unbonding amount whose release time `now` has reached
+    fn release_withdrawals(&mut self, now: u64) {
+        let (released, unbonding): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.stake_ledger.unbonding)
+                .into_iter()
+                .partition(|withdrawal| withdrawal.release_at <= now);
+        self.stake_ledger.unbonding = unbonding;
+
+        for withdrawal in released {
+            self.accounts.entry(withdrawal.staker).or_default().balance += withdrawal.amount;
+            log::info!(
+                "Released {} Abby tokens of matured stake to {}",
+                self.format_abby_amount(withdrawal.amount),
+                withdrawal.staker
+            );
+        }
     }
 
     pub fn get_head_block(&self) -> Option<&Block> {
-        self.blocks.get(&self.head_hash)
+        debug_assert_eq!(
+            self.head_block.header.number, self.head_number,
+            "cached head block is out of sync with head_number"
+        );
+        Some(&self.head_block)
+    }
+
+    /// Move the chain head, keeping the cached head block consistent with
+    /// `head_hash`/`head_number`.
+    fn set_head(&mut self, block: Block, block_hash: H256) {
+        debug_assert_eq!(block.hash(), block_hash, "head hash does not match block");
+        self.head_hash = block_hash;
+        self.head_number = block.header.number;
+        self.head_block = block;
     }
 
     pub fn get_chain_length(&self) -> u64 {
@@ -258,9 +854,9 @@ impl Blockchain {
     }
 
     pub fn get_total_abby_supply(&self) -> U256 {
-        self.abby_balances
+        self.accounts
             .values()
-            .fold(U256::zero(), |acc, balance| acc + balance)
+            .fold(U256::zero(), |acc, account| acc + account.balance)
     }
 
     fn persist_block(&self, block: &Block) -> Result<(), String> {
@@ -277,27 +873,227 @@ impl Blockchain {
         Ok(())
     }
 
-    fn load_from_disk(&mut self) -> Result<(), String> {
+    /// Write every account under its own key, the stake ledger and the head
+    /// hash in one batch that also drops the keys of accounts no longer in
+    /// the state (rewound by a reorg or pruned once empty)
+    fn persist_state(&self) -> Result<(), String> {
         if let Some(ref db) = self.db {
+            let mut batch = sled::Batch::default();
+
+            for result in db.scan_prefix(ACCOUNT_PREFIX) {
+                let (key, _) = result.map_err(|e| format!("Database scan error: {}", e))?;
+                if !self
+                    .accounts
+                    .contains_key(&address_from_key(&key, ACCOUNT_PREFIX)?)
+                {
+                    batch.remove(key);
+                }
+            }
+            for (address, account) in &self.accounts {
+                let serialized = serde_json::to_vec(account)
+                    .map_err(|e| format!("Failed to serialize account: {}", e))?;
+                batch.insert(
+                    format!("{}{:?}", ACCOUNT_PREFIX, address).as_bytes(),
+                    serialized,
+                );
+            }
+
+            let stake_ledger = serde_json::to_vec(&self.stake_ledger)
+                .map_err(|e| format!("Failed to serialize stake ledger: {}", e))?;
+            batch.insert(STAKE_LEDGER_KEY, stake_ledger);
+            batch.insert(HEAD_HASH_KEY, self.head_hash.as_bytes());
+
+            db.apply_batch(batch)
+                .map_err(|e| format!("Failed to persist state: {}", e))?;
+            db.flush()
+                .map_err(|e| format!("Failed to flush database: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Replace the genesis allocation with the stored state, if any was stored
+    fn load_state_from_disk(&mut self, db: &sled::Db) -> Result<(), String> {
+        let mut accounts = HashMap::new();
+        for result in db.scan_prefix(ACCOUNT_PREFIX) {
+            let (key, value) = result.map_err(|e| format!("Database scan error: {}", e))?;
+            let address = address_from_key(&key, ACCOUNT_PREFIX)?;
+            let account: Account = serde_json::from_slice(&value)
+                .map_err(|e| format!("Failed to deserialize account: {}", e))?;
+            accounts.insert(address, account);
+        }
+        self.migrate_legacy_abby_balances(db, &mut accounts)?;
+        if !accounts.is_empty() {
+            self.accounts = accounts;
+        }
+
+        if let Some(stake_ledger) = db
+            .get(STAKE_LEDGER_KEY)
+            .map_err(|e| format!("Failed to read stake ledger: {}", e))?
+        {
+            self.stake_ledger = serde_json::from_slice(&stake_ledger)
+                .map_err(|e| format!("Failed to deserialize stake ledger: {}", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the Abby balances of a database written before the ledgers were
+    /// merged to the balances of `accounts`, then store the accounts and drop
+    /// the old keys in one batch so no balance is added twice
+    fn migrate_legacy_abby_balances(
+        &self,
+        db: &sled::Db,
+        accounts: &mut HashMap<Address, Account>,
+    ) -> Result<(), String> {
+        let mut batch = sled::Batch::default();
+        let mut migrated = Vec::new();
+        for result in db.scan_prefix(LEGACY_ABBY_BALANCE_PREFIX) {
+            let (key, value) = result.map_err(|e| format!("Database scan error: {}", e))?;
+            let address = address_from_key(&key, LEGACY_ABBY_BALANCE_PREFIX)?;
+            let balance: U256 = serde_json::from_slice(&value)
+                .map_err(|e| format!("Failed to deserialize Abby balance: {}", e))?;
+            accounts.entry(address).or_default().balance += balance;
+            migrated.push(address);
+            batch.remove(key);
+        }
+        if migrated.is_empty() {
+            return Ok(());
+        }
+
+        for address in &migrated {
+            let serialized = serde_json::to_vec(&accounts[address])
+                .map_err(|e| format!("Failed to serialize account: {}", e))?;
+            batch.insert(
+                format!("{}{:?}", ACCOUNT_PREFIX, address).as_bytes(),
+                serialized,
+            );
+        }
+        db.apply_batch(batch)
+            .map_err(|e| format!("Failed to migrate Abby balances: {}", e))?;
+        db.flush()
+            .map_err(|e| format!("Failed to flush database: {}", e))?;
+        log::info!("Moved {} Abby balances into their accounts", migrated.len());
+        Ok(())
+    }
+
+    fn load_from_disk(&mut self) -> Result<(), String> {
+        if let Some(db) = self.db.clone() {
+            self.load_state_from_disk(&db)?;
+
             for result in db.scan_prefix("block_") {
-                let (key, value) = result.map_err(|e| format!("Database scan error: {}", e))?;
+                let (_, value) = result.map_err(|e| format!("Database scan error: {}", e))?;
 
                 let block: Block = serde_json::from_slice(&value)
                     .map_err(|e| format!("Failed to deserialize block: {}", e))?;
 
                 let block_hash = block.hash();
-                self.blocks.insert(block_hash, block.clone());
-                self.block_by_number.insert(block.header.number, block_hash);
-
-                if block.header.number > self.head_number {
-                    self.head_hash = block_hash;
-                    self.head_number = block.header.number;
+                if block.header.number == 0 && block_hash != self.genesis_hash() {
+                    return Err(format!(
+                        "Stored genesis block {} does not match expected genesis {}",
+                        block_hash,
+                        self.genesis_hash()
+                    ));
                 }
+                self.blocks.insert(block_hash, block);
             }
+
+            // The head the state was written for; fork choice keeps the
+            // current head on ties, which the blocks alone cannot tell
+            let stored_head = db
+                .get(HEAD_HASH_KEY)
+                .map_err(|e| format!("Failed to read head hash: {}", e))?
+                .map(|head| H256::from_slice(&head));
+            self.rebuild_canonical_chain(stored_head);
         }
         Ok(())
     }
 
+    /// Make `head` canonical if its ancestry reaches genesis. Otherwise apply
+    /// the fork choice rule over every stored block whose ancestry does: the
+    /// heaviest total difficulty wins, then the greatest height, then the
+    /// lower hash so reloads are deterministic. Rebuilds `block_by_number`
+    /// and `total_difficulty` along the winner's parent chain. Orphans and
+    /// stale forks stay in `blocks` but are not indexed by number.
+    fn rebuild_canonical_chain(&mut self, head: Option<H256>) {
+        let genesis_hash = self.genesis_hash();
+
+        let mut by_number: Vec<(u64, H256)> = self
+            .blocks
+            .iter()
+            .map(|(hash, block)| (block.header.number, *hash))
+            .collect();
+        by_number.sort();
+
+        // Total difficulty of each block connected to genesis, parents first
+        let mut total_difficulties = HashMap::from([(genesis_hash, U256::zero())]);
+        for (number, hash) in by_number {
+            let block = &self.blocks[&hash];
+            let parent_connected = self
+                .blocks
+                .get(&block.header.parent_hash)
+                .is_some_and(|parent| parent.header.number + 1 == number);
+            if number == 0 || !parent_connected {
+                continue;
+            }
+            if let Some(&parent_td) = total_difficulties.get(&block.header.parent_hash) {
+                total_difficulties.insert(hash, parent_td + block.header.difficulty);
+            }
+        }
+
+        let heaviest = total_difficulties
+            .iter()
+            .max_by(|(a_hash, a_td), (b_hash, b_td)| {
+                let a_number = self.blocks[*a_hash].header.number;
+                let b_number = self.blocks[*b_hash].header.number;
+                (*a_td, a_number)
+                    .cmp(&(*b_td, b_number))
+                    .then(b_hash.cmp(a_hash))
+            })
+            .map(|(hash, _)| *hash)
+            .unwrap_or(genesis_hash);
+        let head_hash = head
+            .filter(|head| total_difficulties.contains_key(head))
+            .unwrap_or(heaviest);
+        let canonical = self
+            .ancestry_to_genesis(head_hash, genesis_hash)
+            .unwrap_or_else(|| vec![genesis_hash]);
+
+        self.block_by_number.clear();
+        self.transaction_index.clear();
+        self.total_difficulty = U256::zero();
+        for hash in &canonical {
+            let block = self.blocks[hash].clone();
+            self.block_by_number.insert(block.header.number, *hash);
+            self.index_transactions(&block, *hash);
+            if block.header.number > 0 {
+                self.total_difficulty += block.header.difficulty;
+            }
+        }
+
+        let head_hash = *canonical.last().expect("canonical chain includes genesis");
+        let head = self.blocks[&head_hash].clone();
+        self.set_head(head, head_hash);
+    }
+
+    /// Hashes from genesis up to `hash`, or `None` if a parent is missing
+    /// or the numbers along the way are not consecutive
+    fn ancestry_to_genesis(&self, mut hash: H256, genesis_hash: H256) -> Option<Vec<H256>> {
+        let mut path = Vec::new();
+        loop {
+            let block = self.blocks.get(&hash)?;
+            path.push(hash);
+            if block.header.number == 0 {
+                path.reverse();
+                return (hash == genesis_hash).then_some(path);
+            }
+            let parent = self.blocks.get(&block.header.parent_hash)?;
+            if parent.header.number + 1 != block.header.number {
+                return None;
+            }
+            hash = block.header.parent_hash;
+        }
+    }
+
     pub fn validate_chain(&self) -> Result<(), String> {
         let mut current_number = 0u64;
         let mut current_hash = self
@@ -313,7 +1109,7 @@ impl Blockchain {
                 .ok_or(format!("Block {} not found", current_hash))?;
 
             // Validate block
-            block.validate()?;
+            block.validate_with_limits(&self.genesis.block_limits)?;
 
             // Check parent hash (except for genesis)
             if current_number > 0 {
@@ -343,13 +1139,121 @@ impl Blockchain {
         Ok(())
     }
 
+    /// Write all blocks, receipts and accounts to a single
+    /// JSON file that `import_from_file` can rebuild the chain from
+    pub fn export_to_file(&self, path: &Path) -> Result<(), String> {
+        let mut blocks: Vec<Block> = self.blocks.values().cloned().collect();
+        blocks.sort_by_key(|block| (block.header.number, block.hash()));
+        let mut receipts: Vec<TransactionReceipt> = self.receipts.values().cloned().collect();
+        receipts.sort_by_key(|receipt| receipt.transaction_hash);
+
+        let export = ChainExport {
+            genesis: self.genesis.clone(),
+            head_hash: self.head_hash,
+            blocks,
+            receipts,
+            accounts: self.accounts.clone().into_iter().collect(),
+        };
+        let serialized = serde_json::to_vec_pretty(&export)
+            .map_err(|e| format!("Failed to serialize chain: {}", e))?;
+        std::fs::write(path, serialized)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Rebuild an in-memory chain from a file written by `export_to_file`.
+    /// The blocks must reach the exported head from the file's genesis and
+    /// pass `validate_chain`. Account state and receipts are rebuilt by
+    /// running the canonical blocks, each checked against the roots in its
+    /// header, and the exported ones must match what that produces.
+    pub fn import_from_file(path: &Path) -> Result<Self, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let export: ChainExport = serde_json::from_slice(&data)
+            .map_err(|e| format!("Failed to parse chain export: {}", e))?;
+
+        let mut blockchain = Self::from_genesis(export.genesis)?;
+        let genesis_hash = blockchain.genesis_hash();
+        for block in export.blocks {
+            let block_hash = block.hash();
+            if block.header.number == 0 {
+                if block_hash != genesis_hash {
+                    return Err(format!(
+                        "Exported genesis block {} does not match its genesis config ({})",
+                        block_hash, genesis_hash
+                    ));
+                }
+                continue;
+            }
+            blockchain.blocks.insert(block_hash, block);
+        }
+        blockchain.rebuild_canonical_chain(Some(export.head_hash));
+        if blockchain.head_hash != export.head_hash {
+            return Err(format!(
+                "Exported head {} does not reach genesis through its blocks",
+                export.head_hash
+            ));
+        }
+        blockchain.validate_chain()?;
+
+        let mut state = Self::from_genesis(blockchain.genesis.clone())?;
+        state.reward_schedule = blockchain.reward_schedule.clone();
+        for number in 1..=blockchain.head_number {
+            let block = &blockchain.blocks[&blockchain.block_by_number[&number]];
+            let receipts = state.process_block(block)?;
+            if block.header.state_root != state.state_root() {
+                return Err(format!("Invalid state root in block #{}", number));
+            }
+            if block.header.receipts_root != Block::calculate_receipts_root(&receipts) {
+                return Err(format!("Invalid receipts root in block #{}", number));
+            }
+            for receipt in receipts {
+                state.receipts.insert(receipt.transaction_hash, receipt);
+            }
+        }
+
+        if accounts_root(export.accounts.iter().collect()) != state.state_root() {
+            return Err("Exported accounts do not match the state the blocks produce".to_string());
+        }
+        let receipts_match = export.receipts.len() == state.receipts.len()
+            && export.receipts.iter().all(|receipt| {
+                state
+                    .receipts
+                    .get(&receipt.transaction_hash)
+                    .is_some_and(|rebuilt| {
+                        rebuilt.block_hash == receipt.block_hash
+                            && rebuilt.transaction_index == receipt.transaction_index
+                            && rebuilt.consensus_hash() == receipt.consensus_hash()
+                    })
+            });
+        if !receipts_match {
+            return Err("Exported receipts do not match the blocks".to_string());
+        }
+
+        blockchain.accounts = state.accounts;
+        blockchain.stake_ledger = state.stake_ledger;
+        blockchain.receipts = state.receipts;
+        Ok(blockchain)
+    }
+
+    /// Store every block and the current state in the database at `db_path`,
+    /// which must be empty or hold a chain with the same genesis
+    pub fn save_to_database(&mut self, db_path: &str) -> Result<(), String> {
+        self.db = Some(sled::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?);
+        self.verify_stored_genesis()?;
+        for block in self.blocks.values() {
+            self.persist_block(block)?;
+        }
+        self.persist_state()
+    }
+
     pub fn get_abby_rich_list(&self, limit: usize) -> Vec<(Address, U256)> {
         let mut balances: Vec<_> = self
-            .abby_balances
+            .accounts
             .iter()
-            .map(|(&addr, &balance)| (addr, balance))
+            .filter(|(_, account)| !account.balance.is_zero())
+            .map(|(&addr, account)| (addr, account.balance))
             .collect();
-        balances.sort_by(|a, b| b.1.cmp(&a.1));
+        balances.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
         balances.into_iter().take(limit).collect()
     }
 
@@ -362,8 +1266,1184 @@ impl Blockchain {
     }
 }
 
+/// Merkle root over `accounts`, in address order: what a header's state root
+/// commits to
+fn accounts_root(accounts: BTreeMap<&Address, &Account>) -> H256 {
+    let leaves = accounts
+        .into_iter()
+        .map(|(address, account)| {
+            let storage: BTreeMap<_, _> = account.storage.iter().collect();
+
+            let mut stream = RlpStream::new_list(5);
+            stream.append(address);
+            stream.append(&account.nonce);
+            stream.append(&account.balance);
+            stream.append(&H256::from_slice(&Keccak256::digest(&account.code)));
+            stream.begin_list(storage.len());
+            for (key, value) in storage {
+                stream.begin_list(2);
+                stream.append(key);
+                stream.append(value);
+            }
+            H256::from_slice(&Keccak256::digest(stream.out()))
+        })
+        .collect();
+
+    merkle_root(leaves)
+}
+
+/// Gas used by a whole block, from its receipts
+fn total_gas_used(receipts: &[TransactionReceipt]) -> U256 {
+    receipts
+        .last()
+        .map_or(U256::zero(), |receipt| receipt.cumulative_gas_used)
+}
+
+/// Parse the address out of a `<prefix><0x-address>` database key
+fn address_from_key(key: &[u8], prefix: &str) -> Result<Address, String> {
+    std::str::from_utf8(&key[prefix.len()..])
+        .ok()
+        .and_then(|address| address.parse().ok())
+        .ok_or_else(|| format!("Malformed state key: {}", String::from_utf8_lossy(key)))
+}
+
 impl Default for Blockchain {
     fn default() -> Self {
         Self::new().expect("Failed to create default blockchain")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{BlockHeader, GenesisAccount, Transaction};
+    use secp256k1::SecretKey;
+
+    /// A zero-value transfer signed for the default chain by the key `[key_byte; 32]`
+    fn key_address(key_byte: u8) -> Address {
+        let key = SecretKey::from_slice(&[key_byte; 32]).unwrap();
+        crate::utils::public_key_to_address(&key.public_key(&secp256k1::Secp256k1::signing_only()))
+    }
+
+    /// The default genesis, plus one Abby token for the address of every
+    /// test key so their transactions can pay fees
+    fn funded_genesis() -> GenesisConfig {
+        let mut genesis = GenesisConfig::default();
+        for key_byte in 1..=9 {
+            genesis.alloc.insert(
+                key_address(key_byte),
+                GenesisAccount {
+                    balance: U256::exp10(18),
+                },
+            );
+        }
+        genesis
+    }
+
+    fn value_transfer(key_byte: u8, to: Address, value: U256, nonce: u64) -> Transaction {
+        let key = SecretKey::from_slice(&[key_byte; 32]).unwrap();
+        let mut tx = Transaction::new(
+            key_address(key_byte),
+            Some(to),
+            value,
+            U256::from(21000),
+            U256::from(1),
+            Vec::new(),
+            U256::from(nonce),
+        );
+        tx.sign(&key);
+        tx
+    }
+
+    fn zero_value_transfer(key_byte: u8, nonce: u64) -> Transaction {
+        let to = Address::from_low_u64_be(key_byte as u64 + 100);
+        value_transfer(key_byte, to, U256::zero(), nonce)
+    }
+
+    #[test]
+    fn test_competing_genesis_is_rejected() {
+        let mut chain = Blockchain::new().unwrap();
+        let genesis_hash = chain.genesis_hash();
+
+        let header = BlockHeader::new(
+            0,
+            H256::zero(),
+            Address::from_low_u64_be(42),
+            U256::from(10_000_000u64),
+        );
+        let result = chain.add_block(Block::new(header, Vec::new()));
+
+        assert!(result.is_err());
+        assert_eq!(chain.genesis_hash(), genesis_hash);
+        assert_eq!(chain.head_number, 0);
+    }
+
+    #[test]
+    fn test_cached_head_tracks_added_blocks() {
+        let mut chain = Blockchain::new().unwrap();
+        let header = BlockHeader::new(
+            1,
+            chain.genesis_hash(),
+            Address::from_low_u64_be(42),
+            U256::from(10_000_000u64),
+        );
+        let mut block = Block::new(header, Vec::new());
+        chain.seal_block(&mut block).unwrap();
+        let block_hash = block.hash();
+        chain.add_block(block).unwrap();
+
+        let head = chain.get_head_block().unwrap();
+        assert_eq!(head.hash(), block_hash);
+        assert_eq!(
+            head.hash(),
+            chain.get_block_by_number(chain.head_number).unwrap().hash()
+        );
+    }
+
+    #[test]
+    fn test_block_roots_are_checked_against_execution() {
+        let mut chain = Blockchain::new().unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        let header = BlockHeader::new(1, chain.genesis_hash(), proposer, U256::from(10_000_000u64));
+        let mut block = Block::new(header, Vec::new());
+        chain.seal_block(&mut block).unwrap();
+
+        // The proposer reward changes the state, so the root must move
+        assert_ne!(block.header.state_root, chain.state_root());
+
+        let mut wrong_state = block.clone();
+        wrong_state.header.state_root = chain.state_root();
+        assert_eq!(
+            chain.add_block(wrong_state).unwrap_err(),
+            "Invalid state root"
+        );
+
+        let mut wrong_receipts = block.clone();
+        wrong_receipts.header.receipts_root = H256::repeat_byte(1);
+        assert_eq!(
+            chain.add_block(wrong_receipts).unwrap_err(),
+            "Invalid receipts root"
+        );
+
+        // Rejected blocks leave the state alone
+        assert_eq!(chain.get_abby_balance(&proposer), U256::zero());
+
+        let expected_root = block.header.state_root;
+        chain.add_block(block).unwrap();
+        assert_eq!(chain.state_root(), expected_root);
+        assert!(chain.get_abby_balance(&proposer) > U256::zero());
+    }
+
+    #[test]
+    fn test_account_state_survives_reopening() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        let recipient = Address::from_low_u64_be(43);
+
+        let mut chain = Blockchain::with_db(db.clone(), funded_genesis()).unwrap();
+        let transfer = value_transfer(7, recipient, U256::from(5), 0);
+        let sender = transfer.from;
+        let header = BlockHeader::new(1, chain.genesis_hash(), proposer, U256::from(10_000_000u64));
+        let mut block = Block::new(header, vec![transfer]);
+        chain.seal_block(&mut block).unwrap();
+        chain.add_block(block).unwrap();
+        let state_root = chain.state_root();
+        let recipient_balance = chain.get_abby_balance(&recipient);
+        let proposer_balance = chain.get_abby_balance(&proposer);
+        assert!(recipient_balance >= U256::from(5));
+        assert!(proposer_balance > U256::zero());
+        drop(chain);
+
+        let reopened = Blockchain::with_db(db, funded_genesis()).unwrap();
+        assert_eq!(reopened.head_number, 1);
+        assert_eq!(reopened.get_abby_balance(&recipient), recipient_balance);
+        assert_eq!(reopened.get_abby_balance(&proposer), proposer_balance);
+        assert_eq!(reopened.get_account(&sender).unwrap().nonce, U256::one());
+        assert_eq!(reopened.get_nonce(&sender), U256::one());
+        assert_eq!(reopened.state_root(), state_root);
+    }
+
+    #[test]
+    fn test_head_is_rebuilt_from_canonical_chain_on_load() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        let mut chain = Blockchain::with_db(db.clone(), GenesisConfig::default()).unwrap();
+
+        let mut hashes = vec![chain.genesis_hash()];
+        for number in 1..=3 {
+            let header = BlockHeader::new(
+                number,
+                *hashes.last().unwrap(),
+                proposer,
+                U256::from(10_000_000u64),
+            );
+            let mut block = Block::new(header, Vec::new());
+            block.header.difficulty = U256::from(number);
+            chain.seal_block(&mut block).unwrap();
+            hashes.push(block.hash());
+            chain.add_block(block).unwrap();
+        }
+
+        // A stale fork at height 2 and an orphan far above the head
+        let fork = Block::new(
+            BlockHeader::new(2, hashes[1], Address::from_low_u64_be(7), U256::from(1u64)),
+            Vec::new(),
+        );
+        let orphan = Block::new(
+            BlockHeader::new(9, H256::repeat_byte(0xee), proposer, U256::from(1u64)),
+            Vec::new(),
+        );
+        chain.persist_block(&fork).unwrap();
+        chain.persist_block(&orphan).unwrap();
+        drop(chain);
+
+        let reopened = Blockchain::with_db(db, GenesisConfig::default()).unwrap();
+        assert_eq!(reopened.head_number, 3);
+        assert_eq!(reopened.head_hash, hashes[3]);
+        assert_eq!(reopened.get_head_block().unwrap().hash(), hashes[3]);
+        assert_eq!(reopened.get_chain_length(), 4);
+        assert_eq!(reopened.total_difficulty, U256::from(6));
+        for (number, hash) in hashes.iter().enumerate() {
+            assert_eq!(reopened.block_by_number[&(number as u64)], *hash);
+        }
+        assert!(reopened.validate_chain().is_ok());
+    }
+
+    #[test]
+    fn test_storage_is_read_as_of_a_past_block() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let signed = |to: Option<Address>, data: Vec<u8>, nonce: u64| {
+            let from = crate::utils::public_key_to_address(
+                &key.public_key(&secp256k1::Secp256k1::signing_only()),
+            );
+            let mut tx = Transaction::new(
+                from,
+                to,
+                U256::zero(),
+                U256::from(100_000),
+                U256::from(1),
+                data,
+                U256::from(nonce),
+            );
+            tx.sign(&key);
+            tx
+        };
+        let store = |value: u8| {
+            let mut word = vec![0u8; 32];
+            word[31] = value;
+            word
+        };
+
+        // Runtime code: SSTORE(0, CALLDATALOAD(0))
+        let deploy = signed(
+            None,
+            hex::decode("6007600c60003960076000f360003560005500").unwrap(),
+            0,
+        );
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        // Dry-run the deployment to learn the contract's address
+        let header = BlockHeader::new(1, chain.head_hash, proposer, U256::from(10_000_000u64));
+        let (scratch, _) = chain
+            .execute_block(&Block::new(header, vec![deploy.clone()]))
+            .unwrap();
+        let contract = *scratch
+            .accounts
+            .iter()
+            .find(|(_, account)| !account.code.is_empty())
+            .unwrap()
+            .0;
+
+        extend_chain(
+            &mut chain,
+            1,
+            proposer,
+            1,
+            vec![deploy, signed(Some(contract), store(1), 1)],
+        );
+        extend_chain(
+            &mut chain,
+            1,
+            proposer,
+            1,
+            vec![signed(Some(contract), store(2), 2)],
+        );
+
+        let slot = U256::zero();
+        assert_eq!(
+            chain.get_storage_at(&contract, slot, 0).unwrap(),
+            U256::zero()
+        );
+        assert_eq!(
+            chain.get_storage_at(&contract, slot, 1).unwrap(),
+            U256::from(1)
+        );
+        assert_eq!(
+            chain.get_storage_at(&contract, slot, 2).unwrap(),
+            U256::from(2)
+        );
+        assert!(chain.get_storage_at(&contract, slot, 3).is_err());
+    }
+
+    #[test]
+    fn test_exported_chain_imports_unchanged() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let transfer = value_transfer(7, Address::from_low_u64_be(43), U256::from(5), 0);
+        let tx_hash = transfer.hash();
+        extend_chain(
+            &mut chain,
+            3,
+            Address::from_low_u64_be(42),
+            1,
+            vec![transfer],
+        );
+
+        let path = std::env::temp_dir().join(format!("abby-chain-{}.json", rand::random::<u64>()));
+        chain.export_to_file(&path).unwrap();
+        let imported = Blockchain::import_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let imported = imported.unwrap();
+
+        assert_eq!(imported.genesis, chain.genesis);
+        assert_eq!(imported.head_hash, chain.head_hash);
+        assert_eq!(imported.head_number, 3);
+        assert_eq!(imported.total_difficulty, chain.total_difficulty);
+        assert_eq!(imported.block_by_number, chain.block_by_number);
+        assert_eq!(imported.state_root(), chain.state_root());
+        assert_eq!(
+            imported.get_receipt(&tx_hash).unwrap().block_hash,
+            chain.get_receipt(&tx_hash).unwrap().block_hash
+        );
+        assert_eq!(imported.get_transaction(&tx_hash).unwrap().hash(), tx_hash);
+    }
+
+    #[test]
+    fn test_import_rejects_state_the_blocks_do_not_produce() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let recipient = Address::from_low_u64_be(43);
+        let transfer = value_transfer(7, recipient, U256::from(5), 0);
+        let tx_hash = transfer.hash();
+        extend_chain(
+            &mut chain,
+            2,
+            Address::from_low_u64_be(42),
+            1,
+            vec![transfer],
+        );
+
+        let path = std::env::temp_dir().join(format!("abby-chain-{}.json", rand::random::<u64>()));
+        chain.export_to_file(&path).unwrap();
+        let export: ChainExport = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let import = |export: &ChainExport| {
+            std::fs::write(&path, serde_json::to_vec(export).unwrap()).unwrap();
+            Blockchain::import_from_file(&path)
+        };
+
+        let mut richer = export.clone();
+        richer.accounts.get_mut(&recipient).unwrap().balance += U256::exp10(18);
+        let mut failed = export.clone();
+        let receipt = failed
+            .receipts
+            .iter_mut()
+            .find(|receipt| receipt.transaction_hash == tx_hash)
+            .unwrap();
+        receipt.status = false;
+        let results = [import(&richer), import(&failed), import(&export)];
+        std::fs::remove_file(&path).unwrap();
+
+        let [richer, failed, unchanged] = results;
+        assert_eq!(
+            richer.unwrap_err(),
+            "Exported accounts do not match the state the blocks produce"
+        );
+        assert_eq!(
+            failed.unwrap_err(),
+            "Exported receipts do not match the blocks"
+        );
+        assert_eq!(unchanged.unwrap().state_root(), chain.state_root());
+    }
+
+    /// Build `count` sealed blocks on top of `chain`'s head, adding each one
+    fn extend_chain(
+        chain: &mut Blockchain,
+        count: usize,
+        proposer: Address,
+        difficulty: u64,
+        transactions: Vec<Transaction>,
+    ) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        for i in 0..count {
+            let header = BlockHeader::new(
+                chain.head_number + 1,
+                chain.head_hash,
+                proposer,
+                U256::from(10_000_000u64),
+            );
+            let txs = if i == 0 {
+                transactions.clone()
+            } else {
+                Vec::new()
+            };
+            let mut block = Block::new(header, txs);
+            block.header.difficulty = U256::from(difficulty);
+            chain.seal_block(&mut block).unwrap();
+            chain.add_block(block.clone()).unwrap();
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_block_opcodes_read_the_executing_block() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        // Stores NUMBER, TIMESTAMP, COINBASE, GASLIMIT, BASEFEE, CHAINID and
+        // DIFFICULTY in slots 0 to 6
+        let contract = Address::from_low_u64_be(0xb10c);
+        chain.accounts.insert(
+            contract,
+            Account {
+                code: hex::decode("4360005542600155416002554560035548600455466005554460065500")
+                    .unwrap(),
+                nonce: U256::one(),
+                ..Default::default()
+            },
+        );
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let mut tx = Transaction::new(
+            crate::utils::public_key_to_address(
+                &key.public_key(&secp256k1::Secp256k1::signing_only()),
+            ),
+            Some(contract),
+            U256::zero(),
+            U256::from(100_000),
+            U256::from(1),
+            Vec::new(),
+            U256::zero(),
+        );
+        tx.sign(&key);
+
+        extend_chain(&mut chain, 1, Address::zero(), 0, Vec::new());
+        let header = extend_chain(&mut chain, 1, proposer, 3, vec![tx])[0]
+            .header
+            .clone();
+
+        let storage = &chain.get_account(&contract).unwrap().storage;
+        let slot = |index: u64| storage.get(&U256::from(index)).copied().unwrap_or_default();
+        assert_eq!(slot(0), U256::from(2));
+        assert_eq!(slot(1), U256::from(header.timestamp.timestamp()));
+        assert_eq!(slot(2), U256::from_big_endian(proposer.as_bytes()));
+        assert_eq!(slot(3), header.gas_limit);
+        assert_eq!(slot(4), header.base_fee);
+        assert_eq!(slot(5), U256::from(chain.genesis.chain_id));
+        assert_eq!(slot(6), U256::from(3));
+    }
+
+    #[test]
+    fn test_base_fee_rises_with_full_blocks_and_falls_with_empty_ones() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        let add = |chain: &mut Blockchain, transactions: Vec<Transaction>| {
+            // Room for exactly one transfer, so one transfer fills the block
+            let header = BlockHeader::new(
+                chain.head_number + 1,
+                chain.head_hash,
+                proposer,
+                U256::from(21000),
+            );
+            let mut block = Block::new(header, transactions);
+            chain.seal_block(&mut block).unwrap();
+            chain.add_block(block.clone()).unwrap();
+            block.header
+        };
+
+        let mut fees = vec![chain.get_head_block().unwrap().header.base_fee];
+        for key in 1..=3 {
+            let header = add(&mut chain, vec![zero_value_transfer(key, 0)]);
+            assert_eq!(header.gas_used, U256::from(21000));
+            fees.push(header.base_fee);
+        }
+        for _ in 0..3 {
+            fees.push(add(&mut chain, Vec::new()).base_fee);
+        }
+
+        // The genesis block is empty, so the first block already pays less;
+        // each full block then raises the fee and each empty one lowers it
+        assert!(fees[1] < fees[0]);
+        assert!(fees[1] < fees[2] && fees[2] < fees[3] && fees[3] < fees[4]);
+        assert!(fees[4] > fees[5] && fees[5] > fees[6]);
+
+        // A block with any other base fee is rejected
+        let header = BlockHeader::new(7, chain.head_hash, proposer, U256::from(21000));
+        let mut block = Block::new(header, Vec::new());
+        chain.seal_block(&mut block).unwrap();
+        block.header.base_fee += U256::one();
+        assert!(chain
+            .add_block(block)
+            .unwrap_err()
+            .starts_with("Invalid base fee"));
+    }
+
+    #[test]
+    fn test_longer_side_chain_triggers_reorg() {
+        let (alice, bob) = (Address::from_low_u64_be(10), Address::from_low_u64_be(20));
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let abandoned_tx = zero_value_transfer(1, 0);
+        let main = extend_chain(&mut chain, 2, alice, 0, vec![abandoned_tx.clone()]);
+
+        let mut rival = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let adopted_tx = zero_value_transfer(2, 0);
+        let side = extend_chain(&mut rival, 3, bob, 0, vec![adopted_tx.clone()]);
+
+        // A shorter or equally long side chain is stored but not adopted
+        chain.add_block(side[0].clone()).unwrap();
+        chain.add_block(side[1].clone()).unwrap();
+        assert_eq!(chain.head_hash, main[1].hash());
+        assert!(chain.get_receipt(&adopted_tx.hash()).is_none());
+
+        chain.add_block(side[2].clone()).unwrap();
+        assert_eq!(chain.head_hash, side[2].hash());
+        assert_eq!(chain.head_number, 3);
+        for block in &side {
+            assert_eq!(
+                chain
+                    .get_block_by_number(block.header.number)
+                    .unwrap()
+                    .hash(),
+                block.hash()
+            );
+        }
+
+        // State is rewound past the abandoned blocks and the new branch re-applied
+        assert_eq!(chain.get_abby_balance(&alice), U256::zero());
+        assert_eq!(chain.get_abby_balance(&bob), rival.get_abby_balance(&bob));
+        assert_eq!(chain.state_root(), rival.state_root());
+        assert!(chain.get_receipt(&abandoned_tx.hash()).is_none());
+        assert!(chain.get_receipt(&adopted_tx.hash()).is_some());
+        assert!(chain.get_transaction(&abandoned_tx.hash()).is_none());
+        assert_eq!(
+            chain
+                .get_transaction(&adopted_tx.hash())
+                .map(Transaction::hash),
+            Some(adopted_tx.hash())
+        );
+        assert!(chain.validate_chain().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_abby_balances_move_into_accounts_once() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let (alice, bob) = (Address::from_low_u64_be(10), Address::from_low_u64_be(20));
+        drop(Blockchain::with_db(db.clone(), GenesisConfig::default()).unwrap());
+
+        // State as an older version wrote it: Alice's EVM account and, apart
+        // from it, Abby balances for her and for Bob
+        let alice_account = Account {
+            balance: U256::from(30),
+            nonce: U256::one(),
+            ..Default::default()
+        };
+        db.insert(
+            format!("{}{:?}", ACCOUNT_PREFIX, alice),
+            serde_json::to_vec(&alice_account).unwrap(),
+        )
+        .unwrap();
+        for (address, balance) in [(alice, 700u64), (bob, 5)] {
+            db.insert(
+                format!("{}{:?}", LEGACY_ABBY_BALANCE_PREFIX, address),
+                serde_json::to_vec(&U256::from(balance)).unwrap(),
+            )
+            .unwrap();
+        }
+
+        let chain = Blockchain::with_db(db.clone(), GenesisConfig::default()).unwrap();
+        assert_eq!(chain.get_abby_balance(&alice), U256::from(730));
+        assert_eq!(chain.get_account(&alice).unwrap().nonce, U256::one());
+        assert_eq!(chain.get_abby_balance(&bob), U256::from(5));
+        assert_eq!(db.scan_prefix(LEGACY_ABBY_BALANCE_PREFIX).count(), 0);
+        drop(chain);
+
+        let reopened = Blockchain::with_db(db, GenesisConfig::default()).unwrap();
+        assert_eq!(reopened.get_abby_balance(&alice), U256::from(730));
+        assert_eq!(reopened.get_abby_balance(&bob), U256::from(5));
+    }
+
+    #[test]
+    fn test_accounts_rewound_by_a_reorg_stay_gone_after_reopening() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let (alice, bob) = (Address::from_low_u64_be(10), Address::from_low_u64_be(20));
+        let mut chain = Blockchain::with_db(db.clone(), GenesisConfig::default()).unwrap();
+        extend_chain(&mut chain, 1, alice, 0, Vec::new());
+
+        let mut rival = Blockchain::new().unwrap();
+        let side = extend_chain(&mut rival, 2, bob, 0, Vec::new());
+        for block in side {
+            chain.add_block(block).unwrap();
+        }
+        // Alice's block reward went with the abandoned block
+        assert!(chain.get_account(&alice).is_none());
+        let state_root = chain.state_root();
+        drop(chain);
+
+        let reopened = Blockchain::with_db(db, GenesisConfig::default()).unwrap();
+        assert!(reopened.get_account(&alice).is_none());
+        assert_eq!(reopened.state_root(), state_root);
+        assert_eq!(reopened.state_root(), rival.state_root());
+    }
+
+    #[test]
+    fn test_reopening_keeps_the_head_an_equal_side_chain_did_not_replace() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut chain = Blockchain::with_db(db.clone(), GenesisConfig::default()).unwrap();
+        let main = extend_chain(&mut chain, 2, Address::from_low_u64_be(10), 1, Vec::new());
+
+        let mut rival = Blockchain::new().unwrap();
+        let side = extend_chain(&mut rival, 2, Address::from_low_u64_be(20), 1, Vec::new());
+        for block in side {
+            chain.add_block(block).unwrap();
+        }
+        assert_eq!(chain.head_hash, main[1].hash());
+        let state_root = chain.state_root();
+        drop(chain);
+
+        let reopened = Blockchain::with_db(db, GenesisConfig::default()).unwrap();
+        assert_eq!(reopened.head_hash, main[1].hash());
+        assert_eq!(reopened.state_root(), state_root);
+    }
+
+    /// Add a sealed block on top of `chain`'s head, timestamped `seconds`
+    /// after genesis
+    fn add_block_at(chain: &mut Blockchain, seconds: i64, transactions: Vec<Transaction>) -> Block {
+        let genesis_time = chain
+            .get_block(&chain.genesis_hash())
+            .unwrap()
+            .header
+            .timestamp;
+        let mut header = BlockHeader::new(
+            chain.head_number + 1,
+            chain.head_hash,
+            Address::from_low_u64_be(42),
+            U256::from(10_000_000u64),
+        );
+        header.timestamp = genesis_time + chrono::Duration::seconds(seconds);
+        let mut block = Block::new(header, transactions);
+        chain.seal_block(&mut block).unwrap();
+        chain.add_block(block.clone()).unwrap();
+        block
+    }
+
+    #[test]
+    fn test_staking_transactions_bond_and_unbond_in_block_processing() {
+        let delay = 3600;
+        let genesis = GenesisConfig {
+            slots_per_epoch: 2,
+            withdrawal_delay: delay,
+            ..funded_genesis()
+        };
+        let mut chain = Blockchain::from_genesis(genesis.clone()).unwrap();
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let staker = key_address(7);
+        let validator = Address::from_low_u64_be(0x10);
+        let staking = |nonce: u64, value: U256, data: Vec<u8>| {
+            let mut tx = Transaction::new(
+                staker,
+                Some(STAKING_ADDRESS),
+                value,
+                U256::from(21000),
+                U256::one(),
+                data,
+                U256::from(nonce),
+            );
+            tx.sign(&key);
+            tx
+        };
+        let amount = U256::exp10(17);
+        let fee = U256::from(21000);
+        let balance = chain.get_abby_balance(&staker);
+
+        // Block 1 is mid-epoch: bond twice the amount, unbond half of it
+        let malformed = staking(2, U256::zero(), vec![0x03]);
+        let mut blocks = vec![add_block_at(
+            &mut chain,
+            100,
+            vec![
+                staking(0, amount * 2, StakingOperation::Bond { validator }.encode()),
+                staking(
+                    1,
+                    U256::zero(),
+                    StakingOperation::Unbond { validator, amount }.encode(),
+                ),
+                malformed.clone(),
+            ],
+        )];
+        assert!(!chain.get_receipt(&malformed.hash()).unwrap().status);
+        assert_eq!(
+            chain.get_abby_balance(&staker),
+            balance - amount * 2 - fee * 3
+        );
+        assert_eq!(chain.stake_ledger.bonded[&staker], amount);
+        // Released as of the unbonding block's timestamp, not the wall clock
+        let first_release = blocks[0].header.timestamp.timestamp() as u64 + delay;
+        assert_eq!(chain.stake_ledger.unbonding[0].release_at, first_release);
+        assert!(chain.get_account(&STAKING_ADDRESS).is_none());
+
+        // Block 2 starts an epoch before the delay has passed
+        blocks.push(add_block_at(&mut chain, 200, Vec::new()));
+        assert_eq!(chain.stake_ledger.unbonding.len(), 1);
+
+        // Unbonding more than is bonded unbonds what is left
+        let unbond_all = StakingOperation::Unbond {
+            validator,
+            amount: amount * 2,
+        };
+        blocks.push(add_block_at(
+            &mut chain,
+            100 + delay as i64,
+            vec![staking(3, U256::zero(), unbond_all.encode())],
+        ));
+        assert!(chain.stake_ledger.bonded.is_empty());
+
+        // Block 4 starts an epoch past the first release only
+        blocks.push(add_block_at(&mut chain, 200 + delay as i64, Vec::new()));
+        assert_eq!(chain.get_abby_balance(&staker), balance - amount - fee * 4);
+        assert_eq!(chain.stake_ledger.unbonding.len(), 1);
+        assert!(chain.stake_ledger.unbonding[0].release_at > first_release);
+
+        // Another node replaying the blocks ends in the same state
+        let mut replay = Blockchain::from_genesis(genesis).unwrap();
+        for block in blocks {
+            replay.add_block(block).unwrap();
+        }
+        assert_eq!(replay.stake_ledger, chain.stake_ledger);
+        assert_eq!(replay.state_root(), chain.state_root());
+    }
+
+    #[test]
+    fn test_transactions_are_found_by_hash_and_by_position() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let (first, second) = (zero_value_transfer(1, 0), zero_value_transfer(2, 0));
+        let blocks = extend_chain(
+            &mut chain,
+            2,
+            Address::from_low_u64_be(10),
+            0,
+            vec![first.clone(), second.clone()],
+        );
+        let block_hash = blocks[0].hash();
+
+        assert_eq!(
+            chain.get_transaction(&second.hash()).map(Transaction::hash),
+            Some(second.hash())
+        );
+        assert_eq!(
+            chain.transaction_index.get(&second.hash()),
+            Some(&(block_hash, 1))
+        );
+        assert_eq!(
+            chain
+                .get_transaction_by_block_index(&block_hash, 0)
+                .map(Transaction::hash),
+            Some(first.hash())
+        );
+        assert!(chain
+            .get_transaction_by_block_index(&block_hash, 2)
+            .is_none());
+        assert!(chain
+            .get_transaction_by_block_index(&blocks[1].hash(), 0)
+            .is_none());
+        assert!(chain.get_transaction(&H256::zero()).is_none());
+    }
+
+    #[test]
+    fn test_heavier_side_chain_wins_at_equal_height() {
+        let mut chain = Blockchain::new().unwrap();
+        extend_chain(&mut chain, 2, Address::from_low_u64_be(10), 3, Vec::new());
+
+        let mut rival = Blockchain::new().unwrap();
+        let side = extend_chain(&mut rival, 2, Address::from_low_u64_be(20), 4, Vec::new());
+
+        chain.add_block(side[0].clone()).unwrap();
+        assert_eq!(chain.head_number, 2);
+        assert_ne!(chain.head_hash, side[0].hash());
+
+        chain.add_block(side[1].clone()).unwrap();
+        assert_eq!(chain.head_hash, side[1].hash());
+        assert_eq!(chain.total_difficulty, U256::from(8));
+    }
+
+    #[test]
+    fn test_block_with_transaction_for_another_chain_is_rejected() {
+        let mut chain = Blockchain::new().unwrap();
+        let mut tx = zero_value_transfer(7, 0);
+        tx.sign_for_chain(&SecretKey::from_slice(&[7; 32]).unwrap(), 2);
+
+        let header = BlockHeader::new(
+            1,
+            chain.genesis_hash(),
+            Address::from_low_u64_be(42),
+            U256::from(10_000_000u64),
+        );
+        let mut block = Block::new(header, vec![tx]);
+        block.header.base_fee = chain.get_head_block().unwrap().header.next_base_fee();
+
+        let err = chain.add_block(block).unwrap_err();
+        assert!(err.contains("not signed for chain 1337"), "{}", err);
+        assert_eq!(chain.head_number, 0);
+    }
+
+    #[test]
+    fn test_block_claiming_more_than_the_scheduled_reward_is_rejected() {
+        let mut chain = Blockchain::new().unwrap();
+        let header = BlockHeader::new(
+            1,
+            chain.genesis_hash(),
+            Address::from_low_u64_be(42),
+            U256::from(10_000_000u64),
+        );
+        let mut block = Block::new(header, Vec::new());
+        chain.seal_block(&mut block).unwrap();
+        block.header.abby_reward = chain.reward_schedule.reward_at(1) * 2;
+
+        let err = chain.add_block(block).unwrap_err();
+        assert!(err.contains("Invalid block reward"), "{}", err);
+        assert_eq!(chain.head_number, 0);
+    }
+
+    #[test]
+    fn test_get_logs_filters_by_address_and_topics() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let (a, b) = (Address::from_low_u64_be(0xa), Address::from_low_u64_be(0xb));
+        let topic = H256::from_low_u64_be;
+
+        // a: LOG1(topic 0x11); b: LOG2(topics 0x11, 0x22)
+        chain.accounts.entry(a).or_default().code = hex::decode("601160006000a100").unwrap();
+        chain.accounts.entry(b).or_default().code = hex::decode("6022601160006000a200").unwrap();
+
+        let call = |to: Address, nonce: u64| {
+            let mut tx = zero_value_transfer(7, nonce);
+            tx.to = Some(to);
+            tx.sign(&SecretKey::from_slice(&[7; 32]).unwrap());
+            tx
+        };
+        let proposer = Address::from_low_u64_be(42);
+        extend_chain(&mut chain, 1, proposer, 0, vec![call(a, 0)]);
+        extend_chain(&mut chain, 1, proposer, 0, vec![call(b, 1), call(a, 2)]);
+
+        let by_address = chain.get_logs(0, 10, Some(a), &[]);
+        assert_eq!(by_address.len(), 2);
+        assert!(by_address.iter().all(|entry| entry.log.address == a));
+
+        let by_first_topic = chain.get_logs(0, 10, None, &[Some(topic(0x11))]);
+        assert_eq!(by_first_topic.len(), 3);
+        assert_eq!(by_first_topic[1].log.address, b);
+
+        let combined = chain.get_logs(0, 10, Some(b), &[None, Some(topic(0x22))]);
+        assert_eq!(combined.len(), 1);
+        assert_eq!(combined[0].log.topics, vec![topic(0x11), topic(0x22)]);
+        assert!(chain
+            .get_logs(0, 10, Some(a), &[None, Some(topic(0x22))])
+            .is_empty());
+
+        assert_eq!(chain.get_logs(2, 2, Some(a), &[]).len(), 1);
+    }
+
+    #[test]
+    fn test_receipt_carries_logs_with_their_context() {
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let emitter = Address::from_low_u64_be(0xe);
+        // MSTORE8(0, 0xab), then LOG1(offset 0, size 1, topic 0x77) twice
+        chain.accounts.entry(emitter).or_default().code =
+            hex::decode("60ab600053607760016000a1607760016000a100").unwrap();
+
+        let mut call = zero_value_transfer(7, 0);
+        call.to = Some(emitter);
+        call.sign(&SecretKey::from_slice(&[7; 32]).unwrap());
+        let block = extend_chain(
+            &mut chain,
+            1,
+            Address::from_low_u64_be(42),
+            0,
+            vec![zero_value_transfer(8, 0), call.clone()],
+        )
+        .remove(0);
+
+        let receipt = chain.get_receipt(&call.hash()).unwrap();
+        assert!(receipt.status);
+        assert_eq!(receipt.logs.len(), 2);
+        for (i, entry) in receipt.logs.iter().enumerate() {
+            assert_eq!(entry.log.address, emitter);
+            assert_eq!(entry.log.topics, vec![H256::from_low_u64_be(0x77)]);
+            assert_eq!(entry.log.data, vec![0xab]);
+            assert_eq!(entry.block_hash, block.hash());
+            assert_eq!(entry.block_number, 1);
+            assert_eq!(entry.transaction_hash, call.hash());
+            assert_eq!(entry.transaction_index, 1);
+            assert_eq!(entry.log_index, i as u64);
+        }
+        assert_eq!(chain.get_logs(1, 1, Some(emitter), &[]).len(), 2);
+    }
+
+    #[test]
+    fn test_block_timestamps_must_be_monotonic_and_bounded() {
+        let mut chain = Blockchain::new().unwrap();
+        let proposer = Address::from_low_u64_be(42);
+        let parent = extend_chain(&mut chain, 1, proposer, 0, Vec::new()).remove(0);
+
+        let new_child = |offset: chrono::Duration| {
+            let mut header =
+                BlockHeader::new(2, parent.hash(), proposer, U256::from(10_000_000u64));
+            header.timestamp = parent.header.timestamp + offset;
+            let mut block = Block::new(header, Vec::new());
+            chain.seal_block(&mut block).unwrap();
+            block
+        };
+        let backward = new_child(chrono::Duration::seconds(-1));
+        let same = new_child(chrono::Duration::zero());
+        let far_future = new_child(chrono::Duration::seconds(60));
+        let next = new_child(chrono::Duration::seconds(1));
+
+        assert!(chain.add_block(backward).unwrap_err().contains("not after"));
+        assert!(chain.add_block(same).unwrap_err().contains("not after"));
+        assert!(chain
+            .add_block(far_future)
+            .unwrap_err()
+            .contains("in the future"));
+        assert_eq!(chain.head_number, 1);
+
+        chain.add_block(next).unwrap();
+        assert_eq!(chain.head_number, 2);
+    }
+
+    #[test]
+    fn test_mismatched_stored_genesis_is_rejected() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert(GENESIS_HASH_KEY, H256::repeat_byte(0xab).as_bytes())
+            .unwrap();
+
+        let mut blockchain = Blockchain::new().unwrap();
+        blockchain.db = Some(db);
+
+        assert!(blockchain
+            .verify_stored_genesis()
+            .unwrap_err()
+            .contains("Genesis mismatch"));
+    }
+
+    /// A chain whose accounts 1..=4 each hold 100 wei, and a block of
+    /// `value`-carrying transfers between them. Transfers are free, so only
+    /// their values and the recipients' gas rewards move balances.
+    fn funded_chain_and_block(transfers: &[(u64, u64, u64)]) -> (Blockchain, Block) {
+        let mut chain = Blockchain::new().unwrap();
+        for id in 1..=4 {
+            chain.accounts.insert(
+                Address::from_low_u64_be(id),
+                Account {
+                    balance: U256::from(100),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let transactions = transfers
+            .iter()
+            .map(|&(from, to, value)| {
+                Transaction::new(
+                    Address::from_low_u64_be(from),
+                    Some(Address::from_low_u64_be(to)),
+                    U256::from(value),
+                    U256::from(21000),
+                    U256::zero(),
+                    Vec::new(),
+                    U256::zero(),
+                )
+            })
+            .collect();
+        let header = BlockHeader::new(
+            1,
+            chain.head_hash,
+            Address::zero(),
+            U256::from(10_000_000u64),
+        );
+        (chain, Block::new(header, transactions))
+    }
+
+    fn balance(chain: &Blockchain, id: u64) -> U256 {
+        chain.accounts[&Address::from_low_u64_be(id)].balance
+    }
+
+    #[test]
+    fn test_independent_transfers_match_serial_execution() {
+        let (mut parallel, block) = funded_chain_and_block(&[(1, 2, 30), (3, 4, 70)]);
+        let mut serial = parallel.state_snapshot();
+        assert_eq!(
+            crate::blockchain::parallel::independent_groups(&block.transactions),
+            Some(vec![vec![0], vec![1]])
+        );
+
+        let parallel_receipts = parallel.process_block(&block).unwrap();
+        let serial_receipts = serial
+            .process_block_in_groups(&block, &[vec![0, 1]])
+            .unwrap();
+
+        let reward = parallel_receipts[0].abby_rewards;
+        assert_eq!(balance(&parallel, 2), U256::from(130) + reward);
+        assert_eq!(balance(&parallel, 3), U256::from(30));
+        assert_eq!(parallel.state_root(), serial.state_root());
+        assert_eq!(
+            Block::calculate_receipts_root(&parallel_receipts),
+            Block::calculate_receipts_root(&serial_receipts)
+        );
+    }
+
+    #[test]
+    fn test_conflicting_transfers_run_in_block_order() {
+        // Account 2 can only afford its transfer after receiving from account 1
+        let (mut chain, block) = funded_chain_and_block(&[(1, 2, 50), (2, 3, 150), (4, 1, 10)]);
+        assert_eq!(
+            crate::blockchain::parallel::independent_groups(&block.transactions),
+            Some(vec![vec![0, 1, 2]])
+        );
+
+        let receipts = chain.process_block(&block).unwrap();
+        assert_eq!(receipts.len(), 3);
+        let reward = |index: usize| receipts[index].abby_rewards;
+        assert_eq!(balance(&chain, 1), U256::from(60) + reward(2));
+        assert_eq!(balance(&chain, 2), reward(0));
+        assert_eq!(balance(&chain, 3), U256::from(250) + reward(1));
+
+        // Reversed, the spend comes first and the block is rejected
+        let (mut chain, block) = funded_chain_and_block(&[(2, 3, 150), (1, 2, 50)]);
+        assert_eq!(
+            chain.process_block(&block).unwrap_err(),
+            "Insufficient balance"
+        );
+    }
+
+    #[test]
+    fn test_value_forwarded_by_a_contract_reaches_its_recipient() {
+        // Account 2 forwards whatever it receives: CALL(gas, 5, CALLVALUE, 0, 0, 0, 0)
+        let (mut chain, block) = funded_chain_and_block(&[(1, 2, 40), (3, 4, 10)]);
+        chain
+            .accounts
+            .get_mut(&Address::from_low_u64_be(2))
+            .unwrap()
+            .code = hex::decode("60006000600060003460055af15000").unwrap();
+
+        let receipts = chain.process_block(&block).unwrap();
+        assert!(receipts.iter().all(|receipt| receipt.status));
+        assert_eq!(balance(&chain, 1), U256::from(60));
+        assert_eq!(
+            balance(&chain, 2),
+            U256::from(100) + receipts[0].abby_rewards
+        );
+        assert_eq!(balance(&chain, 5), U256::from(40));
+        assert_eq!(
+            balance(&chain, 4),
+            U256::from(110) + receipts[1].abby_rewards
+        );
+    }
+
+    #[test]
+    fn test_account_left_empty_by_a_block_is_removed_from_chain_state() {
+        // Account 2 calls account 5 with no value, touching it while it is empty
+        let (mut chain, block) = funded_chain_and_block(&[(1, 2, 0)]);
+        chain
+            .accounts
+            .get_mut(&Address::from_low_u64_be(2))
+            .unwrap()
+            .code = hex::decode("60006000600060003460055af15000").unwrap();
+        let empty = Address::from_low_u64_be(5);
+        chain.accounts.insert(empty, Account::default());
+        let root_with_empty = chain.state_root();
+
+        let receipts = chain.process_block(&block).unwrap();
+
+        assert!(receipts[0].status);
+        assert!(!chain.accounts.contains_key(&empty));
+        assert_ne!(chain.state_root(), root_with_empty);
+    }
+
+    #[test]
+    fn test_fee_is_charged_before_execution_and_must_be_covered() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let value = U256::from(1_000);
+        let mut tx = Transaction::new(
+            key_address(7),
+            Some(Address::from_low_u64_be(0xb0b)),
+            value,
+            U256::from(21000),
+            U256::from(10),
+            Vec::new(),
+            U256::zero(),
+        );
+        tx.sign(&key);
+        let fee = U256::from(210_000);
+        assert_eq!(tx.fee(), fee);
+
+        // Enough for the value but one wei short of the fee on top of it
+        let mut chain = Blockchain::new().unwrap();
+        chain.accounts.entry(tx.from).or_default().balance = value + fee - 1;
+        let header = BlockHeader::new(
+            1,
+            chain.head_hash,
+            Address::zero(),
+            U256::from(10_000_000u64),
+        );
+        let block = Block::new(header, vec![tx.clone()]);
+        assert_eq!(
+            chain.process_block(&block).unwrap_err(),
+            "Insufficient balance"
+        );
+
+        // Covering both spends the whole balance, the fee included
+        chain.accounts.entry(tx.from).or_default().balance = value + fee;
+        chain.process_block(&block).unwrap();
+        assert_eq!(chain.get_abby_balance(&tx.from), U256::zero());
+    }
+
+    #[test]
+    fn test_value_transfer_moves_the_same_balance_every_view_reads() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let sender = crate::utils::public_key_to_address(
+            &key.public_key(&secp256k1::Secp256k1::signing_only()),
+        );
+        let recipient = Address::from_low_u64_be(0xb0b);
+        let proposer = Address::from_low_u64_be(42);
+        let mut chain = Blockchain::from_genesis(funded_genesis()).unwrap();
+        let supply = chain.get_total_abby_supply();
+
+        // The sender's genesis Abby tokens are spent as EVM value
+        let funding = U256::exp10(18);
+        assert_eq!(chain.get_account(&sender).unwrap().balance, funding);
+
+        let value = U256::from(1_000_000u64);
+        let mut tx = Transaction::new(
+            sender,
+            Some(recipient),
+            value,
+            U256::from(21000),
+            U256::from(1_000_000_000u64),
+            Vec::new(),
+            U256::zero(),
+        );
+        tx.sign(&key);
+        let fee = tx.abby_fee;
+        extend_chain(&mut chain, 1, proposer, 1, vec![tx.clone()]);
+        let gas_reward = chain.get_receipt(&tx.hash()).unwrap().abby_rewards;
+        let block_reward = chain.reward_schedule.reward_at(1);
+
+        assert_eq!(chain.get_abby_balance(&sender), funding - value - fee);
+        assert_eq!(chain.get_abby_balance(&recipient), value + gas_reward);
+        for address in [sender, recipient, proposer] {
+            assert_eq!(
+                chain.get_abby_balance(&address),
+                chain.get_account(&address).unwrap().balance
+            );
+        }
+        // Fees burn and rewards mint; nothing else creates or destroys tokens
+        assert_eq!(
+            chain.get_total_abby_supply(),
+            supply - fee + gas_reward + block_reward
+        );
+    }
+}
@@ -1,10 +1,11 @@
-use crate::blockchain::Block;
-use ethereum_types::{Address, U256};
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
+use crate::blockchain::{Block, StakingManager, DEFAULT_ANNUAL_REWARD_RATE};
+use ethereum_types::{Address, H256, U256};
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+
+/// Validators allowed to leave the active set per epoch by default
+pub const DEFAULT_MAX_EXITS_PER_EPOCH: usize = 4;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Validator {
@@ -66,6 +67,17 @@ pub struct ConsensusState {
     pub slots_per_epoch: u64,
     pub block_time: u64, // seconds
     pub total_stake: U256,
+    /// Current staking reward rate in basis points, refreshed by
+    /// `StakingManager` whenever stake moves; epoch rewards scale with it
+    pub staking_reward_rate: u32,
+    /// RANDAO accumulator: every block mixes in its proposer's reveal, and
+    /// proposer selection draws from it instead of the head hash
+    pub randao_mix: H256,
+    /// Validators waiting to leave, oldest request first. They stay in the
+    /// set, and keep proposing, until their turn comes at an epoch boundary.
+    pub exit_queue: VecDeque<Address>,
+    /// How many queued validators leave at each epoch boundary
+    pub max_exits_per_epoch: usize,
 }
 
 impl ConsensusState {
@@ -77,6 +89,10 @@ impl ConsensusState {
             slots_per_epoch: 32,
             block_time: 12, // 12 seconds per block (like Ethereum 2.0)
             total_stake: U256::zero(),
+            staking_reward_rate: DEFAULT_ANNUAL_REWARD_RATE,
+            randao_mix: H256::zero(),
+            exit_queue: VecDeque::new(),
+            max_exits_per_epoch: DEFAULT_MAX_EXITS_PER_EPOCH,
         }
     }
 
@@ -90,6 +106,38 @@ impl ConsensusState {
         Ok(())
     }
 
+    /// Queue a validator to leave the set. It stays active until
+    /// `advance_epoch` reaches it, `max_exits_per_epoch` at a time.
+    pub fn request_exit(&mut self, address: &Address) -> Result<(), String> {
+        if !self.validators.contains_key(address) {
+            return Err("Validator not found".to_string());
+        }
+        if self.exit_queue.contains(address) {
+            return Err("Validator is already queued to exit".to_string());
+        }
+        self.exit_queue.push_back(*address);
+        Ok(())
+    }
+
+    /// Remove the validators whose turn in the exit queue has come,
+    /// returning their addresses
+    fn process_exit_queue(&mut self) -> Vec<Address> {
+        let count = self.max_exits_per_epoch.min(self.exit_queue.len());
+        let exited: Vec<Address> = self.exit_queue.drain(..count).collect();
+        for address in &exited {
+            // Validators removed directly while queued are already gone
+            let _ = self.remove_validator(address);
+            log::info!(
+                "Validator {} exited at epoch {}",
+                address,
+                self.current_epoch
+            );
+        }
+        exited
+    }
+
+    /// Drop a validator from the set immediately. Voluntary exits should go
+    /// through `request_exit` so the set can't shrink all at once.
     pub fn remove_validator(&mut self, address: &Address) -> Result<(), String> {
         if let Some(validator) = self.validators.remove(address) {
             self.total_stake = self.total_stake.saturating_sub(validator.stake);
@@ -100,7 +148,7 @@ impl ConsensusState {
     }
 
     pub fn select_proposer(&self, slot: u64, randomness: &[u8]) -> Option<Address> {
-        let active_validators: Vec<&Validator> = self
+        let mut active_validators: Vec<&Validator> = self
             .validators
             .values()
             .filter(|v| v.is_active && v.stake >= Validator::minimum_stake())
@@ -109,6 +157,8 @@ impl ConsensusState {
         if active_validators.is_empty() {
             return None;
         }
+        // HashMap order differs between nodes; every node must walk the same list
+        active_validators.sort_by_key(|v| v.address);
 
         // Use randomness + slot to deterministically select proposer
         let mut hasher = Keccak256::new();
@@ -116,34 +166,15 @@ impl ConsensusState {
         hasher.update(slot.to_be_bytes());
         let hash = hasher.finalize();
 
-        // Convert hash to seed for RNG
-        let seed = u64::from_be_bytes([
-            hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7],
-        ]);
-
-        let mut rng = StdRng::seed_from_u64(seed);
-
-        // Use modular arithmetic to handle large stakes
-        // We'll work with stake percentages rather than absolute values
-        let total_stake_u64 = if self.total_stake > U256::from(u64::MAX) {
-            // For very large stakes, use the last 64 bits
-            (self.total_stake % U256::from(u64::MAX)).as_u64()
-        } else {
-            self.total_stake.as_u64()
-        };
+        // Stakes are in wei, well beyond u64, so draw and sum in U256
+        let total_stake = active_validators
+            .iter()
+            .fold(U256::zero(), |total, v| total.saturating_add(v.stake));
+        let random_stake = U256::from_big_endian(&hash) % total_stake;
 
-        let random_stake = rng.gen_range(0..total_stake_u64.max(1));
-
-        let mut cumulative_stake = 0u64;
+        let mut cumulative_stake = U256::zero();
         for validator in &active_validators {
-            let validator_stake = if validator.stake > U256::from(u64::MAX) {
-                // For very large stakes, use the last 64 bits
-                (validator.stake % U256::from(u64::MAX)).as_u64()
-            } else {
-                validator.stake.as_u64()
-            };
-
-            cumulative_stake += validator_stake;
+            cumulative_stake = cumulative_stake.saturating_add(validator.stake);
             if cumulative_stake > random_stake {
                 return Some(validator.address);
             }
@@ -153,6 +184,34 @@ impl ConsensusState {
         active_validators.first().map(|v| v.address)
     }
 
+    /// Proposer for the current slot, drawn from the RANDAO mix
+    pub fn current_proposer(&self) -> Option<Address> {
+        self.select_proposer(self.current_slot, self.randao_mix.as_bytes())
+    }
+
+    /// A proposer's RANDAO contribution for `epoch`. It is fixed per
+    /// validator and epoch, so a proposer can withhold a block but not
+    /// grind the value it mixes in
+    pub fn randao_reveal(proposer: &Address, epoch: u64) -> H256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(proposer.as_bytes());
+        hasher.update(epoch.to_be_bytes());
+        H256::from_slice(&hasher.finalize())
+    }
+
+    /// Mix a block proposer's reveal into the accumulator
+    pub fn mix_randao(&mut self, proposer: &Address, reveal: &[u8]) -> Result<(), String> {
+        if reveal != Self::randao_reveal(proposer, self.current_epoch).as_bytes() {
+            return Err("Invalid RANDAO reveal".to_string());
+        }
+
+        let mut hasher = Keccak256::new();
+        hasher.update(self.randao_mix.as_bytes());
+        hasher.update(reveal);
+        self.randao_mix = H256::from_slice(&hasher.finalize());
+        Ok(())
+    }
+
     pub fn validate_proposal(&self, block: &Block, proposer: &Address) -> Result<(), String> {
         let validator = self
             .validators
@@ -171,28 +230,35 @@ impl ConsensusState {
         block.validate()?;
 
         // Check if proposer is the expected one for this slot
-        let expected_proposer =
-            self.select_proposer(self.current_slot, &block.hash().as_bytes()[0..8]);
-
-        if expected_proposer != Some(*proposer) {
+        if self.current_proposer() != Some(*proposer) {
             return Err("Unexpected proposer for this slot".to_string());
         }
 
+        // The block carries the proposer's RANDAO reveal
+        if block.header.extra_data != Self::randao_reveal(proposer, self.current_epoch).as_bytes() {
+            return Err("Invalid RANDAO reveal".to_string());
+        }
+
         Ok(())
     }
 
-    pub fn advance_slot(&mut self) {
+    /// Move to the next slot. Epoch rewards at a boundary are minted through
+    /// `staking`, under the same supply cap as staking rewards.
+    pub fn advance_slot(&mut self, staking: &mut StakingManager) {
         self.current_slot += 1;
         if self.current_slot.is_multiple_of(self.slots_per_epoch) {
-            self.advance_epoch();
+            self.advance_epoch(staking);
         }
     }
 
-    pub fn advance_epoch(&mut self) {
+    pub fn advance_epoch(&mut self, staking: &mut StakingManager) {
         self.current_epoch += 1;
 
         // Distribute rewards at epoch end
-        self.distribute_epoch_rewards();
+        self.distribute_epoch_rewards(staking);
+
+        // Let the next batch of queued validators leave
+        self.process_exit_queue();
 
         // Update validator activity
         for validator in self.validators.values_mut() {
@@ -205,19 +271,27 @@ impl ConsensusState {
         }
     }
 
-    fn distribute_epoch_rewards(&mut self) {
+    fn distribute_epoch_rewards(&mut self, staking: &mut StakingManager) {
         if self.total_stake == U256::zero() {
             return;
         }
 
-        // Total rewards per epoch: 1000 Abby tokens
-        let total_rewards = U256::from_dec_str("1000000000000000000000").unwrap(); // 1k rewards
+        // Total rewards per epoch: 1000 Abby tokens at the base reward rate,
+        // scaled by the current rate. Minting pays less once the supply cap
+        // is near and nothing after it is reached.
+        let total_rewards = U256::from_dec_str("1000000000000000000000") // 1k rewards
+            .unwrap()
+            * U256::from(self.staking_reward_rate)
+            / U256::from(DEFAULT_ANNUAL_REWARD_RATE);
 
         for validator in self.validators.values_mut() {
             if validator.is_active {
                 // Reward proportional to stake
                 let validator_reward = total_rewards * validator.stake / self.total_stake;
-                validator.reward(validator_reward);
+                let Ok(minted) = staking.mint_abby_tokens(validator_reward) else {
+                    break;
+                };
+                validator.reward(minted);
             }
         }
     }
@@ -240,7 +314,9 @@ impl ConsensusState {
     pub fn get_top_validators(&self, limit: usize) -> Vec<&Validator> {
         let mut validators: Vec<&Validator> =
             self.validators.values().filter(|v| v.is_active).collect();
-        validators.sort_by(|a, b| b.stake.cmp(&a.stake));
+        // Highest stake first; equal stakes fall back to address order so the
+        // result doesn't depend on HashMap iteration order
+        validators.sort_by(|a, b| b.stake.cmp(&a.stake).then(a.address.cmp(&b.address)));
         validators.into_iter().take(limit).collect()
     }
 }
@@ -278,3 +354,123 @@ impl Attestation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_validators_break_ties_by_address() {
+        let stake = Validator::minimum_stake();
+        let mut consensus = ConsensusState::new();
+        for byte in [0x30, 0x10, 0x20] {
+            consensus
+                .add_validator(Validator::new(Address::repeat_byte(byte), stake))
+                .unwrap();
+        }
+        consensus
+            .add_validator(Validator::new(Address::repeat_byte(0x40), stake * 2))
+            .unwrap();
+
+        let order: Vec<Address> = consensus
+            .get_top_validators(4)
+            .iter()
+            .map(|v| v.address)
+            .collect();
+
+        assert_eq!(
+            order,
+            vec![
+                Address::repeat_byte(0x40),
+                Address::repeat_byte(0x10),
+                Address::repeat_byte(0x20),
+                Address::repeat_byte(0x30),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_queued_exits_leave_a_few_per_epoch() {
+        let mut consensus = ConsensusState::new();
+        consensus.slots_per_epoch = 4;
+        consensus.max_exits_per_epoch = 2;
+        // Enough stake to stay active through the inactivity penalty
+        for byte in 1..=6 {
+            consensus
+                .add_validator(Validator::new(
+                    Address::repeat_byte(byte),
+                    Validator::minimum_stake() * 2,
+                ))
+                .unwrap();
+        }
+        for byte in 1..=5 {
+            consensus.request_exit(&Address::repeat_byte(byte)).unwrap();
+        }
+        assert!(consensus.request_exit(&Address::repeat_byte(1)).is_err());
+
+        // Nobody leaves before the epoch boundary
+        assert_eq!(consensus.total_active_validators(), 6);
+
+        let mut staking = StakingManager::new();
+        let mut remaining = Vec::new();
+        for _ in 0..3 {
+            for _ in 0..consensus.slots_per_epoch {
+                consensus.advance_slot(&mut staking);
+            }
+            remaining.push(consensus.total_active_validators());
+        }
+        assert_eq!(remaining, vec![4, 2, 1]);
+        assert!(consensus.exit_queue.is_empty());
+        assert!(consensus.get_validator(&Address::repeat_byte(6)).is_some());
+    }
+
+    #[test]
+    fn test_randao_mix_drives_proposer_selection() {
+        let mut consensus = ConsensusState::new();
+        consensus.slots_per_epoch = 4;
+        for byte in 1..=8 {
+            consensus
+                .add_validator(Validator::new(
+                    Address::repeat_byte(byte),
+                    Validator::minimum_stake(),
+                ))
+                .unwrap();
+        }
+        let start = consensus.clone();
+
+        let run = |consensus: &mut ConsensusState| {
+            let mut staking = StakingManager::new();
+            let mut mixes = vec![consensus.randao_mix];
+            let mut proposers = Vec::new();
+            for _ in 0..12 {
+                let proposer = consensus.current_proposer().unwrap();
+                let reveal = ConsensusState::randao_reveal(&proposer, consensus.current_epoch);
+                consensus.mix_randao(&proposer, reveal.as_bytes()).unwrap();
+                consensus.advance_slot(&mut staking);
+                mixes.push(consensus.randao_mix);
+                proposers.push(proposer);
+            }
+            (mixes, proposers)
+        };
+
+        // The accumulator changes with every block
+        let (mixes, proposers) = run(&mut consensus);
+        let mut distinct = mixes.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), mixes.len());
+
+        // Replaying the same reveals selects the same proposers
+        assert_eq!(run(&mut start.clone()), (mixes, proposers.clone()));
+
+        // A different mix leads to a different schedule
+        let mut other = start.clone();
+        other.randao_mix = H256::repeat_byte(0xab);
+        assert_ne!(run(&mut other).1, proposers);
+
+        // A proposer can only mix in its own reveal for the current epoch
+        let proposer = Address::repeat_byte(1);
+        let stale = ConsensusState::randao_reveal(&proposer, consensus.current_epoch - 1);
+        assert!(consensus.mix_randao(&proposer, stale.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::blockchain::{BlockLimits, ABBY_CHAIN_ID};
+use ethereum_types::{Address, U256};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Initial Abby token balance of a genesis account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub balance: U256,
+}
+
+/// Validator registered with its stake before the first block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenesisValidator {
+    pub address: Address,
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub stake: U256,
+}
+
+/// Genesis allocation and chain parameters, usually read from `genesis.json`.
+/// Fields missing from the file keep their default values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GenesisConfig {
+    pub chain_id: u64,
+    /// Seconds between blocks
+    pub block_time: u64,
+    pub slots_per_epoch: u64,
+    /// Seconds unbonded stake waits, from the block that unbonded it, before
+    /// an epoch-boundary block pays it back
+    pub withdrawal_delay: u64,
+    pub alloc: BTreeMap<Address, GenesisAccount>,
+    pub validators: Vec<GenesisValidator>,
+    /// Transaction count and byte size allowed per block
+    pub block_limits: BlockLimits,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        let tokens = |amount: u64| U256::from(amount) * U256::exp10(18);
+        let alloc = [(1, 100_000), (2, 50_000), (3, 25_000)]
+            .into_iter()
+            .map(|(address, amount)| {
+                (
+                    Address::from_low_u64_be(address),
+                    GenesisAccount {
+                        balance: tokens(amount),
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            chain_id: ABBY_CHAIN_ID,
+            block_time: 12, // 12 seconds per block (like Ethereum 2.0)
+            slots_per_epoch: 32,
+            withdrawal_delay: 7 * 24 * 3600, // 7 days
+            alloc,
+            validators: Vec::new(),
+            block_limits: BlockLimits::default(),
+        }
+    }
+}
+
+impl GenesisConfig {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read genesis file {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let config: Self =
+            serde_json::from_str(json).map_err(|e| format!("Invalid genesis config: {}", e))?;
+        if config.block_time == 0 {
+            return Err("Invalid genesis config: blockTime must be positive".to_string());
+        }
+        if config.slots_per_epoch == 0 {
+            return Err("Invalid genesis config: slotsPerEpoch must be positive".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Token amounts are written as strings, either decimal or `0x`-prefixed hex
+fn deserialize_amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(&text).ok(),
+    };
+    parsed.ok_or_else(|| serde::de::Error::custom(format!("invalid amount: {}", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+
+    #[test]
+    fn test_genesis_file_sets_balances_and_chain_id() {
+        let path =
+            std::env::temp_dir().join(format!("abby-genesis-{}.json", rand::random::<u64>()));
+        std::fs::write(
+            &path,
+            r#"{
+                "chainId": 4242,
+                "blockTime": 5,
+                "alloc": {
+                    "0x00000000000000000000000000000000000000aa": { "balance": "1000" },
+                    "0x00000000000000000000000000000000000000bb": { "balance": "0x10" }
+                },
+                "validators": [
+                    { "address": "0x00000000000000000000000000000000000000aa", "stake": "32000000000000000000" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let config = GenesisConfig::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let config = config.unwrap();
+
+        assert_eq!(config.chain_id, 4242);
+        assert_eq!(config.block_time, 5);
+        assert_eq!(config.slots_per_epoch, 32);
+        assert_eq!(config.validators.len(), 1);
+
+        let chain = Blockchain::from_genesis(config).unwrap();
+        assert_eq!(chain.genesis.chain_id, 4242);
+        assert_eq!(
+            chain.get_abby_balance(&Address::from_low_u64_be(0xaa)),
+            U256::from(1000)
+        );
+        assert_eq!(
+            chain.get_abby_balance(&Address::from_low_u64_be(0xbb)),
+            U256::from(16)
+        );
+        assert_eq!(
+            chain.get_abby_balance(&Address::from_low_u64_be(1)),
+            U256::zero()
+        );
+        assert_ne!(
+            chain.genesis_hash(),
+            Blockchain::new().unwrap().genesis_hash()
+        );
+    }
+}
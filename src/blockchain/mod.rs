@@ -1,14 +1,18 @@
 pub mod block;
 pub mod chain;
 pub mod consensus;
+pub mod genesis;
 pub mod network;
 pub mod node;
+pub mod parallel;
+pub mod rpc;
 pub mod staking;
 pub mod transaction;
 
 pub use block::*;
 pub use chain::*;
 pub use consensus::*;
+pub use genesis::*;
 pub use node::*;
 pub use staking::*;
 pub use transaction::*;
@@ -1,12 +1,55 @@
 use crate::blockchain::{
-    network::{NetworkManager, NetworkMessage, SyncManager},
-    Block, BlockHeader, Blockchain, ConsensusState, StakingManager, Transaction, TransactionPool,
+    network::{
+        IncomingMessage, NetworkManager, NetworkMessage, SyncManager, INVALID_SIGNATURE_PENALTY,
+    },
+    Block, BlockHeader, Blockchain, ConsensusState, GenesisConfig, StakingManager,
+    StakingOperation, Transaction, TransactionPool, STAKING_ADDRESS,
 };
 use ethereum_types::{Address, H256, U256};
+use secp256k1::{Secp256k1, SecretKey};
 use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tokio::time::{interval, Duration};
 
+/// Pending transactions older than this many seconds are pruned from the pool
+const TX_POOL_MAX_AGE: u64 = 3 * 60 * 60;
+/// Events a subscriber may fall behind by before it starts missing some
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+/// Most recent blocks whose transactions `suggest_gas_price` samples
+const GAS_PRICE_SAMPLE_BLOCKS: u64 = 20;
+/// Percentile of the sampled gas prices that gets suggested
+const GAS_PRICE_PERCENTILE: usize = 60;
+/// Default lowest gas price to suggest: 1 gwei
+pub const DEFAULT_MIN_GAS_PRICE: u64 = 1_000_000_000;
+
+/// Notifications pushed to `AbbyNode::subscribe` receivers
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    /// The canonical head moved to this block
+    NewHead(Box<Block>),
+    /// A transaction with this hash was accepted into the pool
+    PendingTransaction(H256),
+}
+
+/// Gas price for wallets to offer: the 60th percentile of the prices paid in
+/// the last 20 blocks of `blockchain`, never below `min_gas_price`
+pub fn suggest_gas_price(blockchain: &Blockchain, min_gas_price: U256) -> U256 {
+    let oldest = blockchain
+        .head_number
+        .saturating_sub(GAS_PRICE_SAMPLE_BLOCKS - 1);
+    let mut prices: Vec<U256> = (oldest..=blockchain.head_number)
+        .filter_map(|number| blockchain.get_block_by_number(number))
+        .flat_map(|block| block.transactions.iter().map(|tx| tx.gas_price))
+        .collect();
+    if prices.is_empty() {
+        return min_gas_price;
+    }
+
+    prices.sort();
+    let index = (prices.len() - 1) * GAS_PRICE_PERCENTILE / 100;
+    prices[index].max(min_gas_price)
+}
+
 pub struct AbbyNode {
     pub blockchain: Arc<RwLock<Blockchain>>,
     pub consensus: Arc<RwLock<ConsensusState>>,
@@ -17,6 +60,9 @@ pub struct AbbyNode {
     pub validator_address: Option<Address>,
     pub is_mining: Arc<Mutex<bool>>,
     pub node_id: String,
+    pub events: broadcast::Sender<NodeEvent>,
+    /// Floor for `suggest_gas_price`, also used when recent blocks are empty
+    pub min_gas_price: U256,
 }
 
 impl AbbyNode {
@@ -24,24 +70,42 @@ impl AbbyNode {
         validator_address: Option<Address>,
         network_port: u16,
         db_path: Option<&str>,
+        genesis: GenesisConfig,
     ) -> Result<Self, Box<dyn std::error::Error>> {
+        let chain_id = genesis.chain_id;
+        let withdrawal_delay = genesis.withdrawal_delay;
+        let genesis_validators = genesis.validators.clone();
+
+        // Initialize consensus
+        let mut consensus_state = ConsensusState::new();
+        consensus_state.block_time = genesis.block_time;
+        consensus_state.slots_per_epoch = genesis.slots_per_epoch;
+
         // Initialize blockchain
         let blockchain = if let Some(path) = db_path {
-            Arc::new(RwLock::new(Blockchain::new_with_persistence(path)?))
+            Arc::new(RwLock::new(Blockchain::new_with_persistence(
+                path, genesis,
+            )?))
         } else {
             Arc::new(RwLock::new(
-                Blockchain::new().map_err(|e| format!("Failed to create blockchain: {}", e))?,
+                Blockchain::from_genesis(genesis)
+                    .map_err(|e| format!("Failed to create blockchain: {}", e))?,
             ))
         };
 
-        // Initialize consensus
-        let consensus = Arc::new(RwLock::new(ConsensusState::new()));
-
-        // Initialize staking
-        let staking = Arc::new(RwLock::new(StakingManager::new()));
+        // Initialize staking, registering the genesis validator set
+        let mut staking_manager = StakingManager::new();
+        staking_manager.withdrawal_delay = withdrawal_delay;
+        for validator in genesis_validators {
+            staking_manager
+                .create_validator(validator.address, validator.stake, &mut consensus_state)
+                .map_err(|e| format!("Invalid genesis validator {:?}: {}", validator.address, e))?;
+        }
+        let consensus = Arc::new(RwLock::new(consensus_state));
+        let staking = Arc::new(RwLock::new(staking_manager));
 
         // Initialize transaction pool
-        let tx_pool = Arc::new(Mutex::new(TransactionPool::new()));
+        let tx_pool = Arc::new(Mutex::new(TransactionPool::new().with_chain_id(chain_id)));
 
         // Initialize network
         let mut network_manager = NetworkManager::new()?;
@@ -63,17 +127,107 @@ impl AbbyNode {
             validator_address,
             is_mining: Arc::new(Mutex::new(false)),
             node_id,
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            min_gas_price: U256::from(DEFAULT_MIN_GAS_PRICE),
         };
 
         // If we have a validator address, add it to consensus
         if let Some(addr) = validator_address {
-            node.initialize_validator(addr).await?;
+            if !node.consensus.read().await.validators.contains_key(&addr) {
+                node.initialize_validator(addr).await?;
+            }
         }
 
         log::info!("AbbyNode {} initialized", node.node_id);
         Ok(node)
     }
 
+    /// Receive an event for every new head block and pending transaction
+    /// from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Add a block from outside the mining loop, notifying subscribers if
+    /// it becomes the new head
+    pub async fn import_block(&self, block: Block) -> Result<(), String> {
+        Self::add_block_and_purge(&self.blockchain, &self.tx_pool, &self.events, block).await
+    }
+
+    pub(crate) async fn add_block_and_notify(
+        blockchain: &Arc<RwLock<Blockchain>>,
+        events: &broadcast::Sender<NodeEvent>,
+        block: Block,
+    ) -> Result<(), String> {
+        let mut blockchain_write = blockchain.write().await;
+        let previous_head = blockchain_write.head_hash;
+        blockchain_write.add_block(block)?;
+
+        if blockchain_write.head_hash != previous_head {
+            if let Some(head) = blockchain_write.get_head_block() {
+                // Sending only fails when nobody is subscribed
+                let _ = events.send(NodeEvent::NewHead(Box::new(head.clone())));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a transaction to the pool, which rejects nonces the sender has
+    /// already used on chain
+    pub(crate) async fn add_transaction_and_notify(
+        blockchain: &Arc<RwLock<Blockchain>>,
+        tx_pool: &Arc<Mutex<TransactionPool>>,
+        events: &broadcast::Sender<NodeEvent>,
+        transaction: Transaction,
+    ) -> Result<(), String> {
+        let tx_hash = transaction.hash();
+        let (account_nonce, balance) = {
+            let blockchain = blockchain.read().await;
+            (
+                blockchain.get_nonce(&transaction.from),
+                blockchain.get_abby_balance(&transaction.from),
+            )
+        };
+        // A block carrying it would be rejected, so don't let a miner pick it
+        if balance < transaction.value.saturating_add(transaction.fee()) {
+            return Err("Insufficient balance for value and fee".to_string());
+        }
+        let mut tx_pool = tx_pool.lock().await;
+        tx_pool.set_account_nonce(transaction.from, account_nonce);
+        tx_pool.add_transaction(transaction)?;
+        drop(tx_pool);
+        let _ = events.send(NodeEvent::PendingTransaction(tx_hash));
+        Ok(())
+    }
+
+    /// Tell the pool the on-chain nonces of the senders in `transactions`,
+    /// once their block was added, so it drops what the block made stale:
+    /// the included transactions and any same-nonce competitors
+    async fn purge_spent_nonces(
+        blockchain: &Arc<RwLock<Blockchain>>,
+        tx_pool: &Arc<Mutex<TransactionPool>>,
+        transactions: &[Transaction],
+    ) {
+        let blockchain = blockchain.read().await;
+        let mut tx_pool = tx_pool.lock().await;
+        for tx in transactions {
+            tx_pool.set_account_nonce(tx.from, blockchain.get_nonce(&tx.from));
+        }
+    }
+
+    /// Add a block from a peer or an import and purge the pool of what it spent
+    async fn add_block_and_purge(
+        blockchain: &Arc<RwLock<Blockchain>>,
+        tx_pool: &Arc<Mutex<TransactionPool>>,
+        events: &broadcast::Sender<NodeEvent>,
+        block: Block,
+    ) -> Result<(), String> {
+        let transactions = block.transactions.clone();
+        Self::add_block_and_notify(blockchain, events, block).await?;
+        Self::purge_spent_nonces(blockchain, tx_pool, &transactions).await;
+        Ok(())
+    }
+
     async fn initialize_validator(
         &self,
         address: Address,
@@ -115,13 +269,16 @@ impl AbbyNode {
     async fn start_mining(&self) {
         let blockchain = Arc::clone(&self.blockchain);
         let consensus = Arc::clone(&self.consensus);
+        let staking = Arc::clone(&self.staking);
         let tx_pool = Arc::clone(&self.tx_pool);
         let network = Arc::clone(&self.network);
+        let events = self.events.clone();
         let is_mining = Arc::clone(&self.is_mining);
         let validator_address = self.validator_address;
+        let block_time = self.consensus.read().await.block_time;
 
         tokio::spawn(async move {
-            let mut mining_interval = interval(Duration::from_secs(12)); // 12 second block time
+            let mut mining_interval = interval(Duration::from_secs(block_time));
 
             loop {
                 mining_interval.tick().await;
@@ -136,8 +293,10 @@ impl AbbyNode {
                     if let Err(e) = Self::mine_block(
                         &blockchain,
                         &consensus,
+                        &staking,
                         &tx_pool,
                         &network,
+                        &events,
                         validator_addr,
                     )
                     .await
@@ -155,21 +314,21 @@ impl AbbyNode {
     async fn mine_block(
         blockchain: &Arc<RwLock<Blockchain>>,
         consensus: &Arc<RwLock<ConsensusState>>,
+        staking: &Arc<RwLock<StakingManager>>,
         tx_pool: &Arc<Mutex<TransactionPool>>,
         network: &Arc<Mutex<NetworkManager>>,
+        events: &broadcast::Sender<NodeEvent>,
         validator_address: Address,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let blockchain_read = blockchain.read().await;
         let consensus_read = consensus.read().await;
 
         // Check if we're the selected proposer for this slot
-        let current_slot = consensus_read.current_slot;
-        let randomness = blockchain_read.head_hash.as_bytes();
-        let selected_proposer = consensus_read.select_proposer(current_slot, randomness);
-
-        if selected_proposer != Some(validator_address) {
+        if consensus_read.current_proposer() != Some(validator_address) {
             return Ok(()); // Not our turn to propose
         }
+        let randao_reveal =
+            ConsensusState::randao_reveal(&validator_address, consensus_read.current_epoch);
 
         drop(consensus_read);
 
@@ -181,19 +340,30 @@ impl AbbyNode {
 
         let head_hash = head_block.hash();
         let next_number = head_block.header.number + 1;
+        let reward_schedule = blockchain_read.reward_schedule.clone();
+        let block_limits = blockchain_read.genesis.block_limits;
         drop(blockchain_read);
 
         // Select transactions from pool
         let tx_pool_lock = tx_pool.lock().await;
         let gas_limit = U256::from(10_000_000u64); // 10M gas limit
-        let transactions = tx_pool_lock.select_transactions_for_block(gas_limit);
+        let transactions = tx_pool_lock.select_transactions_for_block(gas_limit, &block_limits);
         drop(tx_pool_lock);
 
-        // Create block header
-        let header = BlockHeader::new(next_number, head_hash, validator_address, gas_limit);
+        // Create block header carrying our RANDAO reveal
+        let mut header = BlockHeader::with_reward_schedule(
+            next_number,
+            head_hash,
+            validator_address,
+            gas_limit,
+            &reward_schedule,
+        );
+        header.extra_data = randao_reveal.as_bytes().to_vec();
 
-        // Create block
-        let block = Block::new(header, transactions.clone());
+        // Create block; sealing sets its base fee from the head's gas usage
+        // and commits to the state it produces
+        let mut block = Block::new(header, transactions.clone());
+        blockchain.read().await.seal_block(&mut block)?;
 
         // Validate with consensus
         let consensus_read = consensus.read().await;
@@ -201,26 +371,24 @@ impl AbbyNode {
         drop(consensus_read);
 
         // Add block to blockchain
-        let mut blockchain_write = blockchain.write().await;
-        blockchain_write.add_block(block.clone())?;
-        drop(blockchain_write);
+        Self::add_block_and_notify(blockchain, events, block.clone()).await?;
 
-        // Remove processed transactions from pool
-        let mut tx_pool_lock = tx_pool.lock().await;
-        for tx in &transactions {
-            tx_pool_lock.remove_transaction(&tx.hash());
-        }
-        drop(tx_pool_lock);
+        // Remove processed transactions, and competitors for their nonces,
+        // from the pool
+        Self::purge_spent_nonces(blockchain, tx_pool, &transactions).await;
 
         // Broadcast block to network
         let mut network_lock = network.lock().await;
         network_lock.broadcast_block(block.clone())?;
         drop(network_lock);
 
-        // Advance consensus slot
+        // Mix our reveal into the RANDAO accumulator and advance the slot
+        let mut staking_write = staking.write().await;
         let mut consensus_write = consensus.write().await;
-        consensus_write.advance_slot();
+        consensus_write.mix_randao(&validator_address, &block.header.extra_data)?;
+        consensus_write.advance_slot(&mut staking_write);
         drop(consensus_write);
+        drop(staking_write);
 
         log::info!(
             "Mined block #{} with {} transactions",
@@ -236,6 +404,7 @@ impl AbbyNode {
         let tx_pool = Arc::clone(&self.tx_pool);
         let sync_manager = Arc::clone(&self.sync_manager);
         let network: Arc<Mutex<NetworkManager>> = Arc::clone(&self.network);
+        let events = self.events.clone();
 
         tokio::spawn(async move {
             let mut network_lock = network.lock().await;
@@ -252,6 +421,7 @@ impl AbbyNode {
                     &tx_pool,
                     &sync_manager,
                     &network,
+                    &events,
                 )
                 .await
                 {
@@ -262,18 +432,37 @@ impl AbbyNode {
     }
 
     async fn handle_network_message(
-        message: NetworkMessage,
+        incoming: IncomingMessage,
         blockchain: &Arc<RwLock<Blockchain>>,
         tx_pool: &Arc<Mutex<TransactionPool>>,
         _sync_manager: &Arc<Mutex<SyncManager>>,
-        _network: &Arc<Mutex<NetworkManager>>,
+        network: &Arc<Mutex<NetworkManager>>,
+        events: &broadcast::Sender<NodeEvent>,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let IncomingMessage {
+            connection_id,
+            signed,
+        } = incoming;
+        if network.lock().await.is_banned(&connection_id) {
+            return Err(format!("Dropped message from banned peer {}", connection_id).into());
+        }
+        let message = match signed.verify() {
+            Ok(message) => message,
+            Err(e) => {
+                network
+                    .lock()
+                    .await
+                    .penalize_peer(&connection_id, INVALID_SIGNATURE_PENALTY);
+                return Err(format!("Dropped message from peer {}: {}", connection_id, e).into());
+            }
+        };
+
         match message {
             NetworkMessage::NewBlock(block) => {
                 log::info!("Received new block #{}", block.header.number);
 
-                let mut blockchain_write = blockchain.write().await;
-                if let Err(e) = blockchain_write.add_block(block) {
+                if let Err(e) = Self::add_block_and_purge(blockchain, tx_pool, events, block).await
+                {
                     log::warn!("Failed to add received block: {}", e);
                 }
             }
@@ -281,8 +470,9 @@ impl AbbyNode {
             NetworkMessage::NewTransaction(transaction) => {
                 log::debug!("Received new transaction {}", transaction.hash());
 
-                let mut tx_pool_lock = tx_pool.lock().await;
-                if let Err(e) = tx_pool_lock.add_transaction(transaction) {
+                if let Err(e) =
+                    Self::add_transaction_and_notify(blockchain, tx_pool, events, transaction).await
+                {
                     log::warn!("Failed to add received transaction: {}", e);
                 }
             }
@@ -299,8 +489,9 @@ impl AbbyNode {
 
             NetworkMessage::BlockResponse { block } => {
                 if let Some(block) = block {
-                    let mut blockchain_write = blockchain.write().await;
-                    if let Err(e) = blockchain_write.add_block(block) {
+                    if let Err(e) =
+                        Self::add_block_and_purge(blockchain, tx_pool, events, block).await
+                    {
                         log::warn!("Failed to add block from response: {}", e);
                     }
                 }
@@ -339,9 +530,10 @@ impl AbbyNode {
             }
 
             NetworkMessage::SyncResponse { blocks } => {
-                let mut blockchain_write = blockchain.write().await;
                 for block in blocks {
-                    if let Err(e) = blockchain_write.add_block(block) {
+                    if let Err(e) =
+                        Self::add_block_and_purge(blockchain, tx_pool, events, block).await
+                    {
                         log::warn!("Failed to add sync block: {}", e);
                     }
                 }
@@ -395,7 +587,7 @@ impl AbbyNode {
 
     async fn start_periodic_tasks(&self) {
         let staking = Arc::clone(&self.staking);
-        let _consensus = Arc::clone(&self.consensus);
+        let tx_pool = Arc::clone(&self.tx_pool);
 
         tokio::spawn(async move {
             let mut reward_interval = interval(Duration::from_secs(300)); // 5 minutes
@@ -403,22 +595,32 @@ impl AbbyNode {
             loop {
                 reward_interval.tick().await;
 
-                // Distribute staking rewards
-                let staking_lock = staking.write().await;
-                // Process pending rewards, withdrawals, etc.
-                // This would include more complex reward distribution logic
-                drop(staking_lock);
+                // Forget unbonded stakes once they matured; block processing
+                // pays the tokens back
+                let now = chrono::Utc::now().timestamp() as u64;
+                let matured = staking.write().await.process_matured_withdrawals(now);
+                log::debug!("Dropped matured stakes of {} stakers", matured.len());
 
-                log::debug!("Processed periodic staking rewards");
+                // Drop transactions that have sat in the pool too long
+                let pruned = tx_pool.lock().await.prune_expired(TX_POOL_MAX_AGE);
+                if pruned > 0 {
+                    log::info!("Pruned {} expired transactions from the pool", pruned);
+                }
             }
         });
     }
 
+    /// Add a transaction to the pool and broadcast it. Transactions reusing
+    /// a nonce the sender already spent on chain are rejected.
     pub async fn submit_transaction(&self, transaction: Transaction) -> Result<H256, String> {
-        let mut tx_pool = self.tx_pool.lock().await;
         let tx_hash = transaction.hash();
-        tx_pool.add_transaction(transaction.clone())?;
-        drop(tx_pool);
+        Self::add_transaction_and_notify(
+            &self.blockchain,
+            &self.tx_pool,
+            &self.events,
+            transaction.clone(),
+        )
+        .await?;
 
         // Broadcast transaction to network
         let mut network = self.network.lock().await;
@@ -429,45 +631,107 @@ impl AbbyNode {
         Ok(tx_hash)
     }
 
+    /// Nonce for the next transaction from `address`: one past its highest
+    /// pending transaction, or its account nonce if none are pending
+    pub async fn next_nonce(&self, address: &Address) -> U256 {
+        let account_nonce = self.blockchain.read().await.get_nonce(address);
+        self.tx_pool
+            .lock()
+            .await
+            .get_pending_transactions()
+            .into_iter()
+            .filter(|tx| tx.from == *address)
+            .map(|tx| tx.nonce + 1)
+            .fold(account_nonce, U256::max)
+    }
+
+    /// Gas price for wallets to offer, see `suggest_gas_price`
+    pub async fn suggest_gas_price(&self) -> U256 {
+        suggest_gas_price(&*self.blockchain.read().await, self.min_gas_price)
+    }
+
     pub async fn get_balance(&self, address: &Address) -> U256 {
         let blockchain = self.blockchain.read().await;
         blockchain.get_abby_balance(address)
     }
 
+    /// Send `amount` to `to` from the account of `key`, signed for this
+    /// node's chain
     pub async fn transfer_abby(
         &self,
-        from: &Address,
+        key: &SecretKey,
         to: &Address,
         amount: U256,
     ) -> Result<H256, String> {
-        // Create a transfer transaction
-        let nonce = U256::zero(); // Simplified - should get actual nonce
-        let gas_limit = U256::from(21000);
-        let gas_price = U256::from(1_000_000_000u64);
+        let transaction = self.signed_transaction(key, *to, amount, Vec::new()).await;
+        self.submit_transaction(transaction).await
+    }
 
-        let transaction = Transaction::new(
-            *from,
-            Some(*to),
-            amount,
-            gas_limit,
-            gas_price,
-            Vec::new(),
-            nonce,
-        );
+    /// Stake `amount` of `key`'s balance with `validator`. The tokens leave
+    /// the balance when a block includes the returned transaction.
+    pub async fn stake_tokens(
+        &self,
+        key: &SecretKey,
+        validator: Address,
+        amount: U256,
+    ) -> Result<H256, String> {
+        let operation = StakingOperation::Bond { validator };
+        let transaction = self
+            .signed_transaction(key, STAKING_ADDRESS, amount, operation.encode())
+            .await;
+        if self.get_balance(&transaction.from).await < amount.saturating_add(transaction.fee()) {
+            return Err("Insufficient Abby token balance to stake".to_string());
+        }
 
+        {
+            let mut staking = self.staking.write().await;
+            let mut consensus = self.consensus.write().await;
+            staking.stake(transaction.from, validator, amount, &mut consensus)?;
+        }
         self.submit_transaction(transaction).await
     }
 
-    pub async fn stake_tokens(
+    /// Unstake `amount` from `validator`. The block that includes the
+    /// returned transaction unbonds it, and the first epoch-boundary block
+    /// past the withdrawal delay pays it back to the staker.
+    pub async fn unstake_tokens(
         &self,
-        staker: Address,
+        key: &SecretKey,
         validator: Address,
         amount: U256,
-    ) -> Result<(), String> {
-        let mut staking = self.staking.write().await;
-        let mut consensus = self.consensus.write().await;
+    ) -> Result<H256, String> {
+        let operation = StakingOperation::Unbond { validator, amount };
+        let transaction = self
+            .signed_transaction(key, STAKING_ADDRESS, U256::zero(), operation.encode())
+            .await;
+
+        {
+            let mut staking = self.staking.write().await;
+            let mut consensus = self.consensus.write().await;
+            staking.unstake(transaction.from, validator, amount, &mut consensus)?;
+        }
+        self.submit_transaction(transaction).await
+    }
+
+    /// A transaction from `key`'s account with its next nonce, at the
+    /// default gas price and signed for this node's chain
+    async fn signed_transaction(
+        &self,
+        key: &SecretKey,
+        to: Address,
+        value: U256,
+        data: Vec<u8>,
+    ) -> Transaction {
+        let from = crate::utils::public_key_to_address(&key.public_key(&Secp256k1::signing_only()));
+        let nonce = self.next_nonce(&from).await;
+        let gas_limit = U256::from(21000);
+        let gas_price = U256::from(1_000_000_000u64);
 
-        staking.stake(staker, validator, amount, &mut consensus)
+        let mut transaction =
+            Transaction::new(from, Some(to), value, gas_limit, gas_price, data, nonce);
+        let chain_id = self.tx_pool.lock().await.chain_id;
+        transaction.sign_for_chain(key, chain_id);
+        transaction
     }
 
     pub async fn get_validator_info(
@@ -498,3 +762,323 @@ impl AbbyNode {
         network.peer_count()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::network::SignedMessage;
+
+    /// The default genesis, plus 10 Abby tokens for the address of `key`
+    fn genesis_funding(key: &SecretKey) -> GenesisConfig {
+        let mut genesis = GenesisConfig::default();
+        genesis.alloc.insert(
+            crate::utils::public_key_to_address(&key.public_key(&Secp256k1::signing_only())),
+            crate::blockchain::GenesisAccount {
+                balance: U256::exp10(19),
+            },
+        );
+        genesis
+    }
+
+    #[tokio::test]
+    async fn test_transfers_use_consecutive_nonces() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let node = AbbyNode::new(None, 0, None, genesis_funding(&key))
+            .await
+            .unwrap();
+        let recipient = Address::from_low_u64_be(0xb0b);
+
+        let first = node
+            .transfer_abby(&key, &recipient, U256::one())
+            .await
+            .unwrap();
+        let second = node
+            .transfer_abby(&key, &recipient, U256::one())
+            .await
+            .unwrap();
+
+        let tx_pool = node.tx_pool.lock().await;
+        assert_eq!(tx_pool.len(), 2);
+        assert_eq!(tx_pool.get_transaction(&first).unwrap().nonce, U256::zero());
+        assert_eq!(tx_pool.get_transaction(&second).unwrap().nonce, U256::one());
+    }
+
+    #[tokio::test]
+    async fn test_pool_refuses_transfers_the_sender_cannot_pay_the_fee_for() {
+        let node = AbbyNode::new(None, 0, None, Default::default())
+            .await
+            .unwrap();
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+
+        let err = node
+            .transfer_abby(&key, &Address::from_low_u64_be(0xb0b), U256::zero())
+            .await
+            .unwrap_err();
+        assert!(err.contains("Insufficient balance"), "{}", err);
+        assert_eq!(node.tx_pool.lock().await.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_staking_moves_tokens_out_of_the_balance_until_released() {
+        let validator = Address::from_low_u64_be(0x10);
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let node = AbbyNode::new(Some(validator), 0, None, genesis_funding(&key))
+            .await
+            .unwrap();
+        let staker =
+            crate::utils::public_key_to_address(&key.public_key(&Secp256k1::signing_only()));
+        let amount = U256::exp10(18) * 5;
+        let balance = node.get_balance(&staker).await;
+        let include_pending = || async {
+            let block = {
+                let blockchain = node.blockchain.read().await;
+                let header = BlockHeader::new(
+                    blockchain.head_number + 1,
+                    blockchain.head_hash,
+                    Address::from_low_u64_be(42),
+                    U256::from(10_000_000u64),
+                );
+                let pending: Vec<Transaction> = node
+                    .tx_pool
+                    .lock()
+                    .await
+                    .get_pending_transactions()
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                let mut block = Block::new(header, pending);
+                blockchain.seal_block(&mut block).unwrap();
+                block
+            };
+            node.import_block(block).await.unwrap();
+        };
+
+        // Staking only sends a transaction; its block moves the tokens
+        let stake = node.stake_tokens(&key, validator, amount).await.unwrap();
+        assert_eq!(node.get_balance(&staker).await, balance);
+        include_pending().await;
+        let fee = node
+            .blockchain
+            .read()
+            .await
+            .get_transaction(&stake)
+            .unwrap()
+            .fee();
+        assert_eq!(node.get_balance(&staker).await, balance - amount - fee);
+        // More than the remaining balance is refused without staking anything
+        assert!(node.stake_tokens(&key, validator, balance).await.is_err());
+        assert_eq!(node.staking.read().await.stakes[&staker].len(), 1);
+
+        // Unstaked tokens wait in the ledger for a later block to pay them back
+        node.unstake_tokens(&key, validator, amount).await.unwrap();
+        include_pending().await;
+        assert_eq!(node.get_balance(&staker).await, balance - amount - fee * 2);
+        let blockchain = node.blockchain.read().await;
+        assert!(blockchain.stake_ledger.bonded.is_empty());
+        assert_eq!(blockchain.stake_ledger.unbonding[0].amount, amount);
+    }
+
+    #[tokio::test]
+    async fn test_suggested_gas_price_follows_recent_blocks() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let node = AbbyNode::new(None, 0, None, genesis_funding(&key))
+            .await
+            .unwrap();
+        assert_eq!(
+            node.suggest_gas_price().await,
+            U256::from(DEFAULT_MIN_GAS_PRICE)
+        );
+
+        // Two blocks paying 1..=10 gwei between them
+        let gwei = U256::from(1_000_000_000u64);
+        let from = crate::utils::public_key_to_address(&key.public_key(&Secp256k1::signing_only()));
+        for prices in [[3, 1, 4, 5, 9], [2, 6, 10, 8, 7]] {
+            let blockchain = node.blockchain.read().await;
+            let nonce = blockchain.get_nonce(&from);
+            let transactions = prices
+                .into_iter()
+                .zip(0u64..)
+                .map(|(price, i)| {
+                    let mut tx = Transaction::new(
+                        from,
+                        Some(Address::from_low_u64_be(0xb0b)),
+                        U256::zero(),
+                        U256::from(21000),
+                        gwei * price,
+                        Vec::new(),
+                        nonce + i,
+                    );
+                    tx.sign(&key);
+                    tx
+                })
+                .collect();
+            let header = BlockHeader::new(
+                blockchain.head_number + 1,
+                blockchain.head_hash,
+                Address::from_low_u64_be(42),
+                U256::from(10_000_000u64),
+            );
+            let mut block = Block::new(header, transactions);
+            blockchain.seal_block(&mut block).unwrap();
+            drop(blockchain);
+            node.import_block(block).await.unwrap();
+        }
+
+        let suggestion = node.suggest_gas_price().await;
+        assert!(
+            suggestion >= gwei * 5 && suggestion <= gwei * 7,
+            "{}",
+            suggestion
+        );
+
+        let mut node = node;
+        node.min_gas_price = gwei * 20;
+        assert_eq!(node.suggest_gas_price().await, gwei * 20);
+    }
+
+    #[tokio::test]
+    async fn test_mined_transaction_evicts_same_nonce_competitors() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let node = AbbyNode::new(None, 0, None, genesis_funding(&key))
+            .await
+            .unwrap();
+        let from = crate::utils::public_key_to_address(&key.public_key(&Secp256k1::signing_only()));
+        let transfer = |nonce: u64, gas_price: u64| {
+            let mut tx = Transaction::new(
+                from,
+                Some(Address::from_low_u64_be(0xb0b)),
+                U256::zero(),
+                U256::from(21000),
+                U256::from(gas_price),
+                Vec::new(),
+                U256::from(nonce),
+            );
+            tx.sign(&key);
+            tx
+        };
+
+        let competitor = transfer(0, 5);
+        let follow_up = transfer(1, 5);
+        node.submit_transaction(competitor.clone()).await.unwrap();
+        node.submit_transaction(follow_up.clone()).await.unwrap();
+
+        // Another validator mined a different transaction with nonce 0
+        let block = {
+            let blockchain = node.blockchain.read().await;
+            let header = BlockHeader::new(
+                1,
+                blockchain.head_hash,
+                Address::from_low_u64_be(42),
+                U256::from(10_000_000u64),
+            );
+            let mut block = Block::new(header, vec![transfer(0, 10)]);
+            blockchain.seal_block(&mut block).unwrap();
+            block
+        };
+        node.import_block(block).await.unwrap();
+
+        {
+            let tx_pool = node.tx_pool.lock().await;
+            assert!(tx_pool.get_transaction(&competitor.hash()).is_none());
+            assert!(tx_pool.get_transaction(&follow_up.hash()).is_some());
+            assert_eq!(tx_pool.len(), 1);
+        }
+        let err = node.submit_transaction(competitor).await.unwrap_err();
+        assert!(err.contains("Nonce too low"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_tampered_block_message_is_dropped_and_peer_penalized() {
+        let node = AbbyNode::new(None, 0, None, Default::default())
+            .await
+            .unwrap();
+        let head_before = node.blockchain.read().await.head_hash;
+
+        let block = {
+            let blockchain = node.blockchain.read().await;
+            let header = BlockHeader::new(
+                1,
+                blockchain.head_hash,
+                Address::from_low_u64_be(42),
+                U256::from(10_000_000u64),
+            );
+            let mut block = Block::new(header, Vec::new());
+            blockchain.seal_block(&mut block).unwrap();
+            block
+        };
+        let peer_key = SecretKey::from_slice(&[9; 32]).unwrap();
+        let signed = SignedMessage::sign(
+            "peer-1".to_string(),
+            NetworkMessage::NewBlock(block),
+            &peer_key,
+        )
+        .unwrap();
+        let handle = |signed: SignedMessage| {
+            AbbyNode::handle_network_message(
+                IncomingMessage {
+                    connection_id: "conn-1".to_string(),
+                    signed,
+                },
+                &node.blockchain,
+                &node.tx_pool,
+                &node.sync_manager,
+                &node.network,
+                &node.events,
+            )
+        };
+
+        // Neither the block nor the claimed peer id can be changed in transit
+        let mut tampered = signed.clone();
+        if let NetworkMessage::NewBlock(block) = &mut tampered.message {
+            block.header.proposer = Address::from_low_u64_be(0xbad);
+        }
+        assert!(handle(tampered).await.is_err());
+        let mut renamed = signed;
+        renamed.peer_id = "peer-2".to_string();
+        assert!(handle(renamed).await.is_err());
+
+        // The connection pays, whatever peer id the messages claimed
+        assert_eq!(node.blockchain.read().await.head_hash, head_before);
+        let network = node.network.lock().await;
+        assert_eq!(network.peer_score("conn-1"), -2 * INVALID_SIGNATURE_PENALTY);
+        assert_eq!(network.peer_score("peer-1"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_hear_about_new_heads_and_pending_transactions() {
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let node = AbbyNode::new(None, 0, None, genesis_funding(&key))
+            .await
+            .unwrap();
+        let mut events = node.subscribe();
+
+        let block = {
+            let blockchain = node.blockchain.read().await;
+            let header = BlockHeader::new(
+                1,
+                blockchain.head_hash,
+                Address::from_low_u64_be(42),
+                U256::from(10_000_000u64),
+            );
+            let mut block = Block::new(header, Vec::new());
+            blockchain.seal_block(&mut block).unwrap();
+            block
+        };
+        let block_hash = block.hash();
+        node.import_block(block).await.unwrap();
+
+        match events.recv().await.unwrap() {
+            NodeEvent::NewHead(head) => assert_eq!(head.hash(), block_hash),
+            event => panic!("expected a new head, got {:?}", event),
+        }
+
+        let tx_hash = node
+            .transfer_abby(&key, &Address::from_low_u64_be(0xb0b), U256::one())
+            .await
+            .unwrap();
+        match events.recv().await.unwrap() {
+            NodeEvent::PendingTransaction(hash) => assert_eq!(hash, tx_hash),
+            event => panic!("expected a pending transaction, got {:?}", event),
+        }
+    }
+}
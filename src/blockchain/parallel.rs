@@ -0,0 +1,80 @@
+use crate::blockchain::Transaction;
+use ethereum_types::Address;
+use std::collections::BTreeSet;
+
+/// Accounts a transaction may read or write: its sender and its recipient.
+/// Contract creation writes to an address derived at execution time, so its
+/// accounts are not known up front.
+pub fn touched_accounts(tx: &Transaction) -> Option<[Address; 2]> {
+    tx.to.map(|to| [tx.from, to])
+}
+
+/// Split `transactions` into groups whose touched accounts are disjoint, so
+/// each group can run on its own. Indices keep their original order within
+/// a group and groups are ordered by their first index. `None` if any
+/// transaction's accounts cannot be determined, in which case the block must
+/// run serially.
+pub fn independent_groups(transactions: &[Transaction]) -> Option<Vec<Vec<usize>>> {
+    let mut groups: Vec<(BTreeSet<Address>, Vec<usize>)> = Vec::new();
+
+    for (index, tx) in transactions.iter().enumerate() {
+        let mut accounts: BTreeSet<Address> = touched_accounts(tx)?.into_iter().collect();
+        let mut indices = vec![index];
+
+        // Fold every group sharing an account with this transaction into one
+        groups.retain_mut(|(group_accounts, group_indices)| {
+            if group_accounts.is_disjoint(&accounts) {
+                return true;
+            }
+            accounts.append(group_accounts);
+            indices.append(group_indices);
+            false
+        });
+        indices.sort_unstable();
+        groups.push((accounts, indices));
+    }
+
+    let mut groups: Vec<Vec<usize>> = groups.into_iter().map(|(_, indices)| indices).collect();
+    groups.sort_by_key(|indices| indices[0]);
+    Some(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::U256;
+
+    fn transfer(from: u64, to: Option<u64>) -> Transaction {
+        Transaction::new(
+            Address::from_low_u64_be(from),
+            to.map(Address::from_low_u64_be),
+            U256::zero(),
+            U256::from(21000),
+            U256::from(1),
+            Vec::new(),
+            U256::zero(),
+        )
+    }
+
+    #[test]
+    fn test_transactions_sharing_an_account_are_grouped() {
+        let transactions = [
+            transfer(1, Some(2)),
+            transfer(3, Some(4)),
+            transfer(5, Some(6)),
+            // Joins the first two groups through accounts 2 and 4
+            transfer(2, Some(4)),
+        ];
+
+        assert_eq!(
+            independent_groups(&transactions),
+            Some(vec![vec![0, 1, 3], vec![2]])
+        );
+    }
+
+    #[test]
+    fn test_contract_creation_forces_serial_execution() {
+        let transactions = [transfer(1, Some(2)), transfer(3, None)];
+        assert_eq!(independent_groups(&transactions), None);
+    }
+}
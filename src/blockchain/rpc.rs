@@ -0,0 +1,643 @@
+use crate::blockchain::node::suggest_gas_price;
+use crate::blockchain::{
+    network::NetworkManager, AbbyNode, Block, Blockchain, NodeEvent, Transaction, TransactionPool,
+    TransactionReceipt,
+};
+use ethereum_types::{Address, H256, U256};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use tokio::sync::{Mutex, RwLock};
+
+// Minimal JSON-RPC 2.0 over HTTP/1.1, one request per connection. HTTP
+// cannot push, so `eth_subscribe` events queue until a client collects them
+// with `eth_getFilterChanges`.
+
+/// Largest HTTP request (headers and body) the server will read
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+/// Subscriptions one client address may hold open at a time. Each keeps a
+/// queue of events until collected, so they are not free.
+const MAX_SUBSCRIPTIONS_PER_CLIENT: usize = 16;
+
+/// Shared handles to the node state the RPC methods read and write
+#[derive(Clone)]
+struct RpcHandler {
+    blockchain: Arc<RwLock<Blockchain>>,
+    tx_pool: Arc<Mutex<TransactionPool>>,
+    network: Arc<Mutex<NetworkManager>>,
+    events: broadcast::Sender<NodeEvent>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    min_gas_price: U256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SubscriptionKind {
+    NewHeads,
+    NewPendingTransactions,
+}
+
+/// Open `eth_subscribe` subscriptions by id
+#[derive(Default)]
+struct Subscriptions {
+    next_id: u64,
+    open: HashMap<u64, Subscription>,
+}
+
+struct Subscription {
+    /// Address of the client that opened it. Each HTTP request comes on a
+    /// new connection, so the cap on open subscriptions is per address.
+    client: IpAddr,
+    kind: SubscriptionKind,
+    receiver: broadcast::Receiver<NodeEvent>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_request(message: impl Into<String>) -> Self {
+        Self {
+            code: -32600,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self {
+            code: -32602,
+            message: message.into(),
+        }
+    }
+
+    fn server(message: impl Into<String>) -> Self {
+        Self {
+            code: -32000,
+            message: message.into(),
+        }
+    }
+}
+
+impl AbbyNode {
+    /// Serve JSON-RPC over HTTP on 127.0.0.1:`port` (0 picks a free port).
+    /// Returns the address the server is bound to.
+    pub async fn start_rpc(&self, port: u16) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let local_addr = listener.local_addr()?;
+
+        let handler = RpcHandler {
+            blockchain: Arc::clone(&self.blockchain),
+            tx_pool: Arc::clone(&self.tx_pool),
+            network: Arc::clone(&self.network),
+            events: self.events.clone(),
+            subscriptions: Arc::default(),
+            min_gas_price: self.min_gas_price,
+        };
+
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let handler = handler.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handler.serve_connection(stream, peer.ip()).await {
+                                log::debug!("RPC connection from {} failed: {}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => log::warn!("Failed to accept RPC connection: {}", e),
+                }
+            }
+        });
+
+        log::info!("JSON-RPC server listening on {}", local_addr);
+        Ok(local_addr)
+    }
+}
+
+impl RpcHandler {
+    async fn serve_connection(&self, mut stream: TcpStream, client: IpAddr) -> std::io::Result<()> {
+        let response = match read_http_body(&mut stream).await? {
+            Ok(body) => http_response("200 OK", &self.handle_body(client, &body).await.to_string()),
+            Err(status) => http_response(status, ""),
+        };
+
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    async fn handle_body(&self, client: IpAddr, body: &[u8]) -> Value {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => {
+                return error_response(
+                    Value::Null,
+                    RpcError {
+                        code: -32700,
+                        message: format!("Parse error: {}", e),
+                    },
+                )
+            }
+        };
+
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            return error_response(id, RpcError::invalid_request("Missing method"));
+        };
+        let params = request.get("params").cloned().unwrap_or_else(|| json!([]));
+
+        match self.dispatch(client, method, &params).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => error_response(id, e),
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        client: IpAddr,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "eth_chainId" => {
+                let blockchain = self.blockchain.read().await;
+                Ok(json!(format!("{:#x}", blockchain.genesis.chain_id)))
+            }
+            "eth_blockNumber" => {
+                let blockchain = self.blockchain.read().await;
+                Ok(json!(format!("{:#x}", blockchain.head_number)))
+            }
+            "eth_gasPrice" => {
+                let blockchain = self.blockchain.read().await;
+                Ok(json!(suggest_gas_price(&blockchain, self.min_gas_price)))
+            }
+            "eth_getBalance" => {
+                let address: Address = param(params, 0)?;
+                let blockchain = self.blockchain.read().await;
+                Ok(json!(blockchain.get_abby_balance(&address)))
+            }
+            "eth_getBlockByNumber" => {
+                let tag: String = param(params, 0)?;
+                let full = params.get(1).and_then(Value::as_bool).unwrap_or(false);
+
+                let blockchain = self.blockchain.read().await;
+                let number = match tag.as_str() {
+                    "latest" | "pending" => blockchain.head_number,
+                    "earliest" => 0,
+                    quantity => u64::from_str_radix(quantity.trim_start_matches("0x"), 16)
+                        .map_err(|_| {
+                            RpcError::invalid_params(format!("Invalid block number: {}", tag))
+                        })?,
+                };
+
+                Ok(blockchain
+                    .get_block_by_number(number)
+                    .map(|block| block_json(block, full))
+                    .unwrap_or(Value::Null))
+            }
+            "eth_sendRawTransaction" => {
+                let raw: String = param(params, 0)?;
+                let bytes = hex::decode(raw.trim_start_matches("0x"))
+                    .map_err(|e| RpcError::invalid_params(format!("Invalid hex: {}", e)))?;
+                let transaction: Transaction = rlp::decode(&bytes)
+                    .map_err(|e| RpcError::invalid_params(format!("Invalid transaction: {}", e)))?;
+                let tx_hash = transaction.hash();
+
+                AbbyNode::add_transaction_and_notify(
+                    &self.blockchain,
+                    &self.tx_pool,
+                    &self.events,
+                    transaction.clone(),
+                )
+                .await
+                .map_err(RpcError::server)?;
+
+                let broadcast = self
+                    .network
+                    .lock()
+                    .await
+                    .broadcast_transaction(transaction)
+                    .map_err(|e| e.to_string());
+                if let Err(e) = broadcast {
+                    log::warn!("Failed to broadcast transaction {:?}: {}", tx_hash, e);
+                }
+
+                Ok(json!(tx_hash))
+            }
+            "eth_getTransactionReceipt" => {
+                let tx_hash: H256 = param(params, 0)?;
+                let blockchain = self.blockchain.read().await;
+                Ok(blockchain
+                    .get_receipt(&tx_hash)
+                    .map(receipt_json)
+                    .unwrap_or(Value::Null))
+            }
+            "eth_subscribe" => {
+                let name: String = param(params, 0)?;
+                let kind = match name.as_str() {
+                    "newHeads" => SubscriptionKind::NewHeads,
+                    "newPendingTransactions" => SubscriptionKind::NewPendingTransactions,
+                    _ => {
+                        return Err(RpcError::invalid_params(format!(
+                            "Unsupported subscription: {}",
+                            name
+                        )))
+                    }
+                };
+
+                let mut subscriptions = self.subscriptions.lock().await;
+                let held = subscriptions
+                    .open
+                    .values()
+                    .filter(|subscription| subscription.client == client)
+                    .count();
+                if held >= MAX_SUBSCRIPTIONS_PER_CLIENT {
+                    return Err(RpcError::server(format!(
+                        "Too many subscriptions: at most {} per client",
+                        MAX_SUBSCRIPTIONS_PER_CLIENT
+                    )));
+                }
+                let id = subscriptions.next_id;
+                subscriptions.next_id += 1;
+                subscriptions.open.insert(
+                    id,
+                    Subscription {
+                        client,
+                        kind,
+                        receiver: self.events.subscribe(),
+                    },
+                );
+                Ok(json!(format!("{:#x}", id)))
+            }
+            "eth_unsubscribe" => {
+                let id = subscription_id(params)?;
+                let removed = self.subscriptions.lock().await.open.remove(&id);
+                Ok(json!(removed.is_some()))
+            }
+            "eth_getFilterChanges" => {
+                let id = subscription_id(params)?;
+                let mut subscriptions = self.subscriptions.lock().await;
+                let Subscription { kind, receiver, .. } = subscriptions
+                    .open
+                    .get_mut(&id)
+                    .ok_or_else(|| RpcError::invalid_params("Subscription not found"))?;
+
+                let mut changes = Vec::new();
+                loop {
+                    match (receiver.try_recv(), *kind) {
+                        (Ok(NodeEvent::NewHead(block)), SubscriptionKind::NewHeads) => {
+                            changes.push(block_json(&block, false))
+                        }
+                        (
+                            Ok(NodeEvent::PendingTransaction(hash)),
+                            SubscriptionKind::NewPendingTransactions,
+                        ) => changes.push(json!(hash)),
+                        (Ok(_), _) => {}
+                        (Err(TryRecvError::Lagged(missed)), _) => {
+                            log::warn!("Subscription {:#x} missed {} events", id, missed)
+                        }
+                        (Err(TryRecvError::Empty | TryRecvError::Closed), _) => break,
+                    }
+                }
+                Ok(json!(changes))
+            }
+            "txpool_status" => {
+                let tx_pool = self.tx_pool.lock().await;
+                Ok(json!({
+                    "pending": format!("{:#x}", tx_pool.len()),
+                    "queued": format!("{:#x}", tx_pool.queued.values().map(Vec::len).sum::<usize>()),
+                    "maxSize": format!("{:#x}", tx_pool.max_size),
+                }))
+            }
+            _ => Err(RpcError {
+                code: -32601,
+                message: format!("Method not found: {}", method),
+            }),
+        }
+    }
+}
+
+/// Deserialize positional parameter `index`
+fn param<T: DeserializeOwned>(params: &Value, index: usize) -> Result<T, RpcError> {
+    let value = params
+        .get(index)
+        .cloned()
+        .ok_or_else(|| RpcError::invalid_params(format!("Missing parameter {}", index)))?;
+    serde_json::from_value(value)
+        .map_err(|e| RpcError::invalid_params(format!("Invalid parameter {}: {}", index, e)))
+}
+
+/// Subscription id given as the first parameter
+fn subscription_id(params: &Value) -> Result<u64, RpcError> {
+    let id: String = param(params, 0)?;
+    u64::from_str_radix(id.trim_start_matches("0x"), 16)
+        .map_err(|_| RpcError::invalid_params(format!("Invalid subscription id: {}", id)))
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn block_json(block: &Block, full_transactions: bool) -> Value {
+    let header = &block.header;
+    let transactions: Vec<Value> = if full_transactions {
+        block.transactions.iter().map(transaction_json).collect()
+    } else {
+        block
+            .transactions
+            .iter()
+            .map(|tx| json!(tx.hash()))
+            .collect()
+    };
+
+    json!({
+        "number": format!("{:#x}", header.number),
+        "hash": block.hash(),
+        "parentHash": header.parent_hash,
+        "stateRoot": header.state_root,
+        "transactionsRoot": header.transactions_root,
+        "receiptsRoot": header.receipts_root,
+        "miner": header.proposer,
+        "timestamp": format!("{:#x}", header.timestamp.timestamp()),
+        "gasLimit": header.gas_limit,
+        "gasUsed": header.gas_used,
+        "baseFeePerGas": header.base_fee,
+        "extraData": format!("0x{}", hex::encode(&header.extra_data)),
+        "transactions": transactions,
+    })
+}
+
+fn transaction_json(tx: &Transaction) -> Value {
+    json!({
+        "hash": tx.hash(),
+        "from": tx.from,
+        "to": tx.to,
+        "value": tx.value,
+        "gas": tx.gas_limit,
+        "gasPrice": tx.gas_price,
+        "input": format!("0x{}", hex::encode(&tx.data)),
+        "nonce": tx.nonce,
+        "v": format!("{:#x}", tx.v),
+        "r": tx.r,
+        "s": tx.s,
+    })
+}
+
+fn receipt_json(receipt: &TransactionReceipt) -> Value {
+    let logs: Vec<Value> = receipt
+        .logs
+        .iter()
+        .map(|entry| {
+            json!({
+                "address": entry.log.address,
+                "topics": entry.log.topics,
+                "data": format!("0x{}", hex::encode(&entry.log.data)),
+                "blockHash": entry.block_hash,
+                "blockNumber": format!("{:#x}", entry.block_number),
+                "transactionHash": entry.transaction_hash,
+                "transactionIndex": format!("{:#x}", entry.transaction_index),
+                "logIndex": format!("{:#x}", entry.log_index),
+            })
+        })
+        .collect();
+
+    json!({
+        "transactionHash": receipt.transaction_hash,
+        "transactionIndex": format!("{:#x}", receipt.transaction_index),
+        "blockHash": receipt.block_hash,
+        "blockNumber": format!("{:#x}", receipt.block_number),
+        "from": receipt.from,
+        "to": receipt.to,
+        "cumulativeGasUsed": receipt.cumulative_gas_used,
+        "gasUsed": receipt.gas_used,
+        "contractAddress": receipt.contract_address,
+        "logs": logs,
+        "status": if receipt.status { "0x1" } else { "0x0" },
+    })
+}
+
+/// Read a single HTTP request and return its body, or the error status to
+/// reply with when the request isn't a usable JSON-RPC POST
+async fn read_http_body(stream: &mut TcpStream) -> std::io::Result<Result<Vec<u8>, &'static str>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buffer.len() > MAX_REQUEST_SIZE {
+            return Ok(Err("413 Payload Too Large"));
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(Err("400 Bad Request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let headers = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = headers.lines();
+    if !lines.next().unwrap_or_default().starts_with("POST ") {
+        return Ok(Err("405 Method Not Allowed"));
+    }
+
+    let content_length = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse::<usize>().ok())
+            .flatten()
+    });
+    let Some(content_length) = content_length else {
+        return Ok(Err("411 Length Required"));
+    };
+    if header_end + content_length > MAX_REQUEST_SIZE {
+        return Ok(Err("413 Payload Too Large"));
+    }
+
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(Err("400 Bad Request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    Ok(Ok(buffer[header_end..header_end + content_length].to_vec()))
+}
+
+fn http_response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn post(addr: SocketAddr, body: &str) -> Value {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rpc_serves_block_number_and_blocks() {
+        let node = AbbyNode::new(None, 0, None, Default::default())
+            .await
+            .unwrap();
+        let addr = node.start_rpc(0).await.unwrap();
+
+        let reply = post(
+            addr,
+            r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber","params":[]}"#,
+        )
+        .await;
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"], "0x0");
+
+        let reply = post(
+            addr,
+            r#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId","params":[]}"#,
+        )
+        .await;
+        assert_eq!(reply["result"], "0x539");
+
+        let reply = post(
+            addr,
+            r#"{"jsonrpc":"2.0","id":2,"method":"eth_getBlockByNumber","params":["latest",false]}"#,
+        )
+        .await;
+        assert_eq!(reply["result"]["number"], "0x0");
+
+        let reply = post(
+            addr,
+            r#"{"jsonrpc":"2.0","id":3,"method":"txpool_status","params":[]}"#,
+        )
+        .await;
+        assert_eq!(reply["result"]["pending"], "0x0");
+        assert_eq!(reply["result"]["maxSize"], "0x1000");
+
+        // No transactions yet, so the floor price is suggested
+        let reply = post(
+            addr,
+            r#"{"jsonrpc":"2.0","id":4,"method":"eth_gasPrice","params":[]}"#,
+        )
+        .await;
+        assert_eq!(reply["result"], json!(node.min_gas_price));
+
+        let reply = post(addr, r#"{"jsonrpc":"2.0","id":3,"method":"eth_mine"}"#).await;
+        assert_eq!(reply["error"]["code"], -32601);
+    }
+
+    #[tokio::test]
+    async fn test_new_heads_subscription_collects_imported_blocks() {
+        use crate::blockchain::BlockHeader;
+        use ethereum_types::U256;
+
+        let node = AbbyNode::new(None, 0, None, Default::default())
+            .await
+            .unwrap();
+        let addr = node.start_rpc(0).await.unwrap();
+
+        let reply = post(
+            addr,
+            r#"{"jsonrpc":"2.0","id":1,"method":"eth_subscribe","params":["newHeads"]}"#,
+        )
+        .await;
+        let id = reply["result"].as_str().unwrap().to_string();
+
+        let block = {
+            let blockchain = node.blockchain.read().await;
+            let header = BlockHeader::new(
+                1,
+                blockchain.head_hash,
+                Address::from_low_u64_be(42),
+                U256::from(10_000_000u64),
+            );
+            let mut block = Block::new(header, Vec::new());
+            blockchain.seal_block(&mut block).unwrap();
+            block
+        };
+        node.import_block(block.clone()).await.unwrap();
+
+        let changes = format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"eth_getFilterChanges","params":["{}"]}}"#,
+            id
+        );
+        let reply = post(addr, &changes).await;
+        let heads = reply["result"].as_array().unwrap();
+        assert_eq!(heads.len(), 1);
+        assert_eq!(heads[0]["number"], "0x1");
+        assert_eq!(heads[0]["hash"], json!(block.hash()));
+
+        // Events are handed out once
+        let reply = post(addr, &changes).await;
+        assert_eq!(reply["result"], json!([]));
+
+        let reply = post(
+            addr,
+            &format!(
+                r#"{{"jsonrpc":"2.0","id":3,"method":"eth_unsubscribe","params":["{}"]}}"#,
+                id
+            ),
+        )
+        .await;
+        assert_eq!(reply["result"], true);
+        assert_eq!(post(addr, &changes).await["error"]["code"], -32602);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_per_client_are_capped() {
+        let node = AbbyNode::new(None, 0, None, Default::default())
+            .await
+            .unwrap();
+        let addr = node.start_rpc(0).await.unwrap();
+        let subscribe =
+            r#"{"jsonrpc":"2.0","id":1,"method":"eth_subscribe","params":["newHeads"]}"#;
+
+        let mut ids = Vec::new();
+        for _ in 0..MAX_SUBSCRIPTIONS_PER_CLIENT {
+            ids.push(post(addr, subscribe).await["result"].clone());
+        }
+        assert_eq!(post(addr, subscribe).await["error"]["code"], -32000);
+
+        // Closing one makes room for another
+        let unsubscribe = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "eth_unsubscribe",
+            "params": [ids[0]],
+        });
+        post(addr, &unsubscribe.to_string()).await;
+        assert!(post(addr, subscribe).await["result"].is_string());
+    }
+}
@@ -1,14 +1,78 @@
 use crate::blockchain::{ConsensusState, Validator};
+use crate::utils::u256_to_bytes32;
 use ethereum_types::{Address, U256};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Annual reward rate, in basis points, paid at the target staking ratio
+pub const DEFAULT_ANNUAL_REWARD_RATE: u32 = 800;
+/// Share of the token supply, in basis points, the reward rate steers towards
+pub const TARGET_STAKING_RATIO: u32 = 5000;
+/// Floor and ceiling of the dynamic reward rate, in basis points
+pub const MIN_REWARD_RATE: u32 = 200;
+pub const MAX_REWARD_RATE: u32 = 2000;
+
+/// Reward rate in basis points for `total_staked` out of `supply`. It is
+/// `base_rate` when the staked share equals `TARGET_STAKING_RATIO` and
+/// scales inversely with that share, so low participation pays more and
+/// high participation less, within `MIN_REWARD_RATE..=MAX_REWARD_RATE`.
+pub fn dynamic_reward_rate(base_rate: u32, total_staked: U256, supply: U256) -> u32 {
+    if total_staked.is_zero() {
+        return MAX_REWARD_RATE;
+    }
+    let rate = U256::from(base_rate) * U256::from(TARGET_STAKING_RATIO) * supply
+        / (total_staked * U256::from(10000));
+    rate.min(U256::from(MAX_REWARD_RATE))
+        .max(U256::from(MIN_REWARD_RATE))
+        .as_u32()
+}
+
+/// Address staking transactions are sent to. It has no code and holds no
+/// tokens: block processing turns a transaction to it into a bond or an
+/// unbond of the sender's stake.
+pub const STAKING_ADDRESS: Address = Address::repeat_byte(0x5a);
+
+/// What a transaction to `STAKING_ADDRESS` asks for, read from its call data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StakingOperation {
+    /// Bond the transaction's value with `validator`: `0x01 ++ validator`
+    Bond { validator: Address },
+    /// Unbond `amount` from `validator`: `0x02 ++ validator ++ amount`
+    Unbond { validator: Address, amount: U256 },
+}
+
+impl StakingOperation {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            StakingOperation::Bond { validator } => [&[0x01], validator.as_bytes()].concat(),
+            StakingOperation::Unbond { validator, amount } => {
+                [&[0x02], validator.as_bytes(), &u256_to_bytes32(*amount)].concat()
+            }
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        match data {
+            [0x01, validator @ ..] if validator.len() == 20 => Ok(StakingOperation::Bond {
+                validator: Address::from_slice(validator),
+            }),
+            [0x02, rest @ ..] if rest.len() == 52 => Ok(StakingOperation::Unbond {
+                validator: Address::from_slice(&rest[..20]),
+                amount: U256::from_big_endian(&rest[20..]),
+            }),
+            _ => Err("Malformed staking operation".to_string()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StakeInfo {
     pub staker: Address,
     pub validator: Address,
     pub amount: U256,
+    /// Total rewards paid out for this stake so far
     pub rewards_earned: U256,
+    /// Start of the current reward period: staking time, then the last claim
     pub delegation_time: u64,
     pub withdrawal_time: Option<u64>,
 }
@@ -40,9 +104,14 @@ pub struct StakingManager {
     pub stakes: HashMap<Address, Vec<StakeInfo>>, // staker -> stakes
     pub validator_delegations: HashMap<Address, Vec<StakeInfo>>, // validator -> delegated stakes
     pub total_staked: U256,
+    /// Tokens in existence; staking claims and epoch rewards both mint
+    /// through `mint_abby_tokens`
     pub abby_token_supply: U256,
-    pub annual_reward_rate: u32, // basis points (e.g., 800 = 8%)
-    pub withdrawal_delay: u64,   // seconds before withdrawal is available
+    /// Supply that reward minting may never exceed
+    pub max_supply: U256,
+    /// Reward rate at the target staking ratio, in basis points (800 = 8%)
+    pub annual_reward_rate: u32,
+    pub withdrawal_delay: u64, // seconds before withdrawal is available
 }
 
 impl StakingManager {
@@ -51,12 +120,21 @@ impl StakingManager {
             stakes: HashMap::new(),
             validator_delegations: HashMap::new(),
             total_staked: U256::zero(),
-            abby_token_supply: U256::from_dec_str("1000000000000000000000000").unwrap(), // 1 million Abby tokens
-            annual_reward_rate: 800,         // 8% annual reward
+            abby_token_supply: Self::initial_supply(),
+            max_supply: Self::default_max_supply(),
+            annual_reward_rate: DEFAULT_ANNUAL_REWARD_RATE,
             withdrawal_delay: 7 * 24 * 3600, // 7 days
         }
     }
 
+    pub fn initial_supply() -> U256 {
+        U256::from_dec_str("1000000000000000000000000").unwrap() // 1 million Abby tokens
+    }
+
+    pub fn default_max_supply() -> U256 {
+        U256::from_dec_str("10000000000000000000000000").unwrap() // 10 million Abby tokens
+    }
+
     pub fn stake(
         &mut self,
         staker: Address,
@@ -93,6 +171,7 @@ impl StakingManager {
         }
 
         self.total_staked += amount;
+        consensus.staking_reward_rate = self.effective_reward_rate();
 
         log::info!(
             "Staked {} Abby tokens from {} to validator {}",
@@ -116,17 +195,25 @@ impl StakingManager {
             .get_mut(&staker)
             .ok_or("No stakes found for staker")?;
 
+        // Unstaked amounts stay in the staker's list, marked with the time
+        // they started unbonding, until `withdraw` releases them
+        let now = chrono::Utc::now().timestamp() as u64;
         let mut remaining_amount = amount;
-        let mut stakes_to_remove = Vec::new();
+        let mut unbonding = Vec::new();
 
-        for (i, stake) in staker_stakes.iter_mut().enumerate() {
-            if stake.validator == validator && remaining_amount > U256::zero() {
+        for stake in staker_stakes.iter_mut() {
+            if stake.validator == validator
+                && stake.withdrawal_time.is_none()
+                && remaining_amount > U256::zero()
+            {
                 if stake.amount <= remaining_amount {
                     remaining_amount -= stake.amount;
-                    stake.withdrawal_time = Some(chrono::Utc::now().timestamp() as u64);
-                    stakes_to_remove.push(i);
+                    stake.withdrawal_time = Some(now);
                 } else {
                     stake.amount -= remaining_amount;
+                    let mut split = StakeInfo::new(staker, validator, remaining_amount);
+                    split.withdrawal_time = Some(now);
+                    unbonding.push(split);
                     remaining_amount = U256::zero();
                 }
             }
@@ -135,11 +222,7 @@ impl StakingManager {
         if remaining_amount > U256::zero() {
             return Err("Insufficient staked amount".to_string());
         }
-
-        // Remove fully unstaked stakes
-        for &i in stakes_to_remove.iter().rev() {
-            staker_stakes.remove(i);
-        }
+        staker_stakes.extend(unbonding);
 
         // Update validator's stake
         if let Some(val) = consensus.validators.get_mut(&validator) {
@@ -147,6 +230,7 @@ impl StakingManager {
         }
 
         self.total_staked = self.total_staked.saturating_sub(amount);
+        consensus.staking_reward_rate = self.effective_reward_rate();
 
         log::info!(
             "Unstaked {} Abby tokens from validator {} by {}",
@@ -198,29 +282,75 @@ impl StakingManager {
         Ok(withdrawn_amount)
     }
 
+    /// Drop every unbonding stake whose withdrawal delay has passed by `now`
+    /// (unix seconds), returning the total per staker. The tokens themselves
+    /// are paid back by block processing, see `Blockchain::unbond_stake`.
+    pub fn process_matured_withdrawals(&mut self, now: u64) -> Vec<(Address, U256)> {
+        let mut released = Vec::new();
+
+        for (staker, stakes) in self.stakes.iter_mut() {
+            let mut total = U256::zero();
+            stakes.retain(|stake| match stake.withdrawal_time {
+                Some(started) if now >= started + self.withdrawal_delay => {
+                    total += stake.amount;
+                    false
+                }
+                _ => true,
+            });
+            if !total.is_zero() {
+                released.push((*staker, total));
+            }
+        }
+
+        released.sort();
+        released
+    }
+
     pub fn claim_rewards(&mut self, staker: Address, validator: Address) -> Result<U256, String> {
         let current_time = chrono::Utc::now().timestamp() as u64;
-        let staker_stakes = self
-            .stakes
-            .get_mut(&staker)
-            .ok_or("No stakes found for staker")?;
-
-        let mut total_rewards = U256::zero();
+        self.claim_rewards_at(staker, validator, current_time)
+    }
 
-        for stake in staker_stakes.iter_mut() {
-            if stake.validator == validator {
+    /// Pay out the rewards accrued since the last claim (or since staking)
+    /// up to `current_time` at the current effective reward rate, then start
+    /// a new reward period. Near the supply
+    /// cap only what can still be minted is paid; once it is reached claims
+    /// fail.
+    pub fn claim_rewards_at(
+        &mut self,
+        staker: Address,
+        validator: Address,
+        current_time: u64,
+    ) -> Result<U256, String> {
+        let annual_reward_rate = self.effective_reward_rate();
+        // Unbonding stakes stop earning
+        let accrued = |stake: &StakeInfo| {
+            (stake.validator == validator && stake.withdrawal_time.is_none()).then(|| {
                 let time_staked = current_time.saturating_sub(stake.delegation_time);
-                let pending_rewards = stake.calculate_rewards(self.annual_reward_rate, time_staked);
-                let new_rewards = pending_rewards.saturating_sub(stake.rewards_earned);
+                stake.calculate_rewards(annual_reward_rate, time_staked)
+            })
+        };
 
-                total_rewards += new_rewards;
-                stake.rewards_earned = pending_rewards;
-                stake.delegation_time = current_time; // Reset for next calculation
+        let total_accrued = self
+            .stakes
+            .get(&staker)
+            .ok_or("No stakes found for staker")?
+            .iter()
+            .filter_map(accrued)
+            .fold(U256::zero(), |acc, reward| acc + reward);
+        let total_rewards = self.mint_abby_tokens(total_accrued)?;
+
+        let mut unpaid = total_rewards;
+        for stake in self.stakes.get_mut(&staker).into_iter().flatten() {
+            if let Some(reward) = accrued(stake) {
+                let paid = reward.min(unpaid);
+                unpaid -= paid;
+                stake.rewards_earned += paid;
+                stake.delegation_time = current_time.max(stake.delegation_time);
             }
         }
 
         if total_rewards > U256::zero() {
-            self.mint_abby_tokens(total_rewards)?;
             log::info!(
                 "Claimed {} Abby token rewards for {} from validator {}",
                 self.format_abby_amount(total_rewards),
@@ -291,11 +421,22 @@ impl StakingManager {
         Ok(())
     }
 
-    fn mint_abby_tokens(&mut self, amount: U256) -> Result<(), String> {
-        // Simple inflation model - for a real implementation,
-        // this would need proper monetary policy
-        self.abby_token_supply += amount;
-        Ok(())
+    /// Mint up to `amount`, stopping at `max_supply`. Returns how much was
+    /// minted, or an error if the cap was already reached.
+    pub fn mint_abby_tokens(&mut self, amount: U256) -> Result<U256, String> {
+        if amount.is_zero() {
+            return Ok(amount);
+        }
+        if self.abby_token_supply >= self.max_supply {
+            return Err(format!(
+                "Abby token supply cap of {} reached",
+                self.format_abby_amount(self.max_supply)
+            ));
+        }
+
+        let minted = amount.min(self.max_supply - self.abby_token_supply);
+        self.abby_token_supply += minted;
+        Ok(minted)
     }
 
     fn format_abby_amount(&self, amount: U256) -> String {
@@ -306,8 +447,17 @@ impl StakingManager {
         format!("{}.{:06}", whole, fractional.as_u64())
     }
 
+    /// Reward rate in basis points for the current share of supply staked
+    pub fn effective_reward_rate(&self) -> u32 {
+        dynamic_reward_rate(
+            self.annual_reward_rate,
+            self.total_staked,
+            self.abby_token_supply,
+        )
+    }
+
     pub fn get_staking_apy(&self) -> f64 {
-        self.annual_reward_rate as f64 / 100.0
+        self.effective_reward_rate() as f64 / 100.0
     }
 
     pub fn get_total_rewards_distributed(&self) -> U256 {
@@ -324,3 +474,191 @@ impl Default for StakingManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matured_unbonding_stakes_are_dropped() {
+        let mut consensus = ConsensusState::new();
+        let mut staking = StakingManager::new();
+        let validator = Address::from_low_u64_be(0x10);
+        let staker = Address::from_low_u64_be(0x20);
+        let amount = U256::exp10(18) * 5;
+
+        staking
+            .create_validator(validator, Validator::minimum_stake(), &mut consensus)
+            .unwrap();
+        staking
+            .stake(staker, validator, amount * 2, &mut consensus)
+            .unwrap();
+        staking
+            .unstake(staker, validator, amount, &mut consensus)
+            .unwrap();
+        let unbonding_since = staking.stakes[&staker]
+            .iter()
+            .find_map(|stake| stake.withdrawal_time)
+            .unwrap();
+
+        // Nothing matures before the delay has passed
+        assert!(staking
+            .process_matured_withdrawals(unbonding_since + 1)
+            .is_empty());
+        assert_eq!(staking.stakes[&staker].len(), 2);
+
+        let later = unbonding_since + staking.withdrawal_delay;
+        assert_eq!(
+            staking.process_matured_withdrawals(later),
+            vec![(staker, amount)]
+        );
+
+        // The still-bonded half stays staked and is not released again
+        assert_eq!(staking.stakes[&staker].len(), 1);
+        assert_eq!(staking.stakes[&staker][0].amount, amount);
+        assert!(staking.process_matured_withdrawals(later).is_empty());
+    }
+
+    #[test]
+    fn test_claiming_twice_pays_each_interval_once() {
+        let mut consensus = ConsensusState::new();
+        let mut staking = StakingManager::new();
+        let validator = Address::from_low_u64_be(0x10);
+        let staker = Address::from_low_u64_be(0x20);
+        // So little of the supply is staked that the rate is at its 20%
+        // ceiling, where this stake earns exactly 2.5 * 10^13 wei per second
+        let amount = U256::from(31_536_000u64 * 125) * U256::exp10(12);
+        let per_second = U256::from(25) * U256::exp10(12);
+
+        staking
+            .create_validator(validator, Validator::minimum_stake(), &mut consensus)
+            .unwrap();
+        staking
+            .stake(staker, validator, amount, &mut consensus)
+            .unwrap();
+        let staked_at = staking.stakes[&staker][0].delegation_time;
+
+        let first = staking
+            .claim_rewards_at(staker, validator, staked_at + 1_000)
+            .unwrap();
+        assert_eq!(first, per_second * 1_000);
+
+        let second = staking
+            .claim_rewards_at(staker, validator, staked_at + 3_500)
+            .unwrap();
+        assert_eq!(second, per_second * 2_500);
+
+        // Nothing more accrues without time passing
+        let third = staking
+            .claim_rewards_at(staker, validator, staked_at + 3_500)
+            .unwrap();
+        assert!(third.is_zero());
+
+        assert_eq!(first + second, per_second * 3_500);
+        assert_eq!(staking.stakes[&staker][0].rewards_earned, first + second);
+    }
+
+    #[test]
+    fn test_apy_falls_as_more_of_the_supply_is_staked() {
+        let mut consensus = ConsensusState::new();
+        let mut staking = StakingManager::new();
+        let validator = Address::from_low_u64_be(0x10);
+        let staker = Address::from_low_u64_be(0x20);
+        staking
+            .create_validator(validator, Validator::minimum_stake(), &mut consensus)
+            .unwrap();
+
+        // Stake up to 10%, 25%, 50% (the target), 75% and 100% of supply
+        let mut apys = vec![staking.get_staking_apy()];
+        for share in [10u64, 25, 50, 75, 100] {
+            let wanted = staking.abby_token_supply * share / 100;
+            staking
+                .stake(
+                    staker,
+                    validator,
+                    wanted - staking.total_staked,
+                    &mut consensus,
+                )
+                .unwrap();
+            apys.push(staking.get_staking_apy());
+            assert_eq!(
+                consensus.staking_reward_rate,
+                staking.effective_reward_rate()
+            );
+        }
+
+        assert_eq!(apys[0], MAX_REWARD_RATE as f64 / 100.0);
+        assert!(apys.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", apys);
+        assert!(apys[2] > apys[3] && apys[3] > apys[4] && apys[4] > apys[5]);
+        assert_eq!(apys[3], DEFAULT_ANNUAL_REWARD_RATE as f64 / 100.0);
+        assert_eq!(apys[5], 4.0);
+    }
+
+    #[test]
+    fn test_minting_stops_at_supply_cap() {
+        let mut staking = StakingManager::new();
+        let room = staking.max_supply - staking.abby_token_supply;
+
+        assert_eq!(staking.mint_abby_tokens(room - 1).unwrap(), room - 1);
+        // Only what is left under the cap is minted
+        assert_eq!(
+            staking.mint_abby_tokens(U256::from(5)).unwrap(),
+            U256::one()
+        );
+        assert_eq!(staking.abby_token_supply, staking.max_supply);
+
+        assert!(staking.mint_abby_tokens(U256::one()).is_err());
+        assert_eq!(staking.abby_token_supply, staking.max_supply);
+    }
+
+    #[test]
+    fn test_rewards_stop_at_supply_cap() {
+        let mut consensus = ConsensusState::new();
+        let mut staking = StakingManager::new();
+        let validator = Address::from_low_u64_be(0x10);
+        staking
+            .create_validator(validator, Validator::minimum_stake(), &mut consensus)
+            .unwrap();
+        let staked_at = staking.stakes[&validator][0].delegation_time;
+
+        // Epoch rewards are minted from the same supply
+        let supply = staking.abby_token_supply;
+        let before = consensus.get_validator(&validator).unwrap().abby_tokens;
+        for _ in 0..consensus.slots_per_epoch {
+            consensus.advance_slot(&mut staking);
+        }
+        let rewarded = consensus.get_validator(&validator).unwrap().abby_tokens - before;
+        assert!(!rewarded.is_zero());
+        assert_eq!(staking.abby_token_supply, supply + rewarded);
+
+        // Leave room for 10 wei of rewards: a claim takes it all
+        staking.abby_token_supply = staking.max_supply - U256::from(10);
+        let paid = staking
+            .claim_rewards_at(validator, validator, staked_at + 1_000)
+            .unwrap();
+        assert_eq!(paid, U256::from(10));
+        assert_eq!(staking.stakes[&validator][0].rewards_earned, paid);
+        assert!(staking
+            .claim_rewards_at(validator, validator, staked_at + 2_000)
+            .is_err());
+
+        // ...leaving nothing for the next epoch
+        let before = consensus.get_validator(&validator).unwrap().abby_tokens;
+        for _ in 0..consensus.slots_per_epoch {
+            consensus.advance_slot(&mut staking);
+        }
+        assert_eq!(
+            consensus.get_validator(&validator).unwrap().abby_tokens,
+            before
+        );
+
+        // A lower configured cap applies to both
+        staking.max_supply = staking.abby_token_supply + U256::from(7);
+        for _ in 0..consensus.slots_per_epoch {
+            consensus.advance_slot(&mut staking);
+        }
+        let rewarded = consensus.get_validator(&validator).unwrap().abby_tokens - before;
+        assert_eq!(rewarded, U256::from(7));
+        assert_eq!(staking.abby_token_supply, staking.max_supply);
+    }
+}
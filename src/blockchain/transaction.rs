@@ -1,8 +1,17 @@
+use crate::blockchain::BlockLimits;
 use crate::types::Bytes;
+use crate::utils::{public_key_to_address, u256_to_bytes32};
 use ethereum_types::{Address, H256, U256};
+use rlp::{DecoderError, Rlp, RlpStream};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, Secp256k1, SecretKey};
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
 
+/// EIP-155 chain id of the Abby network, mixed into every signature so
+/// transactions can't be replayed on other chains
+pub const ABBY_CHAIN_ID: u64 = 1337;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub hash: H256,
@@ -16,7 +25,7 @@ pub struct Transaction {
     pub v: u64,
     pub r: U256,
     pub s: U256,
-    pub abby_fee: U256, // Fee paid in Abby tokens
+    pub abby_fee: U256, // Fee paid in Abby tokens: gas_limit * gas_price
 }
 
 impl Transaction {
@@ -41,43 +50,131 @@ impl Transaction {
             v: 0,
             r: U256::zero(),
             s: U256::zero(),
-            abby_fee: gas_limit * gas_price / U256::from(1000), // Convert to Abby tokens
+            abby_fee: U256::zero(),
         };
+        tx.abby_fee = tx.fee();
         tx.hash = tx.calculate_hash();
         tx
     }
 
+    /// Fee charged to the sender before the transaction runs, in wei of
+    /// Abby: the whole gas limit at the gas price. Saturates, so a fee too
+    /// large to represent is one no balance can cover.
+    pub fn fee(&self) -> U256 {
+        self.gas_limit.saturating_mul(self.gas_price)
+    }
+
     pub fn hash(&self) -> H256 {
         self.hash
     }
 
+    /// Bytes taken by the signed RLP encoding
+    pub fn size(&self) -> usize {
+        rlp::encode(self).len()
+    }
+
+    /// Keccak-256 of the canonical RLP encoding, signature included
     fn calculate_hash(&self) -> H256 {
-        let mut hasher = Keccak256::new();
-        hasher.update(self.from.as_bytes());
+        H256::from_slice(&Keccak256::digest(rlp::encode(self)))
+    }
 
-        if let Some(to) = self.to {
-            hasher.update(to.as_bytes());
-        }
+    /// Append the fields shared by the signed and signing-payload encodings:
+    /// `[nonce, gasPrice, gasLimit, to, value, data]`
+    fn rlp_append_payload(&self, stream: &mut RlpStream) {
+        stream.append(&self.nonce);
+        stream.append(&self.gas_price);
+        stream.append(&self.gas_limit);
+        match &self.to {
+            Some(to) => stream.append(to),
+            None => stream.append_empty_data(), // Contract creation
+        };
+        stream.append(&self.value);
+        stream.append(&self.data);
+    }
+
+    /// The EIP-155 message that gets signed:
+    /// `keccak256(rlp([nonce, gasPrice, gasLimit, to, value, data, chainId, 0, 0]))`
+    pub fn signing_hash(&self, chain_id: u64) -> H256 {
+        let mut stream = RlpStream::new_list(9);
+        self.rlp_append_payload(&mut stream);
+        stream.append(&chain_id);
+        stream.append(&0u8);
+        stream.append(&0u8);
+        H256::from_slice(&Keccak256::digest(stream.out()))
+    }
+
+    /// Chain id committed to by an EIP-155 `v` value, if the transaction is signed
+    pub fn chain_id(&self) -> Option<u64> {
+        (self.v >= 35).then(|| (self.v - 35) / 2)
+    }
+
+    /// Sign the transaction for the Abby chain, filling in `v`, `r` and `s`
+    pub fn sign(&mut self, private_key: &SecretKey) {
+        self.sign_for_chain(private_key, ABBY_CHAIN_ID);
+    }
+
+    /// Sign for an explicit chain id. `v` encodes it as `recovery_id + chain_id * 2 + 35`.
+    pub fn sign_for_chain(&mut self, private_key: &SecretKey, chain_id: u64) {
+        let message = Message::from_digest(self.signing_hash(chain_id).0);
+        let signature = Secp256k1::signing_only().sign_ecdsa_recoverable(&message, private_key);
+        let (recovery_id, compact) = signature.serialize_compact();
 
-        let mut value_bytes = [0u8; 32];
-        self.value.to_big_endian(&mut value_bytes);
-        hasher.update(value_bytes);
+        self.v = recovery_id.to_i32() as u64 + chain_id * 2 + 35;
+        self.r = U256::from_big_endian(&compact[..32]);
+        self.s = U256::from_big_endian(&compact[32..]);
+        self.hash = self.calculate_hash();
+    }
+
+    /// Recover the address that produced the signature
+    pub fn recover_sender(&self) -> Result<Address, String> {
+        let chain_id = self
+            .chain_id()
+            .ok_or_else(|| format!("Invalid EIP-155 signature value v={}", self.v))?;
+        let recovery_id = RecoveryId::from_i32(((self.v - 35) % 2) as i32)
+            .map_err(|e| format!("Invalid recovery id: {}", e))?;
+
+        let mut compact = [0u8; 64];
+        compact[..32].copy_from_slice(&u256_to_bytes32(self.r));
+        compact[32..].copy_from_slice(&u256_to_bytes32(self.s));
+        let signature = RecoverableSignature::from_compact(&compact, recovery_id)
+            .map_err(|e| format!("Malformed signature: {}", e))?;
+
+        let message = Message::from_digest(self.signing_hash(chain_id).0);
+        let public_key = Secp256k1::verification_only()
+            .recover_ecdsa(&message, &signature)
+            .map_err(|e| format!("Signature recovery failed: {}", e))?;
+
+        Ok(public_key_to_address(&public_key))
+    }
 
-        let mut gas_limit_bytes = [0u8; 32];
-        self.gas_limit.to_big_endian(&mut gas_limit_bytes);
-        hasher.update(gas_limit_bytes);
+    /// Check that the cached hash matches the contents and that the signature
+    /// was made by `from` for this chain
+    pub fn verify(&self) -> Result<(), String> {
+        self.verify_for_chain(ABBY_CHAIN_ID)
+    }
 
-        let mut gas_price_bytes = [0u8; 32];
-        self.gas_price.to_big_endian(&mut gas_price_bytes);
-        hasher.update(gas_price_bytes);
+    /// Like `verify`, for a chain configured with a different chain id
+    pub fn verify_for_chain(&self, chain_id: u64) -> Result<(), String> {
+        if self.hash != self.calculate_hash() {
+            return Err("Transaction hash does not match its contents".to_string());
+        }
 
-        hasher.update(&self.data);
+        if self.chain_id() != Some(chain_id) {
+            return Err(format!(
+                "Transaction is not signed for chain {} (v={})",
+                chain_id, self.v
+            ));
+        }
 
-        let mut nonce_bytes = [0u8; 32];
-        self.nonce.to_big_endian(&mut nonce_bytes);
-        hasher.update(nonce_bytes);
+        let signer = self.recover_sender()?;
+        if signer != self.from {
+            return Err(format!(
+                "Signature is from {:?}, not the sender {:?}",
+                signer, self.from
+            ));
+        }
 
-        H256::from_slice(&hasher.finalize())
+        Ok(())
     }
 
     pub fn is_contract_creation(&self) -> bool {
@@ -125,10 +222,97 @@ impl Transaction {
     }
 }
 
+/// Signed legacy transaction encoding: `rlp([nonce, gasPrice, gasLimit, to, value, data, v, r, s])`
+impl rlp::Encodable for Transaction {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(9);
+        self.rlp_append_payload(stream);
+        stream.append(&self.v);
+        stream.append(&self.r);
+        stream.append(&self.s);
+    }
+}
+
+/// Decode a signed legacy transaction, recovering `from` from its signature
+impl rlp::Decodable for Transaction {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 9 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let to = {
+            let to = rlp.at(3)?;
+            if to.is_empty() {
+                None
+            } else {
+                Some(to.as_val()?)
+            }
+        };
+        let gas_limit: U256 = rlp.val_at(2)?;
+        let gas_price: U256 = rlp.val_at(1)?;
+
+        let mut tx = Self {
+            hash: H256::zero(),
+            from: Address::zero(),
+            to,
+            value: rlp.val_at(4)?,
+            gas_limit,
+            gas_price,
+            data: rlp.val_at(5)?,
+            nonce: rlp.val_at(0)?,
+            v: rlp.val_at(6)?,
+            r: rlp.val_at(7)?,
+            s: rlp.val_at(8)?,
+            abby_fee: U256::zero(),
+        };
+        tx.abby_fee = tx.fee();
+        tx.hash = tx.calculate_hash();
+        tx.from = tx
+            .recover_sender()
+            .map_err(|_| DecoderError::Custom("invalid transaction signature"))?;
+        Ok(tx)
+    }
+}
+
+/// Minimum gas price increase, in percent, for a transaction to replace a
+/// pending one with the same sender and nonce
+pub const DEFAULT_MIN_REPLACEMENT_BUMP: u64 = 10;
+
+/// Number of pending transactions a pool holds before it starts evicting
+pub const DEFAULT_MAX_POOL_SIZE: usize = 4096;
+
+fn default_max_pool_size() -> usize {
+    DEFAULT_MAX_POOL_SIZE
+}
+
+fn default_min_replacement_bump() -> u64 {
+    DEFAULT_MIN_REPLACEMENT_BUMP
+}
+
+fn default_chain_id() -> u64 {
+    ABBY_CHAIN_ID
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionPool {
     pub pending: std::collections::HashMap<H256, Transaction>,
     pub queued: std::collections::HashMap<Address, Vec<Transaction>>,
+    /// Unix timestamp (seconds) at which each pending transaction entered the pool
+    #[serde(default)]
+    pub received_at: std::collections::HashMap<H256, u64>,
+    /// Percentage by which a replacement must raise the gas price
+    #[serde(default = "default_min_replacement_bump")]
+    pub min_replacement_bump: u64,
+    /// Chain id that incoming transactions must be signed for
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
+    /// Most pending transactions kept; when full, the cheapest is evicted
+    #[serde(default = "default_max_pool_size")]
+    pub max_size: usize,
+    /// Next on-chain nonce of each sender the pool has been told about;
+    /// transactions below it were already executed
+    #[serde(default)]
+    pub account_nonces: std::collections::HashMap<Address, U256>,
 }
 
 impl Default for TransactionPool {
@@ -142,13 +326,92 @@ impl TransactionPool {
         Self {
             pending: std::collections::HashMap::new(),
             queued: std::collections::HashMap::new(),
+            received_at: std::collections::HashMap::new(),
+            min_replacement_bump: DEFAULT_MIN_REPLACEMENT_BUMP,
+            chain_id: ABBY_CHAIN_ID,
+            max_size: DEFAULT_MAX_POOL_SIZE,
+            account_nonces: std::collections::HashMap::new(),
         }
     }
 
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn with_min_replacement_bump(mut self, percent: u64) -> Self {
+        self.min_replacement_bump = percent;
+        self
+    }
+
+    /// Add a transaction to the pending set. A transaction with the same
+    /// sender and nonce as a pending one replaces it only if its gas price is
+    /// at least `min_replacement_bump` percent higher. A full pool makes room
+    /// by evicting its cheapest transaction, provided the new one pays more.
+    /// Nonces the sender already used on chain are rejected.
     pub fn add_transaction(&mut self, tx: Transaction) -> Result<(), String> {
         tx.validate()?;
+        tx.verify_for_chain(self.chain_id)?;
+
+        let account_nonce = self.account_nonce(&tx.from);
+        if tx.nonce < account_nonce {
+            return Err(format!(
+                "Nonce too low: {} is below the account nonce {}",
+                tx.nonce, account_nonce
+            ));
+        }
+
+        let hash = tx.hash();
+        let replaced = self
+            .pending
+            .values()
+            .find(|pending| {
+                pending.from == tx.from && pending.nonce == tx.nonce && pending.hash() != hash
+            })
+            .map(|pending| (pending.hash(), pending.gas_price));
+
+        if let Some((old_hash, old_gas_price)) = replaced {
+            let bump = U256::from(100 + self.min_replacement_bump);
+            let required = old_gas_price
+                .saturating_mul(bump)
+                .saturating_add(U256::from(99))
+                / 100;
+            if tx.gas_price <= old_gas_price || tx.gas_price < required {
+                return Err(format!(
+                    "Replacement transaction underpriced: gas price {} must be at least {} ({}% above {})",
+                    tx.gas_price, required, self.min_replacement_bump, old_gas_price
+                ));
+            }
+            self.remove_transaction(&old_hash);
+        }
+
+        if self.pending.len() >= self.max_size {
+            let cheapest = self
+                .pending
+                .values()
+                .min_by_key(|pending| (pending.gas_price, pending.hash()))
+                .map(|pending| (pending.hash(), pending.gas_price));
+            match cheapest {
+                Some((cheapest_hash, cheapest_price)) if tx.gas_price > cheapest_price => {
+                    self.remove_transaction(&cheapest_hash);
+                }
+                _ => {
+                    return Err(format!(
+                        "Transaction pool is full ({} transactions) and gas price {} does not beat its cheapest",
+                        self.max_size, tx.gas_price
+                    ));
+                }
+            }
+        }
 
-        self.pending.insert(tx.hash(), tx);
+        self.pending.insert(hash, tx);
+        self.received_at
+            .insert(hash, chrono::Utc::now().timestamp() as u64);
         Ok(())
     }
 
@@ -157,25 +420,87 @@ impl TransactionPool {
     }
 
     pub fn remove_transaction(&mut self, hash: &H256) -> Option<Transaction> {
+        self.received_at.remove(hash);
         self.pending.remove(hash)
     }
 
+    /// Drop pending transactions that have been in the pool for longer than
+    /// `max_age` seconds. Returns the number of transactions removed.
+    pub fn prune_expired(&mut self, max_age: u64) -> usize {
+        let now = chrono::Utc::now().timestamp() as u64;
+        let expired: Vec<H256> = self
+            .pending
+            .keys()
+            .filter(|hash| {
+                let received = self.received_at.get(*hash).copied().unwrap_or(0);
+                now.saturating_sub(received) > max_age
+            })
+            .copied()
+            .collect();
+
+        for hash in &expired {
+            self.remove_transaction(hash);
+        }
+
+        expired.len()
+    }
+
+    /// Next nonce `address` has on chain, as far as the pool knows
+    pub fn account_nonce(&self, address: &Address) -> U256 {
+        self.account_nonces
+            .get(address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Record that `address` has used every nonce below `nonce` on chain and
+    /// drop its pending transactions that can no longer be included: those
+    /// already mined and any competitors for the same nonces. Returns the
+    /// number of transactions removed.
+    pub fn set_account_nonce(&mut self, address: Address, nonce: U256) -> usize {
+        self.account_nonces.insert(address, nonce);
+        let spent: Vec<H256> = self
+            .pending
+            .values()
+            .filter(|tx| tx.from == address && tx.nonce < nonce)
+            .map(|tx| tx.hash())
+            .collect();
+
+        for hash in &spent {
+            self.remove_transaction(hash);
+        }
+
+        spent.len()
+    }
+
     pub fn get_pending_transactions(&self) -> Vec<&Transaction> {
         self.pending.values().collect()
     }
 
-    pub fn select_transactions_for_block(&self, gas_limit: U256) -> Vec<Transaction> {
+    /// Highest-paying pending transactions that fit in a block with
+    /// `gas_limit`, within the transaction count and byte size of `limits`
+    pub fn select_transactions_for_block(
+        &self,
+        gas_limit: U256,
+        limits: &BlockLimits,
+    ) -> Vec<Transaction> {
         let mut selected = Vec::new();
         let mut total_gas = U256::zero();
+        let mut total_size = 0;
 
         // Sort by gas price (highest first) for simple transaction selection
         let mut transactions: Vec<_> = self.pending.values().collect();
-        transactions.sort_by(|a, b| b.gas_price.cmp(&a.gas_price));
+        transactions.sort_by_key(|tx| std::cmp::Reverse(tx.gas_price));
 
         for tx in transactions {
-            if total_gas + tx.gas_limit <= gas_limit {
+            if selected.len() == limits.max_transactions {
+                break;
+            }
+            let size = tx.size();
+            if total_gas + tx.gas_limit <= gas_limit && total_size + size <= limits.max_size {
                 selected.push(tx.clone());
                 total_gas += tx.gas_limit;
+                total_size += size;
             }
         }
 
@@ -185,6 +510,7 @@ impl TransactionPool {
     pub fn clear(&mut self) {
         self.pending.clear();
         self.queued.clear();
+        self.received_at.clear();
     }
 
     pub fn len(&self) -> usize {
@@ -194,4 +520,306 @@ impl TransactionPool {
     pub fn is_empty(&self) -> bool {
         self.pending.is_empty()
     }
+
+    pub fn is_full(&self) -> bool {
+        self.pending.len() >= self.max_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_key() -> SecretKey {
+        SecretKey::from_slice(&[0x42; 32]).unwrap()
+    }
+
+    fn test_transaction(nonce: u64) -> Transaction {
+        test_transaction_with_gas_price(nonce, 1)
+    }
+
+    fn test_transaction_with_gas_price(nonce: u64, gas_price: u64) -> Transaction {
+        let key = test_key();
+        let from = public_key_to_address(&key.public_key(&Secp256k1::signing_only()));
+        let mut tx = Transaction::new(
+            from,
+            Some(Address::random()),
+            U256::from(1),
+            U256::from(21000),
+            U256::from(gas_price),
+            Vec::new(),
+            U256::from(nonce),
+        );
+        tx.sign(&key);
+        tx
+    }
+
+    #[test]
+    fn test_signature_recovers_sender() {
+        // Private key 1 is the generator point, whose address is well known
+        let key = SecretKey::from_slice(&u256_to_bytes32(U256::one())).unwrap();
+        let from: Address = "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+            .parse()
+            .unwrap();
+        let mut tx = Transaction::new(
+            from,
+            None,
+            U256::zero(),
+            U256::from(21000),
+            U256::from(1),
+            vec![0x60, 0x00],
+            U256::zero(),
+        );
+        tx.sign(&key);
+
+        assert_eq!(tx.chain_id(), Some(ABBY_CHAIN_ID));
+        assert_eq!(tx.recover_sender().unwrap(), from);
+        assert!(tx.verify().is_ok());
+
+        // The same transaction signed for another chain must not be accepted here
+        tx.sign_for_chain(&key, 1);
+        assert_eq!(tx.recover_sender().unwrap(), from);
+        assert!(tx.verify().is_err());
+    }
+
+    #[test]
+    fn test_rlp_encoding_matches_eip155_example() {
+        // Example transaction from the EIP-155 specification
+        let key = SecretKey::from_slice(&[0x46; 32]).unwrap();
+        let from: Address = "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"
+            .parse()
+            .unwrap();
+        let mut tx = Transaction::new(
+            from,
+            Some(Address::repeat_byte(0x35)),
+            U256::exp10(18),
+            U256::from(21000),
+            U256::from(20_000_000_000u64),
+            Vec::new(),
+            U256::from(9),
+        );
+
+        assert_eq!(
+            hex::encode(tx.signing_hash(1)),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+
+        tx.sign_for_chain(&key, 1);
+        let expected = hex::decode(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+        )
+        .unwrap();
+
+        assert_eq!(tx.v, 37);
+        assert_eq!(rlp::encode(&tx).to_vec(), expected);
+        assert_eq!(tx.hash(), H256::from_slice(&Keccak256::digest(&expected)));
+        assert_eq!(tx.recover_sender().unwrap(), from);
+
+        let decoded: Transaction = rlp::decode(&expected).unwrap();
+        assert_eq!(decoded.from, from);
+        assert_eq!(decoded.hash(), tx.hash());
+        assert_eq!(decoded.value, tx.value);
+    }
+
+    #[test]
+    fn test_pool_rejects_tampered_and_unsigned_transactions() {
+        let mut pool = TransactionPool::new();
+
+        let mut tampered = test_transaction(0);
+        tampered.value = U256::from(1_000_000);
+        tampered.hash = tampered.calculate_hash();
+        assert!(pool.add_transaction(tampered).is_err());
+
+        let mut unsigned = test_transaction(1);
+        unsigned.v = 0;
+        assert!(pool.add_transaction(unsigned).is_err());
+
+        assert!(pool.add_transaction(test_transaction(2)).is_ok());
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_prune_expired_drops_only_stale_transactions() {
+        let mut pool = TransactionPool::new();
+        let stale = test_transaction(0);
+        let fresh = test_transaction(1);
+        pool.add_transaction(stale.clone()).unwrap();
+        pool.add_transaction(fresh.clone()).unwrap();
+
+        // Pretend the stale transaction arrived two hours ago
+        let now = chrono::Utc::now().timestamp() as u64;
+        pool.received_at.insert(stale.hash(), now - 2 * 60 * 60);
+
+        assert_eq!(pool.prune_expired(60 * 60), 1);
+        assert!(pool.get_transaction(&stale.hash()).is_none());
+        assert!(pool.get_transaction(&fresh.hash()).is_some());
+        assert!(!pool.received_at.contains_key(&stale.hash()));
+    }
+
+    #[test]
+    fn test_spent_nonces_are_purged_and_rejected() {
+        let mut pool = TransactionPool::new();
+        let first = test_transaction(0);
+        let second = test_transaction(1);
+        pool.add_transaction(first.clone()).unwrap();
+        pool.add_transaction(second.clone()).unwrap();
+
+        // Nonce 0 was mined elsewhere
+        assert_eq!(pool.set_account_nonce(first.from, U256::one()), 1);
+        assert!(pool.get_transaction(&first.hash()).is_none());
+        assert!(pool.get_transaction(&second.hash()).is_some());
+
+        let err = pool
+            .add_transaction(test_transaction_with_gas_price(0, 1_000))
+            .unwrap_err();
+        assert!(err.contains("Nonce too low"), "{}", err);
+    }
+
+    #[test]
+    fn test_fee_bump_replaces_pending_transaction() {
+        let mut pool = TransactionPool::new();
+        let original = test_transaction_with_gas_price(0, 100);
+        let replacement = test_transaction_with_gas_price(0, 112);
+        pool.add_transaction(original.clone()).unwrap();
+
+        pool.add_transaction(replacement.clone()).unwrap();
+
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get_transaction(&original.hash()).is_none());
+        assert!(pool.get_transaction(&replacement.hash()).is_some());
+        assert!(!pool.received_at.contains_key(&original.hash()));
+    }
+
+    #[test]
+    fn test_insufficient_fee_bump_is_rejected() {
+        let mut pool = TransactionPool::new().with_min_replacement_bump(10);
+        let original = test_transaction_with_gas_price(0, 100);
+        pool.add_transaction(original.clone()).unwrap();
+
+        let err = pool
+            .add_transaction(test_transaction_with_gas_price(0, 105))
+            .unwrap_err();
+
+        assert!(err.contains("underpriced"), "{}", err);
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get_transaction(&original.hash()).is_some());
+    }
+
+    #[test]
+    fn test_fee_bump_near_max_gas_price_does_not_overflow() {
+        let mut pool = TransactionPool::new();
+        let mut original = test_transaction(0);
+        original.gas_price = U256::MAX - 1;
+        original.sign(&test_key());
+        let mut replacement = test_transaction(0);
+        replacement.gas_price = U256::MAX;
+        replacement.sign(&test_key());
+        pool.add_transaction(original.clone()).unwrap();
+
+        pool.add_transaction(replacement.clone()).unwrap();
+
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get_transaction(&replacement.hash()).is_some());
+    }
+
+    #[test]
+    fn test_pool_rejects_transaction_signed_for_another_chain() {
+        let mut pool = TransactionPool::new().with_chain_id(1);
+
+        let mut foreign = test_transaction(0);
+        foreign.sign_for_chain(&test_key(), 2);
+        let err = pool.add_transaction(foreign.clone()).unwrap_err();
+        assert!(err.contains("not signed for chain 1"), "{}", err);
+        assert!(foreign.verify_for_chain(2).is_ok());
+
+        let mut local = test_transaction(0);
+        local.sign_for_chain(&test_key(), 1);
+        assert!(pool.add_transaction(local).is_ok());
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_full_pool_evicts_cheapest_transaction() {
+        let mut pool = TransactionPool::new().with_max_size(3);
+        let cheapest = test_transaction_with_gas_price(1, 3);
+        for tx in [
+            test_transaction_with_gas_price(0, 5),
+            cheapest.clone(),
+            test_transaction_with_gas_price(2, 7),
+        ] {
+            pool.add_transaction(tx).unwrap();
+        }
+        assert!(pool.is_full());
+
+        // Outbidding the cheapest transaction takes its place
+        let richer = test_transaction_with_gas_price(3, 4);
+        pool.add_transaction(richer.clone()).unwrap();
+        assert_eq!(pool.len(), 3);
+        assert!(pool.get_transaction(&cheapest.hash()).is_none());
+        assert!(!pool.received_at.contains_key(&cheapest.hash()));
+        assert!(pool.get_transaction(&richer.hash()).is_some());
+
+        // Paying no more than the cheapest now pending is refused
+        for gas_price in [2, 4] {
+            let err = pool
+                .add_transaction(test_transaction_with_gas_price(4, gas_price))
+                .unwrap_err();
+            assert!(err.contains("pool is full"), "{}", err);
+        }
+        assert_eq!(pool.len(), 3);
+        assert!(pool.get_transaction(&richer.hash()).is_some());
+    }
+
+    #[test]
+    fn test_block_selection_respects_byte_budget() {
+        use crate::blockchain::{Block, BlockHeader};
+
+        let mut pool = TransactionPool::new();
+        for nonce in 0..10 {
+            let mut tx = test_transaction_with_gas_price(nonce, 10 + nonce);
+            tx.data = vec![0xaa; 10_000];
+            tx.sign(&test_key());
+            pool.add_transaction(tx).unwrap();
+        }
+        let tx_size = pool.get_pending_transactions()[0].size();
+        assert!(tx_size > 10_000);
+        let limits = BlockLimits {
+            max_transactions: 1000,
+            max_size: tx_size * 3 + tx_size / 2,
+        };
+
+        // Only three fit, and they are the best paying ones
+        let selected = pool.select_transactions_for_block(U256::from(10_000_000u64), &limits);
+        let nonces: Vec<_> = selected.iter().map(|tx| tx.nonce.as_u64()).collect();
+        assert_eq!(nonces, [9, 8, 7]);
+
+        let header =
+            || BlockHeader::new(1, H256::zero(), Address::zero(), U256::from(10_000_000u64));
+        assert!(Block::new(header(), selected)
+            .validate_with_limits(&limits)
+            .is_ok());
+
+        let everything: Vec<_> = pool
+            .get_pending_transactions()
+            .into_iter()
+            .cloned()
+            .collect();
+        let err = Block::new(header(), everything)
+            .validate_with_limits(&limits)
+            .unwrap_err();
+        assert!(err.contains("exceeds the limit"), "{}", err);
+
+        // The transaction count is capped as well
+        let limits = BlockLimits {
+            max_transactions: 2,
+            max_size: usize::MAX,
+        };
+        assert_eq!(
+            pool.select_transactions_for_block(U256::from(10_000_000u64), &limits)
+                .len(),
+            2
+        );
+    }
 }
@@ -1,6 +1,17 @@
 use anyhow::Result;
 use colored::Colorize;
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use crate::abi::{ParamType, Token};
+use crate::compiler::Compiler;
+use crate::evm::{ConsoleSink, EvmExecutor, ExecutionContext, PersistentState};
+use crate::examples::{Example, ExampleRegistry};
+use crate::types::ExecutionResult;
 
 pub fn interactive_mode() -> Result<()> {
     println!("{}", "🎮 Interactive EVM Mode".bright_cyan().bold());
@@ -10,6 +21,9 @@ pub fn interactive_mode() -> Result<()> {
     );
     println!("{}", "─".repeat(50).bright_blue());
 
+    // Storage and accounts persist across commands until `reset`
+    let mut world = PersistentState::default();
+
     loop {
         print!("{} ", "evm>".bright_green().bold());
         io::stdout().flush()?;
@@ -30,13 +44,27 @@ pub fn interactive_mode() -> Result<()> {
             "help" | "h" => {
                 print_help();
             }
-            "examples" | "ex" => {
-                list_examples();
+            "examples" | "ex" => match ExampleRegistry::load_default() {
+                Ok(registry) => list_examples(&registry),
+                Err(e) => println!("{}: {}", "Error".bright_red().bold(), e),
+            },
+            "reset" => {
+                world = PersistentState::default();
+                println!("{}", "State cleared".bright_yellow());
+            }
+            "storage" => {
+                print_storage(&world);
+            }
+            input if input.starts_with("balance ") => {
+                let address = input.split_whitespace().nth(1).unwrap_or("");
+                if let Err(e) = print_balance(&world, address) {
+                    println!("{}: {}", "Error".bright_red().bold(), e);
+                }
             }
             input if input.starts_with("execute ") || input.starts_with("exec ") => {
                 let bytecode = input.split_whitespace().nth(1).unwrap_or("");
                 if !bytecode.is_empty() {
-                    if let Err(e) = execute_interactive_bytecode(bytecode) {
+                    if let Err(e) = execute_interactive_bytecode(bytecode, &mut world) {
                         println!("{}: {}", "Error".bright_red().bold(), e);
                     }
                 } else {
@@ -46,6 +74,24 @@ pub fn interactive_mode() -> Result<()> {
                     );
                 }
             }
+            input if input.starts_with("compile ") => {
+                let source = input["compile ".len()..].trim();
+                if let Err(e) = compile_interactive_expression(source) {
+                    println!("{}: {}", "Error".bright_red().bold(), e);
+                }
+            }
+            input if input.starts_with("run ") => {
+                let source = input["run ".len()..].trim();
+                if let Err(e) = run_interactive_expression(source) {
+                    println!("{}: {}", "Error".bright_red().bold(), e);
+                }
+            }
+            input if input.starts_with("debug ") => {
+                let bytecode = input.split_whitespace().nth(1).unwrap_or("");
+                if let Err(e) = debug_interactive_bytecode(bytecode) {
+                    println!("{}: {}", "Error".bright_red().bold(), e);
+                }
+            }
             input if input.starts_with("analyze ") => {
                 let bytecode = input.split_whitespace().nth(1).unwrap_or("");
                 if !bytecode.is_empty() {
@@ -81,6 +127,27 @@ fn print_help() {
         "  {} - Analyze bytecode without execution",
         "analyze <bytecode>".bright_green()
     );
+    println!(
+        "  {} - Step through bytecode one opcode at a time",
+        "debug <bytecode>".bright_green()
+    );
+    println!(
+        "  {} - Compile an AbbyScript expression to bytecode",
+        "compile <expr>".bright_green()
+    );
+    println!(
+        "  {} - Compile and execute an AbbyScript expression",
+        "run <expr>".bright_green()
+    );
+    println!(
+        "  {} - Show storage written by earlier commands",
+        "storage".bright_green()
+    );
+    println!(
+        "  {} - Show an account balance",
+        "balance <address>".bright_green()
+    );
+    println!("  {} - Clear storage and accounts", "reset".bright_green());
     println!("  {} - List available examples", "examples".bright_green());
     println!("  {} - Show this help message", "help".bright_green());
     println!("  {} - Exit the interactive mode", "quit".bright_green());
@@ -88,23 +155,187 @@ fn print_help() {
     println!("{}", "Examples:".bright_yellow().bold());
     println!("  execute 6001600201    # Execute simple addition");
     println!("  analyze 6001600201    # Analyze without execution");
+    println!("  debug 6001600201      # Single-step the addition");
+    println!("  execute 602a600055    # Store 42 in slot 0, then run 'storage'");
+    println!("  compile 1 + 2 * 3     # Show the bytecode for an expression");
+    println!("  run 1 + 2 * 3         # Evaluate an expression (prints 7)");
     println!("  examples              # Show example contracts");
 }
 
-fn execute_interactive_bytecode(bytecode_hex: &str) -> Result<()> {
+fn execute_interactive_bytecode(
+    bytecode_hex: &str,
+    world: &mut PersistentState,
+) -> Result<ExecutionResult> {
     use crate::display_execution_result;
-    use crate::evm::EvmExecutor;
+    use crate::evm::{EvmExecutor, ExecutionContext};
 
     let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
     let mut executor = EvmExecutor::new(1000000);
 
     println!("🔄 {}", "Executing...".bright_green());
-    let result = executor.execute(&bytecode, 0, false)?;
+    let result =
+        executor.execute_with_state(&bytecode, &ExecutionContext::default(), world, false)?;
+    display_execution_result(&result);
+
+    Ok(result)
+}
+
+fn compile_interactive_expression(source: &str) -> Result<Vec<u8>> {
+    use crate::compiler::Compiler;
+
+    let bytecode = Compiler::new().compile_expression(source)?;
+    println!(
+        "{}: {}",
+        "Bytecode".bright_cyan().bold(),
+        hex::encode(&bytecode)
+    );
+
+    Ok(bytecode)
+}
+
+/// Compile `source` and run it, returning the value the expression left on
+/// top of the stack
+fn run_interactive_expression(source: &str) -> Result<Option<crate::types::Word>> {
+    use crate::display_execution_result;
+
+    let bytecode = compile_interactive_expression(source)?;
+    let executor = EvmExecutor::new(1000000);
+    let mut debugger = executor.debugger(&bytecode, &ExecutionContext::default());
+    debugger.run_to_end();
+
+    let result = debugger.result();
     display_execution_result(&result);
+    let value = debugger.stack().last().copied();
+    if let Some(value) = value {
+        println!("{}: {}", "Result".bright_green().bold(), value);
+    }
+
+    Ok(value)
+}
+
+fn print_storage(world: &PersistentState) {
+    if world.storage.is_empty() {
+        println!("Storage is empty");
+        return;
+    }
+
+    let mut slots: Vec<_> = world.storage.iter().collect();
+    slots.sort();
+    for (slot, value) in slots {
+        println!("  [0x{:x}] = 0x{:x}", slot, value);
+    }
+}
+
+fn print_balance(world: &PersistentState, address: &str) -> Result<()> {
+    use crate::abi::{parse_token, ParamType, Token};
+
+    let address = match parse_token(&ParamType::Address, address)? {
+        Token::Address(address) => address,
+        _ => unreachable!(),
+    };
+    let balance = world
+        .accounts
+        .get(&address)
+        .map(|account| account.balance)
+        .unwrap_or_default();
+    println!("  {:?}: {} wei", address, balance);
 
     Ok(())
 }
 
+fn debug_interactive_bytecode(bytecode_hex: &str) -> Result<()> {
+    use crate::display_execution_result;
+    use crate::evm::{EvmExecutor, ExecutionContext};
+
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    let executor = EvmExecutor::new(1000000);
+    let mut debugger = executor.debugger(&bytecode, &ExecutionContext::default());
+
+    println!(
+        "{}",
+        "Commands: step (s, or Enter), continue (c), stack, mem <off> <len>, quit (q)"
+            .bright_yellow()
+    );
+    print_debugger_position(&debugger);
+
+    while !debugger.is_finished() {
+        print!("{} ", "debug>".bright_magenta().bold());
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            break;
+        }
+        let words: Vec<&str> = input.split_whitespace().collect();
+
+        match words.as_slice() {
+            [] | ["step"] | ["s"] => {
+                if let Some(step) = debugger.step() {
+                    print_debug_step(&step);
+                }
+                print_debugger_position(&debugger);
+            }
+            ["continue"] | ["c"] => {
+                for step in debugger.run_to_end() {
+                    print_debug_step(&step);
+                }
+            }
+            ["stack"] => print_debug_stack(debugger.stack()),
+            ["mem", offset, len] => match (offset.parse::<usize>(), len.parse::<usize>()) {
+                (Ok(offset), Ok(len)) if len <= 4096 => {
+                    let memory = debugger.memory(offset, len);
+                    for (i, chunk) in memory.chunks(32).enumerate() {
+                        println!("  {:04x}: {}", offset + i * 32, hex::encode(chunk));
+                    }
+                }
+                _ => println!(
+                    "{}: usage: mem <offset> <len> (len up to 4096)",
+                    "Error".bright_red().bold()
+                ),
+            },
+            ["quit"] | ["q"] => return Ok(()),
+            _ => println!("{}: Unknown debugger command", "Error".bright_red().bold()),
+        }
+    }
+
+    println!();
+    display_execution_result(&debugger.result());
+    Ok(())
+}
+
+fn print_debug_step(step: &crate::evm::DebugStep) {
+    println!(
+        "  {:04x}: {:<12} gas {} -> {}",
+        step.pc,
+        format!("{:?}", step.opcode),
+        step.gas_before,
+        step.gas_after
+    );
+    print_debug_stack(&step.stack);
+}
+
+fn print_debug_stack(stack: &[ethereum_types::U256]) {
+    if stack.is_empty() {
+        println!("    stack: (empty)");
+    }
+    // Top of the stack first
+    for (depth, word) in stack.iter().rev().enumerate() {
+        println!("    stack[{}]: 0x{:x}", depth, word);
+    }
+}
+
+fn print_debugger_position(debugger: &crate::evm::Debugger) {
+    if let Some(opcode) = debugger.next_opcode() {
+        println!(
+            "{} {:04x}: {:?} (gas {})",
+            "next".bright_cyan(),
+            debugger.pc(),
+            opcode,
+            debugger.gas()
+        );
+    }
+}
+
 fn analyze_interactive_bytecode(bytecode_hex: &str) -> Result<()> {
     let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
 
@@ -124,73 +355,57 @@ fn analyze_interactive_bytecode(bytecode_hex: &str) -> Result<()> {
 }
 
 fn disassemble_bytecode(bytecode: &[u8]) {
-    use crate::opcodes::OpCode;
-
-    let mut pc = 0;
-    while pc < bytecode.len() {
-        let opcode = OpCode::from_byte(bytecode[pc]);
-        print!("{:04x}: {:02x} {:?}", pc, bytecode[pc], opcode);
-
-        if let Some(size) = opcode.push_size() {
-            if pc + size < bytecode.len() {
-                let data = &bytecode[pc + 1..pc + 1 + size];
-                print!(" 0x{}", hex::encode(data));
-                pc += size;
-            }
-        }
-
-        println!();
-        pc += 1;
+    for line in crate::opcodes::disassembler::disassemble(bytecode) {
+        println!("{}", line);
     }
 }
 
 pub fn examples_command(list: bool) -> Result<()> {
+    let registry = ExampleRegistry::load_default()?;
     if list {
-        list_examples();
+        list_examples(&registry);
     } else {
         println!("{}", "🧪 Running Example Contracts".bright_cyan().bold());
         println!("{}", "─".repeat(40).bright_cyan());
 
         // Run all examples
-        run_example("simple-add", "Simple Addition (1 + 2)")?;
-        run_example("simple-mul", "Simple Multiplication (2 * 3)")?;
-        run_example("storage", "Storage Operations")?;
+        for example in registry.examples() {
+            run_example(&registry, example)?;
+        }
     }
 
     Ok(())
 }
 
-fn list_examples() {
+fn list_examples(registry: &ExampleRegistry) {
     println!("{}", "📚 Available Examples:".bright_cyan().bold());
-    println!(
-        "  {} - Simple addition (1 + 2)",
-        "simple-add".bright_green()
-    );
-    println!(
-        "  {} - Simple multiplication (2 * 3)",
-        "simple-mul".bright_green()
-    );
-    println!(
-        "  {} - Storage read/write operations",
-        "storage".bright_green()
-    );
+    for example in registry.examples() {
+        println!(
+            "  {} - {}",
+            example.name.bright_green(),
+            example.description
+        );
+    }
     println!();
     println!("{}", "Usage:".bright_yellow().bold());
-    println!("  cargo run -- execute --example simple-add");
-    println!("  cargo run -- execute --example simple-mul");
-    println!("  cargo run -- execute --example storage");
+    for example in registry.examples() {
+        println!("  cargo run -- execute --example {}", example.name);
+    }
 }
 
-fn run_example(example: &str, description: &str) -> Result<()> {
+fn run_example(registry: &ExampleRegistry, example: &Example) -> Result<()> {
+    use crate::display_execution_result;
     use crate::evm::EvmExecutor;
-    use crate::{display_execution_result, get_example_bytecode};
 
-    println!("\n{}: {}", "Example".bright_yellow().bold(), description);
+    println!(
+        "\n{}: {}",
+        "Example".bright_yellow().bold(),
+        example.description
+    );
 
-    let bytecode_hex = get_example_bytecode(example)?;
-    println!("Bytecode: {}", bytecode_hex.bright_blue());
+    let bytecode = registry.bytecode(&example.name)?;
+    println!("Bytecode: {}", hex::encode(&bytecode).bright_blue());
 
-    let bytecode = hex::decode(&bytecode_hex)?;
     let mut executor = EvmExecutor::new(1000000);
 
     let result = executor.execute(&bytecode, 0, false)?;
@@ -199,7 +414,16 @@ fn run_example(example: &str, description: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn analyze_command(bytecode_hex: String) -> Result<()> {
+pub fn analyze_command(bytecode_hex: String, json: bool) -> Result<()> {
+    if json {
+        let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&analysis_json(&bytecode))?
+        );
+        return Ok(());
+    }
+
     println!("{}", "🔍 Bytecode Analysis".bright_cyan().bold());
     println!("{}", "═".repeat(50).bright_blue());
 
@@ -213,77 +437,603 @@ pub fn analyze_command(bytecode_hex: String) -> Result<()> {
     println!("🔧 {}", "Disassembly:".bright_green().bold());
     disassemble_with_details(&bytecode);
 
+    print_function_selectors(&bytecode);
+
     println!("\n⛽ {}", "Gas Analysis:".bright_magenta().bold());
     analyze_gas_usage(&bytecode);
 
     Ok(())
 }
 
+pub fn cfg_command(bytecode_hex: String) -> Result<()> {
+    use crate::opcodes::cfg::ControlFlowGraph;
+
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    print!("{}", ControlFlowGraph::build(&bytecode).to_dot());
+
+    Ok(())
+}
+
+pub fn disassemble_command(bytecode_hex: String, output: Option<&Path>) -> Result<()> {
+    use crate::opcodes::disassembler::disassemble;
+
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    let mut text = disassemble(&bytecode).join("\n");
+    text.push('\n');
+    match output {
+        Some(path) => std::fs::write(path, text)?,
+        None => print!("{}", text),
+    }
+
+    Ok(())
+}
+
+pub fn assemble_command(file: &Path, output: Option<&Path>) -> Result<()> {
+    use crate::opcodes::assembler::assemble;
+
+    let source = if file == Path::new("-") {
+        let mut source = String::new();
+        io::Read::read_to_string(&mut io::stdin(), &mut source)?;
+        source
+    } else {
+        std::fs::read_to_string(file)?
+    };
+    let bytecode = hex::encode(assemble(&source)?);
+    match output {
+        Some(path) => std::fs::write(path, format!("{}\n", bytecode))?,
+        None => println!("{}", bytecode),
+    }
+
+    Ok(())
+}
+
+pub fn lint_command(bytecode_hex: String, json: bool) -> Result<()> {
+    use crate::opcodes::validate::validate_bytecode;
+
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    let issues = validate_bytecode(&bytecode).err().unwrap_or_default();
+
+    if json {
+        let issues: Vec<_> = issues
+            .iter()
+            .map(|issue| serde_json::json!({ "pc": issue.pc, "message": issue.to_string() }))
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "issues": issues }))?
+        );
+    } else if issues.is_empty() {
+        println!("{}", "✅ No issues found".bright_green());
+    } else {
+        println!("{}", "⚠️  Bytecode issues:".bright_yellow().bold());
+        for issue in &issues {
+            println!("  {}", issue);
+        }
+    }
+
+    if !issues.is_empty() {
+        anyhow::bail!("{} issue(s) found", issues.len());
+    }
+    Ok(())
+}
+
+pub fn create2_address_command(deployer: String, salt: String, init_code: String) -> Result<()> {
+    use crate::abi::{parse_token, ParamType, Token};
+    use ethereum_types::H256;
+
+    println!("{}", "🔮 CREATE2 Address Prediction".bright_cyan().bold());
+    println!("{}", "═".repeat(50).bright_blue());
+
+    let deployer = match parse_token(&ParamType::Address, &deployer)? {
+        Token::Address(address) => address,
+        _ => unreachable!(),
+    };
+
+    let salt_bytes = hex::decode(salt.trim_start_matches("0x"))?;
+    if salt_bytes.len() > 32 {
+        anyhow::bail!(
+            "Salt must be at most 32 bytes, got {} bytes",
+            salt_bytes.len()
+        );
+    }
+    let mut salt_word = [0u8; 32];
+    salt_word[32 - salt_bytes.len()..].copy_from_slice(&salt_bytes);
+    let salt = H256::from(salt_word);
+
+    let init_code = hex::decode(init_code.trim_start_matches("0x"))?;
+    let address = crate::utils::create2_address(deployer, salt, &init_code);
+
+    println!("  Deployer: {:?}", deployer);
+    println!("  Salt: {:?}", salt);
+    println!("  Init code: {} bytes", init_code.len());
+    println!();
+    println!(
+        "📍 {}: {:?}",
+        "Predicted address".bright_green().bold(),
+        address
+    );
+
+    Ok(())
+}
+
+/// Decode return data as the given ABI types, one display string per value
+pub fn decode_output(types: &[ParamType], return_data: &[u8]) -> Result<Vec<String>> {
+    let tokens = crate::abi::decode(types, return_data)
+        .map_err(|e| anyhow::anyhow!("Failed to decode return data: {}", e))?;
+    Ok(tokens.iter().map(Token::to_string).collect())
+}
+
+pub fn display_decoded_output(types: &[ParamType], values: &[String]) {
+    println!("\n🔍 Decoded Output:");
+    for (param, value) in types.iter().zip(values) {
+        println!("  {}: {}", param, value.bright_blue());
+    }
+}
+
+/// Wall-clock timings of repeated runs of the same bytecode
+#[derive(Debug)]
+pub struct BenchmarkReport {
+    pub iterations: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+    /// Instructions executed per run
+    pub steps: usize,
+    /// Console output of one run. Timed runs discard theirs, so printing
+    /// does not end up in the timings.
+    pub console: Vec<String>,
+}
+
+impl BenchmarkReport {
+    /// Instructions per second at the median run time
+    pub fn opcodes_per_second(&self) -> f64 {
+        self.steps as f64 / self.median.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Execute `bytecode` `iterations` times, each on a fresh executor so no
+/// storage carries over from one run to the next
+pub fn run_benchmark(
+    bytecode: &[u8],
+    context: &ExecutionContext,
+    gas_limit: u64,
+    iterations: usize,
+) -> Result<BenchmarkReport> {
+    if iterations == 0 {
+        anyhow::bail!("Iterations must be at least 1");
+    }
+
+    // An untimed run collects the console output once
+    let sink = ConsoleSink::buffer();
+    EvmExecutor::new(gas_limit)
+        .with_console_sink(sink.clone())
+        .execute_with_context(bytecode, context, false)?;
+
+    let mut timings = Vec::with_capacity(iterations);
+    let mut steps = 0;
+    for _ in 0..iterations {
+        let mut executor =
+            EvmExecutor::new(gas_limit).with_console_sink(ConsoleSink::callback(|_| {}));
+        let start = Instant::now();
+        executor.execute_with_context(bytecode, context, false)?;
+        timings.push(start.elapsed());
+        steps = executor.executed_steps();
+    }
+    timings.sort();
+
+    Ok(BenchmarkReport {
+        iterations,
+        min: timings[0],
+        median: timings[iterations / 2],
+        max: timings[iterations - 1],
+        steps,
+        console: sink.lines(),
+    })
+}
+
+pub fn benchmark_command(
+    bytecode_hex: String,
+    context: ExecutionContext,
+    gas_limit: u64,
+    iterations: usize,
+    json: bool,
+) -> Result<()> {
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    let report = run_benchmark(&bytecode, &context, gas_limit, iterations)?;
+
+    if json {
+        let output = serde_json::json!({
+            "iterations": report.iterations,
+            "steps": report.steps,
+            "min_ns": report.min.as_nanos() as u64,
+            "median_ns": report.median.as_nanos() as u64,
+            "max_ns": report.max.as_nanos() as u64,
+            "opcodes_per_second": report.opcodes_per_second(),
+            "console": report.console,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    for line in &report.console {
+        println!("{}", line);
+    }
+    println!("{}", "⏱️  Benchmark".bright_cyan().bold());
+    println!("{}", "─".repeat(30).bright_cyan());
+    println!("Iterations: {}", report.iterations);
+    println!("Instructions per run: {}", report.steps);
+    println!("Min: {:?}", report.min);
+    println!("Median: {:?}", report.median);
+    println!("Max: {:?}", report.max);
+    println!(
+        "Opcodes/sec: {}",
+        format!("{:.0}", report.opcodes_per_second()).bright_green()
+    );
+
+    Ok(())
+}
+
 fn disassemble_with_details(bytecode: &[u8]) {
-    use crate::opcodes::OpCode;
+    use crate::opcodes::disassembler::{
+        decode, format_instruction, jump_annotation, jump_destinations, jump_label,
+    };
 
-    let mut pc = 0;
+    let instructions = decode(bytecode);
+    let destinations = jump_destinations(&instructions);
     let mut total_gas = ethereum_types::U256::zero();
 
-    while pc < bytecode.len() {
-        let opcode = OpCode::from_byte(bytecode[pc]);
-        let gas_cost = opcode.gas_cost();
+    for (index, instruction) in instructions.iter().enumerate() {
+        let gas_cost = instruction.opcode.gas_cost();
         total_gas += gas_cost;
 
-        print!("  {:04x}: ", pc);
-        print!("{:02x} ", bytecode[pc]);
-        print!("{:12} ", format!("{:?}", opcode));
-
-        if let Some(size) = opcode.push_size() {
-            if pc + size < bytecode.len() {
-                let data = &bytecode[pc + 1..pc + 1 + size];
-                print!("0x{:20} ", hex::encode(data));
-                pc += size;
-            } else {
-                print!("{:22} ", "");
-            }
-        } else {
-            print!("{:22} ", "");
+        if destinations.contains(&instruction.pc) {
+            println!(
+                "{}",
+                format!("{}:", jump_label(instruction.pc)).bright_yellow()
+            );
         }
 
-        println!("(gas: {})", gas_cost);
-        pc += 1;
+        print!("  {:04x}: ", instruction.pc);
+        print!("{:02x} ", instruction.opcode.to_byte());
+        print!("{:30} ", format_instruction(instruction));
+        print!("(gas: {})", gas_cost);
+        println!(
+            "{}",
+            jump_annotation(&instructions, index, &destinations).bright_blue()
+        );
     }
 
     println!("\nEstimated minimum gas: {}", total_gas);
 }
 
-fn analyze_gas_usage(bytecode: &[u8]) {
-    use crate::opcodes::OpCode;
+fn print_function_selectors(bytecode: &[u8]) {
+    use crate::opcodes::disassembler::{decode, function_selectors};
+
+    let selectors = function_selectors(&decode(bytecode));
+    if selectors.is_empty() {
+        return;
+    }
+
+    println!("\n🔑 {}", "Function Selectors:".bright_cyan().bold());
+    for selector in selectors {
+        match crate::abi::known_signature(selector) {
+            Some(signature) => println!("  0x{}  {}", hex::encode(selector), signature),
+            None => println!("  0x{}", hex::encode(selector)),
+        }
+    }
+}
+
+fn analysis_json(bytecode: &[u8]) -> serde_json::Value {
+    use crate::opcodes::disassembler::{
+        decode, function_selectors, jump_destinations, jump_label, static_jump_target,
+    };
+
+    let instructions = decode(bytecode);
+    let destinations = jump_destinations(&instructions);
+    let disassembly: Vec<_> = instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| {
+            let mut entry = serde_json::json!({
+                "pc": instruction.pc,
+                "opcode": format!("{:?}", instruction.opcode),
+                "gas": instruction.opcode.gas_cost().low_u64(),
+            });
+            if instruction.opcode.push_size().is_some() {
+                entry["immediate"] = format!("0x{}", hex::encode(&instruction.immediate)).into();
+            }
+            if destinations.contains(&instruction.pc) {
+                entry["label"] = jump_label(instruction.pc).into();
+            }
+            if let Some(target) = static_jump_target(&instructions, index) {
+                entry["jump_target"] = if destinations.contains(&target) {
+                    jump_label(target).into()
+                } else {
+                    serde_json::Value::Null
+                };
+            }
+            entry
+        })
+        .collect();
+
+    let (total_gas, breakdown) = gas_breakdown(bytecode);
+    let gas_by_opcode: Vec<_> = breakdown
+        .into_iter()
+        .map(|(opcode, count, gas)| serde_json::json!({ "opcode": opcode, "count": count, "gas": gas }))
+        .collect();
+
+    let selectors: Vec<_> = function_selectors(&instructions)
+        .into_iter()
+        .map(|selector| {
+            serde_json::json!({
+                "selector": format!("0x{}", hex::encode(selector)),
+                "signature": crate::abi::known_signature(selector),
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "length": bytecode.len(),
+        "bytecode": format!("0x{}", hex::encode(bytecode)),
+        "disassembly": disassembly,
+        "function_selectors": selectors,
+        "total_gas": total_gas,
+        "gas_by_opcode": gas_by_opcode,
+    })
+}
+
+/// Static gas per opcode as (opcode, count, total gas), most expensive first
+fn gas_breakdown(bytecode: &[u8]) -> (u64, Vec<(String, u64, u64)>) {
+    use crate::opcodes::disassembler::decode;
     use std::collections::HashMap;
 
     let mut gas_by_opcode: HashMap<String, (u64, u64)> = HashMap::new(); // (count, total_gas)
-    let mut pc = 0;
     let mut total_gas = 0u64;
 
-    while pc < bytecode.len() {
-        let opcode = OpCode::from_byte(bytecode[pc]);
-        let gas_cost = opcode.gas_cost().low_u64();
+    for instruction in decode(bytecode) {
+        let gas_cost = instruction.opcode.gas_cost().low_u64();
         total_gas += gas_cost;
 
-        let opcode_str = format!("{:?}", opcode);
-        let entry = gas_by_opcode.entry(opcode_str).or_insert((0, 0));
+        let entry = gas_by_opcode
+            .entry(format!("{:?}", instruction.opcode))
+            .or_insert((0, 0));
         entry.0 += 1; // count
         entry.1 += gas_cost; // total gas
-
-        if opcode.push_size().is_some() {
-            pc += opcode.push_size().unwrap();
-        }
-        pc += 1;
     }
 
+    let mut sorted: Vec<_> = gas_by_opcode
+        .into_iter()
+        .map(|(opcode, (count, gas))| (opcode, count, gas))
+        .collect();
+    // Sort by total gas desc, then by name so the order is stable
+    sorted.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+    (total_gas, sorted)
+}
+
+fn analyze_gas_usage(bytecode: &[u8]) {
+    let (total_gas, breakdown) = gas_breakdown(bytecode);
+
     println!("  Total estimated gas: {}", total_gas);
     println!("  Gas breakdown by opcode:");
 
-    let mut sorted_opcodes: Vec<_> = gas_by_opcode.iter().collect();
-    sorted_opcodes.sort_by(|a, b| b.1 .1.cmp(&a.1 .1)); // Sort by total gas desc
-
-    for (opcode, (count, gas)) in sorted_opcodes.into_iter().take(10) {
+    for (opcode, count, gas) in breakdown.into_iter().take(10) {
         println!("    {:12}: {} uses, {} gas total", opcode, count, gas);
     }
 }
+
+/// How long a file must stay unchanged before a save triggers a recompile.
+/// Editors often write a file in several steps.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Run `recompile` now and again after every save of `path` or of a file it
+/// imports, until the watcher stops. Errors are reported without ending the
+/// watch. The imports are looked up again after each run, so files added to
+/// or dropped from them are picked up.
+pub fn watch_command(path: &Path, mut recompile: impl FnMut() -> Result<()>) -> Result<()> {
+    let path = std::fs::canonicalize(path)?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mut watched_dirs = BTreeSet::new();
+    let mut files = BTreeSet::from([path.clone()]);
+
+    let mut run = || {
+        if let Err(e) = recompile() {
+            eprintln!("{}", format!("Error: {}", e).bright_red());
+        }
+        // While the file does not parse its imports are unknown, so keep
+        // watching the ones found last time
+        if let Ok(sources) = Compiler::new().source_files(&path) {
+            files = sources.into_iter().collect();
+        }
+        // Watch directories: saving by renaming a temporary file over the
+        // original would end a watch on the file itself
+        for dir in files.iter().filter_map(|file| file.parent()) {
+            if watched_dirs.insert(dir.to_path_buf()) {
+                if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    eprintln!(
+                        "{}",
+                        format!("Cannot watch {}: {}", dir.display(), e).bright_red()
+                    );
+                }
+            }
+        }
+        println!(
+            "\n{}",
+            format!(
+                "👀 Watching {} and {} imported file(s) for changes...",
+                path.display(),
+                files.len() - 1
+            )
+            .bright_yellow()
+        );
+        files.clone()
+    };
+    let files = run();
+    watch_events(files, &rx, WATCH_DEBOUNCE, run);
+    Ok(())
+}
+
+/// Call `on_change` once for each burst of events touching one of `files`,
+/// where a burst ends after `debounce` without events. `on_change` returns
+/// the files to watch from then on. Returns how many times it was called
+/// once the event channel closes.
+fn watch_events(
+    mut files: BTreeSet<PathBuf>,
+    events: &Receiver<notify::Result<notify::Event>>,
+    debounce: Duration,
+    mut on_change: impl FnMut() -> BTreeSet<PathBuf>,
+) -> usize {
+    let touches = |files: &BTreeSet<PathBuf>, event: &notify::Result<notify::Event>| matches!(event, Ok(event) if !event.kind.is_access() && event.paths.iter().any(|p| files.contains(p)));
+
+    let mut changes = 0;
+    while let Ok(event) = events.recv() {
+        if !touches(&files, &event) {
+            continue;
+        }
+        while events.recv_timeout(debounce).is_ok() {}
+        files = on_change();
+        changes += 1;
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::U256;
+
+    #[test]
+    fn test_storage_persists_between_interactive_commands() {
+        let mut world = PersistentState::default();
+
+        // PUSH1 42, PUSH1 0, SSTORE
+        execute_interactive_bytecode("602a600055", &mut world).unwrap();
+        assert_eq!(world.storage.get(&U256::zero()), Some(&U256::from(42)));
+
+        // SLOAD slot 0 and return it as a word
+        let result = execute_interactive_bytecode("60005460005260206000f3", &mut world).unwrap();
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::from(42));
+
+        // A fresh state no longer sees the write
+        let result =
+            execute_interactive_bytecode("60005460005260206000f3", &mut PersistentState::default())
+                .unwrap();
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+    }
+
+    #[test]
+    fn test_run_evaluates_an_expression_to_the_top_of_the_stack() {
+        assert_eq!(
+            run_interactive_expression("1 + 2 * 3").unwrap(),
+            Some(U256::from(7))
+        );
+        assert!(run_interactive_expression("1 +").is_err());
+    }
+
+    #[test]
+    fn test_return_data_decodes_to_typed_values() {
+        // Return 1000 as a single word
+        let bytecode = hex::decode("6103e860005260206000f3").unwrap();
+        let result = EvmExecutor::new(100_000)
+            .execute(&bytecode, 0, false)
+            .unwrap();
+
+        let types = ParamType::parse_list("uint256").unwrap();
+        assert_eq!(
+            decode_output(&types, &result.return_data).unwrap(),
+            vec!["1000"]
+        );
+
+        // Asking for more values than were returned is an error
+        let types = ParamType::parse_list("uint256,address").unwrap();
+        assert!(decode_output(&types, &result.return_data).is_err());
+    }
+
+    #[test]
+    fn test_benchmark_reports_timings_for_every_run() {
+        // A 10-iteration countdown loop that stores its counter each pass
+        let bytecode = hex::decode("600a5b8060005560019003806002570000").unwrap();
+        let report = run_benchmark(&bytecode, &ExecutionContext::new(), 1_000_000, 5).unwrap();
+
+        assert_eq!(report.iterations, 5);
+        assert!(report.steps > 0);
+        assert!(report.min <= report.median && report.median <= report.max);
+        assert!(report.max > Duration::ZERO);
+        assert!(report.opcodes_per_second() > 0.0);
+
+        assert!(run_benchmark(&bytecode, &ExecutionContext::new(), 1_000_000, 0).is_err());
+    }
+
+    #[test]
+    fn test_benchmark_keeps_console_output_of_a_single_run() {
+        // memory[0..2] = "hi"; LOG0(0, 2)
+        let bytecode = hex::decode("6068600053606960015360026000a0").unwrap();
+        let report = run_benchmark(&bytecode, &ExecutionContext::new(), 1_000_000, 5).unwrap();
+
+        assert_eq!(report.console, vec!["console.log: hi".to_string()]);
+    }
+
+    #[test]
+    fn test_saves_of_watched_file_trigger_one_recompile() {
+        use notify::event::{EventKind, ModifyKind};
+
+        let source = BTreeSet::from([PathBuf::from("/contracts/token.abby")]);
+        let modified = |path: &str| {
+            Ok(notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.into()))
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        // Two quick writes from one save, then a change to another file
+        tx.send(modified("/contracts/token.abby")).unwrap();
+        tx.send(modified("/contracts/token.abby")).unwrap();
+        tx.send(modified("/contracts/other.abby")).unwrap();
+        drop(tx);
+
+        let mut recompiled = 0;
+        let changes = watch_events(source.clone(), &rx, Duration::from_millis(10), || {
+            recompiled += 1;
+            source.clone()
+        });
+        assert_eq!((changes, recompiled), (1, 1));
+
+        // Changes to other files alone never recompile
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(modified("/contracts/other.abby")).unwrap();
+        drop(tx);
+        assert_eq!(
+            watch_events(source.clone(), &rx, Duration::from_millis(10), || source
+                .clone()),
+            0
+        );
+    }
+
+    #[test]
+    fn test_saves_of_imported_files_trigger_a_recompile() {
+        use notify::event::{EventKind, ModifyKind};
+
+        let modified = |path: &str| -> notify::Result<notify::Event> {
+            Ok(notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.into()))
+        };
+        let main = PathBuf::from("/contracts/main.abby");
+        let lib = PathBuf::from("/lib/math.abby");
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        // Recompiling finds that main.abby now imports math.abby, which is
+        // saved afterwards
+        tx.send(modified("/contracts/main.abby")).unwrap();
+        let mut tx = Some(tx);
+        let changes = watch_events(
+            BTreeSet::from([main.clone()]),
+            &rx,
+            Duration::from_millis(10),
+            || {
+                if let Some(tx) = tx.take() {
+                    tx.send(modified("/lib/math.abby")).unwrap();
+                }
+                BTreeSet::from([main.clone(), lib.clone()])
+            },
+        );
+        assert_eq!(changes, 2);
+    }
+}
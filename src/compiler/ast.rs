@@ -1,5 +1,24 @@
 use std::fmt;
 
+/// Source position of an AST node (1-based line and column)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 /// Represents the entire program
 #[derive(Debug, Clone)]
 pub struct Program {
@@ -16,6 +35,13 @@ pub enum Statement {
     WhileStmt(WhileStmt),
     ReturnStmt(ReturnStmt),
     Block(Block),
+    MappingDecl(MappingDecl),
+    Emit(EmitStmt),
+    Import(ImportStmt),
+    /// break; at the given position
+    Break(Span),
+    /// continue; at the given position
+    Continue(Span),
 }
 
 /// Expression types in AbbyScript
@@ -33,6 +59,8 @@ pub enum Expression {
     ArrayAccess(ArrayAccessExpr),
     MemoryAssignment(MemoryAssignmentExpr),
     StorageArrayAssignment(StorageArrayAssignmentExpr),
+    MappingAssignment(MappingAssignmentExpr),
+    Conditional(ConditionalExpr),
 }
 
 /// Variable declaration: let x = expression;
@@ -50,6 +78,28 @@ pub struct FuncDecl {
     pub body: Block,
 }
 
+/// Mapping declaration: mapping name;
+#[derive(Debug, Clone)]
+pub struct MappingDecl {
+    pub name: String,
+    pub span: Span,
+}
+
+/// Import of another source file: import "path.abby";
+#[derive(Debug, Clone)]
+pub struct ImportStmt {
+    pub path: String,
+    pub span: Span,
+}
+
+/// Event emission: emit EventName(topic1, topic2, data);
+#[derive(Debug, Clone)]
+pub struct EmitStmt {
+    pub event: String,
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
+
 /// Expression statement: expression;
 #[derive(Debug, Clone)]
 pub struct ExprStmt {
@@ -91,6 +141,14 @@ pub struct BinaryExpr {
     pub right: Box<Expression>,
 }
 
+/// Conditional expression: condition ? then_value : else_value
+#[derive(Debug, Clone)]
+pub struct ConditionalExpr {
+    pub condition: Box<Expression>,
+    pub then_value: Box<Expression>,
+    pub else_value: Box<Expression>,
+}
+
 /// Binary operators
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOperator {
@@ -135,12 +193,14 @@ pub struct CallExpr {
 pub struct AssignmentExpr {
     pub name: String,
     pub value: Box<Expression>,
+    pub span: Span,
 }
 
 /// Variable reference: name
 #[derive(Debug, Clone)]
 pub struct VariableExpr {
     pub name: String,
+    pub span: Span,
 }
 
 /// Literal values
@@ -157,6 +217,7 @@ pub enum LiteralExpr {
 pub struct MemberAccessExpr {
     pub object: Box<Expression>,
     pub property: String,
+    pub span: Span,
 }
 
 /// Storage access: storage.get(key) or storage.set(key, value)
@@ -193,6 +254,15 @@ pub struct StorageArrayAssignmentExpr {
     pub value: Box<Expression>,
 }
 
+/// Mapping assignment: name[key] = value
+#[derive(Debug, Clone)]
+pub struct MappingAssignmentExpr {
+    pub name: String,
+    pub key: Box<Expression>,
+    pub value: Box<Expression>,
+    pub span: Span,
+}
+
 impl fmt::Display for BinaryOperator {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -232,6 +302,18 @@ impl Expression {
         })
     }
 
+    pub fn conditional(
+        condition: Expression,
+        then_value: Expression,
+        else_value: Expression,
+    ) -> Self {
+        Expression::Conditional(ConditionalExpr {
+            condition: Box::new(condition),
+            then_value: Box::new(then_value),
+            else_value: Box::new(else_value),
+        })
+    }
+
     pub fn unary(operator: UnaryOperator, operand: Expression) -> Self {
         Expression::Unary(UnaryExpr {
             operator,
@@ -246,15 +328,16 @@ impl Expression {
         })
     }
 
-    pub fn assignment(name: String, value: Expression) -> Self {
+    pub fn assignment(name: String, value: Expression, span: Span) -> Self {
         Expression::Assignment(AssignmentExpr {
             name,
             value: Box::new(value),
+            span,
         })
     }
 
-    pub fn variable(name: String) -> Self {
-        Expression::Variable(VariableExpr { name })
+    pub fn variable(name: String, span: Span) -> Self {
+        Expression::Variable(VariableExpr { name, span })
     }
 
     pub fn number(value: u64) -> Self {
@@ -280,10 +363,11 @@ impl Expression {
         })
     }
 
-    pub fn member_access(object: Expression, property: String) -> Self {
+    pub fn member_access(object: Expression, property: String, span: Span) -> Self {
         Expression::MemberAccess(MemberAccessExpr {
             object: Box::new(object),
             property,
+            span,
         })
     }
 
@@ -299,6 +383,20 @@ impl Expression {
             value: Box::new(value),
         })
     }
+
+    pub fn mapping_assignment(
+        name: String,
+        key: Expression,
+        value: Expression,
+        span: Span,
+    ) -> Self {
+        Expression::MappingAssignment(MappingAssignmentExpr {
+            name,
+            key: Box::new(key),
+            value: Box::new(value),
+            span,
+        })
+    }
 }
 
 impl Statement {
@@ -340,6 +438,51 @@ impl Statement {
     pub fn block(statements: Vec<Statement>) -> Self {
         Statement::Block(Block { statements })
     }
+
+    pub fn mapping_decl(name: String, span: Span) -> Self {
+        Statement::MappingDecl(MappingDecl { name, span })
+    }
+
+    pub fn import(path: String, span: Span) -> Self {
+        Statement::Import(ImportStmt { path, span })
+    }
+
+    pub fn emit(event: String, arguments: Vec<Expression>, span: Span) -> Self {
+        Statement::Emit(EmitStmt {
+            event,
+            arguments,
+            span,
+        })
+    }
+
+    /// Short human-readable name of the statement kind, for diagnostics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Statement::VarDecl(_) => "variable declaration",
+            Statement::FuncDecl(_) => "function declaration",
+            Statement::ExprStmt(_) => "expression",
+            Statement::IfStmt(_) => "if",
+            Statement::WhileStmt(_) => "while",
+            Statement::ReturnStmt(_) => "return",
+            Statement::Block(_) => "block",
+            Statement::MappingDecl(_) => "mapping declaration",
+            Statement::Emit(_) => "emit",
+            Statement::Import(_) => "import",
+            Statement::Break(_) => "break",
+            Statement::Continue(_) => "continue",
+        }
+    }
+
+    /// Source position of the statement, where the parser recorded one
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::MappingDecl(mapping_decl) => Some(mapping_decl.span),
+            Statement::Emit(emit_stmt) => Some(emit_stmt.span),
+            Statement::Import(import_stmt) => Some(import_stmt.span),
+            Statement::Break(span) | Statement::Continue(span) => Some(*span),
+            _ => None,
+        }
+    }
 }
 
 // Visitor pattern for AST traversal
@@ -355,6 +498,12 @@ pub struct AstPrinter {
     output_buffer: String,
 }
 
+impl Default for AstPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AstPrinter {
     pub fn new() -> Self {
         Self {
@@ -433,6 +582,21 @@ impl AstVisitor<String> for AstPrinter {
                 format!("ReturnStmt {{ value: {:?} }}", return_stmt.value)
             }
             Statement::Block(_) => "Block { ... }".to_string(),
+            Statement::MappingDecl(mapping_decl) => {
+                format!("MappingDecl {{ name: {} }}", mapping_decl.name)
+            }
+            Statement::Emit(emit_stmt) => {
+                format!(
+                    "Emit {{ event: {}, arguments: {} }}",
+                    emit_stmt.event,
+                    emit_stmt.arguments.len()
+                )
+            }
+            Statement::Import(import_stmt) => {
+                format!("Import {{ path: {:?} }}", import_stmt.path)
+            }
+            Statement::Break(_) => "Break".to_string(),
+            Statement::Continue(_) => "Continue".to_string(),
         }
     }
 
@@ -535,6 +699,22 @@ impl AstVisitor<String> for AstPrinter {
                     self.visit_expression(&storage_assign.value)
                 )
             }
+            Expression::MappingAssignment(mapping_assign) => {
+                format!(
+                    "{}[{}] = {}",
+                    mapping_assign.name,
+                    self.visit_expression(&mapping_assign.key),
+                    self.visit_expression(&mapping_assign.value)
+                )
+            }
+            Expression::Conditional(conditional) => {
+                format!(
+                    "({} ? {} : {})",
+                    self.visit_expression(&conditional.condition),
+                    self.visit_expression(&conditional.then_value),
+                    self.visit_expression(&conditional.else_value)
+                )
+            }
         }
     }
 }
@@ -1,6 +1,7 @@
 use crate::compiler::ast::*;
 use crate::opcodes::OpCode;
 use ethereum_types::U256;
+use sha3::{Digest, Keccak256};
 use std::collections::HashMap;
 
 struct PendingJump {
@@ -13,22 +14,138 @@ pub struct CodeGenerator {
     pub bytecode: Vec<u8>,
     variables: HashMap<String, u16>, // Variable name -> stack offset
     functions: HashMap<String, u16>, // Function name -> bytecode address
-    stack_depth: u16,
+    mappings: HashMap<String, u16>,  // Mapping name -> base storage slot
+    stack_depth: i32,
     next_var_slot: u16,
     jump_labels: HashMap<String, u16>, // Jump label -> address
     next_label_id: u32,
     memory_pointer: u16, // Current memory position for implicit allocation
     pending_jumps: Vec<PendingJump>, // Jump fixup information
+    dispatcher: bool,    // Route calls by 4-byte ABI selector
+    loops: Vec<LoopLabels>, // Enclosing loops, innermost last
+    stack_check: StackCheck,
+}
+
+/// Jump targets for `continue` and `break` inside a loop
+struct LoopLabels {
+    continue_label: String,
+    break_label: String,
+}
+
+/// Independent model of the stack depth, derived from the opcodes actually
+/// emitted, used to validate the hand-maintained `stack_depth` bookkeeping
+#[derive(Default)]
+struct StackCheck {
+    enabled: bool,
+    depth: Option<i32>, // None while the code being emitted is unreachable
+    label_depths: HashMap<String, i32>,
+    violation: Option<String>,
+}
+
+impl StackCheck {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            depth: Some(0),
+            ..Self::default()
+        }
+    }
+
+    fn record(&mut self, opcode: &OpCode) {
+        if let Some(depth) = self.depth {
+            let (inputs, outputs) = opcode.stack_effect();
+            if depth < inputs as i32 && self.violation.is_none() {
+                self.violation = Some(format!(
+                    "{:?} pops {} items but only {} are on the stack",
+                    opcode, inputs, depth
+                ));
+            }
+            self.depth = Some(depth - inputs as i32 + outputs as i32);
+        }
+
+        if matches!(
+            opcode,
+            OpCode::STOP
+                | OpCode::JUMP
+                | OpCode::RETURN
+                | OpCode::REVERT
+                | OpCode::INVALID
+                | OpCode::SELFDESTRUCT
+        ) {
+            self.depth = None;
+        }
+    }
+
+    /// Every path into a label must arrive with the same stack depth
+    fn arrive(&mut self, label: &str, depth: i32) {
+        match self.label_depths.get(label) {
+            Some(&expected) if expected != depth => {
+                if self.violation.is_none() {
+                    self.violation = Some(format!(
+                        "paths into '{}' disagree on stack depth ({} vs {})",
+                        label, expected, depth
+                    ));
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.label_depths.insert(label.to_string(), depth);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct CompileError {
     pub message: String,
+    pub span: Option<Span>,
+    pub snippet: Option<String>,
+}
+
+impl CompileError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+            snippet: None,
+        }
+    }
+
+    /// An error attributed to a specific source position
+    pub fn at(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span: Some(span),
+            ..Self::new(message)
+        }
+    }
+
+    /// Attach the offending source line so it can be shown alongside the error
+    pub fn with_source(mut self, source: &str) -> Self {
+        if let Some(span) = self.span {
+            self.snippet = source
+                .lines()
+                .nth(span.line.saturating_sub(1))
+                .map(str::to_string);
+        }
+        self
+    }
 }
 
 impl std::fmt::Display for CompileError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Compile error: {}", self.message)
+        match self.span {
+            Some(span) => write!(f, "Compile error at line {}: {}", span, self.message)?,
+            None => write!(f, "Compile error: {}", self.message)?,
+        }
+        if let (Some(span), Some(snippet)) = (self.span, &self.snippet) {
+            write!(
+                f,
+                "\n    {}\n    {}^",
+                snippet,
+                " ".repeat(span.column.saturating_sub(1))
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -36,23 +153,44 @@ impl std::error::Error for CompileError {}
 
 type CompileResult<T> = Result<T, CompileError>;
 
+impl Default for CodeGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CodeGenerator {
     pub fn new() -> Self {
         Self {
             bytecode: Vec::new(),
             variables: HashMap::new(),
             functions: HashMap::new(),
+            mappings: HashMap::new(),
             stack_depth: 0,
             next_var_slot: 0,
             jump_labels: HashMap::new(),
             next_label_id: 0,
             memory_pointer: 0x80, // Start at 0x80 (common EVM convention)
             pending_jumps: Vec::new(),
+            dispatcher: false,
+            loops: Vec::new(),
+            stack_check: StackCheck::new(cfg!(debug_assertions)),
         }
     }
 
+    /// Compile the program as a contract: top-level functions become public
+    /// entry points selected by the first 4 bytes of calldata
+    pub fn with_dispatcher(mut self, enabled: bool) -> Self {
+        self.dispatcher = enabled;
+        self
+    }
+
     pub fn compile(&mut self, program: &Program) -> CompileResult<Vec<u8>> {
-        self.visit_program(program)?;
+        if self.dispatcher {
+            self.visit_contract(program)?;
+        } else {
+            self.visit_program(program)?;
+        }
 
         // Fix up all pending jumps
         self.fixup_jumps()?;
@@ -65,6 +203,43 @@ impl CodeGenerator {
         Ok(self.bytecode.clone())
     }
 
+    /// Compile the program and wrap it in init code that deploys it: executing
+    /// the result returns the runtime bytecode, which becomes the contract code
+    pub fn compile_deployable(&mut self, program: &Program) -> CompileResult<Vec<u8>> {
+        let runtime = self.compile(program)?;
+        if runtime.len() > u16::MAX as usize {
+            return Err(CompileError::new(format!(
+                "Runtime code is too large to deploy ({} bytes)",
+                runtime.len()
+            )));
+        }
+
+        // Fixed-width prologue so the runtime offset is known up front
+        const PROLOGUE_LEN: u16 = 13;
+        let size = (runtime.len() as u16).to_be_bytes();
+        let offset = PROLOGUE_LEN.to_be_bytes();
+
+        let mut init = vec![
+            OpCode::PUSH2.to_byte(), // size
+            size[0],
+            size[1],
+            OpCode::DUP1.to_byte(),
+            OpCode::PUSH2.to_byte(), // code offset
+            offset[0],
+            offset[1],
+            OpCode::PUSH1.to_byte(), // memory offset
+            0x00,
+            OpCode::CODECOPY.to_byte(),
+            OpCode::PUSH1.to_byte(),
+            0x00,
+            OpCode::RETURN.to_byte(),
+        ];
+        debug_assert_eq!(init.len(), PROLOGUE_LEN as usize);
+
+        init.extend(runtime);
+        Ok(init)
+    }
+
     fn fixup_jumps(&mut self) -> CompileResult<()> {
         for jump in &self.pending_jumps {
             if let Some(&target_addr) = self.jump_labels.get(&jump.label) {
@@ -76,9 +251,10 @@ impl CodeGenerator {
                 self.bytecode[jump.data_start_pos] = high_byte;
                 self.bytecode[jump.data_start_pos + 1] = low_byte;
             } else {
-                return Err(CompileError {
-                    message: format!("Undefined jump label: {}", jump.label),
-                });
+                return Err(CompileError::new(format!(
+                    "Undefined jump label: {}",
+                    jump.label
+                )));
             }
         }
         Ok(())
@@ -103,7 +279,106 @@ impl CodeGenerator {
         Ok(())
     }
 
+    fn visit_contract(&mut self, program: &Program) -> CompileResult<()> {
+        let mut functions = Vec::new();
+        for stmt in &program.statements {
+            match stmt {
+                Statement::FuncDecl(func_decl) => functions.push(func_decl),
+                Statement::MappingDecl(mapping_decl) => self.visit_mapping_decl(mapping_decl)?,
+                _ => {
+                    return Err(CompileError::new(
+                        "Only function and mapping declarations are allowed at the top level of a contract",
+                    ))
+                }
+            }
+        }
+
+        // selector = calldata[0..4]
+        self.emit_push_u256(U256::zero());
+        self.emit_opcode(OpCode::CALLDATALOAD);
+        self.emit_push_u256(U256::from(0xe0));
+        self.emit_opcode(OpCode::SHR);
+        self.stack_depth += 1;
+
+        let mut labels = Vec::new();
+        for func_decl in &functions {
+            let signature = format!(
+                "{}({})",
+                func_decl.name,
+                vec!["uint256"; func_decl.params.len()].join(",")
+            );
+            let selector = crate::abi::selector(&signature);
+            let label = self.generate_label(&format!("fn_{}", func_decl.name));
+
+            self.emit_opcode(OpCode::DUP1);
+            self.emit_push_u256(U256::from_big_endian(&selector));
+            self.emit_opcode(OpCode::EQ);
+            self.stack_depth += 1;
+            self.emit_jump_if(&label);
+            labels.push(label);
+        }
+
+        // No selector matched
+        self.emit_push_u256(U256::zero());
+        self.emit_push_u256(U256::zero());
+        self.emit_opcode(OpCode::REVERT);
+
+        for (func_decl, label) in functions.iter().zip(&labels) {
+            self.place_label(label);
+            self.emit_opcode(OpCode::POP); // Drop the selector
+            self.stack_depth = 0;
+            self.visit_func_decl(func_decl)?;
+        }
+
+        Ok(())
+    }
+
     fn visit_statement(&mut self, stmt: &Statement) -> CompileResult<()> {
+        self.visit_statement_inner(stmt)?;
+        self.check_stack_model(stmt)
+    }
+
+    /// Verify the tracked `stack_depth` still agrees with the net effect of
+    /// the opcodes emitted so far
+    fn check_stack_model(&mut self, stmt: &Statement) -> CompileResult<()> {
+        if !self.stack_check.enabled {
+            return Ok(());
+        }
+
+        let problem = if let Some(violation) = self.stack_check.violation.take() {
+            Some(violation)
+        } else if self.stack_depth < 0 {
+            Some(format!(
+                "tracked stack depth went negative ({})",
+                self.stack_depth
+            ))
+        } else {
+            match self.stack_check.depth {
+                Some(actual) if actual != self.stack_depth => Some(format!(
+                    "tracked stack depth is {} but the emitted code leaves {}",
+                    self.stack_depth, actual
+                )),
+                _ => None,
+            }
+        };
+
+        match problem {
+            Some(problem) => {
+                let message = format!(
+                    "Stack bookkeeping diverged in {} statement: {}",
+                    stmt.kind(),
+                    problem
+                );
+                Err(match stmt.span() {
+                    Some(span) => CompileError::at(span, message),
+                    None => CompileError::new(message),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn visit_statement_inner(&mut self, stmt: &Statement) -> CompileResult<()> {
         match stmt {
             Statement::VarDecl(var_decl) => self.visit_var_decl(var_decl),
             Statement::FuncDecl(func_decl) => self.visit_func_decl(func_decl),
@@ -118,6 +393,29 @@ impl CodeGenerator {
             Statement::WhileStmt(while_stmt) => self.visit_while_stmt(while_stmt),
             Statement::ReturnStmt(return_stmt) => self.visit_return_stmt(return_stmt),
             Statement::Block(block) => self.visit_block(block),
+            Statement::MappingDecl(mapping_decl) => self.visit_mapping_decl(mapping_decl),
+            Statement::Emit(emit_stmt) => self.visit_emit_stmt(emit_stmt),
+            // Top-level imports are resolved before code generation
+            Statement::Import(import_stmt) => Err(CompileError::at(
+                import_stmt.span,
+                "Imports are only allowed at the top level of a program",
+            )),
+            Statement::Break(span) => match self.loops.last() {
+                Some(labels) => {
+                    let label = labels.break_label.clone();
+                    self.emit_jump(&label);
+                    Ok(())
+                }
+                None => Err(CompileError::at(*span, "'break' outside of a loop")),
+            },
+            Statement::Continue(span) => match self.loops.last() {
+                Some(labels) => {
+                    let label = labels.continue_label.clone();
+                    self.emit_jump(&label);
+                    Ok(())
+                }
+                None => Err(CompileError::at(*span, "'continue' outside of a loop")),
+            },
         }
     }
 
@@ -130,10 +428,6 @@ impl CodeGenerator {
         self.variables.insert(var_decl.name.clone(), slot);
         self.next_var_slot += 1;
 
-        // Duplicate the value on stack so we can store it
-        self.emit_opcode(OpCode::DUP1);
-        self.stack_depth += 1;
-
         // Store in storage (for persistent variables)
         self.emit_push_u256(U256::from(slot));
         self.stack_depth += 1;
@@ -143,18 +437,120 @@ impl CodeGenerator {
         Ok(())
     }
 
+    fn visit_mapping_decl(&mut self, mapping_decl: &MappingDecl) -> CompileResult<()> {
+        if self.mappings.contains_key(&mapping_decl.name) {
+            return Err(CompileError::at(
+                mapping_decl.span,
+                format!("Mapping '{}' is already declared", mapping_decl.name),
+            ));
+        }
+
+        // Reserve a base slot so the mapping's entries never collide with
+        // variables or other mappings. The base slot itself is never written.
+        let slot = self.next_var_slot;
+        self.mappings.insert(mapping_decl.name.clone(), slot);
+        self.next_var_slot += 1;
+
+        Ok(())
+    }
+
+    fn visit_emit_stmt(&mut self, emit_stmt: &EmitStmt) -> CompileResult<()> {
+        // The last argument is the log data; any preceding ones are indexed topics
+        let (data, indexed) = match emit_stmt.arguments.split_last() {
+            Some((data, indexed)) => (Some(data), indexed),
+            None => (None, &[][..]),
+        };
+        if indexed.len() > 3 {
+            return Err(CompileError::at(
+                emit_stmt.span,
+                format!(
+                    "Event '{}' has {} indexed arguments, at most 3 are supported",
+                    emit_stmt.event,
+                    indexed.len()
+                ),
+            ));
+        }
+
+        // Every AbbyScript value is a 256-bit word
+        let signature = format!(
+            "{}({})",
+            emit_stmt.event,
+            vec!["uint256"; emit_stmt.arguments.len()].join(",")
+        );
+        let signature_topic = U256::from_big_endian(&Keccak256::digest(signature.as_bytes()));
+
+        // Write the data word to memory
+        let offset = self.memory_pointer;
+        let size = if let Some(data) = data {
+            self.visit_expression(data)?;
+            self.emit_push_u256(U256::from(offset));
+            self.stack_depth += 1;
+            self.emit_opcode(OpCode::MSTORE);
+            self.stack_depth -= 2;
+            self.memory_pointer += 32;
+            32
+        } else {
+            0
+        };
+
+        // LOGn pops offset, size, then topics in order, so push them in reverse
+        for topic in indexed.iter().rev() {
+            self.visit_expression(topic)?;
+        }
+        self.emit_push_u256(signature_topic);
+        self.emit_push_u256(U256::from(size));
+        self.emit_push_u256(U256::from(offset));
+        self.stack_depth += 3;
+
+        let log_opcode = match indexed.len() {
+            0 => OpCode::LOG1,
+            1 => OpCode::LOG2,
+            2 => OpCode::LOG3,
+            _ => OpCode::LOG4,
+        };
+        self.emit_opcode(log_opcode);
+        self.stack_depth -= 3 + indexed.len() as i32;
+
+        Ok(())
+    }
+
     fn visit_func_decl(&mut self, func_decl: &FuncDecl) -> CompileResult<()> {
         // Function declarations are handled in the first pass
         // Here we generate the actual function body
         let _function_start = self.bytecode.len();
 
-        // Create a new scope for function parameters
+        // Outside contract mode the body sits inline, so jump over it
+        let skip_label = if self.dispatcher {
+            None
+        } else {
+            let label = self.generate_label("skip_fn");
+            self.emit_jump(&label);
+            Some(label)
+        };
+
+        // The body is only entered through its own entry point, not by fallthrough
+        if self.stack_check.depth.is_none() {
+            self.stack_check.depth = Some(self.stack_depth);
+        }
+
+        // Create a new scope for function parameters, outside any loop
         let saved_vars = self.variables.clone();
         let saved_next_slot = self.next_var_slot;
+        let saved_loops = std::mem::take(&mut self.loops);
 
         // Add parameters as variables
         for (i, param) in func_decl.params.iter().enumerate() {
-            self.variables.insert(param.clone(), i as u16);
+            let slot = self.next_var_slot;
+            self.variables.insert(param.clone(), slot);
+            self.next_var_slot += 1;
+
+            if self.dispatcher {
+                // Copy the ABI-encoded argument from calldata into the parameter slot
+                self.emit_push_u256(U256::from(4 + 32 * i));
+                self.emit_opcode(OpCode::CALLDATALOAD);
+                self.emit_push_u256(U256::from(slot));
+                self.emit_opcode(OpCode::SSTORE);
+            }
         }
 
         // Generate function body
@@ -162,11 +558,17 @@ impl CodeGenerator {
 
         // If no explicit return, add default return 0
         self.emit_push_u256(U256::zero());
-        self.emit_opcode(OpCode::RETURN);
+        self.stack_depth += 1;
+        self.emit_return_word();
+
+        if let Some(label) = skip_label {
+            self.place_label(&label);
+        }
 
         // Restore previous scope
         self.variables = saved_vars;
         self.next_var_slot = saved_next_slot;
+        self.loops = saved_loops;
 
         Ok(())
     }
@@ -182,7 +584,6 @@ impl CodeGenerator {
         // Jump to else if condition is false (0)
         self.emit_opcode(OpCode::ISZERO); // Invert condition
         self.emit_jump_if(&else_label);
-        self.stack_depth -= 1;
 
         // Generate then branch
         self.visit_statement(&if_stmt.then_branch)?;
@@ -217,10 +618,15 @@ impl CodeGenerator {
         // Jump to end if condition is false
         self.emit_opcode(OpCode::ISZERO);
         self.emit_jump_if(&loop_end);
-        self.stack_depth -= 1;
 
         // Generate body
-        self.visit_statement(&while_stmt.body)?;
+        self.loops.push(LoopLabels {
+            continue_label: loop_start.clone(),
+            break_label: loop_end.clone(),
+        });
+        let body = self.visit_statement(&while_stmt.body);
+        self.loops.pop();
+        body?;
 
         // Jump back to start
         self.emit_jump(&loop_start);
@@ -239,27 +645,21 @@ impl CodeGenerator {
             self.stack_depth += 1;
         }
 
-        // RETURN expects offset and size on stack
-        // For now, just return the value directly (simplified)
-        self.emit_push_u256(U256::from(32)); // size
-        self.emit_push_u256(U256::zero()); // offset
-        self.emit_opcode(OpCode::MSTORE); // Store return value in memory (consumes 3: value, offset, size)
-        if self.stack_depth >= 3 {
-            self.stack_depth -= 3;
-        } else {
-            self.stack_depth = 0;
-        }
+        self.emit_return_word();
+
+        Ok(())
+    }
+
+    /// Return the word on top of the stack as 32 bytes of return data
+    fn emit_return_word(&mut self) {
+        // memory[0x00] = value
+        self.emit_push_u256(U256::zero());
+        self.emit_opcode(OpCode::MSTORE);
+        self.stack_depth -= 1;
 
         self.emit_push_u256(U256::from(32)); // size
         self.emit_push_u256(U256::zero()); // offset
         self.emit_opcode(OpCode::RETURN);
-        if self.stack_depth >= 2 {
-            self.stack_depth -= 2;
-        } else {
-            self.stack_depth = 0;
-        }
-
-        Ok(())
     }
 
     fn visit_block(&mut self, block: &Block) -> CompileResult<()> {
@@ -287,6 +687,10 @@ impl CodeGenerator {
             Expression::StorageArrayAssignment(storage_assign) => {
                 self.visit_storage_array_assignment_expr(storage_assign)
             }
+            Expression::MappingAssignment(mapping_assign) => {
+                self.visit_mapping_assignment_expr(mapping_assign)
+            }
+            Expression::Conditional(conditional) => self.visit_conditional_expr(conditional),
         }
     }
 
@@ -303,7 +707,11 @@ impl CodeGenerator {
             BinaryOperator::Subtract => self.emit_opcode(OpCode::SUB),
             BinaryOperator::Multiply => self.emit_opcode(OpCode::MUL),
             BinaryOperator::Divide => self.emit_opcode(OpCode::DIV),
-            BinaryOperator::Modulo => self.emit_opcode(OpCode::MOD),
+            BinaryOperator::Modulo => {
+                // MOD takes its dividend from the top, but the left operand is below
+                self.emit_opcode(OpCode::SWAP1);
+                self.emit_opcode(OpCode::MOD);
+            }
             BinaryOperator::Equal => self.emit_opcode(OpCode::EQ),
             BinaryOperator::NotEqual => {
                 self.emit_opcode(OpCode::EQ);
@@ -342,6 +750,28 @@ impl CodeGenerator {
         Ok(())
     }
 
+    fn visit_conditional_expr(&mut self, conditional: &ConditionalExpr) -> CompileResult<()> {
+        self.visit_expression(&conditional.condition)?;
+
+        let else_label = self.generate_label("cond_else");
+        let end_label = self.generate_label("cond_end");
+
+        // Only one branch runs, and each leaves its value on the stack
+        self.emit_opcode(OpCode::ISZERO);
+        self.emit_jump_if(&else_label);
+
+        self.visit_expression(&conditional.then_value)?;
+        self.emit_jump(&end_label);
+
+        self.place_label(&else_label);
+        self.stack_depth -= 1; // The then value is not on this path
+        self.visit_expression(&conditional.else_value)?;
+
+        self.place_label(&end_label);
+
+        Ok(())
+    }
+
     fn visit_unary_expr(&mut self, unary: &UnaryExpr) -> CompileResult<()> {
         self.visit_expression(&unary.operand)?;
 
@@ -369,9 +799,10 @@ impl CodeGenerator {
                 match var.name.as_str() {
                     "keccak256" => {
                         if call.arguments.len() != 1 {
-                            return Err(CompileError {
-                                message: "keccak256 expects exactly 1 argument".to_string(),
-                            });
+                            return Err(CompileError::at(
+                                var.span,
+                                "keccak256 expects exactly 1 argument",
+                            ));
                         }
 
                         // For simplicity, we'll just hash a constant for now
@@ -383,14 +814,13 @@ impl CodeGenerator {
                     }
                     "println" => {
                         // Legacy support for println - treat as console.log
-                        return Err(CompileError {
-                            message: "println is not a JavaScript function. Use console.log, console.warn, or console.error instead".to_string(),
-                        });
+                        return Err(CompileError::at(var.span, "println is not a JavaScript function. Use console.log, console.warn, or console.error instead"));
                     }
                     _ => {
-                        return Err(CompileError {
-                            message: format!("Unknown function: {}", var.name),
-                        });
+                        return Err(CompileError::at(
+                            var.span,
+                            format!("Unknown function: {}", var.name),
+                        ));
                     }
                 }
             }
@@ -406,6 +836,7 @@ impl CodeGenerator {
                                     let offset = self.memory_pointer;
                                     self.emit_push_u256(U256::from(0)); // size = 0
                                     self.emit_push_u256(U256::from(offset)); // offset
+                                    self.stack_depth += 2;
                                 } else if call.arguments.len() == 2 {
                                     // Special case for two arguments (most common case)
                                     let arg1 = &call.arguments[0];
@@ -414,57 +845,16 @@ impl CodeGenerator {
                                     // Process first argument (usually a string)
                                     match arg1 {
                                         Expression::Literal(LiteralExpr::String(s)) => {
-                                            let start_offset = self.memory_pointer;
-
-                                            // Store string
-                                            for byte in s.bytes() {
-                                                self.emit_push_u256(U256::from(byte));
-                                                self.emit_push_u256(U256::from(
-                                                    self.memory_pointer,
-                                                ));
-                                                self.emit_opcode(OpCode::MSTORE8);
-                                                self.stack_depth += 2;
-                                                self.stack_depth -= 2;
-                                                self.memory_pointer += 1;
-                                            }
-
-                                            // Add space
-                                            self.emit_push_u256(U256::from(32)); // ASCII space
-                                            self.emit_push_u256(U256::from(self.memory_pointer));
-                                            self.emit_opcode(OpCode::MSTORE8);
-                                            self.stack_depth += 2;
-                                            self.stack_depth -= 2;
-                                            self.memory_pointer += 1;
-
-                                            // Process second argument (usually a variable)
-                                            {
-                                                // Variable or expression
-                                                self.visit_expression(arg2)?;
-
-                                                // Convert to ASCII and store (single digit)
-                                                self.emit_push_u256(U256::from(48)); // ASCII '0'
-                                                self.stack_depth += 1;
-                                                self.emit_opcode(OpCode::ADD);
-                                                self.stack_depth -= 1;
-
-                                                self.emit_push_u256(U256::from(
-                                                    self.memory_pointer,
-                                                ));
-                                                self.stack_depth += 1;
-                                                self.emit_opcode(OpCode::MSTORE8);
-                                                self.stack_depth -= 2;
-                                                self.memory_pointer += 1;
-                                            }
-
-                                            let total_length = self.memory_pointer - start_offset;
-                                            self.emit_push_u256(U256::from(total_length));
-                                            self.emit_push_u256(U256::from(start_offset));
-                                            self.stack_depth += 2;
+                                            // "label value", with the value formatted at runtime
+                                            let mut prefix = s.as_bytes().to_vec();
+                                            prefix.push(b' ');
+                                            self.visit_expression(arg2)?;
+                                            self.emit_format_value(&prefix, arg2);
                                         }
                                         _ => {
-                                            // Fallback: just process first argument
+                                            // Without a label, print the first value alone
                                             self.visit_expression(arg1)?;
-                                            self.emit_opcode(OpCode::SWAP1);
+                                            self.emit_format_value(&[], arg1);
                                         }
                                     }
                                 } else {
@@ -495,8 +885,7 @@ impl CodeGenerator {
                                         }
                                         _ => {
                                             self.visit_expression(arg)?;
-                                            let offset = self.memory_pointer;
-                                            self.emit_number_to_string_conversion(offset)?;
+                                            self.emit_format_value(&[], arg);
                                         }
                                     }
                                 }
@@ -542,29 +931,26 @@ impl CodeGenerator {
                                 self.stack_depth += 1;
                             }
                             _ => {
-                                return Err(CompileError {
-                                    message: format!("Unknown console method: {}", member.property),
-                                });
+                                return Err(CompileError::at(
+                                    member.span,
+                                    format!("Unknown console method: {}", member.property),
+                                ));
                             }
                         }
                     } else {
-                        return Err(CompileError {
-                            message: format!(
-                                "Member access not supported for object: {}",
-                                obj.name
-                            ),
-                        });
+                        return Err(CompileError::at(
+                            obj.span,
+                            format!("Member access not supported for object: {}", obj.name),
+                        ));
                     }
                 } else {
-                    return Err(CompileError {
-                        message: "Complex member access not yet supported".to_string(),
-                    });
+                    return Err(CompileError::new("Complex member access not yet supported"));
                 }
             }
             _ => {
-                return Err(CompileError {
-                    message: "Complex function calls not yet supported".to_string(),
-                });
+                return Err(CompileError::new(
+                    "Complex function calls not yet supported",
+                ));
             }
         }
 
@@ -576,12 +962,12 @@ impl CodeGenerator {
         self.visit_expression(&assignment.value)?;
 
         // Get variable slot
-        let slot = *self
-            .variables
-            .get(&assignment.name)
-            .ok_or_else(|| CompileError {
-                message: format!("Undefined variable: {}", assignment.name),
-            })?;
+        let slot = *self.variables.get(&assignment.name).ok_or_else(|| {
+            CompileError::at(
+                assignment.span,
+                format!("Undefined variable: {}", assignment.name),
+            )
+        })?;
 
         // Duplicate value for return
         self.emit_opcode(OpCode::DUP1);
@@ -607,12 +993,12 @@ impl CodeGenerator {
             }
             _ => {
                 // Regular variable access
-                let slot = *self
-                    .variables
-                    .get(&variable.name)
-                    .ok_or_else(|| CompileError {
-                        message: format!("Undefined variable: {}", variable.name),
-                    })?;
+                let slot = *self.variables.get(&variable.name).ok_or_else(|| {
+                    CompileError::at(
+                        variable.span,
+                        format!("Undefined variable: {}", variable.name),
+                    )
+                })?;
 
                 // Load variable from storage
                 self.emit_push_u256(U256::from(slot));
@@ -629,9 +1015,10 @@ impl CodeGenerator {
         // For now, we don't actually emit code for member access itself -
         // it will be handled by the CallExpr that uses this as a callee
         // This is a placeholder that returns an error if used outside of calls
-        Err(CompileError {
-            message: "Member access expressions are only supported in function calls".to_string(),
-        })
+        Err(CompileError::at(
+            member.span,
+            "Member access expressions are only supported in function calls",
+        ))
     }
 
     fn visit_literal_expr(&mut self, literal: &LiteralExpr) -> CompileResult<()> {
@@ -732,16 +1119,20 @@ impl CodeGenerator {
                     self.visit_expression(&array_access.index)?;
                     self.emit_opcode(OpCode::MLOAD);
                 }
+                name if self.mappings.contains_key(name) => {
+                    // mapping[key] -> SLOAD(keccak256(key ++ base_slot))
+                    self.emit_mapping_slot(name, &array_access.index)?;
+                    self.emit_opcode(OpCode::SLOAD);
+                }
                 _ => {
-                    return Err(CompileError {
-                        message: format!("Array access not supported for '{}'", var.name),
-                    });
+                    return Err(CompileError::at(
+                        var.span,
+                        format!("Array access not supported for '{}'", var.name),
+                    ));
                 }
             }
         } else {
-            return Err(CompileError {
-                message: "Complex array access not yet supported".to_string(),
-            });
+            return Err(CompileError::new("Complex array access not yet supported"));
         }
         Ok(())
     }
@@ -785,9 +1176,60 @@ impl CodeGenerator {
         Ok(())
     }
 
+    fn visit_mapping_assignment_expr(
+        &mut self,
+        mapping_assign: &MappingAssignmentExpr,
+    ) -> CompileResult<()> {
+        // mapping[key] = value -> SSTORE(keccak256(key ++ base_slot), value)
+        self.visit_expression(&mapping_assign.value)?;
+
+        // Duplicate value for return
+        self.emit_opcode(OpCode::DUP1);
+        self.stack_depth += 1;
+
+        self.emit_mapping_slot(&mapping_assign.name, &mapping_assign.key)?;
+        self.emit_opcode(OpCode::SSTORE);
+        self.stack_depth -= 2;
+
+        Ok(())
+    }
+
+    /// Compute the storage slot of `name[key]` following the Solidity layout:
+    /// keccak256(key ++ base_slot), hashed from the 0x00-0x3f scratch space
+    fn emit_mapping_slot(&mut self, name: &str, key: &Expression) -> CompileResult<()> {
+        let base_slot = *self
+            .mappings
+            .get(name)
+            .ok_or_else(|| CompileError::new(format!("Undefined mapping: {}", name)))?;
+
+        // memory[0x00] = key
+        self.visit_expression(key)?;
+        self.emit_push_u256(U256::zero());
+        self.stack_depth += 1;
+        self.emit_opcode(OpCode::MSTORE);
+        self.stack_depth -= 2;
+
+        // memory[0x20] = base_slot
+        self.emit_push_u256(U256::from(base_slot));
+        self.emit_push_u256(U256::from(0x20));
+        self.stack_depth += 2;
+        self.emit_opcode(OpCode::MSTORE);
+        self.stack_depth -= 2;
+
+        // keccak256(memory[0x00..0x40])
+        self.emit_push_u256(U256::from(0x40)); // size
+        self.emit_push_u256(U256::zero()); // offset
+        self.stack_depth += 2;
+        self.emit_opcode(OpCode::SHA3);
+        self.stack_depth -= 1;
+
+        Ok(())
+    }
+
     // Code emission helpers
 
     pub fn emit_opcode(&mut self, opcode: OpCode) {
+        self.stack_check.record(&opcode);
         self.bytecode.push(opcode.to_byte());
     }
 
@@ -801,15 +1243,15 @@ impl CodeGenerator {
             _ => OpCode::PUSH32, // Use PUSH32 for larger values
         };
 
+        self.stack_check.record(&push_opcode);
         self.bytecode.push(push_opcode.to_byte());
-        if bytes.len() <= 32 {
-            self.bytecode.extend(bytes);
-        } else {
-            // For values larger than 32 bytes, pad to 32 bytes
-            let mut padded = vec![0u8; 32];
-            let start = 32 - bytes.len().min(32);
-            padded[start..].copy_from_slice(&bytes[..bytes.len().min(32)]);
+        if push_opcode == OpCode::PUSH32 {
+            // PUSH32 always reads 32 immediate bytes, so left-pad shorter values
+            let mut padded = vec![0u8; 32 - bytes.len()];
+            padded.extend(bytes);
             self.bytecode.extend(padded);
+        } else {
+            self.bytecode.extend(bytes);
         }
     }
 
@@ -829,6 +1271,11 @@ impl CodeGenerator {
     }
 
     fn place_label(&mut self, label: &str) {
+        match self.stack_check.depth {
+            Some(depth) => self.stack_check.arrive(label, depth),
+            None => self.stack_check.depth = self.stack_check.label_depths.get(label).copied(),
+        }
+
         self.jump_labels
             .insert(label.to_string(), self.bytecode.len() as u16);
         self.emit_opcode(OpCode::JUMPDEST);
@@ -850,6 +1297,11 @@ impl CodeGenerator {
         self.bytecode.push(0x00); // Placeholder for high byte
         self.bytecode.push(0x00); // Placeholder for low byte
 
+        self.stack_check.record(&OpCode::PUSH2);
+        if let Some(depth) = self.stack_check.depth {
+            self.stack_check.arrive(label, depth - 1);
+        }
+
         self.stack_depth += 1;
         self.emit_opcode(OpCode::JUMP);
         self.stack_depth -= 1;
@@ -871,88 +1323,177 @@ impl CodeGenerator {
         self.bytecode.push(0x00); // Placeholder for high byte
         self.bytecode.push(0x00); // Placeholder for low byte
 
+        self.stack_check.record(&OpCode::PUSH2);
+        if let Some(depth) = self.stack_check.depth {
+            self.stack_check.arrive(label, depth - 2);
+        }
+
         self.stack_depth += 1;
         self.emit_opcode(OpCode::JUMPI);
         self.stack_depth -= 2; // JUMPI consumes two stack items (condition and address)
     }
 
-    fn emit_number_to_string_conversion(&mut self, offset: u16) -> CompileResult<()> {
-        // Super simple version: only handle single digits properly for now
-        // Stack has: [number]
+    /// Format the value of `expr`, on top of the stack, as text after
+    /// `prefix` in memory, replacing it with the [length, offset] LOG expects.
+    /// Comparisons and logical operators print as true or false; negations
+    /// and subtractions print as signed numbers, anything else as unsigned.
+    fn emit_format_value(&mut self, prefix: &[u8], expr: &Expression) {
+        let depth = self.stack_depth;
+        if is_boolean(expr) {
+            self.emit_format_bool(prefix);
+        } else {
+            self.emit_format_decimal(prefix, is_signed(expr));
+        }
+        self.stack_depth = depth + 1;
+    }
 
-        let base_offset = offset;
+    /// [value] -> [length, offset] of `prefix` followed by true or false
+    fn emit_format_bool(&mut self, prefix: &[u8]) {
+        let start = self.memory_pointer;
+        self.emit_store_bytes(prefix, start);
+        let text = start + prefix.len() as u16;
+        self.memory_pointer = text + "false".len() as u16;
 
-        // For numbers 0-9, just convert to ASCII and store
-        self.emit_push_u256(U256::from(48)); // ASCII '0'
-        self.stack_depth += 1;
-        self.emit_opcode(OpCode::ADD); // [ascii_number]
-        self.stack_depth -= 1;
+        let false_label = self.generate_label("fmt_false");
+        let end_label = self.generate_label("fmt_end");
+        self.emit_opcode(OpCode::ISZERO);
+        self.emit_jump_if(&false_label);
 
-        // Store the single digit
-        self.emit_push_u256(U256::from(base_offset));
-        self.stack_depth += 1;
-        self.emit_opcode(OpCode::MSTORE8); // []
-        self.stack_depth -= 2;
+        for (word, label) in [("true", None), ("false", Some(&false_label))] {
+            if let Some(label) = label {
+                self.place_label(label);
+            }
+            self.emit_store_bytes(word.as_bytes(), text);
+            self.emit_push_u256(U256::from(prefix.len() + word.len()));
+            if label.is_none() {
+                self.emit_jump(&end_label);
+            }
+        }
 
-        // Return length=1, offset=base_offset
-        self.emit_push_u256(U256::from(1)); // length
-        self.emit_push_u256(U256::from(base_offset)); // offset
-        self.stack_depth += 2;
+        self.place_label(&end_label);
+        self.emit_push_u256(U256::from(start));
+    }
 
-        self.memory_pointer += 1;
+    /// [value] -> [length, offset] of `prefix` followed by the value in
+    /// decimal, with a leading '-' for negative two's-complement values if
+    /// `signed`. Digits are written right to left, then the prefix is placed
+    /// just before the first one.
+    fn emit_format_decimal(&mut self, prefix: &[u8], signed: bool) {
+        // Room for the prefix, a sign and the 78 digits of 2^256 - 1
+        let end = self.memory_pointer + prefix.len() as u16 + 1 + 78;
+        self.memory_pointer = end;
+
+        // [value] -> [negative, magnitude]
+        if signed {
+            let positive = self.generate_label("fmt_positive");
+            self.emit_opcode(OpCode::DUP1);
+            self.emit_push_u256(U256::from(255));
+            self.emit_opcode(OpCode::SHR);
+            self.emit_opcode(OpCode::DUP1);
+            self.emit_opcode(OpCode::ISZERO);
+            self.emit_jump_if(&positive);
+            self.emit_opcode(OpCode::SWAP1);
+            self.emit_push_u256(U256::zero());
+            self.emit_opcode(OpCode::SUB);
+            self.emit_opcode(OpCode::SWAP1);
+            self.place_label(&positive);
+            self.emit_opcode(OpCode::SWAP1);
+        } else {
+            self.emit_push_u256(U256::zero());
+            self.emit_opcode(OpCode::SWAP1);
+        }
 
-        Ok(())
+        // [negative, magnitude, pointer]: at least one digit, even for zero
+        self.emit_push_u256(U256::from(end));
+        let digit = self.generate_label("fmt_digit");
+        self.place_label(&digit);
+        self.emit_push_u256(U256::one());
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::SUB);
+        self.emit_opcode(OpCode::DUP2);
+        self.emit_push_u256(U256::from(10));
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::MOD);
+        self.emit_push_u256(U256::from(b'0'));
+        self.emit_opcode(OpCode::ADD);
+        self.emit_opcode(OpCode::DUP2);
+        self.emit_opcode(OpCode::MSTORE8);
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_push_u256(U256::from(10));
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::DIV);
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::DUP2);
+        self.emit_jump_if(&digit);
+
+        // [negative, 0, pointer] -> [pointer], after a '-' if negative
+        self.emit_opcode(OpCode::SWAP2);
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::POP);
+        let unsigned = self.generate_label("fmt_unsigned");
+        self.emit_opcode(OpCode::ISZERO);
+        self.emit_jump_if(&unsigned);
+        self.emit_push_u256(U256::one());
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::SUB);
+        self.emit_push_u256(U256::from(b'-'));
+        self.emit_opcode(OpCode::DUP2);
+        self.emit_opcode(OpCode::MSTORE8);
+        self.place_label(&unsigned);
+
+        // Prefix byte i goes to pointer - (prefix length - i)
+        for (i, &byte) in prefix.iter().enumerate() {
+            self.emit_push_u256(U256::from(byte));
+            self.emit_opcode(OpCode::DUP2);
+            self.emit_push_u256(U256::from(prefix.len() - i));
+            self.emit_opcode(OpCode::SWAP1);
+            self.emit_opcode(OpCode::SUB);
+            self.emit_opcode(OpCode::MSTORE8);
+        }
+
+        // [pointer] -> [end - start, start]
+        self.emit_push_u256(U256::from(prefix.len()));
+        self.emit_opcode(OpCode::SWAP1);
+        self.emit_opcode(OpCode::SUB);
+        self.emit_opcode(OpCode::DUP1);
+        self.emit_push_u256(U256::from(end));
+        self.emit_opcode(OpCode::SUB);
+        self.emit_opcode(OpCode::SWAP1);
+    }
+
+    /// Store `bytes` in memory starting at `offset`
+    fn emit_store_bytes(&mut self, bytes: &[u8], offset: u16) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.emit_push_u256(U256::from(byte));
+            self.emit_push_u256(U256::from(offset + i as u16));
+            self.emit_opcode(OpCode::MSTORE8);
+        }
     }
 }
 
-// Extension trait to convert OpCode to byte
-trait OpCodeExt {
-    fn to_byte(&self) -> u8;
+/// Whether `expr` produces a truth value rather than a number
+fn is_boolean(expr: &Expression) -> bool {
+    match expr {
+        Expression::Literal(LiteralExpr::Boolean(_)) => true,
+        Expression::Unary(unary) => unary.operator == UnaryOperator::Not,
+        Expression::Binary(binary) => !matches!(
+            binary.operator,
+            BinaryOperator::Add
+                | BinaryOperator::Subtract
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+                | BinaryOperator::Modulo
+        ),
+        _ => false,
+    }
 }
 
-impl OpCodeExt for OpCode {
-    fn to_byte(&self) -> u8 {
-        match self {
-            OpCode::STOP => 0x00,
-            OpCode::ADD => 0x01,
-            OpCode::MUL => 0x02,
-            OpCode::SUB => 0x03,
-            OpCode::DIV => 0x04,
-            OpCode::MOD => 0x06,
-            OpCode::EXP => 0x0a,
-            OpCode::LT => 0x10,
-            OpCode::GT => 0x11,
-            OpCode::EQ => 0x14,
-            OpCode::ISZERO => 0x15,
-            OpCode::AND => 0x16,
-            OpCode::OR => 0x17,
-            OpCode::XOR => 0x18,
-            OpCode::NOT => 0x19,
-            OpCode::SHA3 => 0x20,
-            OpCode::POP => 0x50,
-            OpCode::MLOAD => 0x51,
-            OpCode::MSTORE => 0x52,
-            OpCode::MSTORE8 => 0x53,
-            OpCode::SLOAD => 0x54,
-            OpCode::SSTORE => 0x55,
-            OpCode::JUMP => 0x56,
-            OpCode::JUMPI => 0x57,
-            OpCode::JUMPDEST => 0x5b,
-            OpCode::PUSH1 => 0x60,
-            OpCode::PUSH2 => 0x61,
-            OpCode::PUSH3 => 0x62,
-            OpCode::PUSH4 => 0x63,
-            OpCode::PUSH32 => 0x7f,
-            OpCode::DUP1 => 0x80,
-            OpCode::DUP2 => 0x81,
-            OpCode::DUP3 => 0x82,
-            OpCode::SWAP1 => 0x90,
-            OpCode::SWAP2 => 0x91,
-            OpCode::LOG0 => 0xa0,
-            OpCode::LOG1 => 0xa1,
-            OpCode::RETURN => 0xf3,
-            _ => 0xfe, // INVALID for unimplemented opcodes
-        }
+/// Whether `expr` may produce a negative result that should print as one
+fn is_signed(expr: &Expression) -> bool {
+    match expr {
+        Expression::Unary(unary) => unary.operator == UnaryOperator::Minus,
+        Expression::Binary(binary) => binary.operator == BinaryOperator::Subtract,
+        _ => false,
     }
 }
 
@@ -987,6 +1528,403 @@ mod tests {
         assert_eq!(bytecode, vec![0x60, 1, 0x60, 2, 0x01]);
     }
 
+    fn run_program(source: &str) -> crate::evm::EvmState {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut generator = CodeGenerator::new();
+        let bytecode = generator.compile(&program).unwrap();
+
+        let mut state = crate::evm::EvmState::new(U256::from(1_000_000), U256::zero());
+        let executor = crate::evm::EvmExecutor::new(1_000_000);
+        executor.execute_bytecode(&bytecode, &mut state).unwrap();
+        assert!(state.error.is_none(), "execution failed: {:?}", state.error);
+        state
+    }
+
+    #[test]
+    fn test_mapping_slots_do_not_collide() {
+        let state = run_program(
+            r#"
+            mapping balances;
+            mapping allowances;
+            balances[1] = 10;
+            balances[2] = 20;
+            allowances[1] = 30;
+            storage[100] = balances[1];
+            storage[101] = balances[2];
+            storage[102] = allowances[1];
+        "#,
+        );
+
+        assert_eq!(state.storage_load(&U256::from(100)), U256::from(10));
+        assert_eq!(state.storage_load(&U256::from(101)), U256::from(20));
+        assert_eq!(state.storage_load(&U256::from(102)), U256::from(30));
+
+        // balances[1] lives at keccak256(1 ++ 0), allowances[1] at keccak256(1 ++ 1)
+        let slot = |key: u64, base: u64| {
+            use sha3::{Digest, Keccak256};
+            let mut preimage = [0u8; 64];
+            U256::from(key).to_big_endian(&mut preimage[..32]);
+            U256::from(base).to_big_endian(&mut preimage[32..]);
+            U256::from_big_endian(&Keccak256::digest(preimage))
+        };
+        assert_eq!(state.storage_load(&slot(1, 0)), U256::from(10));
+        assert_eq!(state.storage_load(&slot(2, 0)), U256::from(20));
+        assert_eq!(state.storage_load(&slot(1, 1)), U256::from(30));
+    }
+
+    #[test]
+    fn test_compound_assignment_stores_the_combined_value() {
+        let state = run_program(
+            r#"
+            let x = 10;
+            x += 5;
+            storage[100] = x;
+            let y = 6;
+            y *= 7;
+            storage[101] = y;
+        "#,
+        );
+
+        assert_eq!(state.storage_load(&U256::from(100)), U256::from(15));
+        assert_eq!(state.storage_load(&U256::from(101)), U256::from(42));
+    }
+
+    #[test]
+    fn test_conditional_expression_evaluates_one_branch() {
+        for (x, expected) in [(5, 1), (0, 2)] {
+            let state = run_program(&format!("let x = {}; storage[100] = x != 0 ? 1 : 2;", x));
+            assert_eq!(state.storage_load(&U256::from(100)), U256::from(expected));
+        }
+
+        // The branch not taken never runs
+        let state = run_program("let x = 1; storage[100] = x == 1 ? 7 : (storage[101] = 9);");
+        assert_eq!(state.storage_load(&U256::from(100)), U256::from(7));
+        assert_eq!(state.storage_load(&U256::from(101)), U256::zero());
+    }
+
+    #[test]
+    fn test_break_leaves_the_loop_early() {
+        let state = run_program(
+            r#"
+            let i = 0;
+            while (i != 10) {
+                i += 1;
+                if (i == 3) {
+                    break;
+                }
+                storage[100] = i;
+            }
+            storage[101] = i;
+        "#,
+        );
+
+        assert_eq!(state.storage_load(&U256::from(100)), U256::from(2));
+        assert_eq!(state.storage_load(&U256::from(101)), U256::from(3));
+    }
+
+    #[test]
+    fn test_continue_skips_the_rest_of_an_iteration() {
+        let state = run_program(
+            r#"
+            let i = 0;
+            let count = 0;
+            while (i != 5) {
+                i += 1;
+                if (i == 2) {
+                    continue;
+                }
+                count += 1;
+            }
+            storage[100] = count;
+        "#,
+        );
+
+        assert_eq!(state.storage_load(&U256::from(100)), U256::from(4));
+    }
+
+    #[test]
+    fn test_break_targets_the_innermost_loop() {
+        let state = run_program(
+            r#"
+            let outer = 0;
+            let inner = 0;
+            let total = 0;
+            while (outer != 3) {
+                outer += 1;
+                inner = 0;
+                while (inner != 10) {
+                    inner += 1;
+                    if (inner == 2) {
+                        break;
+                    }
+                    total += 1;
+                }
+            }
+            storage[100] = total;
+            storage[101] = outer;
+        "#,
+        );
+
+        assert_eq!(state.storage_load(&U256::from(100)), U256::from(3));
+        assert_eq!(state.storage_load(&U256::from(101)), U256::from(3));
+    }
+
+    #[test]
+    fn test_break_outside_a_loop_is_rejected() {
+        for source in ["break;", "while (1 == 1) { function f() { continue; } }"] {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            let err = CodeGenerator::new().compile(&program).unwrap_err();
+            assert!(err.message.contains("outside of a loop"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_emit_produces_log_with_signature_topic() {
+        let mut lexer = Lexer::new("let amount = 42; emit Transfer(1, 2, amount);");
+        let tokens = lexer.tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let bytecode = CodeGenerator::new().compile(&program).unwrap();
+
+        let mut executor = crate::evm::EvmExecutor::new(1_000_000);
+        let result = executor.execute(&bytecode, 0, false).unwrap();
+        assert_eq!(result.status, crate::types::ExecutionStatus::Success);
+        assert_eq!(result.logs.len(), 1);
+
+        let log = &result.logs[0];
+        assert_eq!(log.topics.len(), 3);
+        let signature = Keccak256::digest(b"Transfer(uint256,uint256,uint256)");
+        assert_eq!(log.topics[0].as_bytes(), signature.as_slice());
+        assert_eq!(log.topics[1].to_low_u64_be(), 1);
+        assert_eq!(log.topics[2].to_low_u64_be(), 2);
+        assert_eq!(U256::from_big_endian(&log.data), U256::from(42));
+    }
+
+    #[test]
+    fn test_emit_rejects_too_many_topics() {
+        let mut lexer = Lexer::new("emit Big(1, 2, 3, 4, 5);");
+        let tokens = lexer.tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert!(CodeGenerator::new().compile(&program).is_err());
+    }
+
+    #[test]
+    fn test_if_else_and_while_keep_stack_balanced() {
+        // Branches at the top level used to underflow the tracked depth
+        let state = run_program(
+            r#"
+            let i = 0;
+            let total = 0;
+            while (i != 5) {
+                if (i == 2) {
+                    total = total + 10;
+                } else {
+                    total = total + 1;
+                }
+                i = i + 1;
+            }
+            if (total == 14) {
+                storage[100] = 1;
+            }
+            storage[101] = total;
+        "#,
+        );
+
+        assert_eq!(state.storage_load(&U256::from(100)), U256::one());
+        assert_eq!(state.storage_load(&U256::from(101)), U256::from(14));
+        assert!(state.stack.is_empty(), "leftover stack: {:?}", state.stack);
+    }
+
+    fn console_lines(source: &str) -> Vec<String> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let bytecode = CodeGenerator::new().compile(&program).unwrap();
+
+        let sink = crate::evm::ConsoleSink::buffer();
+        let result = crate::evm::EvmExecutor::new(1_000_000)
+            .with_console_sink(sink.clone())
+            .execute(&bytecode, 0, false)
+            .unwrap();
+        assert_eq!(result.status, crate::types::ExecutionStatus::Success);
+        sink.lines()
+    }
+
+    #[test]
+    fn test_console_log_prints_booleans_as_words() {
+        assert_eq!(
+            console_lines(
+                r#"
+                let x = 3;
+                console.log("equal", x == 3);
+                console.log("equal", x == 4);
+                console.log("flag", true);
+            "#
+            ),
+            vec![
+                "console.log: equal true",
+                "console.log: equal false",
+                "console.log: flag true"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_console_log_prints_every_digit() {
+        assert_eq!(
+            console_lines(
+                r#"
+                let big = 1234567890;
+                console.log("big", big * 1000);
+                console.log("zero", 0);
+                console.warn("negated", -42);
+            "#
+            ),
+            vec![
+                "console.log: big 1234567890000",
+                "console.log: zero 0",
+                "console.warn: negated -42"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_console_log_formats_an_unlabelled_first_value() {
+        assert_eq!(
+            console_lines(
+                r#"
+                let x = 3;
+                console.log(x * 1000, 2);
+                console.log(x == 3, 0);
+            "#
+            ),
+            vec!["console.log: 3000", "console.log: true"]
+        );
+    }
+
+    #[test]
+    fn test_stack_model_divergence_is_reported() {
+        let tokens = Lexer::new("1;").tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let stmt = &program.statements[0];
+
+        // A push the bookkeeping forgot to count
+        let mut generator = CodeGenerator::new();
+        generator.emit_push_u256(U256::one());
+        let err = generator.check_stack_model(stmt).unwrap_err();
+        assert_eq!(
+            err.message,
+            "Stack bookkeeping diverged in expression statement: \
+             tracked stack depth is 0 but the emitted code leaves 1"
+        );
+
+        // An opcode popping more than the emitted code has pushed
+        let mut generator = CodeGenerator::new();
+        generator.emit_opcode(OpCode::ADD);
+        let err = generator.check_stack_model(stmt).unwrap_err();
+        assert!(
+            err.message
+                .ends_with("ADD pops 2 items but only 0 are on the stack"),
+            "{}",
+            err
+        );
+    }
+
+    fn call_contract(source: &str, calldata: Vec<u8>) -> crate::types::ExecutionResult {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let bytecode = CodeGenerator::new()
+            .with_dispatcher(true)
+            .compile(&program)
+            .unwrap();
+
+        let mut state = crate::evm::EvmState::new(U256::from(1_000_000), U256::zero());
+        state.call_data = calldata;
+        let executor = crate::evm::EvmExecutor::new(1_000_000);
+        executor.execute_bytecode(&bytecode, &mut state).unwrap()
+    }
+
+    #[test]
+    fn test_dispatcher_routes_by_selector() {
+        let source = r#"
+            function add(a, b) {
+                return a + b;
+            }
+
+            function double(x) {
+                return x * 2;
+            }
+        "#;
+
+        let mut calldata = crate::abi::selector("add(uint256,uint256)").to_vec();
+        calldata.extend(
+            crate::abi::encode_args(
+                &[
+                    crate::abi::ParamType::Uint256,
+                    crate::abi::ParamType::Uint256,
+                ],
+                &["19", "23"],
+            )
+            .unwrap(),
+        );
+        let result = call_contract(source, calldata);
+        assert_eq!(result.status, crate::types::ExecutionStatus::Success);
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::from(42));
+
+        let mut calldata = crate::abi::selector("double(uint256)").to_vec();
+        calldata
+            .extend(crate::abi::encode_args(&[crate::abi::ParamType::Uint256], &["21"]).unwrap());
+        let result = call_contract(source, calldata);
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::from(42));
+
+        // Unknown selector reverts
+        let result = call_contract(source, vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(matches!(
+            result.status,
+            crate::types::ExecutionStatus::Revert(_)
+        ));
+    }
+
+    #[test]
+    fn test_deployable_init_code_installs_runtime() {
+        let source = "function answer() { return 42; }";
+        let mut lexer = Lexer::new(source);
+        let program = Parser::new(lexer.tokenize().unwrap()).parse().unwrap();
+
+        let runtime = CodeGenerator::new()
+            .with_dispatcher(true)
+            .compile(&program)
+            .unwrap();
+        let init = CodeGenerator::new()
+            .with_dispatcher(true)
+            .compile_deployable(&program)
+            .unwrap();
+
+        let tx = crate::types::Transaction {
+            from: ethereum_types::Address::from_low_u64_be(1),
+            to: None,
+            value: U256::zero(),
+            gas: U256::from(1_000_000),
+            gas_price: U256::one(),
+            data: init,
+        };
+        let mut accounts = HashMap::new();
+        let mut executor = crate::evm::EvmExecutor::new(1_000_000);
+        let result = executor.execute_transaction(&tx, &mut accounts).unwrap();
+        assert_eq!(result.status, crate::types::ExecutionStatus::Success);
+
+        let deployed: Vec<_> = accounts
+            .values()
+            .filter(|account| !account.code.is_empty())
+            .collect();
+        assert_eq!(deployed.len(), 1);
+        assert_eq!(deployed[0].code, runtime);
+    }
+
     #[test]
     fn test_complex_expression() {
         let bytecode = compile_expression("1 + 2 * 3").unwrap();
@@ -0,0 +1,151 @@
+use crate::compiler::ast::{Program, Statement};
+use crate::compiler::lexer::Lexer;
+use crate::compiler::parser::Parser;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A top-level `import` that could not be resolved
+#[derive(Debug)]
+pub struct ImportError {
+    /// File the import names, or the file that was being read
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Replaces each top-level `import "path";` with the declarations of the
+/// file it names. Paths are relative to the importing file's directory.
+/// A file imported more than once is merged only the first time, and an
+/// import that leads back to a file still being resolved is an error.
+pub struct ImportResolver {
+    /// Files whose imports are being resolved, outermost first
+    in_progress: Vec<PathBuf>,
+    merged: HashSet<PathBuf>,
+}
+
+impl ImportResolver {
+    pub fn new() -> Self {
+        Self {
+            in_progress: Vec::new(),
+            merged: HashSet::new(),
+        }
+    }
+
+    /// Resolve the imports of `program`, parsed from the file at `path`
+    pub fn resolve_file(&mut self, program: Program, path: &Path) -> Result<Program, ImportError> {
+        let path = canonicalize(path)?;
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.merged.insert(path.clone());
+        self.in_progress.push(path);
+        let statements = self.merge_imports(program, &dir);
+        self.in_progress.pop();
+        Ok(Program {
+            statements: statements?,
+        })
+    }
+
+    /// Every file read so far: the file given to `resolve_file` and the
+    /// files it imports, directly or not, sorted
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.merged.iter().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Resolve the imports of `program` relative to `dir`, for source that
+    /// did not come from a file
+    pub fn resolve(&mut self, program: Program, dir: &Path) -> Result<Program, ImportError> {
+        Ok(Program {
+            statements: self.merge_imports(program, dir)?,
+        })
+    }
+
+    fn merge_imports(
+        &mut self,
+        program: Program,
+        dir: &Path,
+    ) -> Result<Vec<Statement>, ImportError> {
+        let mut statements = Vec::new();
+        for stmt in program.statements {
+            let Statement::Import(import_stmt) = stmt else {
+                statements.push(stmt);
+                continue;
+            };
+
+            let path = canonicalize(&dir.join(&import_stmt.path))?;
+            if let Some(start) = self.in_progress.iter().position(|p| *p == path) {
+                let cycle = self.in_progress[start..]
+                    .iter()
+                    .chain(std::iter::once(&path))
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(ImportError {
+                    path,
+                    message: format!("Circular import: {}", cycle),
+                });
+            }
+            if !self.merged.insert(path.clone()) {
+                continue;
+            }
+
+            let imported = parse_file(&path)?;
+            let imported_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+            self.in_progress.push(path.clone());
+            let declarations = self.merge_imports(imported, &imported_dir);
+            self.in_progress.pop();
+
+            for declaration in declarations? {
+                match declaration {
+                    Statement::FuncDecl(_) | Statement::MappingDecl(_) => {
+                        statements.push(declaration)
+                    }
+                    other => {
+                        return Err(ImportError {
+                            path,
+                            message: format!(
+                                "Imported files may only declare functions and mappings, found {}",
+                                other.kind()
+                            ),
+                        })
+                    }
+                }
+            }
+        }
+        Ok(statements)
+    }
+}
+
+impl Default for ImportResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, ImportError> {
+    path.canonicalize().map_err(|e| ImportError {
+        path: path.to_path_buf(),
+        message: format!("Cannot open file: {}", e),
+    })
+}
+
+fn parse_file(path: &Path) -> Result<Program, ImportError> {
+    let error = |message: String| ImportError {
+        path: path.to_path_buf(),
+        message,
+    };
+    let source = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    let tokens = Lexer::new(&source)
+        .tokenize()
+        .map_err(|e| error(e.to_string()))?;
+    Parser::new(tokens)
+        .parse()
+        .map_err(|e| error(e.to_string()))
+}
@@ -36,7 +36,12 @@ pub enum TokenType {
     Else,
     While,
     For,
+    Break,
+    Continue,
     Return,
+    Mapping,
+    Emit,
+    Import,
     True,
     False,
 
@@ -47,6 +52,11 @@ pub enum TokenType {
     Slash,
     Percent,
     Equal,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
     EqualEqual,
     Bang,
     BangEqual,
@@ -67,6 +77,8 @@ pub enum TokenType {
     Semicolon,
     Comma,
     Dot,
+    Question,
+    Colon,
 
     // Built-ins
     Storage,
@@ -124,14 +136,6 @@ impl Lexer {
         }
     }
 
-    fn error(&self, message: &str) -> LexError {
-        LexError {
-            message: message.to_string(),
-            line: self.line,
-            column: self.column,
-        }
-    }
-
     pub fn tokenize(&mut self) -> LexResult<Vec<Token>> {
         let mut tokens = Vec::new();
 
@@ -146,30 +150,70 @@ impl Lexer {
             let start_column = self.column;
 
             match self.advance() {
-                '+' => tokens.push(Token::new(
-                    TokenType::Plus,
-                    "+".to_string(),
-                    start_line,
-                    start_column,
-                )),
-                '-' => tokens.push(Token::new(
-                    TokenType::Minus,
-                    "-".to_string(),
-                    start_line,
-                    start_column,
-                )),
-                '*' => tokens.push(Token::new(
-                    TokenType::Star,
-                    "*".to_string(),
-                    start_line,
-                    start_column,
-                )),
+                '+' => {
+                    if self.match_char('=') {
+                        tokens.push(Token::new(
+                            TokenType::PlusEqual,
+                            "+=".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    } else {
+                        tokens.push(Token::new(
+                            TokenType::Plus,
+                            "+".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    }
+                }
+                '-' => {
+                    if self.match_char('=') {
+                        tokens.push(Token::new(
+                            TokenType::MinusEqual,
+                            "-=".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    } else {
+                        tokens.push(Token::new(
+                            TokenType::Minus,
+                            "-".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    }
+                }
+                '*' => {
+                    if self.match_char('=') {
+                        tokens.push(Token::new(
+                            TokenType::StarEqual,
+                            "*=".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    } else {
+                        tokens.push(Token::new(
+                            TokenType::Star,
+                            "*".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    }
+                }
                 '/' => {
                     if self.match_char('/') {
                         // Line comment - skip until end of line
                         while self.peek() != '\n' && !self.is_at_end() {
                             self.advance();
                         }
+                    } else if self.match_char('=') {
+                        tokens.push(Token::new(
+                            TokenType::SlashEqual,
+                            "/=".to_string(),
+                            start_line,
+                            start_column,
+                        ));
                     } else {
                         tokens.push(Token::new(
                             TokenType::Slash,
@@ -179,12 +223,23 @@ impl Lexer {
                         ));
                     }
                 }
-                '%' => tokens.push(Token::new(
-                    TokenType::Percent,
-                    "%".to_string(),
-                    start_line,
-                    start_column,
-                )),
+                '%' => {
+                    if self.match_char('=') {
+                        tokens.push(Token::new(
+                            TokenType::PercentEqual,
+                            "%=".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    } else {
+                        tokens.push(Token::new(
+                            TokenType::Percent,
+                            "%".to_string(),
+                            start_line,
+                            start_column,
+                        ));
+                    }
+                }
                 '(' => tokens.push(Token::new(
                     TokenType::LeftParen,
                     "(".to_string(),
@@ -239,6 +294,18 @@ impl Lexer {
                     start_line,
                     start_column,
                 )),
+                '?' => tokens.push(Token::new(
+                    TokenType::Question,
+                    "?".to_string(),
+                    start_line,
+                    start_column,
+                )),
+                ':' => tokens.push(Token::new(
+                    TokenType::Colon,
+                    ":".to_string(),
+                    start_line,
+                    start_column,
+                )),
 
                 '=' => {
                     if self.match_char('=') {
@@ -444,7 +511,12 @@ impl Lexer {
             "else" => TokenType::Else,
             "while" => TokenType::While,
             "for" => TokenType::For,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
             "return" => TokenType::Return,
+            "mapping" => TokenType::Mapping,
+            "emit" => TokenType::Emit,
+            "import" => TokenType::Import,
             "true" => TokenType::True,
             "false" => TokenType::False,
             "storage" => TokenType::Storage,
@@ -462,11 +534,12 @@ impl Lexer {
 
         // Skip the opening quote
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
+            let c = self.advance();
+            if c == '\n' {
                 self.line += 1;
                 self.column = 1;
             }
-            value.push(self.advance());
+            value.push(c);
         }
 
         if self.is_at_end() {
@@ -495,9 +568,9 @@ impl Lexer {
                     self.advance();
                 }
                 '\n' => {
+                    self.advance();
                     self.line += 1;
                     self.column = 1;
-                    self.advance();
                 }
                 _ => break,
             }
@@ -555,6 +628,25 @@ mod tests {
         assert_eq!(tokens[4].token_type, TokenType::Percent);
     }
 
+    #[test]
+    fn test_compound_assignment_tokens() {
+        let mut lexer = Lexer::new("+= -= *= /= %= // not /=");
+        let tokens = lexer.tokenize().unwrap();
+
+        let types: Vec<_> = tokens.into_iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::PlusEqual,
+                TokenType::MinusEqual,
+                TokenType::StarEqual,
+                TokenType::SlashEqual,
+                TokenType::PercentEqual,
+                TokenType::Eof,
+            ]
+        );
+    }
+
     #[test]
     fn test_numbers() {
         let mut lexer = Lexer::new("42 0xFF 123");
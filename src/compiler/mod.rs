@@ -1,11 +1,14 @@
 pub mod ast;
 pub mod codegen;
+pub mod imports;
 pub mod lexer;
 pub mod parser;
 
 use codegen::CodeGenerator;
+use imports::ImportResolver;
 use lexer::Lexer;
 use parser::Parser;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 #[allow(clippy::enum_variant_names)]
@@ -13,6 +16,7 @@ pub enum CompilerError {
     LexError(lexer::LexError),
     ParseError(parser::ParseError),
     CodegenError(codegen::CompileError),
+    ImportError(imports::ImportError),
 }
 
 impl std::fmt::Display for CompilerError {
@@ -21,6 +25,7 @@ impl std::fmt::Display for CompilerError {
             CompilerError::LexError(e) => write!(f, "Lexer error: {}", e),
             CompilerError::ParseError(e) => write!(f, "Parser error: {}", e),
             CompilerError::CodegenError(e) => write!(f, "Codegen error: {}", e),
+            CompilerError::ImportError(e) => write!(f, "Import error: {}", e),
         }
     }
 }
@@ -39,6 +44,12 @@ impl From<parser::ParseError> for CompilerError {
     }
 }
 
+impl From<imports::ImportError> for CompilerError {
+    fn from(err: imports::ImportError) -> Self {
+        CompilerError::ImportError(err)
+    }
+}
+
 impl From<codegen::CompileError> for CompilerError {
     fn from(err: codegen::CompileError) -> Self {
         CompilerError::CodegenError(err)
@@ -47,11 +58,17 @@ impl From<codegen::CompileError> for CompilerError {
 
 pub struct Compiler {
     debug: bool,
+    contract: bool,
+    deployable: bool,
 }
 
 impl Compiler {
     pub fn new() -> Self {
-        Self { debug: false }
+        Self {
+            debug: false,
+            contract: false,
+            deployable: false,
+        }
     }
 
     pub fn with_debug(mut self, debug: bool) -> Self {
@@ -59,7 +76,39 @@ impl Compiler {
         self
     }
 
+    /// Emit an ABI selector dispatcher so functions can be called by calldata
+    pub fn with_contract(mut self, contract: bool) -> Self {
+        self.contract = contract;
+        self
+    }
+
+    /// Wrap the output in init code that returns the runtime code on deployment
+    pub fn with_deployable(mut self, deployable: bool) -> Self {
+        self.deployable = deployable;
+        self
+    }
+
+    /// Compile `source`, resolving its imports relative to the current directory
     pub fn compile(&self, source: &str) -> Result<Vec<u8>, CompilerError> {
+        self.compile_source(source, None)
+    }
+
+    /// Compile the file at `path`, resolving its imports relative to the
+    /// file's own directory
+    pub fn compile_file(&self, path: &Path) -> Result<Vec<u8>, CompilerError> {
+        self.compile_source(&read_source(path)?, Some(path))
+    }
+
+    /// The file at `path` and every file it imports, directly or not, e.g.
+    /// to know which files a rebuild depends on
+    pub fn source_files(&self, path: &Path) -> Result<Vec<PathBuf>, CompilerError> {
+        let program = self.parse(&read_source(path)?)?;
+        let mut resolver = ImportResolver::new();
+        resolver.resolve_file(program, path)?;
+        Ok(resolver.files())
+    }
+
+    fn compile_source(&self, source: &str, path: Option<&Path>) -> Result<Vec<u8>, CompilerError> {
         // Lexical analysis
         let mut lexer = Lexer::new(source);
         let tokens = lexer.tokenize()?;
@@ -72,6 +121,13 @@ impl Compiler {
         let mut parser = Parser::new(tokens);
         let program = parser.parse()?;
 
+        // Merge imported declarations
+        let mut resolver = ImportResolver::new();
+        let program = match path {
+            Some(path) => resolver.resolve_file(program, path)?,
+            None => resolver.resolve(program, Path::new("."))?,
+        };
+
         if self.debug {
             use ast::AstVisitor;
             let mut printer = ast::AstPrinter::new();
@@ -80,8 +136,13 @@ impl Compiler {
         }
 
         // Code generation
-        let mut generator = CodeGenerator::new();
-        let bytecode = generator.compile(&program)?;
+        let mut generator = CodeGenerator::new().with_dispatcher(self.contract);
+        let bytecode = if self.deployable {
+            generator.compile_deployable(&program)
+        } else {
+            generator.compile(&program)
+        }
+        .map_err(|e| e.with_source(source))?;
 
         if self.debug {
             println!("Generated bytecode: {:02X?}", bytecode);
@@ -91,6 +152,12 @@ impl Compiler {
         Ok(bytecode)
     }
 
+    /// Lex and parse `source` without generating code
+    pub fn parse(&self, source: &str) -> Result<ast::Program, CompilerError> {
+        let tokens = Lexer::new(source).tokenize()?;
+        Ok(Parser::new(tokens).parse()?)
+    }
+
     pub fn compile_expression(&self, source: &str) -> Result<Vec<u8>, CompilerError> {
         // Lexical analysis
         let mut lexer = Lexer::new(source);
@@ -104,7 +171,7 @@ impl Compiler {
         let mut generator = CodeGenerator::new();
         generator
             .visit_expression(&expr)
-            .map_err(CompilerError::CodegenError)?;
+            .map_err(|e| CompilerError::CodegenError(e.with_source(source)))?;
 
         // Add STOP to end execution
         generator.emit_opcode(crate::opcodes::OpCode::STOP);
@@ -119,6 +186,15 @@ impl Default for Compiler {
     }
 }
 
+fn read_source(path: &Path) -> Result<String, CompilerError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        CompilerError::ImportError(imports::ImportError {
+            path: path.to_path_buf(),
+            message: format!("Cannot open file: {}", e),
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,4 +244,120 @@ mod tests {
         let result = compiler.compile("let x = ;");
         assert!(matches!(result, Err(CompilerError::ParseError(_))));
     }
+
+    #[test]
+    fn test_undefined_variable_reports_line_and_column() {
+        let source = "let x = 1;\nlet y = x + 2;\n\nlet z = y + missing;\n";
+
+        let err = Compiler::new().compile(source).unwrap_err();
+        let CompilerError::CodegenError(err) = err else {
+            panic!("expected a codegen error, got {}", err);
+        };
+
+        assert_eq!(err.span, Some(ast::Span::new(4, 13)));
+        assert_eq!(err.snippet.as_deref(), Some("let z = y + missing;"));
+        assert!(err
+            .to_string()
+            .starts_with("Compile error at line 4:13: Undefined variable: missing"));
+    }
+
+    #[test]
+    fn test_parsed_program_prints_as_a_tree() {
+        use ast::AstPrinter;
+
+        let program = Compiler::new().parse("let x = 1 + 2;").unwrap();
+        let printed = AstPrinter::new().print(&program);
+        assert!(
+            printed.contains("VarDecl { name: x, initializer: (1 + 2) }"),
+            "{}",
+            printed
+        );
+
+        let err = Compiler::new().parse("let x = ;").unwrap_err();
+        assert!(err.to_string().contains("at 1:9"), "{}", err);
+    }
+
+    fn import_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("abby_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_imported_function_is_callable() {
+        use crate::evm::{EvmExecutor, ExecutionContext};
+        use ethereum_types::U256;
+
+        let dir = import_dir("imports");
+        std::fs::write(
+            dir.join("lib/math.abby"),
+            "function double(x) { return x * 2; }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.abby"),
+            "import \"lib/math.abby\";\nfunction add(a, b) { return a + b; }\n",
+        )
+        .unwrap();
+
+        let bytecode = Compiler::new()
+            .with_contract(true)
+            .compile_file(&dir.join("main.abby"))
+            .unwrap();
+
+        let mut calldata = crate::abi::selector("double(uint256)").to_vec();
+        calldata
+            .extend(crate::abi::encode_args(&[crate::abi::ParamType::Uint256], &["21"]).unwrap());
+        let result = EvmExecutor::new(1_000_000)
+            .execute_with_context(
+                &bytecode,
+                &ExecutionContext::new().calldata(calldata),
+                false,
+            )
+            .unwrap();
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::from(42));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_source_files_lists_nested_imports() {
+        let dir = import_dir("import_sources");
+        std::fs::write(dir.join("main.abby"), "import \"lib/a.abby\";\nreturn 1;").unwrap();
+        std::fs::write(dir.join("lib/a.abby"), "import \"b.abby\";").unwrap();
+        std::fs::write(dir.join("lib/b.abby"), "function b() { return 2; }").unwrap();
+
+        let dir = dir.canonicalize().unwrap();
+        let files = Compiler::new()
+            .source_files(&dir.join("main.abby"))
+            .unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("lib/a.abby"),
+                dir.join("lib/b.abby"),
+                dir.join("main.abby")
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_circular_imports_are_rejected() {
+        let dir = import_dir("import_cycle");
+        std::fs::write(dir.join("a.abby"), "import \"lib/b.abby\";\nreturn 1;").unwrap();
+        std::fs::write(dir.join("lib/b.abby"), "import \"../a.abby\";").unwrap();
+
+        let err = Compiler::new()
+            .compile_file(&dir.join("a.abby"))
+            .unwrap_err();
+        assert!(
+            matches!(&err, CompilerError::ImportError(e) if e.message.starts_with("Circular import")),
+            "unexpected error: {}",
+            err
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
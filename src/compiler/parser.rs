@@ -49,6 +49,10 @@ impl Parser {
             self.var_declaration()
         } else if self.match_token(&TokenType::Function) {
             self.function_declaration()
+        } else if self.match_token(&TokenType::Mapping) {
+            self.mapping_declaration()
+        } else if self.match_token(&TokenType::Import) {
+            self.import_declaration()
         } else {
             self.statement()
         }
@@ -116,6 +120,26 @@ impl Parser {
         Ok(Statement::func_decl(name, params, body))
     }
 
+    fn mapping_declaration(&mut self) -> ParseResult<Statement> {
+        let name = self.consume_identifier("Expected mapping name")?;
+        let span = self.previous_span();
+        self.consume(
+            &TokenType::Semicolon,
+            "Expected ';' after mapping declaration",
+        )?;
+        Ok(Statement::mapping_decl(name, span))
+    }
+
+    fn import_declaration(&mut self) -> ParseResult<Statement> {
+        let span = self.previous_span();
+        let path = match self.advance().map(|token| &token.token_type) {
+            Some(TokenType::String(path)) => path.clone(),
+            _ => return Err(self.error("Expected file path string after 'import'")),
+        };
+        self.consume(&TokenType::Semicolon, "Expected ';' after import")?;
+        Ok(Statement::import(path, span))
+    }
+
     fn statement(&mut self) -> ParseResult<Statement> {
         if self.match_token(&TokenType::If) {
             self.if_statement()
@@ -123,6 +147,16 @@ impl Parser {
             self.while_statement()
         } else if self.match_token(&TokenType::Return) {
             self.return_statement()
+        } else if self.match_token(&TokenType::Break) {
+            let span = self.previous_span();
+            self.consume(&TokenType::Semicolon, "Expected ';' after 'break'")?;
+            Ok(Statement::Break(span))
+        } else if self.match_token(&TokenType::Continue) {
+            let span = self.previous_span();
+            self.consume(&TokenType::Semicolon, "Expected ';' after 'continue'")?;
+            Ok(Statement::Continue(span))
+        } else if self.match_token(&TokenType::Emit) {
+            self.emit_statement()
         } else if self.match_token(&TokenType::LeftBrace) {
             Ok(Statement::Block(self.block()?))
         } else {
@@ -167,6 +201,27 @@ impl Parser {
         Ok(Statement::return_stmt(value))
     }
 
+    fn emit_statement(&mut self) -> ParseResult<Statement> {
+        let event = self.consume_identifier("Expected event name after 'emit'")?;
+        let span = self.previous_span();
+        self.consume(&TokenType::LeftParen, "Expected '(' after event name")?;
+
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                arguments.push(self.expression()?);
+                if !self.match_token(&TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(&TokenType::RightParen, "Expected ')' after event arguments")?;
+        self.consume(&TokenType::Semicolon, "Expected ';' after emit statement")?;
+
+        Ok(Statement::emit(event, arguments, span))
+    }
+
     fn block(&mut self) -> ParseResult<Block> {
         let mut statements = Vec::new();
 
@@ -190,7 +245,23 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> ParseResult<Expression> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
+
+        if let Some(operator) = self.compound_assignment_operator() {
+            // x op= value is x = x op value
+            let value = self.assignment()?;
+            return match expr {
+                Expression::Variable(var) if var.name != "memory" && var.name != "storage" => {
+                    let current = Expression::variable(var.name.clone(), var.span);
+                    Ok(Expression::assignment(
+                        var.name,
+                        Expression::binary(current, operator, value),
+                        var.span,
+                    ))
+                }
+                _ => Err(self.error("Invalid compound assignment target")),
+            };
+        }
 
         if self.match_token(&TokenType::Equal) {
             let value = self.assignment()?;
@@ -201,7 +272,7 @@ impl Parser {
                     // Special handling for memory and storage
                     match var.name.as_str() {
                         "memory" => Ok(Expression::memory_assignment(value)),
-                        _ => Ok(Expression::assignment(var.name, value)),
+                        _ => Ok(Expression::assignment(var.name, value, var.span)),
                     }
                 }
                 // Array access assignment: obj[index] = value
@@ -219,7 +290,13 @@ impl Parser {
                                     Box::new(value),
                                 )))
                             }
-                            _ => Err(self.error("Invalid assignment target")),
+                            // Mapping assignment: name[key] = value
+                            _ => Ok(Expression::mapping_assignment(
+                                var.name,
+                                *array_access.index,
+                                value,
+                                var.span,
+                            )),
                         }
                     } else {
                         Err(self.error("Invalid assignment target"))
@@ -232,6 +309,34 @@ impl Parser {
         }
     }
 
+    /// Consume `+=`, `-=`, `*=`, `/=` or `%=` and return its arithmetic operator
+    fn compound_assignment_operator(&mut self) -> Option<BinaryOperator> {
+        let operator = match self.peek().token_type {
+            TokenType::PlusEqual => BinaryOperator::Add,
+            TokenType::MinusEqual => BinaryOperator::Subtract,
+            TokenType::StarEqual => BinaryOperator::Multiply,
+            TokenType::SlashEqual => BinaryOperator::Divide,
+            TokenType::PercentEqual => BinaryOperator::Modulo,
+            _ => return None,
+        };
+        self.advance();
+        Some(operator)
+    }
+
+    fn conditional(&mut self) -> ParseResult<Expression> {
+        let condition = self.or()?;
+
+        if self.match_token(&TokenType::Question) {
+            let then_value = self.assignment()?;
+            self.consume(&TokenType::Colon, "Expected ':' in conditional expression")?;
+            // Right-associative: a ? b : c ? d : e is a ? b : (c ? d : e)
+            let else_value = self.conditional()?;
+            Ok(Expression::conditional(condition, then_value, else_value))
+        } else {
+            Ok(condition)
+        }
+    }
+
     fn or(&mut self) -> ParseResult<Expression> {
         let mut expr = self.and()?;
 
@@ -328,7 +433,7 @@ impl Parser {
             } else if self.match_token(&TokenType::Dot) {
                 // Handle member access: expr.property
                 let property = self.consume_identifier("Expected property name after '.'")?;
-                expr = Expression::member_access(expr, property);
+                expr = Expression::member_access(expr, property, self.previous_span());
             } else {
                 break;
             }
@@ -356,12 +461,13 @@ impl Parser {
 
     fn primary(&mut self) -> ParseResult<Expression> {
         if let Some(token) = self.advance() {
+            let span = Span::new(token.line, token.column);
             match &token.token_type {
                 TokenType::True => Ok(Expression::boolean(true)),
                 TokenType::False => Ok(Expression::boolean(false)),
                 TokenType::Number(n) => Ok(Expression::number(*n)),
                 TokenType::String(s) => Ok(Expression::string(s.clone())),
-                TokenType::Identifier(name) => Ok(Expression::variable(name.clone())),
+                TokenType::Identifier(name) => Ok(Expression::variable(name.clone(), span)),
                 TokenType::Storage => {
                     // Check if it's storage.method() or storage[index]
                     if self.check(&TokenType::Dot) {
@@ -369,7 +475,7 @@ impl Parser {
                         self.handle_storage_method()
                     } else {
                         // Just return storage as a variable for array access handling
-                        Ok(Expression::variable("storage".to_string()))
+                        Ok(Expression::variable("storage".to_string(), span))
                     }
                 }
                 TokenType::Memory => {
@@ -379,7 +485,7 @@ impl Parser {
                         self.handle_memory_method()
                     } else {
                         // Just return memory as a variable for assignment/array access handling
-                        Ok(Expression::variable("memory".to_string()))
+                        Ok(Expression::variable("memory".to_string(), span))
                     }
                 }
                 TokenType::LeftParen => {
@@ -413,36 +519,6 @@ impl Parser {
         }
     }
 
-    fn handle_builtin_method(&mut self, object: String) -> ParseResult<Expression> {
-        match object.as_str() {
-            "console" => {
-                // Handle console methods like console.log(), console.warn(), console.error()
-                if let Some(token) = self.advance() {
-                    if let TokenType::Identifier(method) = &token.token_type {
-                        let method_name = method.clone();
-                        match method_name.as_str() {
-                            "log" | "warn" | "error" => {
-                                // Return a member access expression that will be handled by call parsing
-                                let console_expr = Expression::variable("console".to_string());
-                                Ok(Expression::member_access(console_expr, method_name))
-                            }
-                            _ => Err(ParseError {
-                                message: format!("Unknown console method '{}'", method_name),
-                                line: token.line,
-                                column: token.column,
-                            }),
-                        }
-                    } else {
-                        Err(self.error("Expected method name after '.'"))
-                    }
-                } else {
-                    Err(self.error("Expected method name after '.'"))
-                }
-            }
-            _ => Err(self.error(&format!("Unknown method on object '{}'", object))),
-        }
-    }
-
     fn handle_storage_method(&mut self) -> ParseResult<Expression> {
         if let Some(token) = self.advance() {
             if let TokenType::Identifier(method) = &token.token_type {
@@ -602,6 +678,12 @@ impl Parser {
         }
     }
 
+    /// Source position of the most recently consumed token
+    fn previous_span(&self) -> Span {
+        let token = &self.tokens[self.current - 1];
+        Span::new(token.line, token.column)
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.tokens.len() || matches!(self.peek().token_type, TokenType::Eof)
     }
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Destination for console.log output and other LOG messages printed while
+/// executing bytecode
+#[derive(Clone, Default)]
+pub enum ConsoleSink {
+    /// Print each line to stdout
+    #[default]
+    Stdout,
+    /// Collect lines in memory, e.g. for tests or embedding
+    Buffer(Arc<Mutex<Vec<String>>>),
+    /// Hand each line to a user-supplied callback
+    Callback(Arc<dyn Fn(&str) + Send + Sync>),
+}
+
+impl ConsoleSink {
+    /// An empty in-memory buffer; clones share the same lines
+    pub fn buffer() -> Self {
+        ConsoleSink::Buffer(Arc::new(Mutex::new(Vec::new())))
+    }
+
+    pub fn callback(f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        ConsoleSink::Callback(Arc::new(f))
+    }
+
+    pub fn write(&self, line: &str) {
+        match self {
+            ConsoleSink::Stdout => println!("{}", line),
+            ConsoleSink::Buffer(lines) => lines
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(line.to_string()),
+            ConsoleSink::Callback(f) => f(line),
+        }
+    }
+
+    /// Lines captured so far by a buffer sink; empty for other sinks
+    pub fn lines(&self) -> Vec<String> {
+        match self {
+            ConsoleSink::Buffer(lines) => lines.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for ConsoleSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsoleSink::Stdout => write!(f, "Stdout"),
+            ConsoleSink::Buffer(lines) => f.debug_tuple("Buffer").field(lines).finish(),
+            ConsoleSink::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::opcodes::disassembler::{decode, format_instruction, Instruction};
+use std::collections::BTreeSet;
+
+/// Which instructions of a program ran, built from `EvmExecutor::executed_offsets`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageReport {
+    instructions: Vec<Instruction>,
+    executed: BTreeSet<usize>,
+}
+
+impl CoverageReport {
+    pub fn new(bytecode: &[u8], executed_offsets: &BTreeSet<usize>) -> Self {
+        let instructions = decode(bytecode);
+        let executed = instructions
+            .iter()
+            .map(|instruction| instruction.pc)
+            .filter(|pc| executed_offsets.contains(pc))
+            .collect();
+        Self {
+            instructions,
+            executed,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn executed(&self) -> usize {
+        self.executed.len()
+    }
+
+    pub fn percentage(&self) -> f64 {
+        if self.instructions.is_empty() {
+            return 100.0;
+        }
+        self.executed() as f64 * 100.0 / self.total() as f64
+    }
+
+    /// Offsets of instructions that never ran
+    pub fn uncovered(&self) -> Vec<usize> {
+        self.instructions
+            .iter()
+            .map(|instruction| instruction.pc)
+            .filter(|pc| !self.executed.contains(pc))
+            .collect()
+    }
+
+    /// Disassembly with unvisited instructions marked by `!`
+    pub fn annotated(&self) -> Vec<String> {
+        self.instructions
+            .iter()
+            .map(|instruction| {
+                let marker = if self.executed.contains(&instruction.pc) {
+                    ' '
+                } else {
+                    '!'
+                };
+                format!(
+                    "{} {:04x}: {}",
+                    marker,
+                    instruction.pc,
+                    format_instruction(instruction)
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::EvmExecutor;
+
+    #[test]
+    fn test_skipped_else_arm_is_uncovered() {
+        // 00: PUSH1 1, PUSH1 0x0a, JUMPI       always taken
+        // 05: PUSH1 2, PUSH1 0x0d, JUMP        else arm, skipped
+        // 0a: JUMPDEST, PUSH1 3
+        // 0d: JUMPDEST, STOP
+        let bytecode = hex::decode("6001600a576002600d565b60035b00").unwrap();
+        let mut executor = EvmExecutor::new(10000).with_coverage(true);
+        executor.execute(&bytecode, 0, false).unwrap();
+
+        let report = CoverageReport::new(&bytecode, executor.executed_offsets());
+        assert_eq!(report.total(), 10);
+        assert_eq!(report.executed(), 7);
+        assert_eq!(report.uncovered(), vec![0x05, 0x07, 0x09]);
+
+        let annotated = report.annotated();
+        assert_eq!(annotated[3], "! 0005: PUSH1 0x02");
+        assert_eq!(annotated[6], "  000a: JUMPDEST");
+    }
+}
@@ -0,0 +1,181 @@
+use super::{capped_refund, EvmState, ExecutionContext};
+use crate::opcodes::{opcode_info, OpCode};
+use crate::types::{ExecutionResult, ExecutionStatus, Word};
+use ethereum_types::U256;
+use std::collections::HashMap;
+
+/// State reported after a single opcode ran
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugStep {
+    pub pc: usize,
+    pub opcode: OpCode,
+    /// Gas left before the opcode ran
+    pub gas_before: U256,
+    pub gas_after: U256,
+    /// Stack after the opcode ran, bottom first
+    pub stack: Vec<Word>,
+}
+
+/// Runs bytecode one opcode at a time, exposing the machine state between
+/// steps. Created with `EvmExecutor::debugger`.
+pub struct Debugger {
+    bytecode: Vec<u8>,
+    state: EvmState,
+    initial_gas: U256,
+}
+
+impl Debugger {
+    pub(super) fn new(bytecode: &[u8], state: EvmState) -> Self {
+        Self {
+            bytecode: bytecode.to_vec(),
+            initial_gas: state.gas,
+            state,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.pc >= self.bytecode.len()
+            || self.state.halted
+            || self.state.reverted
+            || self.state.error.is_some()
+    }
+
+    pub fn pc(&self) -> usize {
+        self.state.pc
+    }
+
+    pub fn gas(&self) -> U256 {
+        self.state.gas
+    }
+
+    /// Stack contents, bottom first
+    pub fn stack(&self) -> &[Word] {
+        &self.state.stack
+    }
+
+    /// `len` bytes of memory from `offset`, zero-filled past its current end
+    pub fn memory(&self, offset: usize, len: usize) -> Vec<u8> {
+        (offset..offset.saturating_add(len))
+            .map(|i| self.state.memory.get(i).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// The opcode that the next `step` will run
+    pub fn next_opcode(&self) -> Option<OpCode> {
+        if self.is_finished() {
+            return None;
+        }
+        Some(opcode_info(self.bytecode[self.state.pc]).opcode.clone())
+    }
+
+    /// Run one opcode; `None` once execution has stopped
+    pub fn step(&mut self) -> Option<DebugStep> {
+        if self.is_finished() {
+            return None;
+        }
+        let pc = self.state.pc;
+        let gas_before = self.state.gas;
+        let info = self.state.step(&self.bytecode);
+
+        Some(DebugStep {
+            pc,
+            opcode: info.opcode.clone(),
+            gas_before,
+            gas_after: self.state.gas,
+            stack: self.state.stack.clone(),
+        })
+    }
+
+    /// Run until execution stops, returning the steps taken
+    pub fn run_to_end(&mut self) -> Vec<DebugStep> {
+        std::iter::from_fn(|| self.step()).collect()
+    }
+
+    /// Outcome so far, in the same form `EvmExecutor::execute` reports it
+    pub fn result(&self) -> ExecutionResult {
+        let status = match &self.state.error {
+            Some(error) => ExecutionStatus::from(error.clone()),
+            None if self.state.reverted => {
+                ExecutionStatus::Revert("Execution reverted".to_string())
+            }
+            None => ExecutionStatus::Success,
+        };
+        let raw_refund = if status == ExecutionStatus::Success {
+            self.state.refund
+        } else {
+            U256::zero()
+        };
+        let refund = capped_refund(self.initial_gas - self.state.gas, raw_refund);
+
+        ExecutionResult {
+            status,
+            gas_used: self.initial_gas - self.state.gas - refund,
+            gas_remaining: self.state.gas + refund,
+            refund,
+            raw_refund,
+            return_data: self.state.return_data.clone(),
+            logs: self.state.logs.clone(),
+            state_changes: HashMap::new(),
+            memory: self.state.memory.clone(),
+            storage: self.state.storage.clone(),
+            created_address: None,
+        }
+    }
+}
+
+impl super::EvmExecutor {
+    /// Prepare `bytecode` for step-by-step execution with this executor's
+    /// gas limit, console and limits
+    pub fn debugger(&self, bytecode: &[u8], context: &ExecutionContext) -> Debugger {
+        let mut state = context.new_state(self.gas_limit);
+        self.configure(&mut state);
+        Debugger::new(bytecode, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::EvmExecutor;
+
+    #[test]
+    fn test_stepping_reports_each_state() {
+        // PUSH1 1, PUSH1 2, ADD
+        let bytecode = hex::decode("6001600201").unwrap();
+        let executor = EvmExecutor::new(1000);
+        let mut debugger = executor.debugger(&bytecode, &ExecutionContext::default());
+
+        assert_eq!(debugger.next_opcode(), Some(OpCode::PUSH1));
+        let first = debugger.step().unwrap();
+        assert_eq!((first.pc, first.opcode.clone()), (0, OpCode::PUSH1));
+        assert_eq!(first.gas_before - first.gas_after, U256::from(3));
+        assert_eq!(first.stack, vec![U256::from(1)]);
+        assert_eq!(debugger.pc(), 2);
+
+        let second = debugger.step().unwrap();
+        assert_eq!(second.pc, 2);
+        assert_eq!(second.stack, vec![U256::from(1), U256::from(2)]);
+
+        let third = debugger.step().unwrap();
+        assert_eq!((third.pc, third.opcode), (4, OpCode::ADD));
+        assert_eq!(debugger.stack(), &[U256::from(3)]);
+
+        assert!(debugger.is_finished());
+        assert!(debugger.step().is_none());
+        let result = debugger.result();
+        assert_eq!(result.status, ExecutionStatus::Success);
+        assert_eq!(result.gas_used, U256::from(9));
+    }
+
+    #[test]
+    fn test_memory_view_is_zero_filled() {
+        // PUSH1 0xff, PUSH1 0, MSTORE8
+        let bytecode = hex::decode("60ff600053").unwrap();
+        let executor = EvmExecutor::new(1000);
+        let mut debugger = executor.debugger(&bytecode, &ExecutionContext::default());
+
+        assert_eq!(debugger.run_to_end().len(), 3);
+        assert_eq!(debugger.memory(0, 2), vec![0xff, 0x00]);
+        assert_eq!(debugger.memory(100, 2), vec![0, 0]);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::opcodes::OpCode;
+use crate::types::ExecutionStatus;
+use ethereum_types::U256;
+
+/// Reasons execution of an opcode can fail
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvmError {
+    StackUnderflow,
+    StackOverflow,
+    OutOfGas,
+    InvalidJump,
+    /// INVALID (0xfe) or a byte that is not an opcode
+    InvalidOpcode(u8),
+    UnimplementedOpcode(OpCode),
+    InvalidDupParameter,
+    /// A PUSH whose immediate data runs past the end of the code
+    PushOutOfBounds,
+    MemoryLimit,
+    ReturnDataTooLarge {
+        size: U256,
+        limit: usize,
+    },
+    /// CREATE or CREATE2 given more init code than `MAX_INITCODE_SIZE`
+    InitCodeTooLarge {
+        size: U256,
+        limit: usize,
+    },
+    /// RETURNDATACOPY reading past the end of the last call's output
+    ReturnDataOutOfBounds,
+    /// State modification attempted while executing under STATICCALL
+    WriteInStaticContext,
+    StepLimit,
+}
+
+impl std::fmt::Display for EvmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvmError::StackUnderflow => write!(f, "Stack underflow"),
+            EvmError::StackOverflow => write!(f, "Stack overflow"),
+            EvmError::OutOfGas => write!(f, "Out of gas"),
+            EvmError::InvalidJump => write!(f, "Invalid jump destination"),
+            EvmError::InvalidOpcode(byte) => write!(f, "Invalid opcode 0x{:02x}", byte),
+            EvmError::UnimplementedOpcode(opcode) => {
+                write!(f, "Unimplemented opcode: {:?}", opcode)
+            }
+            EvmError::InvalidDupParameter => write!(f, "Invalid DUP parameter"),
+            EvmError::PushOutOfBounds => write!(f, "Push instruction exceeds bytecode length"),
+            EvmError::MemoryLimit => write!(f, "Memory limit exceeded"),
+            EvmError::ReturnDataTooLarge { size, limit } => write!(
+                f,
+                "Return data size {} exceeds limit of {} bytes",
+                size, limit
+            ),
+            EvmError::InitCodeTooLarge { size, limit } => write!(
+                f,
+                "Init code size {} exceeds limit of {} bytes",
+                size, limit
+            ),
+            EvmError::ReturnDataOutOfBounds => write!(f, "Return data copy out of bounds"),
+            EvmError::WriteInStaticContext => write!(f, "State modification in static context"),
+            EvmError::StepLimit => write!(f, "Execution limit exceeded (too many steps)"),
+        }
+    }
+}
+
+impl std::error::Error for EvmError {}
+
+impl From<EvmError> for String {
+    fn from(err: EvmError) -> Self {
+        err.to_string()
+    }
+}
+
+impl From<EvmError> for ExecutionStatus {
+    fn from(err: EvmError) -> Self {
+        match err {
+            EvmError::OutOfGas => ExecutionStatus::OutOfGas,
+            other => ExecutionStatus::Error(other.to_string()),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use super::{run_frame, EvmState, Snapshot};
+use crate::types::{Account, ExecutionResult, ExecutionStatus};
+use ethereum_types::{Address, U256};
+
+/// Gas given to every deployment and call the harness sends
+const HARNESS_GAS: u64 = 10_000_000;
+
+/// Where the harness installs its factory contract
+pub const FACTORY_ADDRESS: Address = Address::repeat_byte(0xfa);
+
+/// Factory runtime: copy the call data into memory, CREATE a contract from
+/// it and return the address CREATE pushed, zero if the creation failed
+const FACTORY_CODE: [u8; 20] = [
+    0x36, 0x60, 0x00, 0x60, 0x00, 0x37, // CALLDATACOPY(0, 0, CALLDATASIZE)
+    0x36, 0x60, 0x00, 0x60, 0x00, 0xf0, // CREATE(0, 0, CALLDATASIZE)
+    0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3, // return it as a word
+];
+
+/// Deploys contracts through a factory with CREATE and sends them calls from
+/// a single account, to study how contracts interact, e.g. a reentrant call
+/// back into a contract that is still running. All of it runs against one
+/// long-lived state, so `snapshot` and `restore` rewind whole calls through
+/// the same journal that rolls back a reverted frame.
+pub struct ContractHarness {
+    state: EvmState,
+}
+
+impl ContractHarness {
+    pub fn new(sender: Address) -> Self {
+        let mut state = EvmState::new(U256::zero(), U256::zero());
+        state.address = sender;
+        state.origin = sender;
+        state.accounts.insert(
+            FACTORY_ADDRESS,
+            Account {
+                code: FACTORY_CODE.to_vec(),
+                nonce: U256::one(),
+                ..Default::default()
+            },
+        );
+        Self { state }
+    }
+
+    /// Deploy a contract whose code is `runtime` by calling the factory,
+    /// returning the address its CREATE gave the contract
+    pub fn deploy(&mut self, runtime: &[u8]) -> Result<Address, String> {
+        let result = self.call(FACTORY_ADDRESS, &init_code(runtime)?);
+        if result.status != ExecutionStatus::Success {
+            return Err(format!("Deployment failed: {:?}", result.status));
+        }
+        match Address::from_slice(&result.return_data[12..]) {
+            address if address.is_zero() => Err("Deployment did not create a contract".into()),
+            address => Ok(address),
+        }
+    }
+
+    /// Call `to` with `data`. A reverted call rolls back its state changes
+    /// and is returned with its revert data.
+    pub fn call(&mut self, to: Address, data: &[u8]) -> ExecutionResult {
+        let sender = self.state.address;
+        self.state.account_mut(sender).nonce += U256::one();
+        let logs = self.state.logs.len();
+
+        let gas = U256::from(HARNESS_GAS);
+        let mut frame = self.state.child_frame(to, U256::zero(), data.to_vec(), gas);
+        let snapshot = frame.snapshot();
+        let code = frame
+            .accounts
+            .get(&to)
+            .map(|account| account.code.clone())
+            .unwrap_or_default();
+        run_frame(&mut frame, &code);
+
+        let status = if let Some(error) = frame.error.take() {
+            ExecutionStatus::from(error)
+        } else if frame.reverted {
+            ExecutionStatus::Revert("Execution reverted".to_string())
+        } else {
+            ExecutionStatus::Success
+        };
+        if status == ExecutionStatus::Success {
+            frame.commit(snapshot);
+        } else {
+            frame.revert_to(snapshot);
+        }
+        let result = ExecutionResult {
+            status,
+            gas_used: gas - frame.gas,
+            gas_remaining: frame.gas,
+            return_data: std::mem::take(&mut frame.return_data),
+            logs: frame.logs[logs..].to_vec(),
+            ..Default::default()
+        };
+        self.state.return_from_child(frame);
+        result
+    }
+
+    pub fn account(&self, address: &Address) -> Option<&Account> {
+        self.state.accounts.get(address)
+    }
+
+    pub fn storage_at(&self, address: &Address, slot: U256) -> U256 {
+        self.account(address)
+            .and_then(|account| account.storage.get(&slot).copied())
+            .unwrap_or_default()
+    }
+
+    /// Mark the current state; `restore` undoes every call made since. Only
+    /// a position in the journal is taken, nothing is copied.
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.state.snapshot()
+    }
+
+    /// Roll back every change made since `snapshot` was taken
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.state.revert_to(snapshot);
+    }
+}
+
+/// Init code that copies `runtime` out of itself and returns it:
+/// PUSH2 len, DUP1, PUSH2 13, PUSH1 0, CODECOPY, PUSH1 0, RETURN
+fn init_code(runtime: &[u8]) -> Result<Vec<u8>, String> {
+    let len = u16::try_from(runtime.len()).map_err(|_| {
+        format!(
+            "Runtime code is {} bytes, more than the {} PUSH2 can size",
+            runtime.len(),
+            u16::MAX
+        )
+    })?;
+    let mut code = vec![0x61];
+    code.extend_from_slice(&len.to_be_bytes());
+    code.extend_from_slice(&[0x80, 0x61, 0x00, 0x0d, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+    code.extend_from_slice(runtime);
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::assembler::assemble;
+
+    #[test]
+    fn test_reentrant_call_sees_state_that_a_revert_later_undoes() {
+        let sender = Address::from_low_u64_be(0xa11ce);
+        let mut harness = ContractHarness::new(sender);
+
+        // Calls back into its caller with one byte of call data and returns
+        // whatever the caller answers
+        let attacker = harness
+            .deploy(
+                &assemble(
+                    "
+                    PUSH1 0x20      ; return size
+                    PUSH1 0x00      ; return offset
+                    PUSH1 0x01      ; call data size
+                    PUSH1 0x00
+                    PUSH1 0x00      ; value
+                    CALLER
+                    GAS
+                    CALL
+                    POP
+                    PUSH1 0x20
+                    PUSH1 0x00
+                    RETURN
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        // Without call data: set slot 0 to 42, call the attacker, then
+        // revert with what the attacker saw. With call data (the reentrant
+        // call): return slot 0.
+        let victim = harness
+            .deploy(
+                &assemble(&format!(
+                    "
+                    CALLDATASIZE
+                    JUMPI @reentered
+                    PUSH1 0x2a
+                    PUSH1 0x00
+                    SSTORE
+                    PUSH1 0x20
+                    PUSH1 0x00
+                    PUSH1 0x00
+                    PUSH1 0x00
+                    PUSH1 0x00
+                    PUSH20 0x{:x}
+                    GAS
+                    CALL
+                    POP
+                    PUSH1 0x20
+                    PUSH1 0x00
+                    REVERT
+                    reentered:
+                    JUMPDEST
+                    PUSH1 0x00
+                    SLOAD
+                    PUSH1 0x00
+                    MSTORE
+                    PUSH1 0x20
+                    PUSH1 0x00
+                    RETURN
+                    ",
+                    attacker
+                ))
+                .unwrap(),
+            )
+            .unwrap();
+        let before = harness.snapshot();
+        let nonce = harness.account(&sender).unwrap().nonce;
+
+        let result = harness.call(victim, &[]);
+        assert!(matches!(result.status, ExecutionStatus::Revert(_)));
+        // The reentrant call read the write that was then rolled back
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::from(42));
+        assert_eq!(harness.storage_at(&victim, U256::zero()), U256::zero());
+
+        // Calling in directly reads slot 0 without reverting
+        let result = harness.call(victim, &[1]);
+        assert_eq!(result.status, ExecutionStatus::Success);
+        assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+
+        // Restoring rewinds both calls, the sender's nonce included
+        assert_eq!(
+            harness.account(&sender).unwrap().nonce,
+            nonce + U256::from(2)
+        );
+        harness.restore(before);
+        assert_eq!(harness.account(&sender).unwrap().nonce, nonce);
+    }
+
+    #[test]
+    fn test_contracts_come_from_the_factory_and_restore_removes_them() {
+        let sender = Address::from_low_u64_be(0xa11ce);
+        let mut harness = ContractHarness::new(sender);
+        let first = harness.deploy(&[0x00]).unwrap();
+        let snapshot = harness.snapshot();
+
+        // CREATE numbers the factory's contracts by its nonce, from 1
+        let second = harness.deploy(&[0x01, 0x00]).unwrap();
+        assert_eq!(
+            first,
+            crate::utils::create_address(FACTORY_ADDRESS, U256::one())
+        );
+        assert_eq!(
+            second,
+            crate::utils::create_address(FACTORY_ADDRESS, U256::from(2))
+        );
+        assert_eq!(harness.account(&second).unwrap().code, vec![0x01, 0x00]);
+
+        harness.restore(snapshot);
+        assert!(harness.account(&second).is_none());
+        assert_eq!(harness.account(&first).unwrap().code, vec![0x00]);
+        assert_eq!(
+            harness.account(&FACTORY_ADDRESS).unwrap().nonce,
+            U256::from(2)
+        );
+
+        // The factory hands out the rewound address again
+        assert_eq!(harness.deploy(&[0x02]).unwrap(), second);
+    }
+
+    #[test]
+    fn test_deploy_rejects_runtime_too_long_for_push2() {
+        let sender = Address::from_low_u64_be(0xa11ce);
+        let mut harness = ContractHarness::new(sender);
+
+        let error = harness.deploy(&vec![0x00; 0x10000]).unwrap_err();
+        assert!(error.contains("65536 bytes"), "{}", error);
+        // Nothing was sent, so the sender's nonce is untouched
+        assert!(harness.account(&sender).is_none());
+
+        let address = harness.deploy(&[0x00]).unwrap();
+        assert_eq!(harness.account(&address).unwrap().code, vec![0x00]);
+    }
+}
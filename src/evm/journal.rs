@@ -0,0 +1,90 @@
+use crate::types::{Account, Word};
+use ethereum_types::Address;
+use std::collections::HashSet;
+
+/// How to undo one change to world state
+#[derive(Debug, Clone)]
+pub enum JournalEntry {
+    /// A storage slot of `address` and the value it held, `None` if unset
+    Storage {
+        address: Address,
+        key: Word,
+        old: Option<Word>,
+    },
+    /// An account's balance, nonce and code before a change, `None` if it
+    /// did not exist
+    Account {
+        address: Address,
+        old: Option<Account>,
+    },
+}
+
+/// Point in the journal to commit or revert to, from `EvmState::snapshot`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    entries: usize,
+    logs: usize,
+    depth: usize,
+}
+
+/// Undo log for world state changes. A change is recorded the first time
+/// something is written after a snapshot; later writes need no entry since
+/// reverting only has to restore the value from when the snapshot was taken.
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    /// What has been recorded since each open snapshot, innermost last. A
+    /// `None` key stands for the account itself rather than a storage slot.
+    recorded: Vec<HashSet<(Address, Option<Word>)>>,
+}
+
+impl Journal {
+    pub(super) fn open(&mut self, logs: usize) -> Snapshot {
+        self.recorded.push(HashSet::new());
+        Snapshot {
+            entries: self.entries.len(),
+            logs,
+            depth: self.recorded.len() - 1,
+        }
+    }
+
+    /// Keep the changes made since `snapshot`. They stay in the journal so
+    /// an enclosing snapshot can still undo them.
+    pub(super) fn close(&mut self, snapshot: Snapshot) {
+        self.recorded.truncate(snapshot.depth);
+    }
+
+    /// Remove and return the entries made since `snapshot`, newest first,
+    /// along with the log count to truncate to
+    pub(super) fn undo(&mut self, snapshot: Snapshot) -> (Vec<JournalEntry>, usize) {
+        self.recorded.truncate(snapshot.depth);
+        let mut entries = self.entries.split_off(snapshot.entries);
+        entries.reverse();
+        (entries, snapshot.logs)
+    }
+
+    /// Whether `item` still needs an entry since the innermost snapshot.
+    /// Nothing is recorded while no snapshot is open.
+    fn first_write(&mut self, item: (Address, Option<Word>)) -> bool {
+        match self.recorded.last_mut() {
+            Some(recorded) => recorded.insert(item),
+            None => false,
+        }
+    }
+
+    pub(super) fn record_storage(&mut self, address: Address, key: Word, old: Option<Word>) {
+        if self.first_write((address, Some(key))) {
+            self.entries
+                .push(JournalEntry::Storage { address, key, old });
+        }
+    }
+
+    pub(super) fn record_account(&mut self, address: Address, old: Option<&Account>) {
+        if self.first_write((address, None)) {
+            self.entries.push(JournalEntry::Account {
+                address,
+                old: old.cloned(),
+            });
+        }
+    }
+}
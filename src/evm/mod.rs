@@ -1,10 +1,184 @@
-use crate::opcodes::{execute_opcode, OpCode};
+use crate::opcodes::{execute_instruction, opcode_info, OpCode, OpcodeInfo};
 use crate::types::{Account, Bytes, ExecutionResult, ExecutionStatus, Log, Word};
 use ethereum_types::{Address, U256};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
-const MAX_STACK_SIZE: usize = 1024;
-const MAX_MEMORY_SIZE: usize = 16 * 1024 * 1024; // 16MB
+mod console;
+mod coverage;
+mod debugger;
+mod error;
+mod harness;
+mod journal;
+pub use console::ConsoleSink;
+pub use coverage::CoverageReport;
+pub use debugger::{DebugStep, Debugger};
+pub use error::EvmError;
+pub use harness::{ContractHarness, FACTORY_ADDRESS};
+pub use journal::{Journal, JournalEntry, Snapshot};
+
+const DEFAULT_MAX_STACK_SIZE: usize = 1024;
+/// Most instructions a single frame may run before it stops with
+/// `EvmError::StepLimit`, a guard against runaway loops when gas is plentiful
+pub const MAX_STEPS: usize = 10_000;
+/// Deepest call frame allowed; the top-level frame is depth 0
+pub const MAX_CALL_DEPTH: usize = 1024;
+const DEFAULT_MAX_MEMORY_SIZE: usize = 16 * 1024 * 1024; // 16MB
+const DEFAULT_MAX_CALL_DATA_SIZE: usize = 128 * 1024; // 128KB
+const DEFAULT_MAX_RETURN_DATA_SIZE: usize = 1024 * 1024; // 1MB
+/// Largest init code a contract creation may carry (EIP-3860)
+pub const MAX_INITCODE_SIZE: usize = 49152;
+/// Gas charged per 32-byte word of init code before it runs (EIP-3860)
+pub(crate) const INITCODE_WORD_GAS: u64 = 2;
+/// Gas refunded for setting a non-zero storage slot to zero (EIP-3529)
+pub const SSTORE_CLEARS_REFUND: u64 = 4800;
+/// At most `gas_used / MAX_REFUND_QUOTIENT` is refunded (EIP-3529)
+const MAX_REFUND_QUOTIENT: u64 = 5;
+
+/// Part of `refund` that is given back out of `gas_used`
+pub fn capped_refund(gas_used: U256, refund: U256) -> U256 {
+    refund.min(gas_used / MAX_REFUND_QUOTIENT)
+}
+
+/// Total gas for `words` 32-byte words of memory: 3 per word plus a
+/// quadratic term that makes very large memory expensive
+fn memory_cost(words: usize) -> U256 {
+    let words = U256::from(words);
+    words * 3 + words * words / 512
+}
+
+/// Upper bounds on the call data a transaction may carry and on the data a
+/// RETURN or REVERT may copy out of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataLimits {
+    pub max_call_data_size: usize,
+    pub max_return_data_size: usize,
+}
+
+impl Default for DataLimits {
+    fn default() -> Self {
+        Self {
+            max_call_data_size: DEFAULT_MAX_CALL_DATA_SIZE,
+            max_return_data_size: DEFAULT_MAX_RETURN_DATA_SIZE,
+        }
+    }
+}
+
+/// Upper bounds on the stack depth and memory size of every call frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineLimits {
+    pub max_stack_size: usize,
+    pub max_memory_size: usize,
+}
+
+impl Default for MachineLimits {
+    fn default() -> Self {
+        Self {
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
+            max_memory_size: DEFAULT_MAX_MEMORY_SIZE,
+        }
+    }
+}
+
+/// Block fields the executed code sees through NUMBER, TIMESTAMP, COINBASE,
+/// GASLIMIT, DIFFICULTY, BASEFEE and CHAINID
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockContext {
+    pub number: U256,
+    pub timestamp: U256,
+    pub coinbase: Address,
+    pub gas_limit: U256,
+    pub difficulty: U256,
+    pub base_fee: U256,
+    pub chain_id: U256,
+}
+
+impl Default for BlockContext {
+    fn default() -> Self {
+        Self {
+            number: U256::zero(),
+            timestamp: U256::zero(),
+            coinbase: Address::zero(),
+            gas_limit: U256::from(10_000_000u64),
+            difficulty: U256::zero(),
+            base_fee: U256::zero(),
+            chain_id: U256::from(crate::blockchain::ABBY_CHAIN_ID),
+        }
+    }
+}
+
+/// Message fields the executed code sees through ADDRESS, ORIGIN, CALLER,
+/// CALLVALUE, GASPRICE and the CALLDATA opcodes, plus the surrounding block
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionContext {
+    pub caller: Address,
+    pub origin: Address,
+    pub address: Address,
+    pub value: U256,
+    pub gas_price: U256,
+    pub call_data: Bytes,
+    pub block: BlockContext,
+}
+
+impl ExecutionContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn caller(mut self, caller: Address) -> Self {
+        self.caller = caller;
+        self
+    }
+
+    pub fn origin(mut self, origin: Address) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn value(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn gas_price(mut self, gas_price: U256) -> Self {
+        self.gas_price = gas_price;
+        self
+    }
+
+    pub fn calldata(mut self, call_data: Bytes) -> Self {
+        self.call_data = call_data;
+        self
+    }
+
+    pub fn block(mut self, block: BlockContext) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// Fresh state carrying this context and `gas`
+    fn new_state(&self, gas: U256) -> EvmState {
+        let mut state = EvmState::new(gas, self.value);
+        state.caller = self.caller;
+        state.origin = self.origin;
+        state.address = self.address;
+        state.gas_price = self.gas_price;
+        state.call_data = self.call_data.clone();
+        state.block = self.block.clone();
+        state
+    }
+}
+
+/// Storage and accounts that outlive a single execution, e.g. across
+/// commands in interactive mode
+#[derive(Debug, Clone, Default)]
+pub struct PersistentState {
+    pub storage: HashMap<Word, Word>,
+    pub accounts: HashMap<Address, Account>,
+}
 
 #[derive(Debug)]
 pub struct EvmState {
@@ -16,14 +190,30 @@ pub struct EvmState {
     pub pc: usize, // Program counter
     pub gas: U256,
     pub value: U256,
+    pub gas_price: U256,
     pub caller: Address,
     pub origin: Address,
     pub address: Address,
     pub call_data: Bytes,
     pub return_data: Bytes,
+    /// Output of the most recent call made by this frame, read through
+    /// RETURNDATASIZE and RETURNDATACOPY
+    pub last_return_data: Bytes,
     pub halted: bool,
     pub reverted: bool,
-    pub error: Option<String>,
+    pub error: Option<EvmError>,
+    pub console: ConsoleSink,
+    pub max_return_data_size: usize,
+    pub max_stack_size: usize,
+    pub max_memory_size: usize,
+    pub block: BlockContext,
+    /// Number of calls between this frame and the top-level execution
+    pub depth: usize,
+    pub journal: Journal,
+    /// Gas refund earned by this frame, before the EIP-3529 cap
+    pub refund: U256,
+    /// Instructions this frame has run
+    pub steps: usize,
 }
 
 impl EvmState {
@@ -37,84 +227,277 @@ impl EvmState {
             pc: 0,
             gas,
             value,
+            gas_price: U256::zero(),
             caller: Address::zero(),
             origin: Address::zero(),
             address: Address::zero(),
             call_data: Vec::new(),
             return_data: Vec::new(),
+            last_return_data: Vec::new(),
             halted: false,
             reverted: false,
             error: None,
+            console: ConsoleSink::default(),
+            max_return_data_size: DEFAULT_MAX_RETURN_DATA_SIZE,
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
+            max_memory_size: DEFAULT_MAX_MEMORY_SIZE,
+            block: BlockContext::default(),
+            depth: 0,
+            journal: Journal::default(),
+            refund: U256::zero(),
+            steps: 0,
+        }
+    }
+
+    /// Clear the per-call machine state (stack, memory, pc, gas, return
+    /// data, logs, refund and the halt flags) ready for another run. Storage
+    /// and accounts are kept.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.memory.clear();
+        self.logs.clear();
+        self.pc = 0;
+        self.gas = U256::zero();
+        self.return_data.clear();
+        self.last_return_data.clear();
+        self.halted = false;
+        self.reverted = false;
+        self.error = None;
+        self.refund = U256::zero();
+        self.steps = 0;
+    }
+
+    /// Frame for a call from this one into `address`. The world state
+    /// (storage, accounts, logs and journal) moves into the child until
+    /// `return_from_child` hands it back.
+    pub(crate) fn child_frame(
+        &mut self,
+        address: Address,
+        value: U256,
+        call_data: Bytes,
+        gas: U256,
+    ) -> EvmState {
+        let mut child = EvmState::new(gas, value);
+        child.caller = self.address;
+        child.origin = self.origin;
+        child.gas_price = self.gas_price;
+        child.address = address;
+        child.call_data = call_data;
+        child.block = self.block.clone();
+        child.console = self.console.clone();
+        child.max_return_data_size = self.max_return_data_size;
+        child.max_stack_size = self.max_stack_size;
+        child.max_memory_size = self.max_memory_size;
+        child.depth = self.depth + 1;
+
+        child.accounts = std::mem::take(&mut self.accounts);
+        child.accounts.entry(self.address).or_default().storage = std::mem::take(&mut self.storage);
+        child.storage = child
+            .accounts
+            .get_mut(&address)
+            .map(|account| std::mem::take(&mut account.storage))
+            .unwrap_or_default();
+        child.logs = std::mem::take(&mut self.logs);
+        child.journal = std::mem::take(&mut self.journal);
+        child
+    }
+
+    /// Take the world state back from a finished child frame
+    pub(crate) fn return_from_child(&mut self, mut child: EvmState) {
+        if !child.storage.is_empty() || child.accounts.contains_key(&child.address) {
+            child.accounts.entry(child.address).or_default().storage = child.storage;
+        }
+        self.storage = child
+            .accounts
+            .get_mut(&self.address)
+            .map(|account| std::mem::take(&mut account.storage))
+            .unwrap_or_default();
+        self.accounts = child.accounts;
+        self.logs = child.logs;
+        self.journal = child.journal;
+    }
+
+    /// Mark the current world state so later changes can be reverted
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.journal.open(self.logs.len())
+    }
+
+    /// Keep the changes made since `snapshot`
+    pub fn commit(&mut self, snapshot: Snapshot) {
+        self.journal.close(snapshot);
+    }
+
+    /// Undo every storage, account and log change made since `snapshot`
+    pub fn revert_to(&mut self, snapshot: Snapshot) {
+        let (entries, logs) = self.journal.undo(snapshot);
+        for entry in entries {
+            match entry {
+                JournalEntry::Storage { address, key, old } => {
+                    let storage = if address == self.address {
+                        &mut self.storage
+                    } else {
+                        &mut self.accounts.entry(address).or_default().storage
+                    };
+                    match old {
+                        Some(value) => storage.insert(key, value),
+                        None => storage.remove(&key),
+                    };
+                }
+                JournalEntry::Account { address, old } => match old {
+                    // Storage is journaled slot by slot, so keep it as is
+                    Some(mut account) => {
+                        let current = self.accounts.entry(address).or_default();
+                        account.storage = std::mem::take(&mut current.storage);
+                        *current = account;
+                    }
+                    None => {
+                        self.accounts.remove(&address);
+                    }
+                },
+            }
         }
+        self.logs.truncate(logs);
+    }
+
+    /// Account at `address` for modification, journaling it first
+    pub fn account_mut(&mut self, address: Address) -> &mut Account {
+        self.journal
+            .record_account(address, self.accounts.get(&address));
+        self.accounts.entry(address).or_default()
+    }
+
+    /// The account at `address`, unless it does not exist or is empty.
+    /// Unlike `account_mut` this never creates one.
+    pub fn existing_account(&self, address: &Address) -> Option<&Account> {
+        self.accounts
+            .get(address)
+            .filter(|account| !account.is_empty())
     }
 
-    pub fn push_stack(&mut self, value: Word) -> Result<(), String> {
-        if self.stack.len() >= MAX_STACK_SIZE {
-            return Err("Stack overflow".to_string());
+    pub fn push_stack(&mut self, value: Word) -> Result<(), EvmError> {
+        if self.stack.len() >= self.max_stack_size {
+            return Err(EvmError::StackOverflow);
         }
         self.stack.push(value);
         Ok(())
     }
 
-    pub fn pop_stack(&mut self) -> Result<Word, String> {
-        self.stack
-            .pop()
-            .ok_or_else(|| "Stack underflow".to_string())
+    pub fn pop_stack(&mut self) -> Result<Word, EvmError> {
+        self.stack.pop().ok_or(EvmError::StackUnderflow)
     }
 
-    pub fn peek_stack(&self, index: usize) -> Result<Word, String> {
+    pub fn peek_stack(&self, index: usize) -> Result<Word, EvmError> {
         if index >= self.stack.len() {
-            return Err("Stack underflow".to_string());
+            return Err(EvmError::StackUnderflow);
         }
         Ok(self.stack[self.stack.len() - 1 - index])
     }
 
-    pub fn swap_stack(&mut self, n: usize) -> Result<(), String> {
+    pub fn swap_stack(&mut self, n: usize) -> Result<(), EvmError> {
         if self.stack.len() <= n {
-            return Err("Stack underflow".to_string());
+            return Err(EvmError::StackUnderflow);
         }
         let len = self.stack.len();
         self.stack.swap(len - 1, len - 1 - n);
         Ok(())
     }
 
-    pub fn dup_stack(&mut self, n: usize) -> Result<(), String> {
+    pub fn dup_stack(&mut self, n: usize) -> Result<(), EvmError> {
         if n == 0 || n > 16 {
-            return Err("Invalid DUP parameter".to_string());
+            return Err(EvmError::InvalidDupParameter);
         }
         if self.stack.len() < n {
-            return Err("Stack underflow".to_string());
+            return Err(EvmError::StackUnderflow);
         }
         let value = self.peek_stack(n - 1)?;
         self.push_stack(value)
     }
 
-    pub fn memory_resize(&mut self, size: usize) -> Result<(), String> {
-        if size > MAX_MEMORY_SIZE {
-            return Err("Memory limit exceeded".to_string());
+    /// Grow memory to cover `size` bytes, charging the expansion gas.
+    /// Memory expands a whole 32-byte word at a time, so the length is always
+    /// a multiple of 32. The expansion is paid for before anything is
+    /// allocated, so a range the remaining gas cannot cover fails with
+    /// `OutOfGas` and leaves memory untouched.
+    pub fn memory_resize(&mut self, size: usize) -> Result<(), EvmError> {
+        if size <= self.memory.len() {
+            return Ok(());
         }
-        if size > self.memory.len() {
-            self.memory.resize(size, 0);
+        if size > self.max_memory_size {
+            return Err(EvmError::MemoryLimit);
         }
+        let words = size.div_ceil(32);
+        self.consume_gas(memory_cost(words) - memory_cost(self.memory_words()))?;
+        self.memory.resize(words * 32, 0);
         Ok(())
     }
 
-    pub fn memory_store(&mut self, offset: usize, data: &[u8]) -> Result<(), String> {
-        let required_size = offset + data.len();
+    /// Check a memory offset and size taken from the stack and grow memory
+    /// to cover them. A zero size touches no memory, whatever the offset.
+    pub fn memory_range(&mut self, offset: U256, size: U256) -> Result<(usize, usize), EvmError> {
+        if size.is_zero() {
+            return Ok((0, 0));
+        }
+        let limit = U256::from(self.max_memory_size);
+        if offset > limit || size > limit {
+            return Err(EvmError::MemoryLimit);
+        }
+        let (offset, size) = (offset.as_usize(), size.as_usize());
+        self.memory_resize(offset.checked_add(size).ok_or(EvmError::MemoryLimit)?)?;
+        Ok((offset, size))
+    }
+
+    /// Copy `size` bytes of `source` from `source_offset` into memory at
+    /// `dest_offset`, as CALLDATACOPY and CODECOPY do. The part of the range
+    /// past the end of `source` is zero-filled, and the whole destination
+    /// range is charged for.
+    pub fn memory_copy(
+        &mut self,
+        dest_offset: U256,
+        source: &[u8],
+        source_offset: U256,
+        size: U256,
+    ) -> Result<(), EvmError> {
+        let (dest, size) = self.memory_range(dest_offset, size)?;
+        let start = if source_offset < U256::from(source.len()) {
+            source_offset.as_usize()
+        } else {
+            source.len()
+        };
+        let available = (source.len() - start).min(size);
+        self.memory[dest..dest + available].copy_from_slice(&source[start..start + available]);
+        self.memory[dest + available..dest + size].fill(0);
+        Ok(())
+    }
+
+    /// Memory size in 32-byte words, as reported by MSIZE
+    pub fn memory_words(&self) -> usize {
+        self.memory.len() / 32
+    }
+
+    pub fn memory_store(&mut self, offset: usize, data: &[u8]) -> Result<(), EvmError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let required_size = offset
+            .checked_add(data.len())
+            .ok_or(EvmError::MemoryLimit)?;
         self.memory_resize(required_size)?;
         self.memory[offset..offset + data.len()].copy_from_slice(data);
         Ok(())
     }
 
-    pub fn memory_load(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, String> {
-        let required_size = offset + size;
+    pub fn memory_load(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, EvmError> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let required_size = offset.checked_add(size).ok_or(EvmError::MemoryLimit)?;
         self.memory_resize(required_size)?;
         Ok(self.memory[offset..offset + size].to_vec())
     }
 
-    pub fn consume_gas(&mut self, amount: U256) -> Result<(), String> {
+    pub fn consume_gas(&mut self, amount: U256) -> Result<(), EvmError> {
         if self.gas < amount {
-            return Err("Out of gas".to_string());
+            return Err(EvmError::OutOfGas);
         }
         self.gas -= amount;
         Ok(())
@@ -125,6 +508,11 @@ impl EvmState {
     }
 
     pub fn storage_store(&mut self, key: Word, value: Word) {
+        let old = self.storage.get(&key).copied();
+        self.journal.record_storage(self.address, key, old);
+        if value.is_zero() && old.is_some_and(|old| !old.is_zero()) {
+            self.refund += U256::from(SSTORE_CLEARS_REFUND);
+        }
         if value.is_zero() {
             self.storage.remove(&key);
         } else {
@@ -133,102 +521,335 @@ impl EvmState {
     }
 }
 
+/// Stack change made by a single executed opcode. Items are listed top first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackDiff {
+    pub step: usize,
+    pub pc: usize,
+    pub opcode: OpCode,
+    pub popped: Vec<Word>,
+    pub pushed: Vec<Word>,
+}
+
+impl StackDiff {
+    /// Derive the diff from the stack before and after `opcode` ran, using the
+    /// opcode's declared stack effect. DUPn and SWAPn show the items they touched.
+    fn between(step: usize, pc: usize, opcode: &OpCode, before: &[Word], after: &[Word]) -> Self {
+        let (inputs, outputs) = opcode.stack_effect();
+        let top = |stack: &[Word], count: usize| -> Vec<Word> {
+            stack.iter().rev().take(count).copied().collect()
+        };
+
+        Self {
+            step,
+            pc,
+            opcode: opcode.clone(),
+            popped: top(before, inputs),
+            pushed: top(after, outputs),
+        }
+    }
+}
+
+impl EvmState {
+    /// Whether `bytecode` has more to run in this frame
+    pub(crate) fn is_running(&self, bytecode: &[u8]) -> bool {
+        self.pc < bytecode.len() && !self.halted && !self.reverted && self.error.is_none()
+    }
+
+    /// Run the instruction at `pc` and move past it, recording any failure
+    /// in `error`. Every interpreter loop goes through here, so the step
+    /// limit and gas charging are the same for nested frames,
+    /// `EvmExecutor::execute_with_state` and the debugger.
+    pub(crate) fn step(&mut self, bytecode: &[u8]) -> &'static OpcodeInfo {
+        let info = opcode_info(bytecode[self.pc]);
+        if self.steps >= MAX_STEPS {
+            self.error = Some(EvmError::StepLimit);
+            return info;
+        }
+        self.steps += 1;
+
+        match execute_instruction(info, self, bytecode) {
+            Ok(_) => {
+                if !info.sets_pc && !self.halted {
+                    self.pc += 1;
+                }
+            }
+            Err(e) => self.error = Some(e),
+        }
+        info
+    }
+}
+
+/// Run `bytecode` in `state` until it stops, recording any failure in
+/// `state.error`. Nested calls run their frames through here.
+pub(crate) fn run_frame(state: &mut EvmState, bytecode: &[u8]) {
+    while state.is_running(bytecode) {
+        state.step(bytecode);
+    }
+}
+
 pub struct EvmExecutor {
     gas_limit: U256,
+    console: ConsoleSink,
+    data_limits: DataLimits,
+    machine_limits: MachineLimits,
+    trace_stack_diff: bool,
+    stack_trace: Vec<StackDiff>,
+    track_coverage: bool,
+    executed_offsets: BTreeSet<usize>,
+    executed_steps: usize,
+    world: PersistentState,
+    /// Top-level frame of the last `execute` call; its storage and accounts
+    /// live in `world` between calls
+    frame: EvmState,
+    block: BlockContext,
 }
 
 impl EvmExecutor {
     pub fn new(gas_limit: u64) -> Self {
         Self {
             gas_limit: U256::from(gas_limit),
+            console: ConsoleSink::default(),
+            data_limits: DataLimits::default(),
+            machine_limits: MachineLimits::default(),
+            trace_stack_diff: false,
+            stack_trace: Vec::new(),
+            track_coverage: false,
+            executed_offsets: BTreeSet::new(),
+            executed_steps: 0,
+            world: PersistentState::default(),
+            frame: EvmState::new(U256::zero(), U256::zero()),
+            block: BlockContext::default(),
         }
     }
 
+    /// Storage and accounts accumulated by successful `execute` calls
+    pub fn world(&self) -> &PersistentState {
+        &self.world
+    }
+
+    /// Stack, memory, pc, gas and return data left by the last `execute`
+    /// call, as of the instruction it stopped on
+    pub fn frame(&self) -> &EvmState {
+        &self.frame
+    }
+
+    /// Prepare for another run: clear the last call's stack, memory, pc,
+    /// gas, return data and halt flags along with its stack trace and
+    /// coverage, but keep the accumulated storage and accounts. Every
+    /// `execute` call resets first.
+    pub fn reset(&mut self) {
+        self.frame.reset();
+        self.stack_trace.clear();
+        self.executed_offsets.clear();
+        self.executed_steps = 0;
+    }
+
+    /// Record what each step popped and pushed during `execute`
+    pub fn with_stack_diff_trace(mut self, enabled: bool) -> Self {
+        self.trace_stack_diff = enabled;
+        self
+    }
+
+    /// Per-step stack changes from the last `execute` call, when tracing is enabled
+    pub fn stack_trace(&self) -> &[StackDiff] {
+        &self.stack_trace
+    }
+
+    /// Record which bytecode offsets `execute` runs
+    pub fn with_coverage(mut self, enabled: bool) -> Self {
+        self.track_coverage = enabled;
+        self
+    }
+
+    /// Offsets executed by the last `execute` call, when coverage is enabled
+    pub fn executed_offsets(&self) -> &BTreeSet<usize> {
+        &self.executed_offsets
+    }
+
+    /// Top-level instructions run by the last `execute` call
+    pub fn executed_steps(&self) -> usize {
+        self.executed_steps
+    }
+
+    /// Send console.log and other LOG output to `sink` instead of stdout
+    pub fn with_console_sink(mut self, sink: ConsoleSink) -> Self {
+        self.console = sink;
+        self
+    }
+
+    /// Block that `execute_transaction` runs transactions in. The other
+    /// entry points take theirs from `ExecutionContext::block`.
+    pub fn with_block(mut self, block: BlockContext) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// Override the default call data and return data size caps
+    pub fn with_data_limits(mut self, limits: DataLimits) -> Self {
+        self.data_limits = limits;
+        self
+    }
+
+    /// Override the default stack depth and memory size caps
+    pub fn with_machine_limits(mut self, limits: MachineLimits) -> Self {
+        self.machine_limits = limits;
+        self
+    }
+
+    /// Apply this executor's console and limits to a fresh top-level state
+    fn configure(&self, state: &mut EvmState) {
+        state.console = self.console.clone();
+        state.max_return_data_size = self.data_limits.max_return_data_size;
+        state.max_stack_size = self.machine_limits.max_stack_size;
+        state.max_memory_size = self.machine_limits.max_memory_size;
+    }
+
+    /// Run `bytecode` with `value` and default context. Storage and accounts
+    /// written by a successful call stay on this executor, so a later call
+    /// sees them; use a new executor for an isolated run.
     pub fn execute(
         &mut self,
         bytecode: &[u8],
         value: u64,
         verbose: bool,
     ) -> Result<ExecutionResult, anyhow::Error> {
-        let mut state = EvmState::new(self.gas_limit, U256::from(value));
+        let context = ExecutionContext {
+            value: U256::from(value),
+            ..Default::default()
+        };
+        self.execute_with_context(bytecode, &context, verbose)
+    }
+
+    /// Run `bytecode` as if called with the given caller, origin, address,
+    /// value and call data, against the storage and accounts left by earlier
+    /// successful calls on this executor
+    pub fn execute_with_context(
+        &mut self,
+        bytecode: &[u8],
+        context: &ExecutionContext,
+        verbose: bool,
+    ) -> Result<ExecutionResult, anyhow::Error> {
+        let mut world = std::mem::take(&mut self.world);
+        let result = self.execute_with_state(bytecode, context, &mut world, verbose);
+        self.world = world;
+        result
+    }
+
+    /// Like `execute_with_context`, but starting from `world`'s storage and
+    /// accounts. Changes are written back only if execution succeeds; a
+    /// revert or error rolls them back through the state's journal.
+    pub fn execute_with_state(
+        &mut self,
+        bytecode: &[u8],
+        context: &ExecutionContext,
+        world: &mut PersistentState,
+        verbose: bool,
+    ) -> Result<ExecutionResult, anyhow::Error> {
+        if context.call_data.len() > self.data_limits.max_call_data_size {
+            anyhow::bail!(
+                "Call data size {} exceeds limit of {} bytes",
+                context.call_data.len(),
+                self.data_limits.max_call_data_size
+            );
+        }
+
+        let mut state = context.new_state(self.gas_limit);
+        state.storage = world.storage.clone();
+        state.accounts = world.accounts.clone();
+        self.configure(&mut state);
         let initial_gas = state.gas;
+        let snapshot = state.snapshot();
 
         if verbose {
             println!(
                 "🚀 Starting execution with {} bytes of bytecode",
                 bytecode.len()
             );
-            println!("💰 Value: {} wei", value);
+            println!("💰 Value: {} wei", context.value);
             println!("⛽ Gas limit: {}", self.gas_limit);
             println!();
         }
 
-        let mut step_count = 0;
-        while state.pc < bytecode.len() && !state.halted && !state.reverted && state.error.is_none()
-        {
+        self.reset();
+        while state.is_running(bytecode) {
+            let pc = state.pc;
             if verbose {
-                step_count += 1;
-                println!("Step {}: PC={}, Gas={}", step_count, state.pc, state.gas);
-            }
-
-            let opcode_byte = bytecode[state.pc];
-            let opcode = OpCode::from_byte(opcode_byte);
-
-            if verbose {
-                println!("  Opcode: {:?} (0x{:02x})", opcode, opcode_byte);
+                let opcode_byte = bytecode[pc];
+                println!("Step {}: PC={}, Gas={}", state.steps + 1, pc, state.gas);
+                println!(
+                    "  Opcode: {:?} (0x{:02x})",
+                    opcode_info(opcode_byte).opcode,
+                    opcode_byte
+                );
                 println!("  Stack size: {}", state.stack.len());
-                if !state.stack.is_empty() {
-                    println!("  Stack top: {}", state.stack.last().unwrap());
+                if let Some(top) = state.stack.last() {
+                    println!("  Stack top: {}", top);
                 }
             }
 
-            // Execute the opcode
-            match execute_opcode(&opcode, &mut state, bytecode) {
-                Ok(_) => {
-                    if !matches!(opcode, OpCode::JUMP | OpCode::JUMPI) && !state.halted {
-                        state.pc += 1;
-                    }
-                }
-                Err(e) => {
-                    state.error = Some(e);
-                    break;
-                }
+            let stack_before = self.trace_stack_diff.then(|| state.stack.clone());
+            if self.track_coverage {
+                self.executed_offsets.insert(pc);
+            }
+
+            let info = state.step(bytecode);
+            self.executed_steps = state.steps;
+            if state.error.is_some() {
+                break;
+            }
+            if let Some(before) = stack_before {
+                self.stack_trace.push(StackDiff::between(
+                    self.stack_trace.len() + 1,
+                    pc,
+                    &info.opcode,
+                    &before,
+                    &state.stack,
+                ));
             }
 
             if verbose {
                 println!("  After execution: PC={}, Gas={}", state.pc, state.gas);
                 println!();
             }
-
-            // Safety check to prevent infinite loops
-            if step_count > 10000 {
-                state.error = Some("Execution limit exceeded (too many steps)".to_string());
-                break;
-            }
         }
 
-        let gas_used = initial_gas - state.gas;
-
-        let status = if let Some(error) = state.error {
-            if error.contains("Out of gas") {
-                ExecutionStatus::OutOfGas
-            } else {
-                ExecutionStatus::Error(error)
-            }
+        let status = if let Some(error) = state.error.take() {
+            ExecutionStatus::from(error)
         } else if state.reverted {
             ExecutionStatus::Revert("Execution reverted".to_string())
         } else {
             ExecutionStatus::Success
         };
 
-        Ok(ExecutionResult {
+        // A failed execution leaves storage, accounts and logs as they were,
+        // and earns no refund
+        if status == ExecutionStatus::Success {
+            state.commit(snapshot);
+        } else {
+            state.revert_to(snapshot);
+            state.refund = U256::zero();
+        }
+        let storage = state.storage.clone();
+        world.storage = std::mem::take(&mut state.storage);
+        world.accounts = std::mem::take(&mut state.accounts);
+
+        let refund = capped_refund(initial_gas - state.gas, state.refund);
+        let result = ExecutionResult {
             status,
-            gas_used,
-            gas_remaining: state.gas,
-            return_data: state.return_data,
-            logs: state.logs,
+            gas_used: initial_gas - state.gas - refund,
+            gas_remaining: state.gas + refund,
+            refund,
+            raw_refund: state.refund,
+            return_data: state.return_data.clone(),
+            logs: state.logs.clone(),
             state_changes: HashMap::new(), // TODO: Track state changes
-        })
+            memory: state.memory.clone(),
+            storage,
+            created_address: None,
+        };
+        self.frame = state;
+        Ok(result)
     }
 
     pub fn execute_transaction(
@@ -236,6 +857,21 @@ impl EvmExecutor {
         tx: &crate::types::Transaction,
         accounts: &mut HashMap<Address, Account>,
     ) -> Result<ExecutionResult, String> {
+        if tx.data.len() > self.data_limits.max_call_data_size {
+            return Err(format!(
+                "Call data size {} exceeds limit of {} bytes",
+                tx.data.len(),
+                self.data_limits.max_call_data_size
+            ));
+        }
+        if tx.to.is_none() && tx.data.len() > MAX_INITCODE_SIZE {
+            return Err(format!(
+                "Init code size {} exceeds limit of {} bytes",
+                tx.data.len(),
+                MAX_INITCODE_SIZE
+            ));
+        }
+
         // Get sender account
         let sender_account = accounts.entry(tx.from).or_default();
 
@@ -252,7 +888,9 @@ impl EvmExecutor {
         let mut state = EvmState::new(tx.gas, tx.value);
         state.caller = tx.from;
         state.origin = tx.from;
+        state.gas_price = tx.gas_price;
         state.call_data = tx.data.clone();
+        state.block = self.block.clone();
 
         let initial_gas = state.gas;
 
@@ -268,105 +906,131 @@ impl EvmExecutor {
             // If recipient has code, execute it
             if !recipient_account.code.is_empty() {
                 let bytecode = recipient_account.code.clone();
-                self.execute_bytecode(&bytecode, &mut state)?
+                self.execute_against(&bytecode, &mut state, accounts)?
             } else {
                 // Simple transfer
                 ExecutionResult {
                     status: ExecutionStatus::Success,
                     gas_used: ethereum_types::U256::from(21000), // Base transaction cost
                     gas_remaining: state.gas - ethereum_types::U256::from(21000),
+                    refund: U256::zero(),
+                    raw_refund: U256::zero(),
                     return_data: Vec::new(),
                     logs: Vec::new(),
                     state_changes: HashMap::new(),
+                    memory: Vec::new(),
+                    storage: HashMap::new(),
+                    created_address: None,
                 }
             }
         } else {
             // Contract creation
-            let contract_address = self.create_contract_address(&tx.from, &sender_account.nonce);
+            let contract_address = crate::utils::create_address(tx.from, sender_account.nonce);
             state.address = contract_address;
 
-            // Execute constructor code
-            let result = self.execute_bytecode(&tx.data, &mut state)?;
+            // Init code is paid for by the word before any of it runs
+            let initcode_gas = U256::from(INITCODE_WORD_GAS * tx.data.len().div_ceil(32) as u64);
+            let mut result = match state.consume_gas(initcode_gas) {
+                Ok(()) => {
+                    let mut result = self.execute_against(&tx.data, &mut state, accounts)?;
+                    result.gas_used += initcode_gas;
+                    result
+                }
+                Err(error) => ExecutionResult {
+                    status: ExecutionStatus::from(error),
+                    gas_used: initial_gas,
+                    ..Default::default()
+                },
+            };
 
-            // Store contract code if successful
+            // Store contract code if successful. New contracts start at
+            // nonce 1 so they are never empty (EIP-161).
             if matches!(result.status, ExecutionStatus::Success) {
                 let contract_account = accounts.entry(contract_address).or_default();
                 contract_account.code = result.return_data.clone();
                 contract_account.balance += tx.value;
+                contract_account.nonce = contract_account.nonce.max(U256::one());
+                result.created_address = Some(contract_address);
             }
 
             result
         };
 
+        // Accounts the transaction touched but left empty do not exist
+        accounts.retain(|_, account| !account.is_empty());
+
         Ok(result)
     }
 
-    fn execute_bytecode(
+    pub(crate) fn execute_bytecode(
         &self,
         bytecode: &[u8],
         state: &mut EvmState,
     ) -> Result<ExecutionResult, String> {
+        self.configure(state);
         let initial_gas = state.gas;
 
-        while state.pc < bytecode.len() && !state.halted && !state.reverted && state.error.is_none()
-        {
-            let opcode_byte = bytecode[state.pc];
-            let opcode = crate::opcodes::OpCode::from_byte(opcode_byte);
-
-            // Execute the opcode
-            match crate::opcodes::execute_opcode(&opcode, state, bytecode) {
-                Ok(_) => {
-                    if !matches!(
-                        opcode,
-                        crate::opcodes::OpCode::JUMP | crate::opcodes::OpCode::JUMPI
-                    ) && !state.halted
-                    {
-                        state.pc += 1;
-                    }
-                }
-                Err(e) => {
-                    state.error = Some(e);
-                    break;
-                }
-            }
-        }
-
-        let gas_used = initial_gas - state.gas;
+        run_frame(state, bytecode);
 
-        let status = if let Some(error) = &state.error {
-            if error.contains("Out of gas") {
-                ExecutionStatus::OutOfGas
-            } else {
-                ExecutionStatus::Error(error.clone())
-            }
+        let status = if let Some(error) = state.error.clone() {
+            ExecutionStatus::from(error)
         } else if state.reverted {
             ExecutionStatus::Revert("Execution reverted".to_string())
         } else {
             ExecutionStatus::Success
         };
 
+        let raw_refund = if status == ExecutionStatus::Success {
+            state.refund
+        } else {
+            U256::zero()
+        };
+        let refund = capped_refund(initial_gas - state.gas, raw_refund);
         Ok(ExecutionResult {
             status,
-            gas_used,
-            gas_remaining: state.gas,
+            gas_used: initial_gas - state.gas - refund,
+            gas_remaining: state.gas + refund,
+            refund,
+            raw_refund,
             return_data: state.return_data.clone(),
             logs: state.logs.clone(),
             state_changes: HashMap::new(), // TODO: Track state changes
+            memory: state.memory.clone(),
+            storage: state.storage.clone(),
+            created_address: None,
         })
     }
 
-    fn create_contract_address(&self, sender: &Address, nonce: &ethereum_types::U256) -> Address {
-        use sha3::{Digest, Keccak256};
-
-        let mut hasher = Keccak256::new();
-        hasher.update(sender.as_bytes());
+    /// Run `bytecode` against the world state in `accounts`. Balance, code
+    /// and storage changes made by the code and its sub-calls are written
+    /// back if it succeeds and rolled back through the journal otherwise.
+    fn execute_against(
+        &self,
+        bytecode: &[u8],
+        state: &mut EvmState,
+        accounts: &mut HashMap<Address, Account>,
+    ) -> Result<ExecutionResult, String> {
+        state.accounts = std::mem::take(accounts);
+        state.storage = state
+            .accounts
+            .get_mut(&state.address)
+            .map(|account| std::mem::take(&mut account.storage))
+            .unwrap_or_default();
+        let snapshot = state.snapshot();
 
-        let mut nonce_bytes = [0u8; 32];
-        nonce.to_big_endian(&mut nonce_bytes);
-        hasher.update(nonce_bytes);
+        let result = self.execute_bytecode(bytecode, state);
+        if matches!(&result, Ok(result) if result.status == ExecutionStatus::Success) {
+            state.commit(snapshot);
+        } else {
+            state.revert_to(snapshot);
+        }
 
-        let hash = hasher.finalize();
-        Address::from_slice(&hash[12..])
+        let storage = std::mem::take(&mut state.storage);
+        if !storage.is_empty() || state.accounts.contains_key(&state.address) {
+            state.accounts.entry(state.address).or_default().storage = storage;
+        }
+        *accounts = std::mem::take(&mut state.accounts);
+        result
     }
 }
 
@@ -1,149 +1,1356 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::evm::EvmExecutor;
-    use crate::types::ExecutionStatus;
-    use ethereum_types::U256;
+use crate::evm::EvmExecutor;
+use crate::types::ExecutionStatus;
+use ethereum_types::U256;
 
-    #[test]
-    fn test_simple_addition() {
-        // PUSH1 0x01, PUSH1 0x02, ADD
-        let bytecode = hex::decode("6001600201").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+#[test]
+fn test_simple_addition() {
+    // PUSH1 0x01, PUSH1 0x02, ADD
+    let bytecode = hex::decode("6001600201").unwrap();
+    let mut executor = EvmExecutor::new(1000);
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let result = executor.execute(&bytecode, 0, false).unwrap();
 
-        assert_eq!(result.status, ExecutionStatus::Success);
-        assert!(result.gas_used > U256::zero());
-        // Note: The result should be on the stack, but we don't return stack state
-        // In a real implementation, we might want to add stack inspection
-    }
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert!(result.gas_used > U256::zero());
+    // Note: The result should be on the stack, but we don't return stack state
+    // In a real implementation, we might want to add stack inspection
+}
 
-    #[test]
-    fn test_simple_multiplication() {
-        // PUSH1 0x02, PUSH1 0x03, MUL
-        let bytecode = hex::decode("6002600302").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+#[test]
+fn test_simple_multiplication() {
+    // PUSH1 0x02, PUSH1 0x03, MUL
+    let bytecode = hex::decode("6002600302").unwrap();
+    let mut executor = EvmExecutor::new(1000);
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let result = executor.execute(&bytecode, 0, false).unwrap();
 
-        assert_eq!(result.status, ExecutionStatus::Success);
-        assert!(result.gas_used > U256::zero());
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert!(result.gas_used > U256::zero());
+}
+
+#[test]
+fn test_storage_operations() {
+    // PUSH1 0x01, PUSH1 0x00, SSTORE, PUSH1 0x00, SLOAD
+    let bytecode = hex::decode("6001600055600054").unwrap();
+    let mut executor = EvmExecutor::new(10000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert!(result.gas_used > U256::zero());
+}
+
+#[test]
+fn test_out_of_gas() {
+    // Simple addition but with very low gas limit
+    let bytecode = hex::decode("6001600201").unwrap();
+    let mut executor = EvmExecutor::new(5); // Very low gas limit
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::OutOfGas);
+}
+
+#[test]
+fn test_invalid_jump() {
+    // PUSH1 0xFF, JUMP (jump to invalid destination)
+    let bytecode = hex::decode("60FF56").unwrap();
+    let mut executor = EvmExecutor::new(1000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    match result.status {
+        ExecutionStatus::Error(_) => {} // Expected
+        _ => panic!("Expected error for invalid jump"),
     }
+}
 
-    #[test]
-    fn test_storage_operations() {
-        // PUSH1 0x01, PUSH1 0x00, SSTORE, PUSH1 0x00, SLOAD
-        let bytecode = hex::decode("6001600055600054").unwrap();
-        let mut executor = EvmExecutor::new(10000);
+#[test]
+fn test_stack_underflow() {
+    // ADD without enough items on stack
+    let bytecode = hex::decode("01").unwrap();
+    let mut executor = EvmExecutor::new(1000);
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let result = executor.execute(&bytecode, 0, false).unwrap();
 
-        assert_eq!(result.status, ExecutionStatus::Success);
-        assert!(result.gas_used > U256::zero());
+    match result.status {
+        ExecutionStatus::Error(_) => {} // Expected
+        _ => panic!("Expected error for stack underflow"),
     }
+}
 
-    #[test]
-    fn test_out_of_gas() {
-        // Simple addition but with very low gas limit
-        let bytecode = hex::decode("6001600201").unwrap();
-        let mut executor = EvmExecutor::new(5); // Very low gas limit
+#[test]
+fn test_return_operation() {
+    // PUSH1 0x42, PUSH1 0x00, MSTORE, PUSH1 0x20, PUSH1 0x00, RETURN
+    let bytecode = hex::decode("60426000526020600050f3").unwrap();
+    let mut executor = EvmExecutor::new(1000);
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let result = executor.execute(&bytecode, 0, false).unwrap();
 
-        assert_eq!(result.status, ExecutionStatus::OutOfGas);
+    // The issue is that f3 is not the correct opcode for RETURN
+    // Let's just test that it executes without crashing
+    match result.status {
+        ExecutionStatus::Success => {}
+        ExecutionStatus::Error(_) => {} // Also acceptable for this test
+        _ => panic!("Unexpected status: {:?}", result.status),
     }
+}
 
-    #[test]
-    fn test_invalid_jump() {
-        // PUSH1 0xFF, JUMP (jump to invalid destination)
-        let bytecode = hex::decode("60FF56").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+#[test]
+fn test_revert_operation() {
+    // PUSH1 0x00, PUSH1 0x00, REVERT
+    let bytecode = hex::decode("60006000fd").unwrap();
+    let mut executor = EvmExecutor::new(1000);
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let result = executor.execute(&bytecode, 0, false).unwrap();
 
-        match result.status {
-            ExecutionStatus::Error(_) => {} // Expected
-            _ => panic!("Expected error for invalid jump"),
-        }
+    match result.status {
+        ExecutionStatus::Revert(_) => {} // Expected
+        _ => panic!("Expected revert status"),
     }
+}
+
+#[test]
+fn test_memory_operations() {
+    // PUSH1 0x42, PUSH1 0x00, MSTORE, PUSH1 0x00, MLOAD
+    let bytecode = hex::decode("6042600052600051").unwrap();
+    let mut executor = EvmExecutor::new(1000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert!(result.gas_used > U256::zero());
+}
+
+#[test]
+fn test_comparison_operations() {
+    // PUSH1 0x05, PUSH1 0x03, LT (3 < 5 should be true = 1)
+    let bytecode = hex::decode("6005600310").unwrap();
+    let mut executor = EvmExecutor::new(1000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+}
+
+#[test]
+fn test_bitwise_operations() {
+    // PUSH1 0xFF, PUSH1 0x0F, AND
+    let bytecode = hex::decode("60FF600F16").unwrap();
+    let mut executor = EvmExecutor::new(1000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+}
+
+#[test]
+fn test_console_output_goes_to_buffer_sink() {
+    // memory[0..2] = "hi"; LOG0(0, 2); LOG1(0, 2, topic=1)
+    let bytecode = hex::decode("6068600053606960015360026000a0600160026000a1").unwrap();
+    let sink = crate::evm::ConsoleSink::buffer();
+    let mut executor = EvmExecutor::new(10000).with_console_sink(sink.clone());
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(
+        sink.lines(),
+        vec![
+            "console.log: hi".to_string(),
+            "console.warn: hi".to_string()
+        ]
+    );
+}
+
+#[test]
+fn test_stack_diff_trace_reports_add_operands() {
+    // PUSH1 0x01, PUSH1 0x02, ADD
+    let bytecode = hex::decode("6001600201").unwrap();
+    let mut executor = EvmExecutor::new(1000).with_stack_diff_trace(true);
 
-    #[test]
-    fn test_stack_underflow() {
-        // ADD without enough items on stack
-        let bytecode = hex::decode("01").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+    executor.execute(&bytecode, 0, false).unwrap();
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let trace = executor.stack_trace();
+    assert_eq!(trace.len(), 3);
+    assert_eq!(trace[0].pushed, vec![U256::from(1)]);
 
-        match result.status {
-            ExecutionStatus::Error(_) => {} // Expected
-            _ => panic!("Expected error for stack underflow"),
+    let add = &trace[2];
+    assert_eq!(add.opcode, crate::opcodes::OpCode::ADD);
+    assert_eq!(add.pc, 4);
+    assert_eq!(add.popped, vec![U256::from(2), U256::from(1)]);
+    assert_eq!(add.pushed, vec![U256::from(3)]);
+}
+
+#[test]
+fn test_return_of_a_gigabyte_fails_gracefully() {
+    // PUSH4 0x40000000 (size), PUSH1 0x00 (offset), RETURN
+    let bytecode = hex::decode("63400000006000f3").unwrap();
+    let mut executor = EvmExecutor::new(100000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+
+    match result.status {
+        ExecutionStatus::Error(message) => {
+            assert!(message.contains("Return data size"), "{}", message)
         }
+        other => panic!("expected an error, got {:?}", other),
     }
+    assert!(result.return_data.is_empty());
+}
+
+#[test]
+fn test_return_data_limit_is_configurable() {
+    // PUSH1 0x20 (size), PUSH1 0x00 (offset), REVERT
+    let bytecode = hex::decode("60206000fd").unwrap();
+    let limits = |max_return_data_size| crate::evm::DataLimits {
+        max_return_data_size,
+        ..Default::default()
+    };
+
+    let mut executor = EvmExecutor::new(1000).with_data_limits(limits(16));
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert!(matches!(result.status, ExecutionStatus::Error(_)));
+
+    let mut executor = EvmExecutor::new(1000).with_data_limits(limits(32));
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert!(matches!(result.status, ExecutionStatus::Revert(_)));
+    assert_eq!(result.return_data, vec![0u8; 32]);
+}
+
+#[test]
+fn test_stack_and_memory_limits_are_configurable() {
+    use crate::evm::MachineLimits;
+
+    let limits = MachineLimits {
+        max_stack_size: 4,
+        max_memory_size: 64,
+    };
+
+    // Four pushes fit, the fifth overflows
+    let four = hex::decode("600160016001600100").unwrap();
+    let result = EvmExecutor::new(1000)
+        .with_machine_limits(limits)
+        .execute(&four, 0, false)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+
+    let five = hex::decode("60016001600160016001").unwrap();
+    let result = EvmExecutor::new(1000)
+        .with_machine_limits(limits)
+        .execute(&five, 0, false)
+        .unwrap();
+    assert_eq!(
+        result.status,
+        ExecutionStatus::Error("Stack overflow".to_string())
+    );
+    let result = EvmExecutor::new(1000).execute(&five, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
 
-    #[test]
-    fn test_return_operation() {
-        // PUSH1 0x42, PUSH1 0x00, MSTORE, PUSH1 0x20, PUSH1 0x00, RETURN
-        let bytecode = hex::decode("60426000526020600050f3").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+    // MSTORE at 32 ends at 64 bytes, one more byte is past the limit
+    let result = EvmExecutor::new(1000)
+        .with_machine_limits(limits)
+        .execute(&hex::decode("6001602052").unwrap(), 0, false)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let result = EvmExecutor::new(1000)
+        .with_machine_limits(limits)
+        .execute(&hex::decode("6001602152").unwrap(), 0, false)
+        .unwrap();
+    assert_eq!(
+        result.status,
+        ExecutionStatus::Error("Memory limit exceeded".to_string())
+    );
+}
+
+#[test]
+fn test_execution_context_supplies_call_data_and_caller() {
+    use crate::evm::ExecutionContext;
+    use ethereum_types::Address;
+
+    // CALLDATALOAD(0), MSTORE at 0, RETURN 32 bytes: echoes the first word
+    let echo = hex::decode("60003560005260206000f3").unwrap();
+    let mut call_data = vec![0u8; 32];
+    call_data[31] = 0x2a;
+    let context = ExecutionContext {
+        call_data: call_data.clone(),
+        ..Default::default()
+    };
+
+    let mut executor = EvmExecutor::new(10000);
+    let result = executor
+        .execute_with_context(&echo, &context, false)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(result.return_data, call_data);
+
+    // CALLER, ORIGIN, ADD, then return the sum as a word
+    let caller_plus_origin = hex::decode("33320160005260206000f3").unwrap();
+    let context = ExecutionContext {
+        caller: Address::from_low_u64_be(0x1000),
+        origin: Address::from_low_u64_be(0x0234),
+        ..Default::default()
+    };
+    let result = executor
+        .execute_with_context(&caller_plus_origin, &context, false)
+        .unwrap();
+    assert_eq!(
+        U256::from_big_endian(&result.return_data),
+        U256::from(0x1234)
+    );
+}
+
+#[test]
+fn test_final_memory_and_storage_are_reported() {
+    // SSTORE(2, 0x2a), SSTORE(1, 0), MSTORE(0, 0xff)
+    let bytecode = hex::decode("602a600255600060015560ff600052").unwrap();
+    let mut executor = EvmExecutor::new(100000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+
+    assert_eq!(
+        result.storage_dump(),
+        vec![(U256::from(2), U256::from(0x2a))]
+    );
+    let memory = result.memory_dump();
+    assert_eq!(memory.len(), 2);
+    assert_eq!(
+        memory[1],
+        "0010: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 ff"
+    );
+}
+
+#[test]
+fn test_out_of_gas_is_classified_by_variant() {
+    use crate::evm::{EvmError, EvmState};
+    use crate::opcodes::{execute_opcode, OpCode};
+
+    // ADD costs 3 gas; with 2 left the opcode fails with the typed error
+    let mut state = EvmState::new(U256::from(2), U256::zero());
+    state.push_stack(U256::one()).unwrap();
+    state.push_stack(U256::one()).unwrap();
+    let err = execute_opcode(&OpCode::ADD, &mut state, &[0x01]).unwrap_err();
+    assert_eq!(err, EvmError::OutOfGas);
+    assert_eq!(ExecutionStatus::from(err), ExecutionStatus::OutOfGas);
+
+    // PUSH1 1, PUSH1 2, ADD needs 9 gas
+    let bytecode = hex::decode("6001600201").unwrap();
+    let mut executor = EvmExecutor::new(8);
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::OutOfGas);
+}
 
-        // The issue is that f3 is not the correct opcode for RETURN
-        // Let's just test that it executes without crashing
-        match result.status {
-            ExecutionStatus::Success => {}
-            ExecutionStatus::Error(_) => {} // Also acceptable for this test
-            _ => panic!("Unexpected status: {:?}", result.status),
+#[test]
+fn test_unaffordable_memory_fails_before_allocating() {
+    use crate::evm::{EvmError, EvmState};
+
+    // 16 MiB of memory costs over 500M gas, far beyond what is left
+    let mut state = EvmState::new(U256::from(100_000), U256::zero());
+    let near_limit = state.max_memory_size - 32;
+    assert_eq!(state.memory_load(near_limit, 32), Err(EvmError::OutOfGas));
+    assert_eq!(
+        state.memory_store(near_limit, &[1; 32]),
+        Err(EvmError::OutOfGas)
+    );
+    assert_eq!(
+        state.memory_range(U256::from(near_limit), U256::from(32)),
+        Err(EvmError::OutOfGas)
+    );
+    assert!(state.memory.is_empty());
+    assert_eq!(state.gas, U256::from(100_000));
+
+    // Offsets whose end does not fit in a usize are rejected, not wrapped
+    assert_eq!(
+        state.memory_load(usize::MAX, 32),
+        Err(EvmError::MemoryLimit)
+    );
+    assert_eq!(
+        state.memory_store(usize::MAX - 1, &[1; 32]),
+        Err(EvmError::MemoryLimit)
+    );
+    assert!(state.memory.is_empty());
+
+    // MLOAD at the same offset ends the run with an error status
+    let mut executor = EvmExecutor::new(100_000);
+    let bytecode = hex::decode(format!("62{:06x}51", near_limit)).unwrap();
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::OutOfGas);
+    assert!(result.memory.is_empty());
+}
+
+#[test]
+fn test_arithmetic_wraps_modulo_2_256() {
+    use crate::evm::EvmState;
+    use crate::opcodes::{execute_opcode, OpCode};
+
+    // Operands are listed top of stack first
+    let apply = |opcode: OpCode, operands: &[U256]| {
+        let mut state = EvmState::new(U256::from(1_000_000), U256::zero());
+        for operand in operands.iter().rev() {
+            state.push_stack(*operand).unwrap();
         }
-    }
+        execute_opcode(&opcode, &mut state, &[opcode.to_byte()]).unwrap();
+        state.pop_stack().unwrap()
+    };
+    let max = U256::MAX;
+    let two = U256::from(2);
+
+    assert_eq!(apply(OpCode::ADD, &[max, U256::one()]), U256::zero());
+    assert_eq!(apply(OpCode::SUB, &[U256::zero(), U256::one()]), max);
+    assert_eq!(apply(OpCode::MUL, &[max, two]), max - U256::one());
+    assert_eq!(apply(OpCode::EXP, &[two, U256::from(256)]), U256::zero());
+    assert_eq!(
+        apply(OpCode::EXP, &[two, U256::from(255)]),
+        U256::one() << 255
+    );
+    assert_eq!(
+        apply(OpCode::EXP, &[U256::zero(), U256::zero()]),
+        U256::one()
+    );
+
+    // MAX is -1, so its powers alternate between 1 and -1 even for
+    // exponents far too large to multiply out one at a time
+    assert_eq!(apply(OpCode::EXP, &[max, two]), U256::one());
+    assert_eq!(apply(OpCode::EXP, &[max, max]), max);
+    assert_eq!(apply(OpCode::EXP, &[max, max - U256::one()]), U256::one());
+    assert_eq!(apply(OpCode::EXP, &[U256::from(3), max]).low_u32() % 2, 1);
+    assert_eq!(apply(OpCode::EXP, &[two, max]), U256::zero());
+}
+
+#[test]
+fn test_init_code_size_is_capped_and_charged_per_word() {
+    use crate::evm::MAX_INITCODE_SIZE;
+    use std::collections::HashMap;
+
+    let sender = ethereum_types::Address::from_low_u64_be(1);
+    let create = |init_code: Vec<u8>, gas: u64| crate::types::Transaction {
+        from: sender,
+        to: None,
+        value: U256::zero(),
+        gas: U256::from(gas),
+        gas_price: U256::one(),
+        data: init_code,
+    };
+    let mut executor = EvmExecutor::new(1_000_000);
+
+    // All STOPs: the only cost is 2 gas for each of the 1536 words
+    let mut accounts = HashMap::new();
+    let result = executor
+        .execute_transaction(&create(vec![0; MAX_INITCODE_SIZE], 100_000), &mut accounts)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(result.gas_used, U256::from(2 * 1536));
 
-    #[test]
-    fn test_revert_operation() {
-        // PUSH1 0x00, PUSH1 0x00, REVERT
-        let bytecode = hex::decode("60006000fd").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+    // One byte more is rejected before the sender is touched
+    let mut accounts = HashMap::new();
+    let err = executor
+        .execute_transaction(
+            &create(vec![0; MAX_INITCODE_SIZE + 1], 100_000),
+            &mut accounts,
+        )
+        .unwrap_err();
+    assert!(err.contains("Init code size"), "{}", err);
+    assert!(accounts.is_empty());
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    // Too little gas for the word charge fails the creation
+    let mut accounts = HashMap::new();
+    let result = executor
+        .execute_transaction(&create(vec![0; MAX_INITCODE_SIZE], 3000), &mut accounts)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::OutOfGas);
+    assert!(accounts.values().all(|account| account.code.is_empty()));
+}
+
+#[test]
+fn test_create_opcode_caps_init_code_and_charges_per_word() {
+    use crate::evm::{EvmError, EvmState, MAX_INITCODE_SIZE};
+    use crate::opcodes::{execute_opcode, OpCode};
 
-        match result.status {
-            ExecutionStatus::Revert(_) => {} // Expected
-            _ => panic!("Expected revert status"),
+    // CREATE over zeroed memory that is already allocated, so the only
+    // charges are the base cost and the init code words
+    let create_with = |size: usize| {
+        let mut state = EvmState::new(U256::from(1_000_000), U256::zero());
+        state.memory = vec![0; MAX_INITCODE_SIZE + 32];
+        for operand in [size, 0, 0] {
+            state.push_stack(U256::from(operand)).unwrap();
         }
-    }
+        let result = execute_opcode(&OpCode::CREATE, &mut state, &[0xf0]);
+        (result, state)
+    };
+
+    let (result, state) = create_with(MAX_INITCODE_SIZE);
+    assert_eq!(result, Ok(()));
+    assert!(!state.stack[0].is_zero());
+    assert_eq!(state.gas, U256::from(1_000_000 - 32_000 - 2 * 1536));
+
+    let (result, _) = create_with(MAX_INITCODE_SIZE + 1);
+    assert_eq!(
+        result,
+        Err(EvmError::InitCodeTooLarge {
+            size: U256::from(MAX_INITCODE_SIZE + 1),
+            limit: MAX_INITCODE_SIZE
+        })
+    );
+}
+
+#[test]
+fn test_errors_map_to_typed_variants() {
+    use crate::evm::{EvmError, EvmState};
+    use crate::opcodes::{execute_opcode, OpCode};
 
-    #[test]
-    fn test_memory_operations() {
-        // PUSH1 0x42, PUSH1 0x00, MSTORE, PUSH1 0x00, MLOAD
-        let bytecode = hex::decode("6042600052600051").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+    let mut state = EvmState::new(U256::from(1000), U256::zero());
+    assert_eq!(
+        execute_opcode(&OpCode::ADD, &mut state, &[0x01]),
+        Err(EvmError::StackUnderflow)
+    );
+    assert_eq!(
+        execute_opcode(&OpCode::INVALID, &mut state, &[0xfe]),
+        Err(EvmError::InvalidOpcode(0xfe))
+    );
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    // PUSH1 3, JUMP: offset 3 is past the end of the code
+    let mut executor = EvmExecutor::new(1000);
+    let result = executor
+        .execute(&hex::decode("600356").unwrap(), 0, false)
+        .unwrap();
+    assert_eq!(
+        result.status,
+        ExecutionStatus::Error(EvmError::InvalidJump.to_string())
+    );
+}
+
+#[test]
+fn test_context_builder_sets_caller_and_block() {
+    use crate::evm::{BlockContext, ExecutionContext};
+    use ethereum_types::Address;
 
+    // Return the word pushed by a single environment opcode
+    let run = |opcode: u8, context: &ExecutionContext| {
+        let bytecode = [opcode, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+        let mut executor = EvmExecutor::new(10000);
+        let result = executor
+            .execute_with_context(&bytecode, context, false)
+            .unwrap();
         assert_eq!(result.status, ExecutionStatus::Success);
-        assert!(result.gas_used > U256::zero());
+        U256::from_big_endian(&result.return_data)
+    };
+
+    let caller = Address::from_low_u64_be(0xca11e5);
+    let context = ExecutionContext::new()
+        .caller(caller)
+        .origin(Address::from_low_u64_be(0x0819))
+        .value(U256::from(7))
+        .block(BlockContext {
+            number: U256::from(42),
+            timestamp: U256::from(1_700_000_000u64),
+            ..Default::default()
+        });
+
+    assert_eq!(run(0x33, &context), U256::from(0xca11e5)); // CALLER
+    assert_eq!(run(0x32, &context), U256::from(0x0819)); // ORIGIN
+    assert_eq!(run(0x34, &context), U256::from(7)); // CALLVALUE
+    assert_eq!(run(0x43, &context), U256::from(42)); // NUMBER
+    assert_eq!(run(0x42, &context), U256::from(1_700_000_000u64)); // TIMESTAMP
+    assert_eq!(run(0x46, &context), U256::from(1337)); // CHAINID
+
+    // The plain entry point still runs with a zero caller
+    let mut executor = EvmExecutor::new(10000);
+    let result = executor
+        .execute(
+            &[0x33, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3],
+            0,
+            false,
+        )
+        .unwrap();
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+}
+
+#[test]
+fn test_executor_keeps_storage_between_runs() {
+    let mut executor = EvmExecutor::new(100000).with_stack_diff_trace(true);
+
+    // SSTORE(1, 0x2a)
+    let writer = hex::decode("602a600155").unwrap();
+    let result = executor.execute(&writer, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(
+        executor.world().storage.get(&U256::one()),
+        Some(&U256::from(0x2a))
+    );
+
+    // The writer ran off the end of its code with gas to spare
+    assert_eq!(executor.frame().pc, 5);
+    assert!(!executor.frame().gas.is_zero());
+    executor.reset();
+    assert!(executor.stack_trace().is_empty());
+    let frame = executor.frame();
+    assert!(frame.stack.is_empty() && frame.memory.is_empty() && frame.return_data.is_empty());
+    assert_eq!(
+        (frame.pc, frame.gas, frame.halted),
+        (0, U256::zero(), false)
+    );
+    assert_eq!(
+        executor.world().storage.get(&U256::one()),
+        Some(&U256::from(0x2a))
+    );
+
+    // Return SLOAD(1)
+    let reader = hex::decode("60015460005260206000f3").unwrap();
+    let result = executor.execute(&reader, 0, false).unwrap();
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::from(0x2a));
+    // The second run starts with its own trace and full gas
+    assert_eq!(executor.stack_trace().len(), 7);
+    assert_eq!(result.gas_used + result.gas_remaining, U256::from(100000));
+}
+
+#[test]
+fn test_execute_carries_storage_across_calls_on_one_executor() {
+    let writer = hex::decode("602a600155").unwrap();
+    let reader = hex::decode("60015460005260206000f3").unwrap();
+
+    // No reset in between: storage persists on the executor regardless
+    let mut executor = EvmExecutor::new(100000);
+    executor.execute(&writer, 0, false).unwrap();
+    let result = executor.execute(&reader, 0, false).unwrap();
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::from(0x2a));
+
+    // A new executor starts from empty storage
+    let result = EvmExecutor::new(100000).execute(&reader, 0, false).unwrap();
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+}
+
+#[test]
+fn test_msize_counts_whole_words() {
+    // MSTORE8(0, 0xff), then return MSIZE
+    let bytecode = hex::decode("60ff6000535960005260206000f3").unwrap();
+    let mut executor = EvmExecutor::new(1000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::from(32));
+    assert_eq!(result.memory.len(), 32);
+}
+
+#[test]
+fn test_recursive_call_stops_at_depth_limit() {
+    // Increment storage slot 0, then CALL(gas, ADDRESS, 0, 0, 0, 0, 0) so
+    // the contract calls itself until the depth limit refuses the call
+    let bytecode = hex::decode("60005460010160005560006000600060006000305af100").unwrap();
+    // Each level keeps back 1/64 of its gas, so reaching depth 1024 takes
+    // (64/63)^1024, about 10^7, times the gas the innermost frame needs
+    let mut executor = EvmExecutor::new(10_000_000_000_000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    // Frames at depths 0 through MAX_CALL_DEPTH each ran once
+    assert_eq!(
+        result.storage.get(&U256::zero()),
+        Some(&U256::from(crate::evm::MAX_CALL_DEPTH + 1))
+    );
+}
+
+/// Init code that deploys a runtime returning the word 42
+const RETURNS_42_INIT: &str = "69602a60005260206000f3600052600a6016f3";
+
+/// Code that copies `init_code` (at most 32 bytes) into memory, runs CREATE
+/// on it, or CREATE2 with `salt`, and returns the address it pushes
+fn factory(init_code: &str, salt: Option<u8>) -> Vec<u8> {
+    let init = hex::decode(init_code).unwrap();
+    let mut code = vec![0x5f + init.len() as u8];
+    code.extend(&init);
+    code.extend([0x60, 0x00, 0x52]);
+    if let Some(salt) = salt {
+        code.extend([0x60, salt]);
     }
+    code.extend([
+        0x60,
+        init.len() as u8,
+        0x60,
+        32 - init.len() as u8,
+        0x60,
+        0x00,
+    ]);
+    code.push(if salt.is_some() { 0xf5 } else { 0xf0 });
+    code.extend(hex::decode("60005260206000f3").unwrap());
+    code
+}
 
-    #[test]
-    fn test_comparison_operations() {
-        // PUSH1 0x05, PUSH1 0x03, LT (3 < 5 should be true = 1)
-        let bytecode = hex::decode("6005600310").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+#[test]
+fn test_create_and_create2_deploy_at_the_shared_derivations() {
+    use crate::evm::ExecutionContext;
+    use crate::utils::{create2_address, create_address};
+    use ethereum_types::{Address, H256};
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let creator = Address::from_low_u64_be(0xfac);
+    let context = ExecutionContext::new().address(creator);
+    let init_code = hex::decode(RETURNS_42_INIT).unwrap();
+    let mut executor = EvmExecutor::new(1_000_000);
 
+    let created = |result: &crate::types::ExecutionResult| {
         assert_eq!(result.status, ExecutionStatus::Success);
+        Address::from_slice(&result.return_data[12..])
+    };
+    let first = created(
+        &executor
+            .execute_with_context(&factory(RETURNS_42_INIT, None), &context, false)
+            .unwrap(),
+    );
+    let salted = created(
+        &executor
+            .execute_with_context(&factory(RETURNS_42_INIT, Some(7)), &context, false)
+            .unwrap(),
+    );
+
+    // The same functions the creation transactions and create2-address use
+    assert_eq!(first, create_address(creator, U256::zero()));
+    assert_eq!(
+        salted,
+        create2_address(creator, H256::from_low_u64_be(7), &init_code)
+    );
+
+    let accounts = &executor.world().accounts;
+    assert_eq!(accounts[&creator].nonce, U256::from(2));
+    for address in [first, salted] {
+        assert_eq!(
+            accounts[&address].code,
+            hex::decode("602a60005260206000f3").unwrap()
+        );
+        assert_eq!(accounts[&address].nonce, U256::one());
     }
 
-    #[test]
-    fn test_bitwise_operations() {
-        // PUSH1 0xFF, PUSH1 0x0F, AND
-        let bytecode = hex::decode("60FF600F16").unwrap();
-        let mut executor = EvmExecutor::new(1000);
+    // The same salt and init code again collide with the deployed contract
+    let result = executor
+        .execute_with_context(&factory(RETURNS_42_INIT, Some(7)), &context, false)
+        .unwrap();
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+}
+
+#[test]
+fn test_reverted_creation_leaves_no_account_behind() {
+    use crate::evm::ExecutionContext;
+    use ethereum_types::Address;
+
+    // Init code: SSTORE(0, 1) then REVERT with no data
+    let creator = Address::from_low_u64_be(0xfac);
+    let mut executor = EvmExecutor::new(1_000_000);
+    let result = executor
+        .execute_with_context(
+            &factory("600160005560006000fd", None),
+            &ExecutionContext::new().address(creator),
+            false,
+        )
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+    let created = crate::utils::create_address(creator, U256::zero());
+    assert!(!executor.world().accounts.contains_key(&created));
+    // The creator's nonce still went up
+    assert_eq!(executor.world().accounts[&creator].nonce, U256::one());
+}
+
+#[test]
+fn test_create_at_max_depth_pushes_zero() {
+    use crate::evm::{EvmState, MAX_CALL_DEPTH};
+    use crate::opcodes::{execute_opcode, OpCode};
+    use ethereum_types::Address;
+
+    let creator = Address::from_low_u64_be(0xfac);
+    let create_at = |depth: usize| {
+        let mut state = EvmState::new(U256::from(1_000_000), U256::zero());
+        state.address = creator;
+        state.depth = depth;
+        // Init code STOP: size 1, offset 0, value 0
+        for operand in [1, 0, 0] {
+            state.push_stack(U256::from(operand)).unwrap();
+        }
+        execute_opcode(&OpCode::CREATE, &mut state, &[0xf0]).unwrap();
+        state
+    };
+
+    let state = create_at(MAX_CALL_DEPTH - 1);
+    assert!(!state.stack[0].is_zero());
+
+    // The creator keeps running; nothing is created and its nonce is unchanged
+    let state = create_at(MAX_CALL_DEPTH);
+    assert_eq!(state.stack, vec![U256::zero()]);
+    assert!(state
+        .accounts
+        .values()
+        .all(|account| account.nonce.is_zero()));
+    assert_eq!(state.accounts.len(), 0);
+}
+
+#[test]
+fn test_call_forwards_63_64ths_and_adds_the_stipend_for_value() {
+    use crate::evm::EvmState;
+    use crate::opcodes::{execute_opcode, opcode_info, OpCode, CALL_STIPEND};
+    use crate::types::Account;
+    use ethereum_types::Address;
+
+    // The callee returns the gas it has left after GAS itself
+    let caller = Address::from_low_u64_be(0xca);
+    let callee = Address::from_low_u64_be(0xca11);
+    let gas_cost = opcode_info(0x5a).gas;
+    let call_with = |gas: U256, value: U256| {
+        let mut state = EvmState::new(U256::from(100_000), U256::zero());
+        state.address = caller;
+        state.accounts.insert(
+            caller,
+            Account {
+                balance: U256::from(1000),
+                ..Default::default()
+            },
+        );
+        state.accounts.insert(
+            callee,
+            Account {
+                code: hex::decode("5a60005260206000f3").unwrap(),
+                ..Default::default()
+            },
+        );
+        // retSize, retOffset, argsSize, argsOffset, value, target, gas
+        for operand in [
+            U256::from(32),
+            U256::zero(),
+            U256::zero(),
+            U256::zero(),
+            value,
+            U256::from(0xca11),
+            gas,
+        ] {
+            state.push_stack(operand).unwrap();
+        }
+        execute_opcode(&OpCode::CALL, &mut state, &[0xf1]).unwrap();
+        let success = state.pop_stack().unwrap();
+        let seen = U256::from_big_endian(&state.last_return_data);
+        (success, seen, state.gas)
+    };
+
+    // CALL itself and one word of memory for the output come first
+    let available = U256::from(100_000) - opcode_info(0xf1).gas - U256::from(3);
+    let forwarded = available - available / 64;
+
+    // Asking for everything forwards only 63/64 of what is left
+    let (success, seen, left) = call_with(U256::MAX, U256::zero());
+    assert_eq!(success, U256::one());
+    assert_eq!(seen, forwarded - gas_cost);
+    assert!(left >= available - forwarded);
+
+    // A smaller request is forwarded as it is
+    let (_, seen, _) = call_with(U256::from(5000), U256::zero());
+    assert_eq!(seen, U256::from(5000) - gas_cost);
+
+    // With value, a call forwarding no gas still runs on the stipend, and
+    // the caller pays nothing for it
+    let (success, seen, left) = call_with(U256::zero(), U256::one());
+    assert_eq!(success, U256::one());
+    assert_eq!(seen, U256::from(CALL_STIPEND) - gas_cost);
+    assert_eq!(left, available);
+
+    // Without value the same call has no gas to run at all
+    let (success, _, _) = call_with(U256::zero(), U256::zero());
+    assert_eq!(success, U256::zero());
+}
+
+#[test]
+fn test_extcodehash_is_zero_for_missing_and_empty_accounts() {
+    use crate::evm::{ExecutionContext, PersistentState};
+    use crate::types::Account;
+    use ethereum_types::Address;
+    use sha3::{Digest, Keccak256};
 
-        let result = executor.execute(&bytecode, 0, false).unwrap();
+    let contract = Address::from_low_u64_be(0xc0de);
+    let empty = Address::from_low_u64_be(0xe);
+    let mut world = PersistentState::default();
+    world.accounts.insert(
+        contract,
+        Account {
+            code: vec![0x00],
+            ..Default::default()
+        },
+    );
+    world.accounts.insert(empty, Account::default());
 
+    // Return EXTCODEHASH(address)
+    let mut extcodehash = |address: u64| {
+        let bytecode = hex::decode(format!("61{:04x}3f60005260206000f3", address)).unwrap();
+        let result = EvmExecutor::new(100_000)
+            .execute_with_state(&bytecode, &ExecutionContext::default(), &mut world, false)
+            .unwrap();
         assert_eq!(result.status, ExecutionStatus::Success);
+        U256::from_big_endian(&result.return_data)
+    };
+
+    assert_eq!(extcodehash(0xbeef), U256::zero());
+    assert_eq!(extcodehash(0xe), U256::zero());
+    assert_eq!(
+        extcodehash(0xc0de),
+        U256::from_big_endian(&Keccak256::digest([0x00]))
+    );
+}
+
+#[test]
+fn test_touched_empty_accounts_are_pruned_after_a_transaction() {
+    use ethereum_types::Address;
+    use std::collections::HashMap;
+
+    let sender = Address::from_low_u64_be(1);
+    let recipient = Address::from_low_u64_be(0xbeef);
+    let transaction = |to: Option<Address>, data: Vec<u8>| crate::types::Transaction {
+        from: sender,
+        to,
+        value: U256::zero(),
+        gas: U256::from(100_000),
+        gas_price: U256::one(),
+        data,
+    };
+    let mut executor = EvmExecutor::new(1_000_000);
+    let mut accounts = HashMap::new();
+
+    // A zero-value transfer touches the recipient but leaves it empty
+    let result = executor
+        .execute_transaction(&transaction(Some(recipient), Vec::new()), &mut accounts)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert!(!accounts.contains_key(&recipient));
+    assert_eq!(accounts[&sender].nonce, U256::one());
+
+    // A contract deployed with no code and no value survives at nonce 1
+    let result = executor
+        .execute_transaction(&transaction(None, vec![0x00]), &mut accounts)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(accounts.len(), 2);
+    assert!(accounts
+        .iter()
+        .any(|(address, account)| *address != sender && account.nonce == U256::one()));
+}
+
+#[test]
+fn test_failed_call_pushes_zero_and_keeps_caller_state() {
+    use crate::evm::{ExecutionContext, PersistentState};
+    use crate::types::Account;
+    use ethereum_types::Address;
+
+    // Callee at 0xca11: SSTORE(0, 1) then INVALID
+    let callee = Address::from_low_u64_be(0xca11);
+    let mut world = PersistentState::default();
+    world.accounts.insert(
+        callee,
+        Account {
+            code: hex::decode("6001600055fe").unwrap(),
+            ..Default::default()
+        },
+    );
+
+    // Return CALL(gas, 0xca11, 0, 0, 0, 0, 0)
+    let bytecode = hex::decode("6000600060006000600061ca115af160005260206000f3").unwrap();
+    let mut executor = EvmExecutor::new(100_000);
+    let result = executor
+        .execute_with_state(&bytecode, &ExecutionContext::default(), &mut world, false)
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::zero());
+    assert!(world.accounts[&callee].storage.is_empty());
+}
+
+#[test]
+fn test_reverted_sub_call_leaves_parent_storage_unchanged() {
+    // Without call data: SSTORE(0, 1), CALL itself with one byte of call
+    // data, then return SLOAD(0). With call data: SSTORE(0, 0xbad), REVERT.
+    let bytecode = hex::decode(concat!(
+        "36602257",
+        "6001600055",
+        "60006000600160006000305af150",
+        "60005460005260206000f3",
+        "5b610bad60005560006000fd"
+    ))
+    .unwrap();
+    let mut executor = EvmExecutor::new(100_000);
+
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(U256::from_big_endian(&result.return_data), U256::one());
+    assert_eq!(result.storage_dump(), vec![(U256::zero(), U256::one())]);
+}
+
+#[test]
+fn test_top_level_revert_rolls_back_storage_and_logs() {
+    use crate::evm::{ExecutionContext, PersistentState};
+
+    let mut world = PersistentState::default();
+    world.storage.insert(U256::zero(), U256::from(5));
+
+    // SSTORE(0, 9), SSTORE(1, 9), LOG0(0, 0), REVERT(0, 0)
+    let bytecode = hex::decode("6009600055600960015560006000a060006000fd").unwrap();
+    let mut executor = EvmExecutor::new(100_000);
+    let result = executor
+        .execute_with_state(&bytecode, &ExecutionContext::default(), &mut world, false)
+        .unwrap();
+
+    assert!(matches!(result.status, ExecutionStatus::Revert(_)));
+    assert!(result.logs.is_empty());
+    assert_eq!(world.storage.len(), 1);
+    assert_eq!(world.storage[&U256::zero()], U256::from(5));
+}
+
+#[test]
+fn test_nested_snapshots_revert_independently() {
+    use crate::evm::EvmState;
+
+    let mut state = EvmState::new(U256::zero(), U256::zero());
+    let outer = state.snapshot();
+    state.storage_store(U256::one(), U256::from(1));
+
+    let inner = state.snapshot();
+    state.storage_store(U256::one(), U256::from(2));
+    state.storage_store(U256::from(2), U256::from(2));
+    state.revert_to(inner);
+    assert_eq!(state.storage_load(&U256::one()), U256::from(1));
+    assert_eq!(state.storage_load(&U256::from(2)), U256::zero());
+
+    // Writes after a commit can still be undone by the enclosing snapshot
+    let committed = state.snapshot();
+    state.storage_store(U256::from(3), U256::from(3));
+    state.commit(committed);
+    state.revert_to(outer);
+    assert!(state.storage.is_empty());
+}
+
+#[test]
+fn test_clearing_storage_refunds_gas() {
+    use crate::evm::{ExecutionContext, PersistentState, SSTORE_CLEARS_REFUND};
+
+    let run = |bytecode: &str| {
+        let mut world = PersistentState::default();
+        world.storage.insert(U256::zero(), U256::from(5));
+        let mut executor = EvmExecutor::new(100_000);
+        executor
+            .execute_with_state(
+                &hex::decode(bytecode).unwrap(),
+                &ExecutionContext::default(),
+                &mut world,
+                false,
+            )
+            .unwrap()
+    };
+
+    // SSTORE(0, 1)
+    let overwrite = run("6001600055");
+    assert_eq!(overwrite.gas_used, U256::from(5006));
+    assert!(overwrite.raw_refund.is_zero());
+
+    // SSTORE(0, 0): the refund is capped at a fifth of the 5006 gas spent
+    let clear = run("6000600055");
+    assert_eq!(clear.raw_refund, U256::from(SSTORE_CLEARS_REFUND));
+    assert_eq!(clear.refund, U256::from(1001));
+    assert_eq!(clear.gas_used, U256::from(5006 - 1001));
+    assert_eq!(clear.gas_used + clear.gas_remaining, U256::from(100_000));
+}
+
+#[test]
+fn test_calldatacopy_zero_fills_past_the_end_of_call_data() {
+    use crate::evm::ExecutionContext;
+
+    // MSTORE(0, ~0), CALLDATACOPY(0, 0, 32), RETURN(0, 32)
+    let bytecode = hex::decode("6000196000526020600060003760206000f3").unwrap();
+    let context = ExecutionContext::new().calldata(vec![0xaa, 0xbb, 0xcc]);
+    let result = EvmExecutor::new(100_000)
+        .execute_with_context(&bytecode, &context, false)
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    let mut expected = vec![0u8; 32];
+    expected[..3].copy_from_slice(&[0xaa, 0xbb, 0xcc]);
+    assert_eq!(result.return_data, expected);
+}
+
+#[test]
+fn test_copy_charges_memory_for_the_whole_destination() {
+    // CODECOPY(0, 0, 96) from 8 bytes of code: three PUSH1s and CODECOPY
+    // cost 12, and three words of memory 9 more
+    let bytecode = hex::decode("6060600060003900").unwrap();
+    let result = EvmExecutor::new(100_000)
+        .execute(&bytecode, 0, false)
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(result.gas_used, U256::from(21));
+    assert_eq!(result.memory.len(), 96);
+    assert_eq!(&result.memory[..8], &bytecode[..]);
+    assert!(result.memory[8..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn test_copy_offsets_from_the_stack_are_bounded() {
+    let huge_offset = format!("7f80{}", "00".repeat(31));
+
+    // A zero-size copy touches no memory, wherever it points
+    let bytecode = hex::decode(format!("60006000{}39", huge_offset)).unwrap();
+    let result = EvmExecutor::new(100_000)
+        .execute(&bytecode, 0, false)
+        .unwrap();
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert!(result.memory.is_empty());
+
+    // A non-empty one fails instead of panicking or allocating
+    let bytecode = hex::decode(format!("60016000{}39", huge_offset)).unwrap();
+    let result = EvmExecutor::new(100_000)
+        .execute(&bytecode, 0, false)
+        .unwrap();
+    assert!(matches!(result.status, ExecutionStatus::Error(_)));
+}
+
+#[test]
+fn test_return_data_of_the_last_call_is_readable() {
+    use crate::evm::{ExecutionContext, PersistentState};
+    use crate::types::Account;
+    use ethereum_types::Address;
+
+    // Callee at 0xca11 returns the three bytes aabbcc
+    let mut world = PersistentState::default();
+    world.accounts.insert(
+        Address::from_low_u64_be(0xca11),
+        Account {
+            code: hex::decode("62aabbcc6000526003601df3").unwrap(),
+            ..Default::default()
+        },
+    );
+
+    // CALL 0xca11, RETURNDATACOPY(0, 0, 3), MSTORE(32, RETURNDATASIZE),
+    // then return both words
+    let bytecode = hex::decode(concat!(
+        "6000600060006000600061ca115af150",
+        "6003600060003e",
+        "3d602052",
+        "60406000f3"
+    ))
+    .unwrap();
+    let mut executor = EvmExecutor::new(100_000);
+    let result = executor
+        .execute_with_state(&bytecode, &ExecutionContext::default(), &mut world, false)
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(&result.return_data[..4], &[0xaa, 0xbb, 0xcc, 0x00]);
+    assert_eq!(
+        U256::from_big_endian(&result.return_data[32..]),
+        U256::from(3)
+    );
+}
+
+#[test]
+fn test_return_data_copy_past_the_end_fails() {
+    // No call has been made, so RETURNDATASIZE is 0 and any copy is too long
+    let result = EvmExecutor::new(100_000)
+        .execute(&hex::decode("3d60005260206000f3").unwrap(), 0, false)
+        .unwrap();
+    assert_eq!(result.return_data, vec![0u8; 32]);
+
+    let result = EvmExecutor::new(100_000)
+        .execute(&hex::decode("6001600060003e").unwrap(), 0, false)
+        .unwrap();
+    assert_eq!(
+        result.status,
+        ExecutionStatus::Error("Return data copy out of bounds".to_string())
+    );
+}
+
+#[test]
+fn test_gas_price_and_self_balance_come_from_context_and_account() {
+    use crate::evm::{ExecutionContext, PersistentState};
+    use crate::types::Account;
+    use ethereum_types::Address;
+
+    let address = Address::from_low_u64_be(0x5e1f);
+    let mut world = PersistentState::default();
+    world.accounts.insert(
+        address,
+        Account {
+            balance: U256::from(1_000),
+            ..Default::default()
+        },
+    );
+    let context = ExecutionContext::new()
+        .address(address)
+        .gas_price(U256::from(20_000_000_000u64));
+
+    // MSTORE(0, GASPRICE), MSTORE(32, SELFBALANCE), return both words
+    let bytecode = hex::decode("3a6000524760205260406000f3").unwrap();
+    let result = EvmExecutor::new(100_000)
+        .execute_with_state(&bytecode, &context, &mut world, false)
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(
+        U256::from_big_endian(&result.return_data[..32]),
+        U256::from(20_000_000_000u64)
+    );
+    assert_eq!(
+        U256::from_big_endian(&result.return_data[32..]),
+        U256::from(1_000)
+    );
+}
+
+#[test]
+fn test_gas_reports_what_is_left_after_itself() {
+    // MSTORE(0, 1) costs 12 including one word of memory, PUSH1 1,
+    // PUSH1 2, ADD 9, POP 2 and GAS itself 2: 25 in all. The rest stores
+    // the reading at 32 and returns it.
+    let bytecode = hex::decode(concat!(
+        "6001600052",
+        "600160020150",
+        "5a",
+        "60205260206020f3"
+    ))
+    .unwrap();
+    let result = EvmExecutor::new(100_000)
+        .execute(&bytecode, 0, false)
+        .unwrap();
+
+    assert_eq!(result.status, ExecutionStatus::Success);
+    assert_eq!(
+        U256::from_big_endian(&result.return_data),
+        U256::from(100_000 - 25)
+    );
+}
+
+#[test]
+fn test_step_limit_stops_every_interpreter_loop() {
+    use crate::evm::{ExecutionContext, MAX_STEPS};
+    use crate::types::{Account, Transaction};
+    use ethereum_types::Address;
+    use std::collections::HashMap;
+
+    // JUMPDEST, PUSH1 0, JUMP: loops until something stops it
+    let bytecode = hex::decode("5b600056").unwrap();
+    let too_many = ExecutionStatus::Error("Execution limit exceeded (too many steps)".into());
+
+    let mut executor = EvmExecutor::new(u64::MAX);
+    let result = executor.execute(&bytecode, 0, false).unwrap();
+    assert_eq!(result.status, too_many);
+    assert_eq!(executor.executed_steps(), MAX_STEPS);
+
+    let mut debugger = EvmExecutor::new(u64::MAX).debugger(&bytecode, &ExecutionContext::default());
+    debugger.run_to_end();
+    assert_eq!(debugger.result().status, too_many);
+
+    // Transactions run their frames through `run_frame`, which stops too
+    let looper = Address::from_low_u64_be(0x100);
+    let mut accounts = HashMap::new();
+    accounts.insert(
+        looper,
+        Account {
+            code: bytecode,
+            ..Default::default()
+        },
+    );
+    let tx = Transaction {
+        from: Address::from_low_u64_be(0xa11ce),
+        to: Some(looper),
+        value: U256::zero(),
+        gas: U256::from(u64::MAX),
+        gas_price: U256::zero(),
+        data: Vec::new(),
+    };
+    let result = EvmExecutor::new(u64::MAX)
+        .execute_transaction(&tx, &mut accounts)
+        .unwrap();
+    assert_eq!(result.status, too_many);
+}
+
+/// Invariants of DUP, SWAP and peek over random stacks and every index they
+/// accept
+mod stack_properties {
+    use crate::evm::{EvmError, EvmState};
+    use crate::types::Word;
+    use ethereum_types::U256;
+    use proptest::prelude::*;
+
+    fn state_with(values: &[u64]) -> EvmState {
+        let mut state = EvmState::new(U256::from(1_000_000), U256::zero());
+        state.stack = values.iter().map(|&v| Word::from(v)).collect();
+        state
+    }
+
+    fn sorted(stack: &[Word]) -> Vec<Word> {
+        let mut values = stack.to_vec();
+        values.sort();
+        values
+    }
+
+    proptest! {
+        #[test]
+        fn dup_copies_the_nth_element_onto_the_top(
+            values in prop::collection::vec(any::<u64>(), 0..32),
+            n in 1usize..=16,
+        ) {
+            let mut state = state_with(&values);
+            let before = state.stack.clone();
+            let result = state.dup_stack(n);
+
+            if before.len() < n {
+                prop_assert_eq!(result, Err(EvmError::StackUnderflow));
+                prop_assert_eq!(&state.stack, &before);
+            } else {
+                prop_assert!(result.is_ok());
+                prop_assert_eq!(state.stack.len(), before.len() + 1);
+                prop_assert_eq!(&state.stack[..before.len()], &before[..]);
+                prop_assert_eq!(state.stack[before.len()], before[before.len() - n]);
+            }
+        }
+
+        #[test]
+        fn swap_exchanges_the_top_with_the_nth_element(
+            values in prop::collection::vec(any::<u64>(), 0..32),
+            n in 1usize..=16,
+        ) {
+            let mut state = state_with(&values);
+            let before = state.stack.clone();
+            let result = state.swap_stack(n);
+
+            if before.len() <= n {
+                prop_assert_eq!(result, Err(EvmError::StackUnderflow));
+                prop_assert_eq!(&state.stack, &before);
+            } else {
+                prop_assert!(result.is_ok());
+                let top = before.len() - 1;
+                prop_assert_eq!(state.stack.len(), before.len());
+                prop_assert_eq!(state.stack[top], before[top - n]);
+                prop_assert_eq!(state.stack[top - n], before[top]);
+                for i in (0..before.len()).filter(|&i| i != top && i != top - n) {
+                    prop_assert_eq!(state.stack[i], before[i]);
+                }
+                prop_assert_eq!(sorted(&state.stack), sorted(&before));
+            }
+        }
+
+        #[test]
+        fn peek_reads_from_the_top_without_changing_the_stack(
+            values in prop::collection::vec(any::<u64>(), 0..32),
+            index in 0usize..32,
+        ) {
+            let state = state_with(&values);
+            match state.peek_stack(index) {
+                Ok(value) => prop_assert_eq!(value, Word::from(values[values.len() - 1 - index])),
+                Err(error) => {
+                    prop_assert!(index >= values.len());
+                    prop_assert_eq!(error, EvmError::StackUnderflow);
+                }
+            }
+            prop_assert_eq!(state.stack.len(), values.len());
+        }
+
+        #[test]
+        fn dup_past_the_stack_limit_overflows(n in 1usize..=16) {
+            let mut state = state_with(&[7; 16]);
+            state.max_stack_size = 16;
+            prop_assert_eq!(state.dup_stack(n), Err(EvmError::StackOverflow));
+            prop_assert_eq!(state.stack.len(), 16);
+        }
     }
 }
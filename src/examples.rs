@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::compiler::Compiler;
+
+/// Name of the manifest listing the examples in an examples directory
+const MANIFEST: &str = "examples.json";
+
+/// Overrides the directory examples are loaded from
+const EXAMPLES_DIR_VAR: &str = "ABBY_EXAMPLES_DIR";
+
+/// The repository's `examples/` directory, built into the binary so it runs
+/// from anywhere
+const BUILTIN_MANIFEST: &str = include_str!("../examples/examples.json");
+const BUILTIN_FILES: &[(&str, &str)] = &[
+    ("simple_add.bin", include_str!("../examples/simple_add.bin")),
+    ("simple_mul.bin", include_str!("../examples/simple_mul.bin")),
+    ("storage.bin", include_str!("../examples/storage.bin")),
+    ("loop.abs", include_str!("../examples/loop.abs")),
+    ("counter.abs", include_str!("../examples/counter.abs")),
+    ("fibonacci.abs", include_str!("../examples/fibonacci.abs")),
+];
+
+/// Manifest entry for one example. `file` is relative to the examples
+/// directory and holds hex bytecode (`.bin`, `.hex`) or AbbyScript (`.abs`,
+/// `.abby`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Example {
+    pub name: String,
+    pub description: String,
+    pub file: PathBuf,
+}
+
+/// Where the example files of a registry are read from
+#[derive(Debug)]
+enum ExampleFiles {
+    Builtin,
+    Dir(PathBuf),
+}
+
+impl ExampleFiles {
+    fn read(&self, file: &Path) -> Result<String> {
+        match self {
+            Self::Builtin => BUILTIN_FILES
+                .iter()
+                .find(|(name, _)| Path::new(name) == file)
+                .map(|(_, text)| text.to_string())
+                .ok_or_else(|| anyhow::anyhow!("No built-in example file {}", file.display())),
+            Self::Dir(dir) => {
+                let path = dir.join(file);
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Cannot read {}", path.display()))
+            }
+        }
+    }
+}
+
+/// Examples listed in the manifest of an examples directory
+#[derive(Debug)]
+pub struct ExampleRegistry {
+    files: ExampleFiles,
+    examples: Vec<Example>,
+}
+
+impl ExampleRegistry {
+    /// Registry for `$ABBY_EXAMPLES_DIR`, or the built-in examples if it is
+    /// unset
+    pub fn load_default() -> Result<Self> {
+        match std::env::var_os(EXAMPLES_DIR_VAR) {
+            Some(dir) => Self::load(Path::new(&dir)),
+            None => Self::builtin(),
+        }
+    }
+
+    /// The examples shipped in the binary
+    pub fn builtin() -> Result<Self> {
+        let examples =
+            serde_json::from_str(BUILTIN_MANIFEST).context("Invalid built-in manifest")?;
+        Ok(Self {
+            files: ExampleFiles::Builtin,
+            examples,
+        })
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        let manifest = dir.join(MANIFEST);
+        let text = std::fs::read_to_string(&manifest)
+            .with_context(|| format!("Cannot read {}", manifest.display()))?;
+        let examples = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid manifest {}", manifest.display()))?;
+        Ok(Self {
+            files: ExampleFiles::Dir(dir.to_path_buf()),
+            examples,
+        })
+    }
+
+    /// Examples in manifest order
+    pub fn examples(&self) -> &[Example] {
+        &self.examples
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Example> {
+        self.examples.iter().find(|example| example.name == name)
+    }
+
+    /// Bytecode of the example called `name`, compiling it if it is AbbyScript
+    pub fn bytecode(&self, name: &str) -> Result<Vec<u8>> {
+        let example = self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown example: {}", name))?;
+        let file = &example.file;
+        let text = self.files.read(file)?;
+
+        match file.extension().and_then(|ext| ext.to_str()) {
+            // Imports are resolved relative to the example's own file
+            Some("abs" | "abby") => match &self.files {
+                ExampleFiles::Builtin => Compiler::new().compile(&text),
+                ExampleFiles::Dir(dir) => Compiler::new().compile_file(&dir.join(file)),
+            }
+            .map_err(|e| anyhow::anyhow!("Cannot compile {}: {}", file.display(), e)),
+            _ => {
+                let hex: String = text.split_whitespace().collect();
+                Ok(hex::decode(hex.trim_start_matches("0x"))?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_loads_hex_and_abbyscript_examples() {
+        let dir = std::env::temp_dir().join(format!("abby_examples_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("add.hex"), "0x6001600201\n").unwrap();
+        // The import resolves next to five.abby, not in the working directory
+        std::fs::write(dir.join("math.abby"), "function two() { return 2; }").unwrap();
+        std::fs::write(
+            dir.join("five.abby"),
+            "import \"math.abby\";\nreturn 2 + 3;",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join(MANIFEST),
+            r#"[
+                {"name": "add", "description": "1 + 2", "file": "add.hex"},
+                {"name": "five", "description": "2 + 3", "file": "five.abby"}
+            ]"#,
+        )
+        .unwrap();
+
+        let registry = ExampleRegistry::load(&dir).unwrap();
+        let names: Vec<_> = registry.examples().iter().map(|e| &e.name).collect();
+        assert_eq!(names, ["add", "five"]);
+        assert_eq!(
+            registry.bytecode("add").unwrap(),
+            vec![0x60, 1, 0x60, 2, 0x01]
+        );
+        assert!(!registry.bytecode("five").unwrap().is_empty());
+        assert!(registry.bytecode("missing").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_every_builtin_example_is_embedded() {
+        let registry = ExampleRegistry::builtin().unwrap();
+        assert!(!registry.examples().is_empty());
+        for example in registry.examples() {
+            assert!(
+                !registry.bytecode(&example.name).unwrap().is_empty(),
+                "{}",
+                example.name
+            );
+        }
+    }
+}
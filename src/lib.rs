@@ -0,0 +1,7 @@
+pub mod abi;
+pub mod blockchain;
+pub mod compiler;
+pub mod evm;
+pub mod opcodes;
+pub mod types;
+pub mod utils;
@@ -3,18 +3,16 @@ use clap::{Parser, Subcommand};
 use colored::Colorize;
 use std::path::PathBuf;
 
-mod blockchain;
 mod cli;
-mod compiler;
-mod evm;
-mod opcodes;
-mod types;
-mod utils;
+mod examples;
+
+use abby_evm::{abi, blockchain, compiler, evm, opcodes, types, utils};
 
 use blockchain::AbbyNode;
 use cli::*;
 use compiler::Compiler;
-use evm::EvmExecutor;
+use evm::{ConsoleSink, CoverageReport, EvmExecutor, ExecutionContext, StackDiff};
+use examples::ExampleRegistry;
 use types::{ExecutionResult, ExecutionStatus};
 
 #[derive(Parser)]
@@ -28,6 +26,10 @@ struct Cli {
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
+
+    /// Print execute/analyze results as JSON instead of decorated text
+    #[arg(long, global = true)]
+    json: bool,
 }
 
 #[derive(Subcommand)]
@@ -38,7 +40,7 @@ enum Commands {
         #[arg(short, long, conflicts_with_all = ["file", "example"])]
         bytecode: Option<String>,
 
-        /// File containing bytecode
+        /// File containing bytecode, or `-` to read it from stdin
         #[arg(short, long, conflicts_with_all = ["bytecode", "example"])]
         file: Option<PathBuf>,
 
@@ -54,9 +56,45 @@ enum Commands {
         #[arg(long, default_value = "0")]
         value: u64,
 
+        /// Call data passed to the code (hex string)
+        #[arg(long)]
+        calldata: Option<String>,
+
+        /// Address returned by CALLER
+        #[arg(long)]
+        caller: Option<String>,
+
+        /// Address returned by ORIGIN
+        #[arg(long)]
+        origin: Option<String>,
+
+        /// Address the code runs at, returned by ADDRESS
+        #[arg(long)]
+        address: Option<String>,
+
         /// Enable verbose output for this command
         #[arg(short, long)]
         verbose: bool,
+
+        /// Show the values each opcode popped and pushed
+        #[arg(long)]
+        trace_stack_diff: bool,
+
+        /// Report which instructions ran and list the ones that did not
+        #[arg(long)]
+        coverage: bool,
+
+        /// Print a hex view of memory after execution
+        #[arg(long)]
+        dump_memory: bool,
+
+        /// Print the non-zero storage slots after execution
+        #[arg(long)]
+        dump_storage: bool,
+
+        /// Decode the return data as these ABI types (e.g. `uint256,address`)
+        #[arg(long)]
+        decode_output: Option<String>,
     },
 
     /// Compile AbbyScript source code to EVM bytecode
@@ -88,6 +126,22 @@ enum Commands {
         /// Gas limit for execution (if --run is specified)
         #[arg(long, default_value = "1000000")]
         gas_limit: u64,
+
+        /// Compile as a contract with a 4-byte selector dispatcher for functions
+        #[arg(long)]
+        contract: bool,
+
+        /// Output init code that deploys the compiled program as contract code
+        #[arg(long)]
+        deployable: bool,
+
+        /// Recompile (and re-run with --run) every time the file is saved
+        #[arg(long, requires = "file")]
+        watch: bool,
+
+        /// Print the parsed syntax tree instead of compiling
+        #[arg(long, conflicts_with_all = ["expression", "run", "output"])]
+        ast: bool,
     },
 
     /// Start interactive EVM shell
@@ -99,6 +153,10 @@ enum Commands {
 
     /// Start AbbyEVM blockchain node
     Node {
+        /// Export or import the chain instead of starting the node
+        #[command(subcommand)]
+        action: Option<NodeAction>,
+
         /// Network port for P2P communication
         #[arg(short, long, default_value = "30303")]
         port: u16,
@@ -118,6 +176,14 @@ enum Commands {
         /// Enable mining (validator mode)
         #[arg(short, long)]
         mine: bool,
+
+        /// Serve JSON-RPC over HTTP on this port
+        #[arg(long)]
+        rpc_port: Option<u16>,
+
+        /// Genesis config (JSON) with initial balances, validators, chain id and block time
+        #[arg(long)]
+        genesis: Option<PathBuf>,
     },
 
     /// List and run example contracts
@@ -130,8 +196,125 @@ enum Commands {
     /// Analyze bytecode without executing
     Analyze {
         /// Bytecode to analyze (hex string)
+        #[arg(short, long, conflicts_with = "file", required_unless_present = "file")]
+        bytecode: Option<String>,
+
+        /// File containing bytecode, or `-` to read it from stdin
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Print the control-flow graph in Graphviz DOT format instead
+        #[arg(long)]
+        cfg: bool,
+
+        /// Check for truncated PUSH data, unknown opcodes and unreachable code
+        #[arg(long, conflicts_with = "cfg")]
+        lint: bool,
+    },
+
+    /// Print bytecode as one instruction per line, in the format `assemble` reads
+    Disassemble {
+        /// Bytecode to disassemble (hex string)
+        #[arg(short, long, conflicts_with = "file", required_unless_present = "file")]
+        bytecode: Option<String>,
+
+        /// File containing bytecode, or `-` to read it from stdin
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Write the disassembly to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Turn mnemonic text back into bytecode, resolving labels
+    Assemble {
+        /// Assembly source, or `-` to read it from stdin
+        file: PathBuf,
+
+        /// Write the bytecode hex to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Run bytecode and print only the gas it used
+    EstimateGas {
+        /// Bytecode to execute (hex string)
         #[arg(short, long)]
         bytecode: String,
+
+        /// Call data passed to the code (hex string)
+        #[arg(long)]
+        calldata: Option<String>,
+
+        /// Value in wei
+        #[arg(long, default_value = "0")]
+        value: u64,
+
+        /// Address returned by CALLER
+        #[arg(long)]
+        caller: Option<String>,
+
+        /// Gas limit for execution
+        #[arg(short, long, default_value = "10000000")]
+        gas_limit: u64,
+    },
+
+    /// Time repeated runs of bytecode
+    Benchmark {
+        /// Bytecode to execute (hex string)
+        #[arg(short, long, conflicts_with = "file", required_unless_present = "file")]
+        bytecode: Option<String>,
+
+        /// File containing bytecode, or `-` to read it from stdin
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Number of runs to time
+        #[arg(short, long, default_value = "100")]
+        iterations: usize,
+
+        /// Call data passed to the code (hex string)
+        #[arg(long)]
+        calldata: Option<String>,
+
+        /// Value in wei
+        #[arg(long, default_value = "0")]
+        value: u64,
+
+        /// Gas limit for each run
+        #[arg(short, long, default_value = "10000000")]
+        gas_limit: u64,
+    },
+
+    /// Predict the address of a CREATE2 deployment
+    Create2Address {
+        /// Address of the deploying (factory) contract
+        #[arg(long)]
+        deployer: String,
+
+        /// 32-byte salt (hex, shorter values are left-padded)
+        #[arg(long)]
+        salt: String,
+
+        /// Contract init code (hex)
+        #[arg(long)]
+        init_code: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeAction {
+    /// Write the chain in the node's database to a portable JSON file
+    Export {
+        /// File to write the chain to
+        file: PathBuf,
+    },
+
+    /// Load a chain exported with `node export` into the node's database
+    Import {
+        /// File to read the chain from
+        file: PathBuf,
     },
 }
 
@@ -149,13 +332,24 @@ async fn async_main() -> Result<()> {
         std::env::set_var("RUST_LOG", "debug");
     }
 
-    println!(
-        "{}",
-        "🚀 AbbyEVM - User-Friendly Ethereum Virtual Machine"
-            .bright_cyan()
-            .bold()
-    );
-    println!("{}", "═".repeat(50).bright_blue());
+    // Keep machine-readable output free of the banner
+    let machine_output = cli.json
+        || matches!(
+            cli.command,
+            Commands::Analyze { cfg: true, .. }
+                | Commands::Disassemble { .. }
+                | Commands::Assemble { .. }
+                | Commands::EstimateGas { .. }
+        );
+    if !machine_output {
+        println!(
+            "{}",
+            "🚀 AbbyEVM - User-Friendly Ethereum Virtual Machine"
+                .bright_cyan()
+                .bold()
+        );
+        println!("{}", "═".repeat(50).bright_blue());
+    }
 
     match cli.command {
         Commands::Execute {
@@ -164,10 +358,39 @@ async fn async_main() -> Result<()> {
             example,
             gas_limit,
             value,
+            calldata,
+            caller,
+            origin,
+            address,
             verbose,
+            trace_stack_diff,
+            coverage,
+            dump_memory,
+            dump_storage,
+            decode_output,
         } => {
             let final_verbose = cli.verbose || verbose;
-            execute_command(bytecode, file, example, gas_limit, value, final_verbose)?;
+            let context = execution_context(value, calldata, caller, origin, address)?;
+            let output_types = decode_output
+                .map(|types| abi::ParamType::parse_list(&types))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!(e))?;
+            execute_command(
+                bytecode,
+                file,
+                example,
+                gas_limit,
+                context,
+                final_verbose,
+                trace_stack_diff,
+                coverage,
+                Dumps {
+                    memory: dump_memory,
+                    storage: dump_storage,
+                },
+                output_types,
+                cli.json,
+            )?;
         }
         Commands::Compile {
             file,
@@ -177,72 +400,373 @@ async fn async_main() -> Result<()> {
             debug,
             run,
             gas_limit,
+            contract,
+            deployable,
+            watch,
+            ast,
         } => {
-            compile_command(file, source, expression, output, debug, run, gas_limit)?;
+            if let Some(path) = file.clone().filter(|_| watch) {
+                watch_command(&path, || {
+                    compile_command(
+                        file.clone(),
+                        None,
+                        None,
+                        output.clone(),
+                        debug,
+                        run,
+                        gas_limit,
+                        contract,
+                        deployable,
+                        ast,
+                    )
+                })?;
+            } else {
+                compile_command(
+                    file, source, expression, output, debug, run, gas_limit, contract, deployable,
+                    ast,
+                )?;
+            }
         }
         Commands::Interactive { verbose } => {
             let _final_verbose = cli.verbose || verbose;
             interactive_mode()?;
         }
         Commands::Node {
+            action: Some(action),
+            db_path,
+            genesis,
+            ..
+        } => {
+            node_action_command(action, db_path, genesis)?;
+        }
+        Commands::Node {
+            action: None,
             port,
             validator,
             connect,
             db_path,
             mine,
+            rpc_port,
+            genesis,
         } => {
-            node_command(port, validator, connect, db_path, mine).await?;
+            node_command(port, validator, connect, db_path, mine, rpc_port, genesis).await?;
         }
         Commands::Examples { list } => {
             examples_command(list)?;
         }
-        Commands::Analyze { bytecode } => {
-            analyze_command(bytecode)?;
+        Commands::Analyze {
+            bytecode,
+            file,
+            cfg,
+            lint,
+        } => {
+            let bytecode = match bytecode {
+                Some(bytecode) => bytecode,
+                None => read_bytecode_file(&file.unwrap_or_default())?,
+            };
+            if cfg {
+                cfg_command(bytecode)?;
+            } else if lint {
+                lint_command(bytecode, cli.json)?;
+            } else {
+                analyze_command(bytecode, cli.json)?;
+            }
+        }
+        Commands::Disassemble {
+            bytecode,
+            file,
+            output,
+        } => {
+            let bytecode = match bytecode {
+                Some(bytecode) => bytecode,
+                None => read_bytecode_file(&file.unwrap_or_default())?,
+            };
+            disassemble_command(bytecode, output.as_deref())?;
+        }
+        Commands::Assemble { file, output } => {
+            assemble_command(&file, output.as_deref())?;
+        }
+        Commands::EstimateGas {
+            bytecode,
+            calldata,
+            value,
+            caller,
+            gas_limit,
+        } => {
+            let context = execution_context(value, calldata, caller, None, None)?;
+            estimate_gas_command(bytecode, context, gas_limit)?;
+        }
+        Commands::Benchmark {
+            bytecode,
+            file,
+            iterations,
+            calldata,
+            value,
+            gas_limit,
+        } => {
+            let bytecode = match bytecode {
+                Some(bytecode) => bytecode,
+                None => read_bytecode_file(&file.unwrap_or_default())?,
+            };
+            let context = execution_context(value, calldata, None, None, None)?;
+            benchmark_command(bytecode, context, gas_limit, iterations, cli.json)?;
+        }
+        Commands::Create2Address {
+            deployer,
+            salt,
+            init_code,
+        } => {
+            create2_address_command(deployer, salt, init_code)?;
         }
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn execute_command(
     bytecode: Option<String>,
     file: Option<PathBuf>,
     example: Option<String>,
     gas_limit: u64,
-    value: u64,
+    context: ExecutionContext,
     verbose: bool,
+    trace_stack_diff: bool,
+    coverage: bool,
+    dumps: Dumps,
+    output_types: Option<Vec<abi::ParamType>>,
+    json: bool,
 ) -> Result<()> {
     let bytecode_hex = if let Some(bc) = bytecode {
         bc
     } else if let Some(path) = file {
-        std::fs::read_to_string(path)?
-            .lines()
-            .next()
-            .unwrap_or("")
-            .trim()
-            .to_string()
+        read_bytecode_file(&path)?
     } else if let Some(ex) = example {
-        get_example_bytecode(&ex)?
+        hex::encode(ExampleRegistry::load_default()?.bytecode(&ex)?)
     } else {
         anyhow::bail!("Must provide either --bytecode, --file, or --example");
     };
 
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    let mut executor = EvmExecutor::new(gas_limit)
+        .with_stack_diff_trace(trace_stack_diff)
+        .with_coverage(coverage);
+
+    if json {
+        let console = ConsoleSink::buffer();
+        let mut executor = executor.with_console_sink(console.clone());
+        let result = executor.execute_with_context(&bytecode, &context, verbose)?;
+        let mut output = execution_result_json(&result);
+        output["console"] = console.lines().into();
+        if dumps.memory {
+            output["memory"] = format!("0x{}", hex::encode(&result.memory)).into();
+        }
+        if dumps.storage {
+            let storage: serde_json::Map<_, _> = result
+                .storage_dump()
+                .into_iter()
+                .map(|(slot, value)| (format!("0x{:x}", slot), format!("0x{:x}", value).into()))
+                .collect();
+            output["storage"] = storage.into();
+        }
+        if let Some(types) = &output_types {
+            output["decoded"] = decode_output(types, &result.return_data)?.into();
+        }
+        if coverage {
+            let report = CoverageReport::new(&bytecode, executor.executed_offsets());
+            output["coverage"] = serde_json::json!({
+                "executed": report.executed(),
+                "total": report.total(),
+                "uncovered": report.uncovered(),
+            });
+        }
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
     println!("📋 {}: {}", "Bytecode".bright_yellow(), bytecode_hex);
     println!("⛽ {}: {}", "Gas Limit".bright_yellow(), gas_limit);
-    println!("💰 {}: {} wei", "Value".bright_yellow(), value);
+    println!("💰 {}: {} wei", "Value".bright_yellow(), context.value);
+    if !context.call_data.is_empty() {
+        println!(
+            "📨 {}: 0x{}",
+            "Call Data".bright_yellow(),
+            hex::encode(&context.call_data)
+        );
+    }
     println!();
 
-    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
-    let mut executor = EvmExecutor::new(gas_limit);
-
     println!("{}", "🔄 Executing...".bright_green());
-    let result = executor.execute(&bytecode, value, verbose)?;
+    let result = executor.execute_with_context(&bytecode, &context, verbose)?;
+
+    if trace_stack_diff {
+        display_stack_trace(executor.stack_trace());
+    }
 
     display_execution_result(&result);
 
+    if let Some(types) = &output_types {
+        display_decoded_output(types, &decode_output(types, &result.return_data)?);
+    }
+
+    if dumps.memory {
+        display_memory(&result);
+    }
+    if dumps.storage {
+        display_storage(&result);
+    }
+
+    if coverage {
+        display_coverage(&CoverageReport::new(&bytecode, executor.executed_offsets()));
+    }
+
     Ok(())
 }
 
+/// Hex bytecode from the first line of `path`, or from all of stdin when the
+/// path is `-` so output can be piped in
+fn read_bytecode_file(path: &std::path::Path) -> Result<String> {
+    if path == std::path::Path::new("-") {
+        let mut input = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)?;
+        let hex: String = input.split_whitespace().collect();
+        return Ok(hex.trim_start_matches("0x").to_string());
+    }
+
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_string())
+}
+
+fn estimate_gas_command(
+    bytecode_hex: String,
+    context: ExecutionContext,
+    gas_limit: u64,
+) -> Result<()> {
+    let bytecode = hex::decode(bytecode_hex.trim_start_matches("0x"))?;
+    // Logged lines would mix into the figure, so they are dropped
+    let mut executor = EvmExecutor::new(gas_limit).with_console_sink(ConsoleSink::callback(|_| {}));
+    let result = executor.execute_with_context(&bytecode, &context, false)?;
+
+    match result.status {
+        ExecutionStatus::Success => {
+            println!("{}", result.gas_used);
+            Ok(())
+        }
+        ExecutionStatus::Revert(reason) => anyhow::bail!("Execution reverted: {}", reason),
+        ExecutionStatus::OutOfGas => anyhow::bail!("Out of gas (limit {})", gas_limit),
+        ExecutionStatus::Error(err) => anyhow::bail!("Execution failed: {}", err),
+    }
+}
+
+/// Final state to print after `execute`
+struct Dumps {
+    memory: bool,
+    storage: bool,
+}
+
+fn display_memory(result: &ExecutionResult) {
+    println!();
+    println!(
+        "{} ({} bytes)",
+        "🧠 Memory".bright_cyan().bold(),
+        result.memory.len()
+    );
+    println!("{}", "─".repeat(30).bright_cyan());
+    for row in result.memory_dump() {
+        println!("{}", row);
+    }
+}
+
+fn display_storage(result: &ExecutionResult) {
+    println!();
+    println!("{}", "🗄️  Storage".bright_cyan().bold());
+    println!("{}", "─".repeat(30).bright_cyan());
+    let slots = result.storage_dump();
+    if slots.is_empty() {
+        println!("(no non-zero slots)");
+    }
+    for (slot, value) in slots {
+        println!("[0x{:x}] = 0x{:x}", slot, value);
+    }
+}
+
+fn display_coverage(report: &CoverageReport) {
+    println!();
+    println!("{}", "🧭 Coverage".bright_cyan().bold());
+    println!("{}", "─".repeat(30).bright_cyan());
+    println!(
+        "Executed {} of {} instructions ({:.1}%)",
+        report.executed(),
+        report.total(),
+        report.percentage()
+    );
+    for line in report.annotated() {
+        if line.starts_with('!') {
+            println!("{}", line.bright_red());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+fn execution_context(
+    value: u64,
+    calldata: Option<String>,
+    caller: Option<String>,
+    origin: Option<String>,
+    address: Option<String>,
+) -> Result<ExecutionContext> {
+    let parse_address = |text: Option<String>| -> Result<ethereum_types::Address> {
+        match text {
+            Some(text) => match abi::parse_token(&abi::ParamType::Address, &text)? {
+                abi::Token::Address(address) => Ok(address),
+                _ => unreachable!(),
+            },
+            None => Ok(ethereum_types::Address::zero()),
+        }
+    };
+
+    let call_data = match calldata {
+        Some(hex_data) => hex::decode(hex_data.trim_start_matches("0x"))?,
+        None => Vec::new(),
+    };
+
+    Ok(ExecutionContext::new()
+        .caller(parse_address(caller)?)
+        .origin(parse_address(origin)?)
+        .address(parse_address(address)?)
+        .value(ethereum_types::U256::from(value))
+        .calldata(call_data))
+}
+
+fn display_stack_trace(trace: &[StackDiff]) {
+    println!("{}", "📚 Stack Diff Trace".bright_cyan().bold());
+    println!("{}", "─".repeat(30).bright_cyan());
+
+    let format_words = |words: &[ethereum_types::U256]| {
+        words
+            .iter()
+            .map(|w| format!("0x{:x}", w))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for diff in trace {
+        println!(
+            "{:>4}  {:04x}  {:<12} -[{}] +[{}]",
+            diff.step,
+            diff.pc,
+            format!("{:?}", diff.opcode),
+            format_words(&diff.popped).bright_red(),
+            format_words(&diff.pushed).bright_green()
+        );
+    }
+    println!();
+}
+
 fn display_execution_result(result: &ExecutionResult) {
     println!("{}", "✨ Execution Results".bright_green().bold());
     println!("{}", "─".repeat(30).bright_green());
@@ -271,6 +795,13 @@ fn display_execution_result(result: &ExecutionResult) {
         "Gas Remaining: {}",
         result.gas_remaining.to_string().bright_cyan()
     );
+    if !result.raw_refund.is_zero() {
+        println!(
+            "Gas Refund: {} (earned {})",
+            result.refund.to_string().bright_cyan(),
+            result.raw_refund
+        );
+    }
 
     if !result.return_data.is_empty() {
         println!(
@@ -287,15 +818,38 @@ fn display_execution_result(result: &ExecutionResult) {
     }
 }
 
-fn get_example_bytecode(example: &str) -> Result<String> {
-    match example {
-        "simple-add" => Ok("6001600201".to_string()), // PUSH1 1, PUSH1 2, ADD
-        "simple-mul" => Ok("6002600302".to_string()), // PUSH1 2, PUSH1 3, MUL
-        "storage" => Ok("6001600055600054".to_string()), // Simple storage example
-        _ => anyhow::bail!("Unknown example: {}", example),
-    }
+fn execution_result_json(result: &ExecutionResult) -> serde_json::Value {
+    let (status, message) = match &result.status {
+        ExecutionStatus::Success => ("success", None),
+        ExecutionStatus::Revert(reason) => ("revert", Some(reason.clone())),
+        ExecutionStatus::OutOfGas => ("out_of_gas", None),
+        ExecutionStatus::Error(err) => ("error", Some(err.clone())),
+    };
+    let logs: Vec<_> = result
+        .logs
+        .iter()
+        .map(|log| {
+            serde_json::json!({
+                "address": format!("{:?}", log.address),
+                "topics": log.topics.iter().map(|t| format!("{:?}", t)).collect::<Vec<_>>(),
+                "data": format!("0x{}", hex::encode(&log.data)),
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "status": status,
+        "message": message,
+        "gas_used": result.gas_used.as_u64(),
+        "gas_remaining": result.gas_remaining.as_u64(),
+        "refund": result.refund.as_u64(),
+        "raw_refund": result.raw_refund.as_u64(),
+        "return_data": format!("0x{}", hex::encode(&result.return_data)),
+        "logs": logs,
+    })
 }
 
+#[allow(clippy::too_many_arguments)]
 fn compile_command(
     file: Option<PathBuf>,
     source: Option<String>,
@@ -304,12 +858,15 @@ fn compile_command(
     debug: bool,
     run: bool,
     gas_limit: u64,
+    contract: bool,
+    deployable: bool,
+    ast: bool,
 ) -> Result<()> {
     println!("{}", "🔧 AbbyScript Compiler".bright_magenta().bold());
     println!("{}", "─".repeat(20).bright_blue());
 
     // Get source code
-    let source_code = if let Some(file) = file {
+    let source_code = if let Some(file) = &file {
         println!(
             "Reading from file: {}",
             file.display().to_string().bright_cyan()
@@ -323,8 +880,19 @@ fn compile_command(
         anyhow::bail!("Must provide either --file, --source, or --expression");
     };
 
+    if ast {
+        match Compiler::new().parse(&source_code) {
+            Ok(program) => println!("{}", compiler::ast::AstPrinter::new().print(&program)),
+            Err(e) => eprintln!("{}", format!("Parsing failed: {}", e).bright_red().bold()),
+        }
+        return Ok(());
+    }
+
     // Create compiler
-    let compiler = Compiler::new().with_debug(debug);
+    let compiler = Compiler::new()
+        .with_debug(debug)
+        .with_contract(contract)
+        .with_deployable(deployable);
 
     // Compile the code
     let bytecode = if expression.is_some() {
@@ -332,7 +900,11 @@ fn compile_command(
         compiler.compile_expression(&source_code)
     } else {
         println!("Compiling program...");
-        compiler.compile(&source_code)
+        match &file {
+            // Imports are resolved relative to the file's directory
+            Some(file) => compiler.compile_file(file),
+            None => compiler.compile(&source_code),
+        }
     };
 
     let bytecode = match bytecode {
@@ -388,7 +960,10 @@ async fn node_command(
     connect_peers: Vec<String>,
     db_path: Option<PathBuf>,
     mine: bool,
+    rpc_port: Option<u16>,
+    genesis_path: Option<PathBuf>,
 ) -> Result<()> {
+    use blockchain::GenesisConfig;
     use ethereum_types::Address;
 
     println!(
@@ -419,20 +994,10 @@ async fn node_command(
         None
     };
 
-    // Handle database path with default to ~/.ABBYCHAIN
-    let db_path_str = if let Some(path) = db_path {
-        path.to_str().unwrap_or("~/.ABBYCHAIN").to_string()
-    } else {
-        // Default to ~/.ABBYCHAIN
-        let home_dir = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        format!("{}/.ABBYCHAIN", home_dir)
-    };
+    let db_path_str = resolve_db_path(db_path);
 
     // Create the database directory if it doesn't exist
-    if let Ok(expanded_path) = std::fs::canonicalize(&db_path_str) {
-        // Path exists, use it as-is
-    } else {
-        // Path doesn't exist, create it
+    if std::fs::canonicalize(&db_path_str).is_err() {
         if let Err(e) = std::fs::create_dir_all(&db_path_str) {
             log::warn!("Failed to create database directory {}: {}", db_path_str, e);
         }
@@ -445,7 +1010,15 @@ async fn node_command(
         println!("Validator address: {}", addr);
     }
 
-    let node = AbbyNode::new(validator_address, port, Some(&db_path_str))
+    let genesis = match &genesis_path {
+        Some(path) => {
+            println!("Genesis config: {}", path.display());
+            GenesisConfig::load(path).map_err(|e| anyhow::anyhow!(e))?
+        }
+        None => GenesisConfig::default(),
+    };
+
+    let node = AbbyNode::new(validator_address, port, Some(&db_path_str), genesis)
         .await
         .map_err(|e| anyhow::anyhow!("Failed to create node: {}", e))?;
 
@@ -463,8 +1036,16 @@ async fn node_command(
         .await
         .map_err(|e| anyhow::anyhow!("Failed to start node: {}", e))?;
 
+    if let Some(rpc_port) = rpc_port {
+        let rpc_addr = node
+            .start_rpc(rpc_port)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to start JSON-RPC server: {}", e))?;
+        println!("JSON-RPC listening on http://{}", rpc_addr);
+    }
+
     // Display node info
-    let (chain_length, head_hash, block_count, abby_supply) = node.get_blockchain_info().await;
+    let (chain_length, head_hash, _, abby_supply) = node.get_blockchain_info().await;
     println!("\n{}", "📊 Node Status".bright_green().bold());
     println!("Chain length: {} blocks", chain_length);
     println!("Head hash: {}", head_hash);
@@ -493,6 +1074,63 @@ async fn node_command(
     Ok(())
 }
 
+/// Database path for the node, defaulting to ~/.ABBYCHAIN
+fn resolve_db_path(db_path: Option<PathBuf>) -> String {
+    if let Some(path) = db_path {
+        path.to_str().unwrap_or("~/.ABBYCHAIN").to_string()
+    } else {
+        let home_dir = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        format!("{}/.ABBYCHAIN", home_dir)
+    }
+}
+
+fn node_action_command(
+    action: NodeAction,
+    db_path: Option<PathBuf>,
+    genesis_path: Option<PathBuf>,
+) -> Result<()> {
+    use blockchain::{Blockchain, GenesisConfig};
+
+    let db_path = resolve_db_path(db_path);
+    match action {
+        NodeAction::Export { file } => {
+            let genesis = match &genesis_path {
+                Some(path) => GenesisConfig::load(path).map_err(|e| anyhow::anyhow!(e))?,
+                None => GenesisConfig::default(),
+            };
+            let chain = Blockchain::new_with_persistence(&db_path, genesis)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            chain
+                .export_to_file(&file)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            println!(
+                "Exported {} blocks from {} to {}",
+                chain.get_chain_length(),
+                db_path,
+                file.display()
+            );
+        }
+        NodeAction::Import { file } => {
+            if genesis_path.is_some() {
+                log::warn!("Ignoring --genesis: the export file carries its own genesis config");
+            }
+            let mut chain = Blockchain::import_from_file(&file).map_err(|e| anyhow::anyhow!(e))?;
+            std::fs::create_dir_all(&db_path)?;
+            chain
+                .save_to_database(&db_path)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            println!(
+                "Imported {} blocks from {} into {} (head {})",
+                chain.get_chain_length(),
+                file.display(),
+                db_path,
+                chain.head_hash
+            );
+        }
+    }
+    Ok(())
+}
+
 fn format_abby_amount(amount: ethereum_types::U256) -> String {
     let decimals = ethereum_types::U256::from(1_000_000_000_000_000_000u64); // 18 decimals
     let whole = amount / decimals;
@@ -0,0 +1,253 @@
+use super::OpCode;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A line of assembly that could not be turned into bytecode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    /// 1-based line in the source
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Immediate of a PUSH: literal bytes, or the offset of a label
+enum Operand {
+    Bytes(Vec<u8>),
+    Label(String),
+}
+
+struct Item {
+    line: usize,
+    opcode: OpCode,
+    operand: Option<Operand>,
+}
+
+/// Turn mnemonic text back into bytecode, the inverse of `disassemble`.
+///
+/// Each line holds one instruction (`PUSH1 0x01`, `ADD`) or a label
+/// definition (`loop:`). `;` starts a comment, and a leading `0002:` offset
+/// as printed by the disassembler is ignored. PUSH data is hex, left-padded
+/// to the push size. `PUSHn @label` pushes a label's offset, and
+/// `JUMP @label` / `JUMPI @label` are shorthand for `PUSH2 @label` followed
+/// by the jump.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mnemonics = mnemonics();
+    let mut items = Vec::new();
+    let mut labels = HashMap::new();
+    let mut pc = 0;
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| AssembleError { line, message };
+        let text = strip_offset(raw.split(';').next().unwrap_or("").trim());
+        if text.is_empty() {
+            continue;
+        }
+
+        if let Some(label) = text.strip_suffix(':') {
+            if labels.insert(label.to_string(), pc).is_some() {
+                return Err(error(format!("label '{}' is defined twice", label)));
+            }
+            continue;
+        }
+
+        let mut parts = text.split_whitespace();
+        let mnemonic = parts.next().unwrap_or("").to_ascii_uppercase();
+        let operand = parts.next();
+        if parts.next().is_some() {
+            return Err(error(format!("unexpected text after '{}'", text)));
+        }
+        let opcode = parse_opcode(&mnemonics, &mnemonic)
+            .ok_or_else(|| error(format!("unknown mnemonic '{}'", mnemonic)))?;
+
+        match (opcode.push_size(), operand) {
+            (Some(size), Some(operand)) => {
+                let operand = parse_operand(operand, size).map_err(error)?;
+                pc += 1 + size;
+                items.push(Item {
+                    line,
+                    opcode,
+                    operand: Some(operand),
+                });
+            }
+            (Some(_), None) => {
+                return Err(error(format!("{} needs a value", mnemonic)));
+            }
+            (None, Some(target)) if matches!(opcode, OpCode::JUMP | OpCode::JUMPI) => {
+                let label = target
+                    .strip_prefix('@')
+                    .ok_or_else(|| error(format!("{} takes a @label", mnemonic)))?;
+                pc += 4;
+                items.push(Item {
+                    line,
+                    opcode: OpCode::PUSH2,
+                    operand: Some(Operand::Label(label.to_string())),
+                });
+                items.push(Item {
+                    line,
+                    opcode,
+                    operand: None,
+                });
+            }
+            (None, Some(_)) => {
+                return Err(error(format!("{} takes no operand", mnemonic)));
+            }
+            (None, None) => {
+                pc += 1;
+                items.push(Item {
+                    line,
+                    opcode,
+                    operand: None,
+                });
+            }
+        }
+    }
+
+    let mut bytecode = Vec::with_capacity(pc);
+    for item in items {
+        bytecode.push(item.opcode.to_byte());
+        let size = item.opcode.push_size().unwrap_or(0);
+        match item.operand {
+            Some(Operand::Bytes(bytes)) => bytecode.extend(bytes),
+            Some(Operand::Label(label)) => {
+                let error = |message: String| AssembleError {
+                    line: item.line,
+                    message,
+                };
+                let offset = *labels
+                    .get(&label)
+                    .ok_or_else(|| error(format!("undefined label '{}'", label)))?;
+                let bytes = offset.to_be_bytes();
+                let (high, low) = bytes.split_at(bytes.len().saturating_sub(size));
+                if high.iter().any(|&byte| byte != 0) {
+                    return Err(error(format!(
+                        "label '{}' at 0x{:x} does not fit in {} bytes",
+                        label, offset, size
+                    )));
+                }
+                bytecode.extend(std::iter::repeat_n(0, size - low.len()));
+                bytecode.extend_from_slice(low);
+            }
+            None => {}
+        }
+    }
+
+    Ok(bytecode)
+}
+
+/// Mnemonic of every known opcode, as the disassembler prints it
+fn mnemonics() -> HashMap<String, OpCode> {
+    (0..=u8::MAX)
+        .map(OpCode::from_byte)
+        .filter(|opcode| !matches!(opcode, OpCode::UNKNOWN(_)))
+        .map(|opcode| (format!("{:?}", opcode), opcode))
+        .collect()
+}
+
+/// A known mnemonic, or `UNKNOWN(n)` for a byte that is not an opcode
+fn parse_opcode(mnemonics: &HashMap<String, OpCode>, mnemonic: &str) -> Option<OpCode> {
+    if let Some(opcode) = mnemonics.get(mnemonic) {
+        return Some(opcode.clone());
+    }
+    let byte = mnemonic.strip_prefix("UNKNOWN(")?.strip_suffix(')')?;
+    Some(OpCode::UNKNOWN(byte.parse().ok()?))
+}
+
+fn parse_operand(operand: &str, size: usize) -> Result<Operand, String> {
+    if let Some(label) = operand.strip_prefix('@') {
+        return Ok(Operand::Label(label.to_string()));
+    }
+    let digits = operand
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("expected hex PUSH data, found '{}'", operand))?;
+    let digits = if digits.len() % 2 == 1 {
+        format!("0{}", digits)
+    } else {
+        digits.to_string()
+    };
+    let bytes = hex::decode(&digits).map_err(|e| format!("bad PUSH data '{}': {}", operand, e))?;
+    if bytes.len() > size {
+        return Err(format!(
+            "PUSH data '{}' is wider than {} bytes",
+            operand, size
+        ));
+    }
+    let mut padded = vec![0; size - bytes.len()];
+    padded.extend(bytes);
+    Ok(Operand::Bytes(padded))
+}
+
+/// Drop the `0002: ` offset the disassembler puts before each instruction
+fn strip_offset(text: &str) -> &str {
+    match text.split_once(": ") {
+        Some((offset, rest)) if offset.chars().all(|c| c.is_ascii_hexdigit()) => rest.trim(),
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::disassembler::disassemble;
+
+    #[test]
+    fn test_disassembly_reassembles_to_the_same_bytes() {
+        // The countdown loop, then a PUSH32 and an unknown byte
+        let bytecode = hex::decode(format!(
+            "60035b600190038060025700{}{}0c",
+            "7f",
+            "ff".repeat(32)
+        ))
+        .unwrap();
+
+        let text = disassemble(&bytecode).join("\n");
+        assert_eq!(assemble(&text).unwrap(), bytecode);
+    }
+
+    #[test]
+    fn test_labels_resolve_to_their_offsets() {
+        let source = "
+            PUSH1 0x03
+            loop:
+            JUMPDEST        ; offset 2
+            PUSH1 0x01
+            SWAP1
+            SUB
+            DUP1
+            JUMPI @loop
+            PUSH2 @end
+            JUMP
+            end:
+            JUMPDEST
+            stop
+        ";
+
+        // JUMPI @loop becomes PUSH2 0x0002 JUMPI; `end` lands at 0x10
+        assert_eq!(
+            hex::encode(assemble(source).unwrap()),
+            "60035b600190038061000257610010565b00"
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_line() {
+        assert_eq!(
+            assemble("ADD\nFROB").unwrap_err(),
+            AssembleError {
+                line: 2,
+                message: "unknown mnemonic 'FROB'".to_string()
+            }
+        );
+        assert_eq!(assemble("JUMP @nowhere").unwrap_err().line, 1);
+        assert!(assemble("PUSH1 0x0100").is_err());
+        assert!(assemble("a:\na:").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use super::disassembler::{decode, jump_destinations, static_jump_target, Instruction};
+use super::OpCode;
+use std::fmt::Write;
+
+/// Straight-line run of instructions with a single entry and a single exit
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub instructions: Vec<Instruction>,
+}
+
+impl BasicBlock {
+    pub fn start(&self) -> usize {
+        self.instructions[0].pc
+    }
+
+    fn last(&self) -> &Instruction {
+        self.instructions.last().expect("blocks are never empty")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Unconditional JUMP
+    Jump,
+    /// JUMPI taken
+    BranchTaken,
+    /// JUMPI not taken
+    BranchNotTaken,
+    /// Execution runs on into the next block, e.g. into a JUMPDEST
+    Fallthrough,
+}
+
+/// Edge between two blocks, identified by their start offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+    /// Start offsets of blocks ending in a jump whose target is computed at
+    /// runtime or is not a JUMPDEST
+    pub unresolved: Vec<usize>,
+}
+
+fn ends_block(opcode: &OpCode) -> bool {
+    matches!(
+        opcode,
+        OpCode::JUMP
+            | OpCode::JUMPI
+            | OpCode::STOP
+            | OpCode::RETURN
+            | OpCode::REVERT
+            | OpCode::INVALID
+            | OpCode::SELFDESTRUCT
+    )
+}
+
+impl ControlFlowGraph {
+    /// Split `bytecode` into basic blocks at JUMPDESTs and after jumps and
+    /// halting opcodes, then connect them
+    pub fn build(bytecode: &[u8]) -> Self {
+        let instructions = decode(bytecode);
+        let destinations = jump_destinations(&instructions);
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        let mut current: Vec<Instruction> = Vec::new();
+        for instruction in &instructions {
+            if instruction.opcode == OpCode::JUMPDEST && !current.is_empty() {
+                blocks.push(BasicBlock {
+                    instructions: std::mem::take(&mut current),
+                });
+            }
+            let terminates = ends_block(&instruction.opcode);
+            current.push(instruction.clone());
+            if terminates {
+                blocks.push(BasicBlock {
+                    instructions: std::mem::take(&mut current),
+                });
+            }
+        }
+        if !current.is_empty() {
+            blocks.push(BasicBlock {
+                instructions: current,
+            });
+        }
+
+        let mut edges = Vec::new();
+        let mut unresolved = Vec::new();
+        let mut index = 0;
+        for (number, block) in blocks.iter().enumerate() {
+            index += block.instructions.len();
+            let from = block.start();
+            let next = blocks.get(number + 1).map(BasicBlock::start);
+            let last = block.last();
+
+            // The jump target is pushed by the instruction right before the jump
+            let target = index
+                .checked_sub(2)
+                .and_then(|push| static_jump_target(&instructions, push))
+                .filter(|target| destinations.contains(target));
+
+            match last.opcode {
+                OpCode::JUMP | OpCode::JUMPI => {
+                    let kind = if last.opcode == OpCode::JUMP {
+                        EdgeKind::Jump
+                    } else {
+                        EdgeKind::BranchTaken
+                    };
+                    match target {
+                        Some(to) => edges.push(Edge { from, to, kind }),
+                        None => unresolved.push(from),
+                    }
+                    if last.opcode == OpCode::JUMPI {
+                        if let Some(to) = next {
+                            edges.push(Edge {
+                                from,
+                                to,
+                                kind: EdgeKind::BranchNotTaken,
+                            });
+                        }
+                    }
+                }
+                ref opcode if ends_block(opcode) => {}
+                _ => {
+                    if let Some(to) = next {
+                        edges.push(Edge {
+                            from,
+                            to,
+                            kind: EdgeKind::Fallthrough,
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            blocks,
+            edges,
+            unresolved,
+        }
+    }
+
+    /// Graphviz DOT rendering, one box per block listing its instructions
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box fontname=monospace];\n");
+
+        for block in &self.blocks {
+            let body: String = block
+                .instructions
+                .iter()
+                .map(|i| {
+                    format!(
+                        "{:04x}: {}\\l",
+                        i.pc,
+                        super::disassembler::format_instruction(i)
+                    )
+                })
+                .collect();
+            let _ = writeln!(dot, "    block_{:x} [label=\"{}\"];", block.start(), body);
+        }
+
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Jump => "",
+                EdgeKind::BranchTaken => " [label=\"true\" color=green]",
+                EdgeKind::BranchNotTaken => " [label=\"false\" color=red]",
+                EdgeKind::Fallthrough => " [style=dotted]",
+            };
+            let _ = writeln!(
+                dot,
+                "    block_{:x} -> block_{:x}{};",
+                edge.from, edge.to, style
+            );
+        }
+
+        if !self.unresolved.is_empty() {
+            dot.push_str(
+                "    unresolved [label=\"unresolved jump\" shape=ellipse style=dashed];\n",
+            );
+            for from in &self.unresolved {
+                let _ = writeln!(dot, "    block_{:x} -> unresolved [style=dashed];", from);
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_else_blocks_and_edges() {
+        // 00: PUSH1 1, PUSH1 0x0a, JUMPI       condition
+        // 05: PUSH1 2, PUSH1 0x0d, JUMP        else branch
+        // 0a: JUMPDEST, PUSH1 3                then branch, runs on into the join
+        // 0d: JUMPDEST, STOP                   join
+        let bytecode = hex::decode("6001600a576002600d565b60035b00").unwrap();
+        let cfg = ControlFlowGraph::build(&bytecode);
+
+        let starts: Vec<_> = cfg.blocks.iter().map(BasicBlock::start).collect();
+        assert_eq!(starts, vec![0x00, 0x05, 0x0a, 0x0d]);
+
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            cfg.edges,
+            vec![
+                edge(0x00, 0x0a, EdgeKind::BranchTaken),
+                edge(0x00, 0x05, EdgeKind::BranchNotTaken),
+                edge(0x05, 0x0d, EdgeKind::Jump),
+                edge(0x0a, 0x0d, EdgeKind::Fallthrough),
+            ]
+        );
+        assert!(cfg.unresolved.is_empty());
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("block_0 -> block_a [label=\"true\" color=green];"));
+        assert!(dot.contains("block_5 -> block_d;"));
+    }
+
+    #[test]
+    fn test_dynamic_jump_is_unresolved() {
+        // CALLDATALOAD(0), JUMP, JUMPDEST, STOP
+        let bytecode = hex::decode("600035565b00").unwrap();
+        let cfg = ControlFlowGraph::build(&bytecode);
+
+        assert_eq!(cfg.blocks.len(), 2);
+        assert!(cfg.edges.is_empty());
+        assert_eq!(cfg.unresolved, vec![0]);
+        assert!(cfg
+            .to_dot()
+            .contains("block_0 -> unresolved [style=dashed];"));
+    }
+}
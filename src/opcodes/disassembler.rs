@@ -0,0 +1,266 @@
+use super::OpCode;
+use std::collections::BTreeSet;
+
+/// A decoded instruction with its immediate data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: OpCode,
+    /// PUSH data; shorter than the push size if the code ends early
+    pub immediate: Vec<u8>,
+}
+
+impl Instruction {
+    /// Width of the instruction in the code, immediate included
+    pub fn size(&self) -> usize {
+        1 + self.immediate.len()
+    }
+}
+
+/// Split bytecode into instructions, skipping over PUSH data
+pub fn decode(bytecode: &[u8]) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut pc = 0;
+
+    while pc < bytecode.len() {
+        let info = super::opcode_info(bytecode[pc]);
+        let end = (pc + 1 + info.push_size).min(bytecode.len());
+        let instruction = Instruction {
+            pc,
+            opcode: info.opcode.clone(),
+            immediate: bytecode[pc + 1..end].to_vec(),
+        };
+        pc += instruction.size();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+/// Offsets of JUMPDEST instructions, ignoring 0x5b bytes inside PUSH data
+pub fn jump_destinations(instructions: &[Instruction]) -> BTreeSet<usize> {
+    instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == OpCode::JUMPDEST)
+        .map(|instruction| instruction.pc)
+        .collect()
+}
+
+pub fn jump_label(pc: usize) -> String {
+    format!("L_0x{:x}", pc)
+}
+
+/// Where the PUSH at `index` sends the JUMP or JUMPI right after it, if any
+pub fn static_jump_target(instructions: &[Instruction], index: usize) -> Option<usize> {
+    let push = instructions.get(index)?;
+    push.opcode.push_size()?;
+    let next = instructions.get(index + 1)?;
+    if !matches!(next.opcode, OpCode::JUMP | OpCode::JUMPI) {
+        return None;
+    }
+
+    // Targets too wide for usize can never be valid destinations
+    let significant = push.immediate.iter().skip_while(|&&byte| byte == 0);
+    if significant.clone().count() > std::mem::size_of::<usize>() {
+        return Some(usize::MAX);
+    }
+    Some(significant.fold(0usize, |acc, &byte| (acc << 8) | byte as usize))
+}
+
+/// 4-byte selectors the code compares against in a function dispatcher, in
+/// order of appearance: a PUSH4 (or PUSH3 for selectors starting with a zero
+/// byte) feeding an EQ, optionally through a DUP or SWAP, with a JUMPI within
+/// the next two instructions
+pub fn function_selectors(instructions: &[Instruction]) -> Vec<[u8; 4]> {
+    let mut selectors = Vec::new();
+
+    for (index, push) in instructions.iter().enumerate() {
+        let complete = push.opcode.push_size() == Some(push.immediate.len());
+        if !matches!(push.opcode, OpCode::PUSH3 | OpCode::PUSH4) || !complete {
+            continue;
+        }
+
+        let following = &instructions[index + 1..];
+        let opcode_at = |i: usize| following.get(i).map(|instruction| &instruction.opcode);
+        let eq_at = match opcode_at(0) {
+            Some(OpCode::EQ) => 0,
+            Some(op) if is_dup_or_swap(op) && opcode_at(1) == Some(&OpCode::EQ) => 1,
+            _ => continue,
+        };
+        let jumps = following
+            .iter()
+            .skip(eq_at + 1)
+            .take(2)
+            .any(|instruction| instruction.opcode == OpCode::JUMPI);
+        if !jumps {
+            continue;
+        }
+
+        let mut selector = [0u8; 4];
+        selector[4 - push.immediate.len()..].copy_from_slice(&push.immediate);
+        if !selectors.contains(&selector) {
+            selectors.push(selector);
+        }
+    }
+
+    selectors
+}
+
+fn is_dup_or_swap(opcode: &OpCode) -> bool {
+    matches!(
+        opcode.to_byte(),
+        0x80..=0x9f // DUP1-DUP16, SWAP1-SWAP16
+    )
+}
+
+/// One line per instruction plus a label line before every JUMPDEST, e.g.
+///
+/// ```text
+/// L_0x2:
+/// 0002: JUMPDEST
+/// 0008: PUSH1 0x02 ; -> L_0x2
+/// 000a: JUMPI
+/// ```
+pub fn disassemble(bytecode: &[u8]) -> Vec<String> {
+    let instructions = decode(bytecode);
+    let destinations = jump_destinations(&instructions);
+    let mut lines = Vec::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        if destinations.contains(&instruction.pc) {
+            lines.push(format!("{}:", jump_label(instruction.pc)));
+        }
+        lines.push(format!(
+            "{:04x}: {}{}",
+            instruction.pc,
+            format_instruction(instruction),
+            jump_annotation(&instructions, index, &destinations)
+        ));
+    }
+
+    lines
+}
+
+/// Mnemonic followed by the PUSH data, if any
+pub fn format_instruction(instruction: &Instruction) -> String {
+    if instruction.opcode.push_size().is_some() {
+        format!(
+            "{:?} 0x{}",
+            instruction.opcode,
+            hex::encode(&instruction.immediate)
+        )
+    } else {
+        format!("{:?}", instruction.opcode)
+    }
+}
+
+/// ` ; -> L_0x..` comment for a PUSH feeding a JUMP/JUMPI, empty otherwise
+pub fn jump_annotation(
+    instructions: &[Instruction],
+    index: usize,
+    destinations: &BTreeSet<usize>,
+) -> String {
+    match static_jump_target(instructions, index) {
+        Some(target) if destinations.contains(&target) => {
+            format!(" ; -> {}", jump_label(target))
+        }
+        Some(_) => " ; -> invalid jump destination".to_string(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loop_jumps_are_labelled() {
+        // Count down from 3: the loop body starts at 0x2 and JUMPI returns to it
+        let bytecode = hex::decode("60035b600190038060025700").unwrap();
+
+        assert_eq!(
+            disassemble(&bytecode),
+            vec![
+                "0000: PUSH1 0x03",
+                "L_0x2:",
+                "0002: JUMPDEST",
+                "0003: PUSH1 0x01",
+                "0005: SWAP1",
+                "0006: SUB",
+                "0007: DUP1",
+                "0008: PUSH1 0x02 ; -> L_0x2",
+                "000a: JUMPI",
+                "000b: STOP",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jumpdest_inside_push_data_is_not_a_label() {
+        // PUSH1 4, JUMP, PUSH1 0x5b, STOP
+        let lines = disassemble(&hex::decode("600456605b00").unwrap());
+
+        assert!(lines.iter().all(|line| !line.starts_with("L_")));
+        assert_eq!(lines[0], "0000: PUSH1 0x04 ; -> invalid jump destination");
+    }
+
+    #[test]
+    fn test_dispatcher_selectors_are_extracted() {
+        // selector = calldata[0:4]; DUP1 PUSH4 a9059cbb EQ PUSH2 0x0023 JUMPI;
+        // PUSH4 70a08231 DUP2 EQ PUSH2 0x0024 JUMPI; DUP1 PUSH3 abcdef EQ
+        // PUSH2 0x0025 JUMPI; PUSH1 0x2a EQ PUSH2 0x0025 JUMPI; STOP
+        let bytecode = hex::decode(concat!(
+            "60003560e01c",
+            "8063a9059cbb1461002357",
+            "6370a08231811461002457",
+            "8062abcdef1461002557",
+            "602a1461002557",
+            "00"
+        ))
+        .unwrap();
+
+        let selectors = function_selectors(&decode(&bytecode));
+        assert_eq!(
+            selectors,
+            vec![
+                [0xa9, 0x05, 0x9c, 0xbb],
+                [0x70, 0xa0, 0x82, 0x31],
+                [0x00, 0xab, 0xcd, 0xef],
+            ]
+        );
+        assert_eq!(
+            crate::abi::known_signature(selectors[0]),
+            Some("transfer(address,uint256)")
+        );
+        assert_eq!(
+            crate::abi::known_signature(selectors[1]),
+            Some("balanceOf(address)")
+        );
+        assert_eq!(crate::abi::known_signature(selectors[2]), None);
+    }
+
+    #[test]
+    fn test_compiled_contract_selectors_are_found() {
+        let source = r#"
+            function add(a, b) {
+                return a + b;
+            }
+
+            function double(x) {
+                return x * 2;
+            }
+        "#;
+        let bytecode = crate::compiler::Compiler::new()
+            .with_contract(true)
+            .compile(source)
+            .unwrap();
+
+        assert_eq!(
+            function_selectors(&decode(&bytecode)),
+            vec![
+                crate::abi::selector("add(uint256,uint256)"),
+                crate::abi::selector("double(uint256)"),
+            ]
+        );
+    }
+}
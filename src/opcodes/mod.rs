@@ -1,7 +1,18 @@
-use crate::evm::EvmState;
-use ethereum_types::U256;
+use crate::evm::{
+    run_frame, EvmError, EvmState, INITCODE_WORD_GAS, MAX_CALL_DEPTH, MAX_INITCODE_SIZE,
+};
+use crate::types::Log;
+use ethereum_types::{Address, H256, U256};
 use sha3::{Digest, Keccak256};
 
+pub mod assembler;
+pub mod cfg;
+pub mod disassembler;
+pub mod table;
+pub mod validate;
+
+pub use table::{opcode_info, opcode_table, OpcodeInfo};
+
 // Helper function to decode bytes to a readable string
 fn decode_string_from_bytes(data: &[u8]) -> String {
     // Since the data is now correctly loaded from memory,
@@ -353,6 +364,167 @@ impl OpCode {
         }
     }
 
+    /// Encode an opcode back to its byte value (inverse of `from_byte`)
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            OpCode::STOP => 0x00,
+            OpCode::ADD => 0x01,
+            OpCode::MUL => 0x02,
+            OpCode::SUB => 0x03,
+            OpCode::DIV => 0x04,
+            OpCode::SDIV => 0x05,
+            OpCode::MOD => 0x06,
+            OpCode::SMOD => 0x07,
+            OpCode::ADDMOD => 0x08,
+            OpCode::MULMOD => 0x09,
+            OpCode::EXP => 0x0a,
+            OpCode::SIGNEXTEND => 0x0b,
+
+            OpCode::LT => 0x10,
+            OpCode::GT => 0x11,
+            OpCode::SLT => 0x12,
+            OpCode::SGT => 0x13,
+            OpCode::EQ => 0x14,
+            OpCode::ISZERO => 0x15,
+            OpCode::AND => 0x16,
+            OpCode::OR => 0x17,
+            OpCode::XOR => 0x18,
+            OpCode::NOT => 0x19,
+            OpCode::BYTE => 0x1a,
+            OpCode::SHL => 0x1b,
+            OpCode::SHR => 0x1c,
+            OpCode::SAR => 0x1d,
+
+            OpCode::SHA3 => 0x20,
+
+            OpCode::ADDRESS => 0x30,
+            OpCode::BALANCE => 0x31,
+            OpCode::ORIGIN => 0x32,
+            OpCode::CALLER => 0x33,
+            OpCode::CALLVALUE => 0x34,
+            OpCode::CALLDATALOAD => 0x35,
+            OpCode::CALLDATASIZE => 0x36,
+            OpCode::CALLDATACOPY => 0x37,
+            OpCode::CODESIZE => 0x38,
+            OpCode::CODECOPY => 0x39,
+            OpCode::GASPRICE => 0x3a,
+            OpCode::EXTCODESIZE => 0x3b,
+            OpCode::EXTCODECOPY => 0x3c,
+            OpCode::RETURNDATASIZE => 0x3d,
+            OpCode::RETURNDATACOPY => 0x3e,
+            OpCode::EXTCODEHASH => 0x3f,
+
+            OpCode::BLOCKHASH => 0x40,
+            OpCode::COINBASE => 0x41,
+            OpCode::TIMESTAMP => 0x42,
+            OpCode::NUMBER => 0x43,
+            OpCode::DIFFICULTY => 0x44,
+            OpCode::GASLIMIT => 0x45,
+            OpCode::CHAINID => 0x46,
+            OpCode::SELFBALANCE => 0x47,
+            OpCode::BASEFEE => 0x48,
+
+            OpCode::POP => 0x50,
+            OpCode::MLOAD => 0x51,
+            OpCode::MSTORE => 0x52,
+            OpCode::MSTORE8 => 0x53,
+            OpCode::SLOAD => 0x54,
+            OpCode::SSTORE => 0x55,
+            OpCode::JUMP => 0x56,
+            OpCode::JUMPI => 0x57,
+            OpCode::PC => 0x58,
+            OpCode::MSIZE => 0x59,
+            OpCode::GAS => 0x5a,
+            OpCode::JUMPDEST => 0x5b,
+
+            OpCode::PUSH1 => 0x60,
+            OpCode::PUSH2 => 0x61,
+            OpCode::PUSH3 => 0x62,
+            OpCode::PUSH4 => 0x63,
+            OpCode::PUSH5 => 0x64,
+            OpCode::PUSH6 => 0x65,
+            OpCode::PUSH7 => 0x66,
+            OpCode::PUSH8 => 0x67,
+            OpCode::PUSH9 => 0x68,
+            OpCode::PUSH10 => 0x69,
+            OpCode::PUSH11 => 0x6a,
+            OpCode::PUSH12 => 0x6b,
+            OpCode::PUSH13 => 0x6c,
+            OpCode::PUSH14 => 0x6d,
+            OpCode::PUSH15 => 0x6e,
+            OpCode::PUSH16 => 0x6f,
+            OpCode::PUSH17 => 0x70,
+            OpCode::PUSH18 => 0x71,
+            OpCode::PUSH19 => 0x72,
+            OpCode::PUSH20 => 0x73,
+            OpCode::PUSH21 => 0x74,
+            OpCode::PUSH22 => 0x75,
+            OpCode::PUSH23 => 0x76,
+            OpCode::PUSH24 => 0x77,
+            OpCode::PUSH25 => 0x78,
+            OpCode::PUSH26 => 0x79,
+            OpCode::PUSH27 => 0x7a,
+            OpCode::PUSH28 => 0x7b,
+            OpCode::PUSH29 => 0x7c,
+            OpCode::PUSH30 => 0x7d,
+            OpCode::PUSH31 => 0x7e,
+            OpCode::PUSH32 => 0x7f,
+
+            OpCode::DUP1 => 0x80,
+            OpCode::DUP2 => 0x81,
+            OpCode::DUP3 => 0x82,
+            OpCode::DUP4 => 0x83,
+            OpCode::DUP5 => 0x84,
+            OpCode::DUP6 => 0x85,
+            OpCode::DUP7 => 0x86,
+            OpCode::DUP8 => 0x87,
+            OpCode::DUP9 => 0x88,
+            OpCode::DUP10 => 0x89,
+            OpCode::DUP11 => 0x8a,
+            OpCode::DUP12 => 0x8b,
+            OpCode::DUP13 => 0x8c,
+            OpCode::DUP14 => 0x8d,
+            OpCode::DUP15 => 0x8e,
+            OpCode::DUP16 => 0x8f,
+
+            OpCode::SWAP1 => 0x90,
+            OpCode::SWAP2 => 0x91,
+            OpCode::SWAP3 => 0x92,
+            OpCode::SWAP4 => 0x93,
+            OpCode::SWAP5 => 0x94,
+            OpCode::SWAP6 => 0x95,
+            OpCode::SWAP7 => 0x96,
+            OpCode::SWAP8 => 0x97,
+            OpCode::SWAP9 => 0x98,
+            OpCode::SWAP10 => 0x99,
+            OpCode::SWAP11 => 0x9a,
+            OpCode::SWAP12 => 0x9b,
+            OpCode::SWAP13 => 0x9c,
+            OpCode::SWAP14 => 0x9d,
+            OpCode::SWAP15 => 0x9e,
+            OpCode::SWAP16 => 0x9f,
+
+            OpCode::LOG0 => 0xa0,
+            OpCode::LOG1 => 0xa1,
+            OpCode::LOG2 => 0xa2,
+            OpCode::LOG3 => 0xa3,
+            OpCode::LOG4 => 0xa4,
+
+            OpCode::CREATE => 0xf0,
+            OpCode::CALL => 0xf1,
+            OpCode::CALLCODE => 0xf2,
+            OpCode::RETURN => 0xf3,
+            OpCode::DELEGATECALL => 0xf4,
+            OpCode::CREATE2 => 0xf5,
+            OpCode::STATICCALL => 0xfa,
+            OpCode::REVERT => 0xfd,
+            OpCode::INVALID => 0xfe,
+            OpCode::SELFDESTRUCT => 0xff,
+
+            OpCode::UNKNOWN(byte) => *byte,
+        }
+    }
+
     pub fn gas_cost(&self) -> U256 {
         match self {
             OpCode::STOP => U256::from(0),
@@ -386,6 +558,7 @@ impl OpCode {
             | OpCode::CALLDATASIZE
             | OpCode::CODESIZE
             | OpCode::GASPRICE
+            | OpCode::RETURNDATASIZE
             | OpCode::COINBASE
             | OpCode::TIMESTAMP
             | OpCode::NUMBER
@@ -394,6 +567,10 @@ impl OpCode {
             | OpCode::CHAINID
             | OpCode::SELFBALANCE
             | OpCode::BASEFEE => U256::from(2),
+            OpCode::CALLDATALOAD
+            | OpCode::CALLDATACOPY
+            | OpCode::CODECOPY
+            | OpCode::RETURNDATACOPY => U256::from(3),
 
             OpCode::POP => U256::from(2),
             OpCode::MLOAD => U256::from(3),
@@ -479,11 +656,112 @@ impl OpCode {
 
             OpCode::RETURN => U256::from(0),
             OpCode::REVERT => U256::from(0),
+            OpCode::CALL => U256::from(700),
+            OpCode::CREATE | OpCode::CREATE2 => U256::from(32000),
 
             _ => U256::from(1), // Default gas cost
         }
     }
 
+    /// Number of stack items the opcode pops and pushes, as `(inputs, outputs)`
+    pub fn stack_effect(&self) -> (usize, usize) {
+        let byte = self.to_byte();
+        match byte {
+            // PUSH1..PUSH32
+            0x60..=0x7f => return (0, 1),
+            // DUPn reads the n-th item and pushes a copy
+            0x80..=0x8f => {
+                let n = (byte - 0x7f) as usize;
+                return (n, n + 1);
+            }
+            // SWAPn exchanges the top with the (n+1)-th item
+            0x90..=0x9f => {
+                let n = (byte - 0x8e) as usize;
+                return (n, n);
+            }
+            // LOGn pops offset, size and n topics
+            0xa0..=0xa4 => return ((byte - 0xa0) as usize + 2, 0),
+            _ => {}
+        }
+
+        match self {
+            OpCode::STOP | OpCode::JUMPDEST | OpCode::INVALID | OpCode::UNKNOWN(_) => (0, 0),
+
+            OpCode::ADD
+            | OpCode::MUL
+            | OpCode::SUB
+            | OpCode::DIV
+            | OpCode::SDIV
+            | OpCode::MOD
+            | OpCode::SMOD
+            | OpCode::EXP
+            | OpCode::SIGNEXTEND
+            | OpCode::LT
+            | OpCode::GT
+            | OpCode::SLT
+            | OpCode::SGT
+            | OpCode::EQ
+            | OpCode::AND
+            | OpCode::OR
+            | OpCode::XOR
+            | OpCode::BYTE
+            | OpCode::SHL
+            | OpCode::SHR
+            | OpCode::SAR
+            | OpCode::SHA3 => (2, 1),
+
+            OpCode::ADDMOD | OpCode::MULMOD => (3, 1),
+
+            OpCode::ISZERO
+            | OpCode::NOT
+            | OpCode::BALANCE
+            | OpCode::CALLDATALOAD
+            | OpCode::EXTCODESIZE
+            | OpCode::EXTCODEHASH
+            | OpCode::BLOCKHASH
+            | OpCode::MLOAD
+            | OpCode::SLOAD => (1, 1),
+
+            OpCode::ADDRESS
+            | OpCode::ORIGIN
+            | OpCode::CALLER
+            | OpCode::CALLVALUE
+            | OpCode::CALLDATASIZE
+            | OpCode::CODESIZE
+            | OpCode::GASPRICE
+            | OpCode::RETURNDATASIZE
+            | OpCode::COINBASE
+            | OpCode::TIMESTAMP
+            | OpCode::NUMBER
+            | OpCode::DIFFICULTY
+            | OpCode::GASLIMIT
+            | OpCode::CHAINID
+            | OpCode::SELFBALANCE
+            | OpCode::BASEFEE
+            | OpCode::PC
+            | OpCode::MSIZE
+            | OpCode::GAS => (0, 1),
+
+            OpCode::POP | OpCode::JUMP | OpCode::SELFDESTRUCT => (1, 0),
+            OpCode::MSTORE
+            | OpCode::MSTORE8
+            | OpCode::SSTORE
+            | OpCode::JUMPI
+            | OpCode::RETURN
+            | OpCode::REVERT => (2, 0),
+            OpCode::CALLDATACOPY | OpCode::CODECOPY | OpCode::RETURNDATACOPY => (3, 0),
+            OpCode::EXTCODECOPY => (4, 0),
+
+            OpCode::CREATE => (3, 1),
+            OpCode::CREATE2 => (4, 1),
+            OpCode::CALL | OpCode::CALLCODE => (7, 1),
+            OpCode::DELEGATECALL | OpCode::STATICCALL => (6, 1),
+
+            // PUSH, DUP, SWAP and LOG are handled by opcode range above
+            _ => unreachable!("stack effect for {:?} handled by range", self),
+        }
+    }
+
     pub fn push_size(&self) -> Option<usize> {
         match self {
             OpCode::PUSH1 => Some(1),
@@ -523,15 +801,293 @@ impl OpCode {
     }
 }
 
+/// Pop the operands of a LOGn instruction (offset, size, then topics in
+/// order), record the log on the state and return its data and topics
+fn record_log(state: &mut EvmState, topic_count: usize) -> Result<(Vec<u8>, Vec<U256>), EvmError> {
+    let offset = state.pop_stack()?;
+    let size = state.pop_stack()?;
+    let topics = (0..topic_count)
+        .map(|_| state.pop_stack())
+        .collect::<Result<Vec<_>, _>>()?;
+    let (offset, size) = state.memory_range(offset, size)?;
+    let data = state.memory_load(offset, size)?;
+
+    state.logs.push(Log {
+        address: state.address,
+        topics: topics
+            .iter()
+            .map(|topic| {
+                let mut bytes = [0u8; 32];
+                topic.to_big_endian(&mut bytes);
+                H256::from(bytes)
+            })
+            .collect(),
+        data: data.clone(),
+    });
+
+    Ok((data, topics))
+}
+
+/// Pop the offset and size for RETURN/REVERT and copy that range out of memory,
+/// refusing sizes above the state's return data cap before anything is allocated
+fn load_return_data(state: &mut EvmState) -> Result<Vec<u8>, EvmError> {
+    let offset = state.pop_stack()?;
+    let size = state.pop_stack()?;
+    if size > U256::from(state.max_return_data_size) {
+        return Err(EvmError::ReturnDataTooLarge {
+            size,
+            limit: state.max_return_data_size,
+        });
+    }
+    let (offset, size) = state.memory_range(offset, size)?;
+    state.memory_load(offset, size)
+}
+
+/// Gas a value-bearing CALL gives the callee on top of what the caller forwards
+pub const CALL_STIPEND: u64 = 2300;
+/// Gas CREATE2 charges per 32-byte word of init code it hashes
+const CREATE2_WORD_GAS: u64 = 6;
+
+/// CALL: move `value` to the target and run its code in a child frame, copy
+/// its output into memory and push 1 if it succeeded or 0 if it failed. The
+/// output is also kept as the frame's `last_return_data`. At most all but
+/// one 64th of the remaining gas is forwarded, so the caller can always act
+/// on the result, and a call carrying value adds `CALL_STIPEND` that the
+/// caller is never charged for and never gets back. A
+/// failing child, including one past `MAX_CALL_DEPTH` or one the caller
+/// cannot fund, never aborts the caller; its state changes, value transfer
+/// included, are reverted through the journal.
+#[inline(never)]
+fn call(state: &mut EvmState, bytecode: &[u8]) -> Result<(), EvmError> {
+    let gas = state.pop_stack()?;
+    let target = pop_address(state)?;
+    let value = state.pop_stack()?;
+    let args_offset = state.pop_stack()?;
+    let args_size = state.pop_stack()?;
+    let ret_offset = state.pop_stack()?;
+    let ret_size = state.pop_stack()?;
+
+    let (args_offset, args_size) = state.memory_range(args_offset, args_size)?;
+    let call_data = state.memory_load(args_offset, args_size)?;
+    let (ret_offset, ret_size) = state.memory_range(ret_offset, ret_size)?;
+    // A call that never starts leaves no return data behind
+    state.last_return_data.clear();
+    if state.depth >= MAX_CALL_DEPTH {
+        return state.push_stack(U256::zero());
+    }
+    if !value.is_zero()
+        && state
+            .accounts
+            .get(&state.address)
+            .is_none_or(|account| account.balance < value)
+    {
+        return state.push_stack(U256::zero());
+    }
+
+    // A call to itself runs the code currently executing
+    let code = if target == state.address {
+        bytecode.to_vec()
+    } else {
+        state
+            .accounts
+            .get(&target)
+            .map(|account| account.code.clone())
+            .unwrap_or_default()
+    };
+
+    let child_gas = gas.min(state.gas - state.gas / 64);
+    let stipend = if value.is_zero() {
+        U256::zero()
+    } else {
+        U256::from(CALL_STIPEND)
+    };
+    let mut child = state.child_frame(target, value, call_data, child_gas + stipend);
+    let snapshot = child.snapshot();
+    if !value.is_zero() {
+        child.account_mut(child.caller).balance -= value;
+        child.account_mut(target).balance += value;
+    }
+    // Each nesting level recurses through the interpreter, so make sure up to
+    // MAX_CALL_DEPTH of them fit on the stack
+    stacker::maybe_grow(64 * 1024, 1024 * 1024, || run_frame(&mut child, &code));
+    state.gas = state.gas - child_gas + child.gas.saturating_sub(stipend);
+
+    let output = std::mem::take(&mut child.return_data);
+    let copied = output.len().min(ret_size);
+    state.memory[ret_offset..ret_offset + copied].copy_from_slice(&output[..copied]);
+    state.last_return_data = output;
+
+    let success = child.error.is_none() && !child.reverted;
+    if success {
+        child.commit(snapshot);
+        state.refund += child.refund;
+    } else {
+        child.revert_to(snapshot);
+    }
+    state.return_from_child(child);
+    state.push_stack(U256::from(success as u8))
+}
+
+/// CREATE and CREATE2: run init code taken from memory, at most
+/// `MAX_INITCODE_SIZE` bytes of it, in a child frame and install what it
+/// returns as the code of a new account, pushing that account's address, or
+/// zero if the creation failed. CREATE derives the
+/// address from the creator and its nonce, CREATE2 from the creator, `salt`
+/// and the init code, both through the same functions as creation
+/// transactions and the `create2-address` command. The creator's nonce goes
+/// up either way, and all but one 64th of its gas is forwarded. A failed
+/// creation, or one whose address is already taken, reverts its changes
+/// through the journal and leaves the creator running. Past
+/// `MAX_CALL_DEPTH`, or without the balance for `value`, nothing runs and
+/// the nonce stays put.
+#[inline(never)]
+fn create(state: &mut EvmState, salted: bool) -> Result<(), EvmError> {
+    let value = state.pop_stack()?;
+    let offset = state.pop_stack()?;
+    let size = state.pop_stack()?;
+    let salt = if salted {
+        Some(H256(crate::utils::u256_to_bytes32(state.pop_stack()?)))
+    } else {
+        None
+    };
+
+    // Oversized init code stops the creator before any memory is touched,
+    // and the rest is paid for by the word (EIP-3860)
+    if size > U256::from(MAX_INITCODE_SIZE) {
+        return Err(EvmError::InitCodeTooLarge {
+            size,
+            limit: MAX_INITCODE_SIZE,
+        });
+    }
+    let (offset, size) = state.memory_range(offset, size)?;
+    let init_code = state.memory_load(offset, size)?;
+    let mut word_gas = INITCODE_WORD_GAS;
+    if salted {
+        // CREATE2 also hashes the init code to derive the address
+        word_gas += CREATE2_WORD_GAS;
+    }
+    state.consume_gas(U256::from(word_gas * size.div_ceil(32) as u64))?;
+    // A creation leaves return data behind only if its init code reverts
+    state.last_return_data.clear();
+    if state.depth >= MAX_CALL_DEPTH {
+        return state.push_stack(U256::zero());
+    }
+    if state
+        .accounts
+        .get(&state.address)
+        .map_or(U256::zero(), |account| account.balance)
+        < value
+    {
+        return state.push_stack(U256::zero());
+    }
+
+    let creator = state.address;
+    let nonce = state.account_mut(creator).nonce;
+    state.account_mut(creator).nonce = nonce + 1;
+    let address = match salt {
+        Some(salt) => crate::utils::create2_address(creator, salt, &init_code),
+        None => crate::utils::create_address(creator, nonce),
+    };
+    let child_gas = state.gas - state.gas / 64;
+    if state
+        .accounts
+        .get(&address)
+        .is_some_and(|account| !account.code.is_empty() || !account.nonce.is_zero())
+    {
+        // A collision uses up the gas the creation would have had
+        state.gas -= child_gas;
+        return state.push_stack(U256::zero());
+    }
+
+    let mut child = state.child_frame(address, value, Vec::new(), child_gas);
+    let snapshot = child.snapshot();
+    // New contracts start at nonce 1 so they are never empty (EIP-161)
+    child.account_mut(address).nonce = U256::one();
+    if !value.is_zero() {
+        child.account_mut(creator).balance -= value;
+        child.account_mut(address).balance += value;
+    }
+    stacker::maybe_grow(64 * 1024, 1024 * 1024, || run_frame(&mut child, &init_code));
+    state.gas = state.gas - child_gas + child.gas;
+
+    let success = child.error.is_none() && !child.reverted;
+    let output = std::mem::take(&mut child.return_data);
+    if success {
+        child.account_mut(address).code = output;
+        child.commit(snapshot);
+        state.refund += child.refund;
+    } else {
+        if child.reverted {
+            state.last_return_data = output;
+        }
+        child.revert_to(snapshot);
+    }
+    state.return_from_child(child);
+
+    if success {
+        state.push_stack(U256::from_big_endian(address.as_bytes()))
+    } else {
+        state.push_stack(U256::zero())
+    }
+}
+
+/// Pop a word and keep its low 20 bytes as an address
+fn pop_address(state: &mut EvmState) -> Result<Address, EvmError> {
+    let mut word = [0u8; 32];
+    state.pop_stack()?.to_big_endian(&mut word);
+    Ok(Address::from_slice(&word[12..]))
+}
+
+/// PUSH1-PUSH32: push the `size` bytes after the opcode and skip over them
+fn push_immediate(state: &mut EvmState, bytecode: &[u8], size: usize) -> Result<(), EvmError> {
+    if state.pc + size >= bytecode.len() {
+        return Err(EvmError::PushOutOfBounds);
+    }
+
+    let mut bytes = [0u8; 32]; // U256 is 32 bytes
+    let start_idx = 32 - size;
+    bytes[start_idx..].copy_from_slice(&bytecode[state.pc + 1..state.pc + 1 + size]);
+
+    let value = U256::from_big_endian(&bytes);
+    state.push_stack(value)?;
+    state.pc += size; // Skip the pushed bytes
+    Ok(())
+}
+
 pub fn execute_opcode(
     opcode: &OpCode,
     state: &mut EvmState,
     bytecode: &[u8],
-) -> Result<(), String> {
-    // Consume gas
-    let gas_cost = opcode.gas_cost();
-    state.consume_gas(gas_cost)?;
+) -> Result<(), EvmError> {
+    execute_instruction(opcode_info(opcode.to_byte()), state, bytecode)
+}
+
+/// `base ** exponent` modulo 2^256, by square-and-multiply over the bits of
+/// the exponent, so even a 256-bit exponent takes at most 256 rounds
+fn wrapping_pow(mut base: U256, exponent: U256) -> U256 {
+    let mut result = U256::one();
+    for bit in 0..exponent.bits() {
+        if exponent.bit(bit) {
+            result = result.overflowing_mul(base).0;
+        }
+        base = base.overflowing_mul(base).0;
+    }
+    result
+}
+
+/// Run the opcode described by a table entry, as the interpreter loop does
+pub fn execute_instruction(
+    info: &OpcodeInfo,
+    state: &mut EvmState,
+    bytecode: &[u8],
+) -> Result<(), EvmError> {
+    state.consume_gas(info.gas)?;
+
+    if info.push_size > 0 {
+        return push_immediate(state, bytecode, info.push_size);
+    }
 
+    let opcode = &info.opcode;
     match opcode {
         // Stop and Arithmetic Operations
         OpCode::STOP => {
@@ -567,8 +1123,8 @@ pub fn execute_opcode(
         }
 
         OpCode::MOD => {
-            let b = state.pop_stack()?; // divisor (second operand)
-            let a = state.pop_stack()?; // dividend (first operand)
+            let a = state.pop_stack()?;
+            let b = state.pop_stack()?;
             let result = if b.is_zero() { U256::zero() } else { a % b };
             state.push_stack(result)?;
         }
@@ -576,8 +1132,7 @@ pub fn execute_opcode(
         OpCode::EXP => {
             let a = state.pop_stack()?;
             let b = state.pop_stack()?;
-            let result = a.overflowing_pow(b).0;
-            state.push_stack(result)?;
+            state.push_stack(wrapping_pow(a, b))?;
         }
 
         // Comparison & Bitwise Logic Operations
@@ -639,10 +1194,33 @@ pub fn execute_opcode(
             state.push_stack(result)?;
         }
 
+        OpCode::SHL => {
+            let shift = state.pop_stack()?;
+            let value = state.pop_stack()?;
+            let result = if shift >= U256::from(256) {
+                U256::zero()
+            } else {
+                value << shift.as_usize()
+            };
+            state.push_stack(result)?;
+        }
+
+        OpCode::SHR => {
+            let shift = state.pop_stack()?;
+            let value = state.pop_stack()?;
+            let result = if shift >= U256::from(256) {
+                U256::zero()
+            } else {
+                value >> shift.as_usize()
+            };
+            state.push_stack(result)?;
+        }
+
         // SHA3
         OpCode::SHA3 => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
+            let offset = state.pop_stack()?;
+            let size = state.pop_stack()?;
+            let (offset, size) = state.memory_range(offset, size)?;
             let data = state.memory_load(offset, size)?;
             let mut hasher = Keccak256::new();
             hasher.update(&data);
@@ -657,15 +1235,90 @@ pub fn execute_opcode(
             state.push_stack(addr_u256)?;
         }
 
+        OpCode::ORIGIN => {
+            let origin_u256 = U256::from_big_endian(state.origin.as_bytes());
+            state.push_stack(origin_u256)?;
+        }
+
         OpCode::CALLER => {
             let caller_u256 = U256::from_big_endian(state.caller.as_bytes());
             state.push_stack(caller_u256)?;
         }
 
+        OpCode::BLOCKHASH => {
+            // No block history is available to executed code
+            state.pop_stack()?;
+            state.push_stack(U256::zero())?;
+        }
+
+        OpCode::COINBASE => {
+            let coinbase = U256::from_big_endian(state.block.coinbase.as_bytes());
+            state.push_stack(coinbase)?;
+        }
+
+        OpCode::TIMESTAMP => state.push_stack(state.block.timestamp)?,
+        OpCode::NUMBER => state.push_stack(state.block.number)?,
+        OpCode::DIFFICULTY => state.push_stack(state.block.difficulty)?,
+        OpCode::GASLIMIT => state.push_stack(state.block.gas_limit)?,
+        OpCode::CHAINID => state.push_stack(state.block.chain_id)?,
+        OpCode::BASEFEE => state.push_stack(state.block.base_fee)?,
+
         OpCode::CALLVALUE => {
             state.push_stack(state.value)?;
         }
 
+        OpCode::GASPRICE => state.push_stack(state.gas_price)?,
+
+        OpCode::BALANCE => {
+            let address = pop_address(state)?;
+            let balance = state
+                .existing_account(&address)
+                .map(|account| account.balance)
+                .unwrap_or_default();
+            state.push_stack(balance)?;
+        }
+
+        OpCode::EXTCODESIZE => {
+            let address = pop_address(state)?;
+            let size = state
+                .existing_account(&address)
+                .map(|account| account.code.len())
+                .unwrap_or_default();
+            state.push_stack(U256::from(size))?;
+        }
+
+        // Zero for an account that does not exist or is empty (EIP-1052),
+        // otherwise the hash of its code, empty code included
+        OpCode::EXTCODEHASH => {
+            let address = pop_address(state)?;
+            let hash = match state.existing_account(&address) {
+                Some(account) => U256::from_big_endian(&Keccak256::digest(&account.code)),
+                None => U256::zero(),
+            };
+            state.push_stack(hash)?;
+        }
+
+        OpCode::SELFBALANCE => {
+            let balance = state
+                .accounts
+                .get(&state.address)
+                .map(|account| account.balance)
+                .unwrap_or_default();
+            state.push_stack(balance)?;
+        }
+
+        OpCode::CALLDATALOAD => {
+            // Reads past the end of calldata are zero-padded
+            let offset = state.pop_stack()?;
+            let mut word = [0u8; 32];
+            if offset < U256::from(state.call_data.len()) {
+                let start = offset.as_usize();
+                let end = (start + 32).min(state.call_data.len());
+                word[..end - start].copy_from_slice(&state.call_data[start..end]);
+            }
+            state.push_stack(U256::from_big_endian(&word))?;
+        }
+
         OpCode::CALLDATASIZE => {
             state.push_stack(U256::from(state.call_data.len()))?;
         }
@@ -674,13 +1327,57 @@ pub fn execute_opcode(
             state.push_stack(U256::from(bytecode.len()))?;
         }
 
+        OpCode::CALLDATACOPY => {
+            let dest_offset = state.pop_stack()?;
+            let offset = state.pop_stack()?;
+            let size = state.pop_stack()?;
+
+            // Bytes past the end of the call data are copied as zeros
+            let call_data = std::mem::take(&mut state.call_data);
+            let copied = state.memory_copy(dest_offset, &call_data, offset, size);
+            state.call_data = call_data;
+            copied?;
+        }
+
+        OpCode::CODECOPY => {
+            let dest_offset = state.pop_stack()?;
+            let offset = state.pop_stack()?;
+            let size = state.pop_stack()?;
+
+            // Bytes past the end of the code are copied as zeros
+            state.memory_copy(dest_offset, bytecode, offset, size)?;
+        }
+
+        OpCode::RETURNDATASIZE => {
+            state.push_stack(U256::from(state.last_return_data.len()))?;
+        }
+
+        OpCode::RETURNDATACOPY => {
+            let dest_offset = state.pop_stack()?;
+            let offset = state.pop_stack()?;
+            let size = state.pop_stack()?;
+
+            // Unlike the other copies, reading past the end is an error
+            let end = offset
+                .checked_add(size)
+                .ok_or(EvmError::ReturnDataOutOfBounds)?;
+            if end > U256::from(state.last_return_data.len()) {
+                return Err(EvmError::ReturnDataOutOfBounds);
+            }
+            let return_data = std::mem::take(&mut state.last_return_data);
+            let copied = state.memory_copy(dest_offset, &return_data, offset, size);
+            state.last_return_data = return_data;
+            copied?;
+        }
+
         // Stack, Memory, Storage and Flow Operations
         OpCode::POP => {
             state.pop_stack()?;
         }
 
         OpCode::MLOAD => {
-            let offset = state.pop_stack()?.as_usize();
+            let offset = state.pop_stack()?;
+            let (offset, _) = state.memory_range(offset, U256::from(32))?;
             let data = state.memory_load(offset, 32)?;
             let mut bytes = [0u8; 32];
             bytes[..data.len().min(32)].copy_from_slice(&data[..data.len().min(32)]);
@@ -689,7 +1386,8 @@ pub fn execute_opcode(
         }
 
         OpCode::MSTORE => {
-            let offset = state.pop_stack()?.as_usize();
+            let offset = state.pop_stack()?;
+            let (offset, _) = state.memory_range(offset, U256::from(32))?;
             let value = state.pop_stack()?;
             let mut bytes = [0u8; 32];
             value.to_big_endian(&mut bytes);
@@ -697,7 +1395,8 @@ pub fn execute_opcode(
         }
 
         OpCode::MSTORE8 => {
-            let offset = state.pop_stack()?.as_usize();
+            let offset = state.pop_stack()?;
+            let (offset, _) = state.memory_range(offset, U256::one())?;
             let value = state.pop_stack()?;
             let byte = (value.low_u64() & 0xFF) as u8;
             state.memory_store(offset, &[byte])?;
@@ -719,7 +1418,7 @@ pub fn execute_opcode(
             let dest = state.pop_stack()?.as_usize();
             if dest >= bytecode.len() || bytecode[dest] != 0x5b {
                 // 0x5b is JUMPDEST
-                return Err("Invalid jump destination".to_string());
+                return Err(EvmError::InvalidJump);
             }
             state.pc = dest;
         }
@@ -730,7 +1429,7 @@ pub fn execute_opcode(
             if !condition.is_zero() {
                 if dest >= bytecode.len() || bytecode[dest] != 0x5b {
                     // 0x5b is JUMPDEST
-                    return Err("Invalid jump destination".to_string());
+                    return Err(EvmError::InvalidJump);
                 }
                 state.pc = dest;
             } else {
@@ -743,9 +1442,11 @@ pub fn execute_opcode(
         }
 
         OpCode::MSIZE => {
-            state.push_stack(U256::from(state.memory.len()))?;
+            state.push_stack(U256::from(state.memory_words() * 32))?;
         }
 
+        // The opcode's own cost was taken above, so this is the gas left
+        // after GAS itself, as the spec requires
         OpCode::GAS => {
             state.push_stack(state.gas)?;
         }
@@ -754,22 +1455,6 @@ pub fn execute_opcode(
             // JUMPDEST is a no-op, just marks valid jump destinations
         }
 
-        // Push Operations
-        push_op if push_op.push_size().is_some() => {
-            let size = push_op.push_size().unwrap();
-            if state.pc + size >= bytecode.len() {
-                return Err("Push instruction exceeds bytecode length".to_string());
-            }
-
-            let mut bytes = vec![0u8; 32]; // U256 is 32 bytes
-            let start_idx = 32 - size;
-            bytes[start_idx..].copy_from_slice(&bytecode[state.pc + 1..state.pc + 1 + size]);
-
-            let value = U256::from_big_endian(&bytes);
-            state.push_stack(value)?;
-            state.pc += size; // Skip the pushed bytes
-        }
-
         // DUP Operations
         OpCode::DUP1 => state.dup_stack(1)?,
         OpCode::DUP2 => state.dup_stack(2)?,
@@ -808,92 +1493,73 @@ pub fn execute_opcode(
 
         // System Operations
         OpCode::RETURN => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            state.return_data = state.memory_load(offset, size)?;
+            state.return_data = load_return_data(state)?;
             state.halted = true;
         }
 
         OpCode::REVERT => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            state.return_data = state.memory_load(offset, size)?;
+            state.return_data = load_return_data(state)?;
             state.reverted = true;
         }
 
+        OpCode::CALL => call(state, bytecode)?,
+        OpCode::CREATE => create(state, false)?,
+        OpCode::CREATE2 => create(state, true)?,
+
         // Log Operations
         OpCode::LOG0 => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            let data = state.memory_load(offset, size)?;
+            let (data, _) = record_log(state, 0)?;
 
             // Decode and display the string content
             let message = decode_string_from_bytes(&data);
-            println!("console.log: {}", message);
+            state.console.write(&format!("console.log: {}", message));
         }
 
         OpCode::LOG1 => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            let topic1 = state.pop_stack()?;
-            let data = state.memory_load(offset, size)?;
+            let (data, topics) = record_log(state, 1)?;
 
             // Decode the string content
             let message = decode_string_from_bytes(&data);
 
             // Different output based on topic (1=warn, 2=error)
-            match topic1.as_u64() {
-                1 => println!("console.warn: {}", message),
-                2 => println!("console.error: {}", message),
-                _ => println!("console (topic {}): {}", topic1, message),
-            }
+            let line = if topics[0] == U256::from(1) {
+                format!("console.warn: {}", message)
+            } else if topics[0] == U256::from(2) {
+                format!("console.error: {}", message)
+            } else {
+                format!("console (topic {}): {}", topics[0], message)
+            };
+            state.console.write(&line);
         }
 
-        OpCode::LOG2 => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            let topic2 = state.pop_stack()?;
-            let topic1 = state.pop_stack()?;
-            let data = state.memory_load(offset, size)?;
+        OpCode::LOG2 | OpCode::LOG3 | OpCode::LOG4 => {
+            let topic_count = match opcode {
+                OpCode::LOG2 => 2,
+                OpCode::LOG3 => 3,
+                _ => 4,
+            };
+            let (data, topics) = record_log(state, topic_count)?;
 
             let message = decode_string_from_bytes(&data);
-            println!("LOG2 (topics: {}, {}): {}", topic1, topic2, message);
+            state.console.write(&format!(
+                "LOG{} (topics: {}): {}",
+                topic_count,
+                topics
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                message
+            ));
         }
 
-        OpCode::LOG3 => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            let topic3 = state.pop_stack()?;
-            let topic2 = state.pop_stack()?;
-            let topic1 = state.pop_stack()?;
-            let data = state.memory_load(offset, size)?;
-
-            let message = decode_string_from_bytes(&data);
-            println!(
-                "LOG3 (topics: {}, {}, {}): {}",
-                topic1, topic2, topic3, message
-            );
-        }
-
-        OpCode::LOG4 => {
-            let offset = state.pop_stack()?.as_usize();
-            let size = state.pop_stack()?.as_usize();
-            let topic4 = state.pop_stack()?;
-            let topic3 = state.pop_stack()?;
-            let topic2 = state.pop_stack()?;
-            let topic1 = state.pop_stack()?;
-            let data = state.memory_load(offset, size)?;
-
-            let message = decode_string_from_bytes(&data);
-            println!(
-                "LOG4 (topics: {}, {}, {}, {}): {}",
-                topic1, topic2, topic3, topic4, message
-            );
+        OpCode::INVALID | OpCode::UNKNOWN(_) => {
+            return Err(EvmError::InvalidOpcode(opcode.to_byte()));
         }
 
         // Unimplemented opcodes
         _ => {
-            return Err(format!("Unimplemented opcode: {:?}", opcode));
+            return Err(EvmError::UnimplementedOpcode(opcode.clone()));
         }
     }
 
@@ -0,0 +1,91 @@
+use super::OpCode;
+use ethereum_types::U256;
+use std::sync::OnceLock;
+
+/// Everything the interpreter loop needs to know about an opcode byte,
+/// computed once so running an instruction is a single array index
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodeInfo {
+    pub opcode: OpCode,
+    /// Base gas charged before the opcode runs
+    pub gas: U256,
+    /// Bytes of immediate data, 0 for everything but PUSH1-PUSH32
+    pub push_size: usize,
+    /// JUMP and JUMPI set the program counter themselves
+    pub sets_pc: bool,
+}
+
+impl OpcodeInfo {
+    fn new(byte: u8) -> Self {
+        let opcode = OpCode::from_byte(byte);
+        Self {
+            gas: opcode.gas_cost(),
+            push_size: opcode.push_size().unwrap_or(0),
+            sets_pc: matches!(opcode, OpCode::JUMP | OpCode::JUMPI),
+            opcode,
+        }
+    }
+}
+
+/// Table of all 256 opcode bytes, built on first use
+pub fn opcode_table() -> &'static [OpcodeInfo; 256] {
+    static TABLE: OnceLock<[OpcodeInfo; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|byte| OpcodeInfo::new(byte as u8)))
+}
+
+pub fn opcode_info(byte: u8) -> &'static OpcodeInfo {
+    &opcode_table()[byte as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::EvmExecutor;
+
+    #[test]
+    fn test_table_matches_opcode_methods() {
+        for byte in 0..=255u8 {
+            let info = opcode_info(byte);
+            let opcode = OpCode::from_byte(byte);
+
+            assert_eq!(info.opcode, opcode, "byte 0x{:02x}", byte);
+            assert_eq!(info.gas, opcode.gas_cost(), "gas of {:?}", opcode);
+            assert_eq!(Some(info.push_size).filter(|&n| n > 0), opcode.push_size());
+        }
+        assert!(opcode_info(0x56).sets_pc && opcode_info(0x57).sets_pc);
+        assert!(!opcode_info(0x00).sets_pc);
+    }
+
+    #[test]
+    fn test_example_programs_give_identical_results() {
+        // Gas used by each program under the match-based dispatch the table
+        // replaced
+        let programs = [
+            ("6001600201", 9),                                   // simple-add
+            ("6002600302", 11),                                  // simple-mul
+            ("6001600055600054", 5209),                          // storage
+            ("6103e85b600190038060035700", 26003),               // arithmetic loop
+            ("6101005b808055805450600190038060035700", 1340675), // storage loop
+            (
+                "3860006000396120005b6020900380518161200001528060095700",
+                11278 + 2048,
+            ), // memory copy, plus expanding memory to 512 words
+        ];
+
+        for (program, gas_used) in programs {
+            let bytecode = hex::decode(program).unwrap();
+            let mut executor = EvmExecutor::new(100_000_000);
+            let result = executor.execute(&bytecode, 0, false).unwrap();
+            assert_eq!(result.gas_used, U256::from(gas_used), "{}", program);
+
+            // The debugger steps through the same table and must agree
+            let mut debugger = executor.debugger(&bytecode, &Default::default());
+            debugger.run_to_end();
+            let stepped = debugger.result();
+            assert_eq!(stepped.status, result.status, "{}", program);
+            assert_eq!(stepped.gas_used, result.gas_used, "{}", program);
+            assert_eq!(stepped.memory, result.memory, "{}", program);
+            assert_eq!(stepped.storage, result.storage, "{}", program);
+        }
+    }
+}
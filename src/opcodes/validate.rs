@@ -0,0 +1,147 @@
+use super::disassembler::decode;
+use super::OpCode;
+use std::fmt;
+
+/// Problem found in bytecode by `validate_bytecode`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub pc: usize,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The code ends before all of a PUSH's immediate bytes
+    TruncatedPush { expected: usize, found: usize },
+    /// Instructions after STOP, RETURN, REVERT or JUMP that no JUMPDEST leads to
+    UnreachableCode,
+    /// A byte that is not a known opcode
+    UnknownOpcode(u8),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:04x}: ", self.pc)?;
+        match &self.kind {
+            IssueKind::TruncatedPush { expected, found } => write!(
+                f,
+                "truncated PUSH: expected {} immediate bytes, found {}",
+                expected, found
+            ),
+            IssueKind::UnreachableCode => write!(f, "unreachable code"),
+            IssueKind::UnknownOpcode(byte) => write!(f, "unknown opcode 0x{:02x}", byte),
+        }
+    }
+}
+
+fn ends_execution(opcode: &OpCode) -> bool {
+    matches!(
+        opcode,
+        OpCode::STOP | OpCode::RETURN | OpCode::REVERT | OpCode::JUMP
+    )
+}
+
+/// Check `bytecode` for truncated PUSH immediates, unknown opcodes and
+/// unreachable code, reported in code order. Unreachable code is reported
+/// once per run, at its first instruction.
+pub fn validate_bytecode(bytecode: &[u8]) -> Result<(), Vec<Issue>> {
+    let mut issues = Vec::new();
+    let mut reachable = true;
+    let mut reported = false;
+
+    for instruction in decode(bytecode) {
+        let pc = instruction.pc;
+
+        if instruction.opcode == OpCode::JUMPDEST {
+            reachable = true;
+        } else if !reachable && !reported {
+            issues.push(Issue {
+                pc,
+                kind: IssueKind::UnreachableCode,
+            });
+            reported = true;
+        }
+
+        if let OpCode::UNKNOWN(byte) = instruction.opcode {
+            issues.push(Issue {
+                pc,
+                kind: IssueKind::UnknownOpcode(byte),
+            });
+        }
+        if let Some(expected) = instruction.opcode.push_size() {
+            if instruction.immediate.len() < expected {
+                issues.push(Issue {
+                    pc,
+                    kind: IssueKind::TruncatedPush {
+                        expected,
+                        found: instruction.immediate.len(),
+                    },
+                });
+            }
+        }
+
+        if reachable && ends_execution(&instruction.opcode) {
+            reachable = false;
+            reported = false;
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(issues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_bytecode_passes() {
+        // PUSH1 1, PUSH1 6, JUMPI, STOP, JUMPDEST, PUSH1 0, PUSH1 0, RETURN
+        let bytecode = hex::decode("6001600657005b60006000f3").unwrap();
+        assert_eq!(validate_bytecode(&bytecode), Ok(()));
+    }
+
+    #[test]
+    fn test_truncated_push_is_reported() {
+        // PUSH1 1, PUSH2 with one byte left
+        let issues = validate_bytecode(&hex::decode("60016101").unwrap()).unwrap_err();
+
+        assert_eq!(
+            issues,
+            vec![Issue {
+                pc: 2,
+                kind: IssueKind::TruncatedPush {
+                    expected: 2,
+                    found: 1
+                },
+            }]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "0x0002: truncated PUSH: expected 2 immediate bytes, found 1"
+        );
+    }
+
+    #[test]
+    fn test_unreachable_and_unknown_opcodes_are_reported() {
+        // STOP, PUSH1 1, STOP, 0x0c, JUMPDEST, STOP
+        let issues = validate_bytecode(&hex::decode("006001000c5b00").unwrap()).unwrap_err();
+
+        assert_eq!(
+            issues,
+            vec![
+                Issue {
+                    pc: 1,
+                    kind: IssueKind::UnreachableCode
+                },
+                Issue {
+                    pc: 4,
+                    kind: IssueKind::UnknownOpcode(0x0c)
+                },
+            ]
+        );
+    }
+}
@@ -5,7 +5,7 @@ use std::collections::HashMap;
 pub type Bytes = Vec<u8>;
 pub type Word = U256;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub balance: U256,
     pub nonce: U256,
@@ -24,6 +24,14 @@ impl Default for Account {
     }
 }
 
+impl Account {
+    /// No balance, nonce or code (EIP-161). An empty account reads the same
+    /// as one that does not exist, and is removed when a transaction ends.
+    pub fn is_empty(&self) -> bool {
+        self.balance.is_zero() && self.nonce.is_zero() && self.code.is_empty()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Transaction {
     pub from: Address,
@@ -70,9 +78,19 @@ pub struct ExecutionResult {
     pub status: ExecutionStatus,
     pub gas_used: U256,
     pub gas_remaining: U256,
+    /// Refund already taken off `gas_used`, capped at a fifth of the gas spent
+    pub refund: U256,
+    /// Refund earned before the cap was applied
+    pub raw_refund: U256,
     pub return_data: Bytes,
     pub logs: Vec<Log>,
     pub state_changes: HashMap<Address, Account>,
+    /// Memory as it was when execution stopped
+    pub memory: Bytes,
+    /// Storage of the executed code when execution stopped
+    pub storage: HashMap<Word, Word>,
+    /// Address of the contract a successful creation deployed
+    pub created_address: Option<Address>,
 }
 
 impl Default for ExecutionResult {
@@ -81,9 +99,40 @@ impl Default for ExecutionResult {
             status: ExecutionStatus::Success,
             gas_used: U256::zero(),
             gas_remaining: U256::zero(),
+            refund: U256::zero(),
+            raw_refund: U256::zero(),
             return_data: Vec::new(),
             logs: Vec::new(),
             state_changes: HashMap::new(),
+            memory: Vec::new(),
+            storage: HashMap::new(),
+            created_address: None,
         }
     }
 }
+
+impl ExecutionResult {
+    /// Hex view of memory, 16 bytes per row prefixed with the row offset
+    pub fn memory_dump(&self) -> Vec<String> {
+        self.memory
+            .chunks(16)
+            .enumerate()
+            .map(|(row, bytes)| {
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("{:04x}: {}", row * 16, hex.join(" "))
+            })
+            .collect()
+    }
+
+    /// Storage slots holding a non-zero value, sorted by slot
+    pub fn storage_dump(&self) -> Vec<(Word, Word)> {
+        let mut slots: Vec<_> = self
+            .storage
+            .iter()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(slot, value)| (*slot, *value))
+            .collect();
+        slots.sort();
+        slots
+    }
+}
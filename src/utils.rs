@@ -1,4 +1,5 @@
-use ethereum_types::U256;
+use ethereum_types::{Address, H256, U256};
+use sha3::{Digest, Keccak256};
 
 /// Convert bytes to U256, padding with zeros if necessary
 pub fn bytes_to_u256(bytes: &[u8]) -> U256 {
@@ -46,6 +47,34 @@ pub fn format_hex_bytes(bytes: &[u8]) -> String {
     format!("0x{}", hex::encode(bytes))
 }
 
+/// Derive the address of a contract created by `deployer` with `nonce`, for
+/// creation transactions and the CREATE opcode alike:
+/// `keccak256(deployer ++ nonce as a 32-byte word)[12..]`
+pub fn create_address(deployer: Address, nonce: U256) -> Address {
+    let mut hasher = Keccak256::new();
+    hasher.update(deployer.as_bytes());
+    hasher.update(u256_to_bytes32(nonce));
+    Address::from_slice(&hasher.finalize()[12..])
+}
+
+/// Derive a CREATE2 contract address as defined by EIP-1014:
+/// `keccak256(0xff ++ deployer ++ salt ++ keccak256(init_code))[12..]`
+pub fn create2_address(deployer: Address, salt: H256, init_code: &[u8]) -> Address {
+    let mut hasher = Keccak256::new();
+    hasher.update([0xff]);
+    hasher.update(deployer.as_bytes());
+    hasher.update(salt.as_bytes());
+    hasher.update(Keccak256::digest(init_code));
+    Address::from_slice(&hasher.finalize()[12..])
+}
+
+/// Derive the Ethereum address of a secp256k1 public key:
+/// the last 20 bytes of `keccak256(uncompressed_key[1..])`
+pub fn public_key_to_address(public_key: &secp256k1::PublicKey) -> Address {
+    let uncompressed = public_key.serialize_uncompressed();
+    Address::from_slice(&Keccak256::digest(&uncompressed[1..])[12..])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +114,28 @@ mod tests {
         let bytes = vec![0x12, 0x34, 0x56];
         assert_eq!(format_hex_bytes(&bytes), "0x123456");
     }
+
+    #[test]
+    fn test_create2_address_eip1014_vectors() {
+        // Examples 0, 1 and 5 from EIP-1014
+        let zero = Address::zero();
+        let deadbeef: Address = "0xdeadbeef00000000000000000000000000000000"
+            .parse()
+            .unwrap();
+
+        let expected: Address = "0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"
+            .parse()
+            .unwrap();
+        assert_eq!(create2_address(zero, H256::zero(), &[0x00]), expected);
+
+        let expected: Address = "0xb928f69bb1d91cd65274e3c79d8986362984fda3"
+            .parse()
+            .unwrap();
+        assert_eq!(create2_address(deadbeef, H256::zero(), &[0x00]), expected);
+
+        let expected: Address = "0xe33c0c7f7df4809055c3eba6c09cfe4baf1bd9e0"
+            .parse()
+            .unwrap();
+        assert_eq!(create2_address(zero, H256::zero(), &[]), expected);
+    }
 }
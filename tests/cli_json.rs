@@ -0,0 +1,100 @@
+//! `--json` output of the CLI, checked by running the built binary
+
+use serde_json::Value;
+use std::process::Command;
+
+fn run_json(args: &[&str]) -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_abby_evm"))
+        .arg("--json")
+        .args(args)
+        .output()
+        .expect("failed to run abby_evm");
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{}: {}", e, stdout))
+}
+
+#[test]
+fn execute_json_reports_result_fields() {
+    // PUSH1 1, PUSH1 2, ADD
+    let result = run_json(&["execute", "--bytecode", "6001600201"]);
+
+    assert_eq!(result["status"], "success");
+    assert_eq!(result["gas_used"], 9);
+    assert_eq!(result["gas_remaining"], 1_000_000 - 9);
+    assert_eq!(result["return_data"], "0x");
+    assert_eq!(result["logs"], Value::Array(vec![]));
+}
+
+#[test]
+fn execute_json_keeps_console_output_inside_the_object() {
+    // MSTORE8 "h" at 0 and "i" at 1, then LOG0 over those two bytes
+    let result = run_json(&["execute", "--bytecode", "6068600053606960015360026000a000"]);
+
+    assert_eq!(result["status"], "success");
+    assert_eq!(result["console"], serde_json::json!(["console.log: hi"]));
+    assert_eq!(result["logs"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn analyze_json_reports_disassembly_and_gas() {
+    let analysis = run_json(&["analyze", "--bytecode", "6001600201"]);
+
+    assert_eq!(analysis["length"], 5);
+    let disassembly = analysis["disassembly"].as_array().unwrap();
+    let opcodes: Vec<_> = disassembly.iter().map(|i| &i["opcode"]).collect();
+    assert_eq!(opcodes, ["PUSH1", "PUSH1", "ADD"]);
+    assert_eq!(disassembly[1]["pc"], 2);
+    assert_eq!(disassembly[1]["immediate"], "0x02");
+    assert_eq!(analysis["total_gas"], 9);
+    assert_eq!(analysis["gas_by_opcode"][0]["opcode"], "PUSH1");
+    assert_eq!(analysis["gas_by_opcode"][0]["count"], 2);
+}
+
+#[test]
+fn execute_json_passes_calldata_to_the_code() {
+    // CALLDATALOAD(0), MSTORE at 0, RETURN 32 bytes
+    let word = format!("{:064x}", 0x2a);
+    let result = run_json(&[
+        "execute",
+        "--bytecode",
+        "60003560005260206000f3",
+        "--calldata",
+        &word,
+    ]);
+
+    assert_eq!(result["status"], "success");
+    assert_eq!(result["return_data"], format!("0x{}", word));
+}
+
+#[test]
+fn bytecode_can_be_piped_through_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    for command in ["execute", "analyze"] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_abby_evm"))
+            .args(["--json", command, "--file", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to run abby_evm");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"  0x6001600201\n")
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+
+        let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+        if command == "execute" {
+            assert_eq!(json["status"], "success");
+            assert_eq!(json["gas_used"], 9);
+        } else {
+            assert_eq!(json["length"], 5);
+        }
+    }
+}
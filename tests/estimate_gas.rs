@@ -0,0 +1,48 @@
+//! `estimate-gas` subcommand, checked by running the built binary
+
+use std::process::{Command, Output};
+
+fn estimate_gas(bytecode: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_abby_evm"))
+        .args(["estimate-gas", "--bytecode", bytecode])
+        .output()
+        .expect("failed to run abby_evm")
+}
+
+#[test]
+fn storage_write_reports_only_gas_used() {
+    // PUSH1 0x2a, PUSH1 0, SSTORE
+    let output = estimate_gas("602a600055");
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let gas: u64 = stdout
+        .trim()
+        .parse()
+        .expect("output is just the gas figure");
+    // Two pushes plus a fresh storage write
+    assert!(gas > 6 && gas < 100_000, "implausible gas {}", gas);
+}
+
+#[test]
+fn logging_program_still_prints_only_gas_used() {
+    // MSTORE8 "h" at 0 and "i" at 1, then LOG0 over those two bytes
+    let output = estimate_gas("6068600053606960015360026000a000");
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let gas: u64 = stdout
+        .trim()
+        .parse()
+        .unwrap_or_else(|_| panic!("output is not just the gas figure: {:?}", stdout));
+    assert!(gas > 0);
+}
+
+#[test]
+fn reverting_execution_exits_non_zero() {
+    // PUSH1 0, PUSH1 0, REVERT
+    let output = estimate_gas("60006000fd");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("reverted"));
+}
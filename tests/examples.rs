@@ -0,0 +1,47 @@
+//! Examples registry in `examples/`, checked by running the built binary
+
+use serde_json::Value;
+use std::process::Command;
+
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_abby_evm"))
+        .args(args)
+        .output()
+        .expect("failed to run abby_evm");
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn examples_list_reads_the_manifest() {
+    let listing = run(&["examples", "--list"]);
+
+    for name in [
+        "simple-add",
+        "simple-mul",
+        "storage",
+        "loop",
+        "counter",
+        "fibonacci",
+    ] {
+        assert!(
+            listing.contains(&format!("--example {}", name)),
+            "{} missing from:\n{}",
+            name,
+            listing
+        );
+    }
+}
+
+#[test]
+fn abbyscript_example_runs_end_to_end() {
+    let stdout = run(&["--json", "execute", "--example", "fibonacci"]);
+    let result: Value = serde_json::from_str(&stdout).unwrap();
+
+    assert_eq!(result["status"], "success");
+    assert_eq!(
+        result["return_data"],
+        format!("0x{:064x}", 55),
+        "fib(10) is 55"
+    );
+}
@@ -0,0 +1,193 @@
+//! Regression corpus: small bytecode and AbbyScript programs, each tied to a
+//! bug it exposed.
+//!
+//! Cases for bugs that are still open carry `known_failure: true`. The runner
+//! tolerates those failing, but reports an error once one of them starts to
+//! pass, so fixing a bug means flipping its marker and the case stays locked in.
+
+use abby_evm::compiler::Compiler;
+use abby_evm::evm::EvmExecutor;
+use abby_evm::opcodes::OpCode;
+use abby_evm::types::ExecutionStatus;
+use ethereum_types::U256;
+use std::panic::{self, AssertUnwindSafe};
+
+const GAS_LIMIT: u64 = 1_000_000;
+
+enum Source {
+    /// Hex-encoded runtime bytecode
+    Bytecode(&'static str),
+    /// A top-level AbbyScript program
+    AbbyScript(&'static str),
+}
+
+enum Expect {
+    /// Execution succeeds and returns this value as a 32-byte word
+    Returns(u64),
+    /// Execution succeeds, whatever it returns
+    Succeeds,
+    /// Execution stops with an error mentioning this text
+    Fails(&'static str),
+}
+
+struct Case {
+    name: &'static str,
+    bug: &'static str,
+    source: Source,
+    expect: Expect,
+    known_failure: bool,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "mod_takes_dividend_from_top_of_stack",
+        bug: "operand ordering",
+        // PUSH1 3, PUSH1 10, MOD -> 10 % 3, returned as a word
+        source: Source::Bytecode("6003600a0660005260206000f3"),
+        expect: Expect::Returns(1),
+        known_failure: false,
+    },
+    Case {
+        name: "abbyscript_subtraction_keeps_left_operand_first",
+        bug: "operand ordering",
+        source: Source::AbbyScript("return 10 - 3;"),
+        expect: Expect::Returns(7),
+        known_failure: true,
+    },
+    Case {
+        name: "abbyscript_modulo_keeps_left_operand_first",
+        bug: "operand ordering",
+        source: Source::AbbyScript("return 10 % 3;"),
+        expect: Expect::Returns(1),
+        known_failure: false,
+    },
+    Case {
+        name: "abbyscript_less_than_keeps_left_operand_first",
+        bug: "operand ordering",
+        source: Source::AbbyScript("return 1 < 2;"),
+        expect: Expect::Returns(1),
+        known_failure: true,
+    },
+    Case {
+        name: "truncated_push_at_end_of_code_is_zero_padded",
+        bug: "PUSH truncation",
+        // PUSH2 with a single immediate byte left in the code
+        source: Source::Bytecode("6101"),
+        expect: Expect::Succeeds,
+        known_failure: true,
+    },
+    Case {
+        name: "abbyscript_wide_literal_is_not_truncated",
+        bug: "PUSH truncation",
+        source: Source::AbbyScript("return 0x0102030405;"),
+        expect: Expect::Returns(0x0102030405),
+        known_failure: false,
+    },
+    Case {
+        name: "jump_into_push_data_is_rejected",
+        bug: "jumpdest-in-pushdata",
+        // PUSH1 4, JUMP, PUSH1 0x5b, STOP: offset 4 is push data, not a JUMPDEST
+        source: Source::Bytecode("600456605b00"),
+        expect: Expect::Fails("Invalid jump destination"),
+        known_failure: true,
+    },
+    Case {
+        name: "mload_beyond_usize_fails_cleanly",
+        bug: "as_usize panic",
+        // PUSH9 2^64, MLOAD
+        source: Source::Bytecode("6801000000000000000051"),
+        expect: Expect::Fails("Memory limit exceeded"),
+        known_failure: false,
+    },
+    Case {
+        name: "jump_beyond_usize_fails_cleanly",
+        bug: "as_usize panic",
+        // PUSH9 2^64, JUMP
+        source: Source::Bytecode("6801000000000000000056"),
+        expect: Expect::Fails("Invalid jump destination"),
+        known_failure: true,
+    },
+    Case {
+        name: "return_of_a_gigabyte_fails_cleanly",
+        bug: "unbounded return data",
+        // PUSH4 0x40000000 (size), PUSH1 0 (offset), RETURN
+        source: Source::Bytecode("63400000006000f3"),
+        expect: Expect::Fails("Return data size"),
+        known_failure: false,
+    },
+];
+
+fn run_case(case: &Case) -> Result<(), String> {
+    let bytecode = match case.source {
+        Source::Bytecode(code) => hex::decode(code).map_err(|e| e.to_string())?,
+        Source::AbbyScript(source) => Compiler::new()
+            .compile(source)
+            .map_err(|e| format!("compile error: {}", e))?,
+    };
+
+    let mut executor = EvmExecutor::new(GAS_LIMIT);
+    let result = executor
+        .execute(&bytecode, 0, false)
+        .map_err(|e| e.to_string())?;
+
+    match (&case.expect, &result.status) {
+        (Expect::Returns(expected), ExecutionStatus::Success) => {
+            if result.return_data.len() != 32 {
+                return Err(format!(
+                    "expected a 32-byte word, got {} bytes",
+                    result.return_data.len()
+                ));
+            }
+            let value = U256::from_big_endian(&result.return_data);
+            if value != U256::from(*expected) {
+                return Err(format!("expected {}, got {}", expected, value));
+            }
+            Ok(())
+        }
+        (Expect::Succeeds, ExecutionStatus::Success) => Ok(()),
+        (Expect::Fails(text), ExecutionStatus::Error(message)) if message.contains(text) => Ok(()),
+        (_, status) => Err(format!("unexpected status {:?}", status)),
+    }
+}
+
+#[test]
+fn regression_corpus() {
+    // Panics count as failures. Their messages go through the default hook,
+    // which the test harness captures, so no process-wide hook is swapped in.
+    let outcomes: Vec<_> = CASES
+        .iter()
+        .map(|case| {
+            panic::catch_unwind(AssertUnwindSafe(|| run_case(case)))
+                .unwrap_or_else(|_| Err("panicked".to_string()))
+        })
+        .collect();
+
+    let mut problems = Vec::new();
+    for (case, outcome) in CASES.iter().zip(outcomes) {
+        match (outcome, case.known_failure) {
+            (Ok(()), false) | (Err(_), true) => {}
+            (Ok(()), true) => problems.push(format!(
+                "{} ({}) now passes; clear its known_failure marker",
+                case.name, case.bug
+            )),
+            (Err(e), false) => {
+                problems.push(format!("{} ({}) regressed: {}", case.name, case.bug, e))
+            }
+        }
+    }
+
+    assert!(problems.is_empty(), "\n{}", problems.join("\n"));
+}
+
+#[test]
+fn opcode_bytes_round_trip() {
+    // to_byte gaps: every byte must map back to itself through OpCode
+    for byte in 0..=u8::MAX {
+        assert_eq!(
+            OpCode::from_byte(byte).to_byte(),
+            byte,
+            "byte 0x{:02x}",
+            byte
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Differential tests against a subset of the VMTests from ethereum/tests,
+//! vendored under `tests/vmtests/` in their JSON format.
+//!
+//! Each vector gives the executing account's pre-state, the call (`exec`) and
+//! either the expected post-state, output and gas left, or no `post` at all
+//! when execution must halt exceptionally. The expected gas follows the
+//! Frontier schedule the VMTests were filled with; `logs` and `callcreates`
+//! are not checked.
+//!
+//! As in the regression corpus, vectors failing because of a known bug are
+//! listed in `KNOWN_FAILURES` with the check they fail, and reported once
+//! they start to pass.
+
+use abby_evm::evm::{EvmExecutor, ExecutionContext, PersistentState};
+use abby_evm::types::ExecutionStatus;
+use ethereum_types::{Address, U256};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// Part of a vector's expectations that execution did not meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Check {
+    /// The vector itself could not be read
+    Vector,
+    Status,
+    Storage,
+    Output,
+    Gas,
+}
+
+const FLAT_SSTORE: &str = "SSTORE charges a flat 5000, not 20000 for a new slot";
+
+/// Vector name, the check it fails and the bug that makes it fail
+const KNOWN_FAILURES: &[(&str, Check, &str)] = &[
+    ("add0", Check::Gas, FLAT_SSTORE),
+    ("add1", Check::Gas, FLAT_SSTORE),
+    ("addmod0", Check::Status, "ADDMOD not implemented"),
+    ("div1", Check::Gas, FLAT_SSTORE),
+    (
+        "exp0",
+        Check::Gas,
+        "flat SSTORE, and EXP does not charge per exponent byte",
+    ),
+    ("exp1", Check::Gas, "EXP does not charge per exponent byte"),
+    ("mod0", Check::Gas, FLAT_SSTORE),
+    ("mod1", Check::Gas, FLAT_SSTORE),
+    ("mstore0", Check::Gas, FLAT_SSTORE),
+    ("mul0", Check::Gas, FLAT_SSTORE),
+    ("mul1", Check::Gas, FLAT_SSTORE),
+    ("mulmod0", Check::Status, "MULMOD not implemented"),
+    ("pop0", Check::Gas, FLAT_SSTORE),
+    ("sdiv0", Check::Status, "SDIV not implemented"),
+    ("sdiv1", Check::Status, "SDIV not implemented"),
+    (
+        "signextend_0_BigByte",
+        Check::Status,
+        "SIGNEXTEND not implemented",
+    ),
+    (
+        "signextend_BitIsSetInHigherByte",
+        Check::Status,
+        "SIGNEXTEND not implemented",
+    ),
+    ("sstore_load_0", Check::Gas, FLAT_SSTORE),
+    ("sstore_load_1", Check::Gas, FLAT_SSTORE),
+    (
+        "sstore_load_2",
+        Check::Gas,
+        "SLOAD costs 200 (EIP-150), not the Frontier 50",
+    ),
+    ("sub0", Check::Gas, FLAT_SSTORE),
+    ("sub1", Check::Gas, FLAT_SSTORE),
+];
+
+struct Mismatch {
+    check: Check,
+    message: String,
+}
+
+impl Mismatch {
+    fn new(check: Check, message: String) -> Self {
+        Self { check, message }
+    }
+}
+
+impl From<String> for Mismatch {
+    fn from(message: String) -> Self {
+        Self::new(Check::Vector, message)
+    }
+}
+
+fn word(value: &Value) -> Result<U256, String> {
+    let text = value.as_str().ok_or("expected a hex string")?;
+    U256::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+fn hex_bytes(value: &Value) -> Result<Vec<u8>, String> {
+    let text = value.as_str().ok_or("expected a hex string")?;
+    hex::decode(text.trim_start_matches("0x")).map_err(|e| e.to_string())
+}
+
+fn address(value: &Value) -> Result<Address, String> {
+    let text = value.as_str().ok_or("expected an address")?;
+    text.parse().map_err(|_| format!("bad address {}", text))
+}
+
+/// Non-zero storage slots of `account` in a pre or post section
+fn storage(section: &Value, account: Address) -> Result<BTreeMap<U256, U256>, String> {
+    let slots = section
+        .as_object()
+        .and_then(|accounts| {
+            accounts
+                .iter()
+                .find(|(key, _)| key.parse::<Address>().ok() == Some(account))
+        })
+        .and_then(|(_, state)| state["storage"].as_object());
+
+    let mut storage = BTreeMap::new();
+    for (key, value) in slots.into_iter().flatten() {
+        let value = word(value)?;
+        if !value.is_zero() {
+            storage.insert(word(&Value::String(key.clone()))?, value);
+        }
+    }
+    Ok(storage)
+}
+
+fn run_vector(vector: &Value) -> Result<(), Mismatch> {
+    let exec = &vector["exec"];
+    let account = address(&exec["address"])?;
+    let context = ExecutionContext::new()
+        .address(account)
+        .caller(address(&exec["caller"])?)
+        .origin(address(&exec["origin"])?)
+        .value(word(&exec["value"])?)
+        .calldata(hex_bytes(&exec["data"])?);
+
+    let mut world = PersistentState {
+        storage: storage(&vector["pre"], account)?.into_iter().collect(),
+        accounts: HashMap::new(),
+    };
+    let code = hex_bytes(&exec["code"])?;
+    let mut executor = EvmExecutor::new(word(&exec["gas"])?.as_u64());
+    let result = executor
+        .execute_with_state(&code, &context, &mut world, false)
+        .map_err(|e| Mismatch::new(Check::Status, e.to_string()))?;
+
+    let Some(post) = vector.get("post") else {
+        return match result.status {
+            ExecutionStatus::Success => Err(Mismatch::new(
+                Check::Status,
+                "expected an exceptional halt".to_string(),
+            )),
+            _ => Ok(()),
+        };
+    };
+    if result.status != ExecutionStatus::Success {
+        return Err(Mismatch::new(
+            Check::Status,
+            format!("unexpected status {:?}", result.status),
+        ));
+    }
+
+    let expected = storage(post, account)?;
+    let actual: BTreeMap<U256, U256> = result
+        .storage
+        .into_iter()
+        .filter(|(_, value)| !value.is_zero())
+        .collect();
+    if actual != expected {
+        return Err(Mismatch::new(
+            Check::Storage,
+            format!("storage {:?}, expected {:?}", actual, expected),
+        ));
+    }
+
+    let out = vector
+        .get("out")
+        .map(hex_bytes)
+        .transpose()?
+        .unwrap_or_default();
+    if result.return_data != out {
+        return Err(Mismatch::new(
+            Check::Output,
+            format!(
+                "output 0x{}, expected 0x{}",
+                hex::encode(&result.return_data),
+                hex::encode(&out)
+            ),
+        ));
+    }
+
+    // Refunds are paid out by the transaction, after the VM test's gas count
+    let gas_left = result.gas_remaining - result.refund;
+    let expected_gas = word(&vector["gas"])?;
+    if gas_left != expected_gas {
+        return Err(Mismatch::new(
+            Check::Gas,
+            format!("{} gas left, expected {}", gas_left, expected_gas),
+        ));
+    }
+    Ok(())
+}
+
+/// Every vector in every file under `tests/vmtests`, sorted by name
+fn load_vectors() -> Vec<(String, Value)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vmtests");
+    let mut vectors = Vec::new();
+    for entry in std::fs::read_dir(&dir).expect("tests/vmtests is missing") {
+        let path = entry.unwrap().path();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let file: serde_json::Map<String, Value> =
+            serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        vectors.extend(file);
+    }
+    vectors.sort_by(|a, b| a.0.cmp(&b.0));
+    vectors
+}
+
+#[test]
+fn vm_test_vectors() {
+    let vectors = load_vectors();
+    assert!(!vectors.is_empty());
+
+    // A panicking vector counts as a failed status check. Its message goes
+    // through the default hook, which the test harness captures.
+    let mut problems = Vec::new();
+    for (name, vector) in &vectors {
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| run_vector(vector)))
+            .unwrap_or_else(|_| Err(Mismatch::new(Check::Status, "panicked".to_string())));
+        let known = KNOWN_FAILURES.iter().find(|(known, _, _)| known == name);
+        match (outcome, known) {
+            (Ok(()), None) => {}
+            (Err(mismatch), Some((_, check, _))) if mismatch.check == *check => {}
+            (Ok(()), Some((_, check, bug))) => problems.push(format!(
+                "{} ({:?}: {}) now passes; remove it from KNOWN_FAILURES",
+                name, check, bug
+            )),
+            (Err(mismatch), _) => problems.push(format!(
+                "{} failed {:?}: {}",
+                name, mismatch.check, mismatch.message
+            )),
+        }
+    }
+
+    assert!(problems.is_empty(), "\n{}", problems.join("\n"));
+}